@@ -0,0 +1,42 @@
+/*
+ * rlox1's C API: a Lox interpreter for hosts that aren't written in Rust. Build the library
+ * with
+ *
+ *   cargo build --release --features capi
+ *
+ * which leaves a shared library, target/release/librlox1.so, and a static one, librlox1.a,
+ * and see src/capi.rs for the rules every function follows: a Lox belongs to the thread that
+ * made it, strings are NUL-terminated UTF-8, and a string given back belongs to the Lox,
+ * valid until the next call on it.
+ */
+
+#ifndef RLOX1_H
+#define RLOX1_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct CLox Lox;
+
+/* A Lox with the standard library but no file access, to be freed with lox_free. */
+Lox *lox_new(void);
+
+/* Run source in lox, keeping what it defines. 0 if it ran, else the status rlox1 would exit
+ * with, e.g. 65 for a syntax error or 70 for a runtime error; see lox_get_error. */
+int lox_eval(Lox *lox, const char *source);
+
+/* What lox has printed since this was last asked. */
+const char *lox_get_output(Lox *lox);
+
+/* The error the last lox_eval stopped with, or "" if it ran. */
+const char *lox_get_error(const Lox *lox);
+
+/* Free lox and everything its scripts defined. */
+void lox_free(Lox *lox);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RLOX1_H */
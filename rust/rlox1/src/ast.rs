@@ -0,0 +1,775 @@
+use std::fmt;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::Span;
+use crate::scanner::Token;
+
+// Every node gets an id from a single process-wide counter, so ids stay unique across
+// separate parses (REPL lines, multiple files) and side tables can outlive one parse.
+static NEXT_NODE_ID: AtomicUsize = AtomicUsize::new(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    // fresh: Allocate a new, never before used, node id.
+    pub fn fresh() -> Self {
+        NodeId(NEXT_NODE_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Literal::Nil => write!(f, "nil"),
+            Literal::Bool(b) => write!(f, "{}", b),
+            Literal::Number(n) => write!(f, "{}", n),
+            Literal::Str(s) => write!(f, "{:?}", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Literal(Literal),
+    Grouping(Box<Expr>),
+    List(Vec<Expr>),
+    Map {
+        brace: Token,
+        entries: Vec<(Expr, Expr)>,
+    },
+    Variable(Token),
+    Logical {
+        left: Box<Expr>,
+        op: Token,
+        right: Box<Expr>,
+    },
+    Assign {
+        name: Token,
+        value: Box<Expr>,
+    },
+    Unary {
+        op: Token,
+        right: Box<Expr>,
+    },
+    Binary {
+        left: Box<Expr>,
+        op: Token,
+        right: Box<Expr>,
+    },
+    Call {
+        callee: Box<Expr>,
+        paren: Token,
+        arguments: Vec<Expr>,
+    },
+    Get {
+        object: Box<Expr>,
+        name: Token,
+    },
+    Set {
+        object: Box<Expr>,
+        name: Token,
+        value: Box<Expr>,
+    },
+    This(Token),
+    Super {
+        keyword: Token,
+        method: Token,
+    },
+    Index {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+    },
+    Slice {
+        object: Box<Expr>,
+        bracket: Token,
+        start: Option<Box<Expr>>,
+        end: Option<Box<Expr>>,
+    },
+    SetIndex {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+        value: Box<Expr>,
+    },
+    // Match: The value of the first arm whose pattern matches `subject`.
+    Match {
+        keyword: Token,
+        subject: Box<Expr>,
+        arms: Vec<MatchArm>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub body: Expr,
+}
+
+// Pattern: What a match arm tests its subject against. A literal matches an equal value; a
+// binding matches anything and names it within the arm, and so does the wildcard `_`, minus
+// the name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Literal(Literal),
+    Binding(Token),
+    Wildcard,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub id: NodeId,
+    pub kind: ExprKind,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind) -> Self {
+        Expr {
+            id: NodeId::fresh(),
+            kind,
+        }
+    }
+}
+
+impl Expr {
+    // token: The token that stands for the expression in errors, e.g. the operator of a
+    // binary expression or the name of a variable. Literals and groupings have none.
+    pub fn token(&self) -> Option<&Token> {
+        match &self.kind {
+            ExprKind::Literal(_) | ExprKind::Grouping(_) | ExprKind::List(_) => None,
+            ExprKind::Map { brace, .. } => Some(brace),
+            ExprKind::Variable(name)
+            | ExprKind::Assign { name, .. }
+            | ExprKind::Get { name, .. }
+            | ExprKind::Set { name, .. } => Some(name),
+            ExprKind::Logical { op, .. }
+            | ExprKind::Unary { op, .. }
+            | ExprKind::Binary { op, .. } => Some(op),
+            ExprKind::Call { paren, .. } => Some(paren),
+            ExprKind::This(keyword) | ExprKind::Match { keyword, .. } => Some(keyword),
+            ExprKind::Super { method, .. } => Some(method),
+            ExprKind::Index { bracket, .. }
+            | ExprKind::Slice { bracket, .. }
+            | ExprKind::SetIndex { bracket, .. } => Some(bracket),
+        }
+    }
+}
+
+impl ExprKind {
+    // name: The kind of node, as `--profile` reports it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExprKind::Literal(_) => "Literal",
+            ExprKind::Grouping(_) => "Grouping",
+            ExprKind::List(_) => "List",
+            ExprKind::Map { .. } => "Map",
+            ExprKind::Variable(_) => "Variable",
+            ExprKind::Logical { .. } => "Logical",
+            ExprKind::Assign { .. } => "Assign",
+            ExprKind::Unary { .. } => "Unary",
+            ExprKind::Binary { .. } => "Binary",
+            ExprKind::Call { .. } => "Call",
+            ExprKind::Get { .. } => "Get",
+            ExprKind::Set { .. } => "Set",
+            ExprKind::This(_) => "This",
+            ExprKind::Super { .. } => "Super",
+            ExprKind::Index { .. } => "Index",
+            ExprKind::Slice { .. } => "Slice",
+            ExprKind::SetIndex { .. } => "SetIndex",
+            ExprKind::Match { .. } => "Match",
+        }
+    }
+}
+
+// FunctionDecl: A named function. Function values share their declaration rather than
+// copying the body, so it sits behind an Rc in the tree.
+#[derive(Debug, PartialEq)]
+pub struct FunctionDecl {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub body: Vec<Stmt>,
+    // is_generator: Set if the body yields. Calling a generator makes an iterator that runs
+    // the body a step at a time, rather than running it straight away.
+    pub is_generator: bool,
+    // The comments around a method, if the class was parsed keeping them.
+    pub comments: Option<Box<Comments>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    Expression(Expr),
+    Print(Expr),
+    // Var: A `var` declaration, or a `const` one if `constant` is set. Constants always
+    // have an initializer.
+    Var {
+        name: Token,
+        initializer: Option<Expr>,
+        constant: bool,
+    },
+    Function(Rc<FunctionDecl>),
+    // Class: A class declaration. `mixins` are the classes named after `with`, whose methods
+    // are copied into the class when it is declared. Getters take no parameters and setters
+    // exactly one.
+    Class {
+        name: Token,
+        superclass: Option<Expr>,
+        mixins: Vec<Expr>,
+        methods: Vec<Rc<FunctionDecl>>,
+        getters: Vec<Rc<FunctionDecl>>,
+        setters: Vec<Rc<FunctionDecl>>,
+    },
+    Block(Vec<Stmt>),
+    If {
+        keyword: Token,
+        condition: Expr,
+        then_branch: Box<Stmt>,
+        else_branch: Option<Box<Stmt>>,
+    },
+    Return {
+        keyword: Token,
+        value: Option<Expr>,
+    },
+    Yield {
+        keyword: Token,
+        value: Option<Expr>,
+    },
+    Throw {
+        keyword: Token,
+        value: Expr,
+    },
+    Try {
+        body: Vec<Stmt>,
+        name: Token,
+        handler: Vec<Stmt>,
+    },
+    ForIn {
+        keyword: Token,
+        name: Token,
+        iterable: Expr,
+        body: Box<Stmt>,
+    },
+    While {
+        keyword: Token,
+        condition: Expr,
+        body: Box<Stmt>,
+    },
+    // For: A C-style `for` loop. Any of the three clauses can be left out; a missing
+    // condition is always true. A variable the initializer declares is scoped to the loop.
+    For {
+        keyword: Token,
+        initializer: Option<Box<Stmt>>,
+        condition: Option<Expr>,
+        increment: Option<Expr>,
+        body: Box<Stmt>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub id: NodeId,
+    // The line the statement starts on, or 0 if it wasn't parsed from source.
+    pub line: usize,
+    // The span of the token it starts with, for diagnostics about the whole statement.
+    pub span: Option<Span>,
+    // The comments around the statement, if it was parsed keeping them; see Comments.
+    pub comments: Option<Box<Comments>>,
+    pub kind: StmtKind,
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind) -> Self {
+        Stmt {
+            id: NodeId::fresh(),
+            line: 0,
+            span: None,
+            comments: None,
+            kind,
+        }
+    }
+}
+
+// Comments: The comments `rlox1 fmt` keeps with a statement in a block, or a method, each
+// with its `//`. `before` are on the lines above it, along with any from inside it that
+// aren't around a statement of their own, `trailing` is the one at the end of its last line,
+// and `after` are on the lines between it and the end of the block it ends.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Comments {
+    pub before: Vec<String>,
+    pub trailing: Option<String>,
+    pub after: Vec<String>,
+}
+
+impl StmtKind {
+    // name: The kind of node, as `--profile` reports it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            StmtKind::Expression(_) => "Expression statement",
+            StmtKind::Print(_) => "Print",
+            StmtKind::Var { .. } => "Var",
+            StmtKind::Function(_) => "Function",
+            StmtKind::Class { .. } => "Class",
+            StmtKind::Block(_) => "Block",
+            StmtKind::If { .. } => "If",
+            StmtKind::Return { .. } => "Return",
+            StmtKind::Yield { .. } => "Yield",
+            StmtKind::Throw { .. } => "Throw",
+            StmtKind::Try { .. } => "Try",
+            StmtKind::ForIn { .. } => "ForIn",
+            StmtKind::While { .. } => "While",
+            StmtKind::For { .. } => "For",
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Printing
+// ------------------------------------------------------------------------------------------------
+
+// Expressions print as parenthesized prefix notation, e.g. `(+ 1 (* 2 3))`. The alternate
+// form (`{:#}`) tags every node with its id, which is handy when debugging side tables.
+
+fn write_id(f: &mut fmt::Formatter, id: NodeId) -> fmt::Result {
+    if f.alternate() {
+        write!(f, "{}", id)
+    } else {
+        Ok(())
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            ExprKind::Literal(lit) => {
+                write!(f, "{}", lit)?;
+                write_id(f, self.id)
+            }
+            ExprKind::Grouping(expr) => {
+                write!(f, "(group")?;
+                write_id(f, self.id)?;
+                write!(f, " ")?;
+                expr.fmt(f)?;
+                write!(f, ")")
+            }
+            ExprKind::Variable(name) => {
+                write!(f, "{}", name.typ)?;
+                write_id(f, self.id)
+            }
+            ExprKind::Logical { left, op, right } => {
+                write!(f, "({}", op.typ)?;
+                write_id(f, self.id)?;
+                write!(f, " ")?;
+                left.fmt(f)?;
+                write!(f, " ")?;
+                right.fmt(f)?;
+                write!(f, ")")
+            }
+            ExprKind::Assign { name, value } => {
+                write!(f, "(=")?;
+                write_id(f, self.id)?;
+                write!(f, " {} ", name.typ)?;
+                value.fmt(f)?;
+                write!(f, ")")
+            }
+            ExprKind::List(elements) => {
+                write!(f, "(list")?;
+                write_id(f, self.id)?;
+                for element in elements {
+                    write!(f, " ")?;
+                    element.fmt(f)?;
+                }
+                write!(f, ")")
+            }
+            ExprKind::Map { entries, .. } => {
+                write!(f, "(map")?;
+                write_id(f, self.id)?;
+                for (key, value) in entries {
+                    write!(f, " ")?;
+                    key.fmt(f)?;
+                    write!(f, ":")?;
+                    value.fmt(f)?;
+                }
+                write!(f, ")")
+            }
+            ExprKind::Unary { op, right } => {
+                write!(f, "({}", op.typ)?;
+                write_id(f, self.id)?;
+                write!(f, " ")?;
+                right.fmt(f)?;
+                write!(f, ")")
+            }
+            ExprKind::Binary { left, op, right } => {
+                write!(f, "({}", op.typ)?;
+                write_id(f, self.id)?;
+                write!(f, " ")?;
+                left.fmt(f)?;
+                write!(f, " ")?;
+                right.fmt(f)?;
+                write!(f, ")")
+            }
+            ExprKind::Call {
+                callee, arguments, ..
+            } => {
+                write!(f, "(call")?;
+                write_id(f, self.id)?;
+                write!(f, " ")?;
+                callee.fmt(f)?;
+                for argument in arguments {
+                    write!(f, " ")?;
+                    argument.fmt(f)?;
+                }
+                write!(f, ")")
+            }
+            ExprKind::Get { object, name } => {
+                write!(f, "(.")?;
+                write_id(f, self.id)?;
+                write!(f, " ")?;
+                object.fmt(f)?;
+                write!(f, " {})", name.typ)
+            }
+            ExprKind::Set {
+                object,
+                name,
+                value,
+            } => {
+                write!(f, "(.=")?;
+                write_id(f, self.id)?;
+                write!(f, " ")?;
+                object.fmt(f)?;
+                write!(f, " {} ", name.typ)?;
+                value.fmt(f)?;
+                write!(f, ")")
+            }
+            ExprKind::This(_) => {
+                write!(f, "this")?;
+                write_id(f, self.id)
+            }
+            ExprKind::Super { method, .. } => {
+                write!(f, "(super")?;
+                write_id(f, self.id)?;
+                write!(f, " {})", method.typ)
+            }
+            ExprKind::Index { object, index, .. } => {
+                write!(f, "(index")?;
+                write_id(f, self.id)?;
+                write!(f, " ")?;
+                object.fmt(f)?;
+                write!(f, " ")?;
+                index.fmt(f)?;
+                write!(f, ")")
+            }
+            ExprKind::Slice {
+                object, start, end, ..
+            } => {
+                write!(f, "(slice")?;
+                write_id(f, self.id)?;
+                write!(f, " ")?;
+                object.fmt(f)?;
+                for bound in &[start, end] {
+                    match bound {
+                        Some(bound) => {
+                            write!(f, " ")?;
+                            bound.fmt(f)?;
+                        }
+                        None => write!(f, " _")?,
+                    }
+                }
+                write!(f, ")")
+            }
+            ExprKind::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                write!(f, "(set-index")?;
+                write_id(f, self.id)?;
+                write!(f, " ")?;
+                object.fmt(f)?;
+                write!(f, " ")?;
+                index.fmt(f)?;
+                write!(f, " ")?;
+                value.fmt(f)?;
+                write!(f, ")")
+            }
+            ExprKind::Match { subject, arms, .. } => {
+                write!(f, "(match")?;
+                write_id(f, self.id)?;
+                write!(f, " ")?;
+                subject.fmt(f)?;
+                for arm in arms {
+                    match &arm.pattern {
+                        Pattern::Literal(lit) => write!(f, " ({} -> ", lit)?,
+                        Pattern::Binding(name) => write!(f, " ({} -> ", name.typ)?,
+                        Pattern::Wildcard => write!(f, " (_ -> ")?,
+                    }
+                    arm.body.fmt(f)?;
+                    write!(f, ")")?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            StmtKind::Expression(expr) => {
+                write!(f, "(expr")?;
+                write_id(f, self.id)?;
+                write!(f, " ")?;
+                expr.fmt(f)?;
+                write!(f, ")")
+            }
+            StmtKind::Print(expr) => {
+                write!(f, "(print")?;
+                write_id(f, self.id)?;
+                write!(f, " ")?;
+                expr.fmt(f)?;
+                write!(f, ")")
+            }
+            StmtKind::Var {
+                name,
+                initializer,
+                constant,
+            } => {
+                write!(f, "({}", if *constant { "const" } else { "var" })?;
+                write_id(f, self.id)?;
+                write!(f, " {}", name.typ)?;
+                if let Some(initializer) = initializer {
+                    write!(f, " ")?;
+                    initializer.fmt(f)?;
+                }
+                write!(f, ")")
+            }
+            StmtKind::Function(decl) => {
+                write!(f, "(fun")?;
+                write_id(f, self.id)?;
+                write_function(f, decl)?;
+                write!(f, ")")
+            }
+            StmtKind::Class {
+                name,
+                superclass,
+                mixins,
+                methods,
+                getters,
+                setters,
+            } => {
+                write!(f, "(class")?;
+                write_id(f, self.id)?;
+                write!(f, " {}", name.typ)?;
+                if let Some(superclass) = superclass {
+                    write!(f, " < ")?;
+                    superclass.fmt(f)?;
+                }
+                if !mixins.is_empty() {
+                    write!(f, " with")?;
+                    for mixin in mixins {
+                        write!(f, " ")?;
+                        mixin.fmt(f)?;
+                    }
+                }
+                let members = [("method", methods), ("get", getters), ("set", setters)];
+                for (kind, functions) in members.iter() {
+                    for function in functions.iter() {
+                        write!(f, " ({}", kind)?;
+                        write_function(f, function)?;
+                        write!(f, ")")?;
+                    }
+                }
+                write!(f, ")")
+            }
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                write!(f, "(if")?;
+                write_id(f, self.id)?;
+                write!(f, " ")?;
+                condition.fmt(f)?;
+                write!(f, " ")?;
+                then_branch.fmt(f)?;
+                if let Some(else_branch) = else_branch {
+                    write!(f, " ")?;
+                    else_branch.fmt(f)?;
+                }
+                write!(f, ")")
+            }
+            StmtKind::Return { value, .. } => {
+                write!(f, "(return")?;
+                write_id(f, self.id)?;
+                if let Some(value) = value {
+                    write!(f, " ")?;
+                    value.fmt(f)?;
+                }
+                write!(f, ")")
+            }
+            StmtKind::Yield { value, .. } => {
+                write!(f, "(yield")?;
+                write_id(f, self.id)?;
+                if let Some(value) = value {
+                    write!(f, " ")?;
+                    value.fmt(f)?;
+                }
+                write!(f, ")")
+            }
+            StmtKind::Throw { value, .. } => {
+                write!(f, "(throw")?;
+                write_id(f, self.id)?;
+                write!(f, " ")?;
+                value.fmt(f)?;
+                write!(f, ")")
+            }
+            StmtKind::Try {
+                body,
+                name,
+                handler,
+            } => {
+                write!(f, "(try")?;
+                write_id(f, self.id)?;
+                write!(f, " (")?;
+                for (i, stmt) in body.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    stmt.fmt(f)?;
+                }
+                write!(f, ") (catch {}", name.typ)?;
+                for stmt in handler {
+                    write!(f, " ")?;
+                    stmt.fmt(f)?;
+                }
+                write!(f, "))")
+            }
+            StmtKind::Block(statements) => {
+                write!(f, "(block")?;
+                write_id(f, self.id)?;
+                for stmt in statements {
+                    write!(f, " ")?;
+                    stmt.fmt(f)?;
+                }
+                write!(f, ")")
+            }
+            StmtKind::ForIn {
+                name,
+                iterable,
+                body,
+                ..
+            } => {
+                write!(f, "(for-in")?;
+                write_id(f, self.id)?;
+                write!(f, " {} ", name.typ)?;
+                iterable.fmt(f)?;
+                write!(f, " ")?;
+                body.fmt(f)?;
+                write!(f, ")")
+            }
+            StmtKind::While {
+                condition, body, ..
+            } => {
+                write!(f, "(while")?;
+                write_id(f, self.id)?;
+                write!(f, " ")?;
+                condition.fmt(f)?;
+                write!(f, " ")?;
+                body.fmt(f)?;
+                write!(f, ")")
+            }
+            // A missing clause prints as `_`.
+            StmtKind::For {
+                initializer,
+                condition,
+                increment,
+                body,
+                ..
+            } => {
+                write!(f, "(for")?;
+                write_id(f, self.id)?;
+                match initializer {
+                    Some(initializer) => {
+                        write!(f, " ")?;
+                        initializer.fmt(f)?;
+                    }
+                    None => write!(f, " _")?,
+                }
+                for clause in &[condition, increment] {
+                    match clause {
+                        Some(clause) => {
+                            write!(f, " ")?;
+                            clause.fmt(f)?;
+                        }
+                        None => write!(f, " _")?,
+                    }
+                }
+                write!(f, " ")?;
+                body.fmt(f)?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+// write_function: The name, parameters and body of a function or method, e.g.
+// ` f (a b) (return a)`.
+fn write_function(f: &mut fmt::Formatter, decl: &FunctionDecl) -> fmt::Result {
+    write!(f, " {} (", decl.name.typ)?;
+    for (i, param) in decl.params.iter().enumerate() {
+        if i > 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "{}", param.typ)?;
+    }
+    write!(f, ")")?;
+    for stmt in &decl.body {
+        write!(f, " ")?;
+        fmt::Display::fmt(stmt, f)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn node_ids_are_unique() {
+        let a = Expr::new(ExprKind::Literal(Literal::Nil));
+        let b = Expr::new(ExprKind::Literal(Literal::Nil));
+        let s = Stmt::new(StmtKind::Print(a.clone()));
+        assert_ne!(a.id, b.id);
+        assert_ne!(a.id, s.id);
+        assert_ne!(b.id, s.id);
+    }
+
+    #[test]
+    fn cloned_nodes_keep_their_id() {
+        let a = Expr::new(ExprKind::Literal(Literal::Number(1.0)));
+        assert_eq!(a.id, a.clone().id);
+    }
+
+    #[test]
+    fn alternate_display_shows_ids() {
+        let e = Expr::new(ExprKind::Literal(Literal::Bool(true)));
+        assert_eq!(format!("{}", e), "true");
+        assert_eq!(format!("{:#}", e), format!("true{}", e.id));
+    }
+}
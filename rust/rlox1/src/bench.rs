@@ -0,0 +1,132 @@
+// Benchmarks for `rlox1 bench`: the benchmark programs from the book's repository, built into
+// the binary so that every engine, and every version of rlox1, is timed on the same scripts.
+// `bench` times each run itself, so the scripts' own calls to clock() and the prints of what
+// it measured are left out, and an answer the book's script printed is checked with assert()
+// instead.
+
+use std::fmt;
+use std::time::Duration;
+
+pub struct Benchmark {
+    pub name: &'static str,
+    pub source: &'static str,
+}
+
+pub const BENCHMARKS: [Benchmark; 4] = [
+    Benchmark {
+        name: "fib",
+        source: include_str!("../resources/bench/fib.lox"),
+    },
+    Benchmark {
+        name: "zoo",
+        source: include_str!("../resources/bench/zoo.lox"),
+    },
+    Benchmark {
+        name: "string_equality",
+        source: include_str!("../resources/bench/string_equality.lox"),
+    },
+    Benchmark {
+        name: "trees",
+        source: include_str!("../resources/bench/trees.lox"),
+    },
+];
+
+// find: The built-in benchmark called `name`.
+pub fn find(name: &str) -> Option<&'static Benchmark> {
+    BENCHMARKS.iter().find(|benchmark| benchmark.name == name)
+}
+
+// names: Every benchmark's name, for help and error messages.
+pub fn names() -> Vec<&'static str> {
+    BENCHMARKS.iter().map(|benchmark| benchmark.name).collect()
+}
+
+// Timings: How long each run of a benchmark took.
+pub struct Timings(pub Vec<Duration>);
+
+impl Timings {
+    pub fn mean(&self) -> f64 {
+        self.seconds().sum::<f64>() / self.0.len() as f64
+    }
+
+    // stddev: The sample standard deviation, or 0 for a single run.
+    pub fn stddev(&self) -> f64 {
+        if self.0.len() < 2 {
+            return 0.0;
+        }
+        let mean = self.mean();
+        let squares: f64 = self.seconds().map(|t| (t - mean) * (t - mean)).sum();
+        (squares / (self.0.len() - 1) as f64).sqrt()
+    }
+
+    fn seconds(&self) -> impl Iterator<Item = f64> + '_ {
+        self.0.iter().map(Duration::as_secs_f64)
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "mean {:>10.3}ms  stddev {:>8.3}ms",
+            self.mean() * 1000.0,
+            self.stddev() * 1000.0
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::bench::{find, names, Timings, BENCHMARKS};
+    use crate::error::LoxError;
+    use crate::executive::{Engine, Executor};
+    use crate::parser::Parser;
+    use crate::resolver::Resolver;
+    use crate::scanner::Scanner;
+
+    #[test]
+    fn benchmarks_are_found_by_name() {
+        assert_eq!(names(), ["fib", "zoo", "string_equality", "trees"]);
+        assert_eq!(find("trees").map(|b| b.name), Some("trees"));
+        assert!(find("nope").is_none());
+    }
+
+    #[test]
+    fn timings_summarize_runs() {
+        let millis = |ms: &[u64]| Timings(ms.iter().map(|ms| Duration::from_millis(*ms)).collect());
+        let timings = millis(&[2, 4, 4, 4, 5, 5, 7, 9]);
+        assert!((timings.mean() - 0.005).abs() < 1e-9);
+        assert!((timings.stddev() - 0.002138).abs() < 1e-6);
+        assert_eq!(millis(&[3]).stddev(), 0.0);
+        let shown = format!("{}", millis(&[3]));
+        assert_eq!(shown, "mean      3.000ms  stddev    0.000ms");
+    }
+
+    // Running them takes too long for a test, so they are only checked to be free of static
+    // errors and warnings.
+    #[test]
+    fn benchmarks_are_valid_programs() -> Result<(), LoxError> {
+        for benchmark in &BENCHMARKS {
+            let statements = Parser::new(Scanner::new(benchmark.source).scan_tokens()?).parse()?;
+            let warnings = Resolver::new().check(&statements)?;
+            assert!(warnings.is_empty(), "{}", benchmark.name);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn engines_that_cant_run_a_benchmark_are_refused() {
+        let err = Executor::new()
+            .engine(Engine::Vm)
+            .bench(&[], 1)
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("The vm engine can't run these benchmarks: fib (The bytecode engine"),
+            "{}",
+            err
+        );
+    }
+}
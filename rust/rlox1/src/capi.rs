@@ -0,0 +1,202 @@
+// rlox1 for hosts written in C, C++ or anything else that can call C: a Lox behind an opaque
+// pointer, with output captured rather than written to stdout, so the host shows it where it
+// likes. Built with the `capi` feature, into the shared and static libraries Cargo.toml asks
+// for, e.g. target/release/librlox1.so and librlox1.a from
+//
+//   cargo build --release --features capi
+//
+// and declared for C in include/rlox1.h. In short:
+//
+//   Lox *lox = lox_new();
+//   if (lox_eval(lox, "print 1 + 2;") != 0)
+//       fputs(lox_get_error(lox), stderr);
+//   fputs(lox_get_output(lox), stdout);
+//   lox_free(lox);
+//
+// Every function takes a Lox that lox_new made and lox_free hasn't freed, and strings that
+// are NUL-terminated UTF-8. A string given back belongs to the Lox, and stays valid until the
+// next call on it. As in Rust, a Lox belongs to the thread that made it.
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::diagnostic::{Level, Renderer};
+use crate::embed::Lox;
+use crate::error::{ErrorKind, LoxError};
+
+// CLox: What a C host's Lox pointer points to: the Lox, and the strings last given back.
+pub struct CLox {
+    lox: Lox,
+    output: CString,
+    error: CString,
+}
+
+// lox_new: A Lox with the standard library but no file access, to be freed with lox_free.
+#[no_mangle]
+pub extern "C" fn lox_new() -> *mut CLox {
+    Box::into_raw(Box::new(CLox {
+        lox: captured(),
+        output: CString::default(),
+        error: CString::default(),
+    }))
+}
+
+// lox_eval: Run `source` in `lox`, keeping what it defines for what runs after. Gives 0 if it
+// ran, or else the status rlox1 would exit with, e.g. 65 for a syntax error or 70 for a
+// runtime error, with the error for lox_get_error.
+#[no_mangle]
+pub unsafe extern "C" fn lox_eval(lox: *mut CLox, source: *const c_char) -> c_int {
+    let lox = match lox.as_mut() {
+        Some(lox) => lox,
+        None => return crate::error::EX_SOFTWARE,
+    };
+    let result = match text(source) {
+        Ok(source) => lox.eval(source),
+        Err(err) => Err(err),
+    };
+    match result {
+        Ok(()) => {
+            lox.error = CString::default();
+            0
+        }
+        Err(err) => {
+            lox.error = c_string(&Renderer::new(false).render(Level::Error, &err));
+            err.status()
+        }
+    }
+}
+
+// lox_get_output: What `lox` has printed since this was last asked, or "" for a null lox.
+#[no_mangle]
+pub unsafe extern "C" fn lox_get_output(lox: *mut CLox) -> *const c_char {
+    match lox.as_mut() {
+        Some(lox) => {
+            lox.output = c_string(&lox.lox.interpreter().take_output());
+            lox.output.as_ptr()
+        }
+        None => "\0".as_ptr() as *const c_char,
+    }
+}
+
+// lox_get_error: The error the last lox_eval stopped with, laid out as rlox1 shows errors,
+// or "" if it ran.
+#[no_mangle]
+pub unsafe extern "C" fn lox_get_error(lox: *const CLox) -> *const c_char {
+    match lox.as_ref() {
+        Some(lox) => lox.error.as_ptr(),
+        None => "\0".as_ptr() as *const c_char,
+    }
+}
+
+// lox_free: Free `lox` and everything its scripts defined. A null lox is left alone.
+#[no_mangle]
+pub unsafe extern "C" fn lox_free(lox: *mut CLox) {
+    if !lox.is_null() {
+        drop(Box::from_raw(lox));
+    }
+}
+
+impl CLox {
+    // eval: Run `source`, without letting a panic unwind into C. A bug in rlox1 leaves the
+    // Lox in no state to go on with, so it starts again, as the REPL does.
+    fn eval(&mut self, source: &str) -> Result<(), LoxError> {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.lox.eval(source)));
+        match result {
+            Ok(result) => result.map(|_| ()),
+            Err(_) => {
+                // What was printed before is kept for lox_get_output all the same.
+                let output = self.lox.interpreter().take_output();
+                self.lox = captured();
+                self.lox.interpreter().output().text(&output);
+                Err(lox_error!(
+                    LX0607,
+                    "Internal interpreter error; please report this bug in rlox1"
+                )
+                .of_kind(ErrorKind::Internal))
+            }
+        }
+    }
+}
+
+// captured: A new Lox, keeping what it prints.
+fn captured() -> Lox {
+    let mut lox = Lox::new();
+    lox.interpreter().capture_output();
+    lox
+}
+
+// text: The C string at `source`, which must be UTF-8.
+unsafe fn text<'a>(source: *const c_char) -> Result<&'a str, LoxError> {
+    if source.is_null() {
+        loxerr!(LX0712, "Source can't be a null pointer")
+    }
+    match CStr::from_ptr(source).to_str() {
+        Ok(source) => Ok(source),
+        Err(err) => loxerr!(LX0712, "Source isn't UTF-8: {}", err),
+    }
+}
+
+// c_string: `s` for C, which can't hold a NUL inside a string, so each becomes U+FFFD.
+fn c_string(s: &str) -> CString {
+    CString::new(s.replace('\0', "\u{fffd}")).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{CStr, CString};
+    use std::os::raw::c_char;
+    use std::ptr;
+
+    use crate::capi::{lox_eval, lox_free, lox_get_error, lox_get_output, lox_new, CLox};
+
+    fn string(s: *const c_char) -> String {
+        unsafe { CStr::from_ptr(s) }.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn c_hosts_run_lox_and_read_what_it_printed() {
+        let lox = lox_new();
+        let eval = |source: &str| unsafe { lox_eval(lox, CString::new(source).unwrap().as_ptr()) };
+        assert_eq!(eval("var x = 1 + 2;"), 0);
+        assert_eq!(eval("print x;\nprint(\"four\");"), 0);
+        assert_eq!(string(unsafe { lox_get_output(lox) }), "3\nfour\n");
+        assert_eq!(string(unsafe { lox_get_output(lox) }), "");
+        assert_eq!(eval("print 1 +;"), 65);
+        assert_eq!(eval("print \"before\";\nprint nope;"), 70);
+        let error = string(unsafe { lox_get_error(lox) });
+        assert!(
+            error.starts_with("ERROR[LX0301]: [line 2] Undefined variable 'nope'."),
+            "{}",
+            error
+        );
+        assert_eq!(string(unsafe { lox_get_output(lox) }), "before\n");
+        assert_eq!(eval("exit(3);"), 3);
+        unsafe { lox_free(lox) };
+    }
+
+    #[test]
+    fn bad_pointers_are_errors() {
+        const NULL: *mut CLox = ptr::null_mut();
+        unsafe {
+            assert_eq!(lox_eval(NULL, "".as_ptr() as *const c_char), 70);
+            assert_eq!(string(lox_get_output(NULL)), "");
+            assert_eq!(string(lox_get_error(NULL)), "");
+            lox_free(NULL);
+            let lox = lox_new();
+            assert_eq!(lox_eval(lox, ptr::null()), 65);
+            let error = string(lox_get_error(lox));
+            assert!(
+                error.contains("Source can't be a null pointer"),
+                "{}",
+                error
+            );
+            assert_eq!(
+                lox_eval(lox, b"print 1;\xff\0".as_ptr() as *const c_char),
+                65
+            );
+            lox_free(lox);
+        }
+    }
+}
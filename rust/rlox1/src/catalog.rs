@@ -0,0 +1,262 @@
+// Translations of rlox1's diagnostics, for `--lang`. The messages are in English; a catalog
+// gives them in another language, keyed by their codes (see codes.rs), so that a class can
+// be taught in its own language without changing rlox1. A catalog is a text file with a line
+// per message, e.g.
+//
+//   # Undefined variable '{}'
+//   LX0301: Variable '{}' no definida
+//
+// A code with several messages numbers them from 1, in the order codes.rs lists them, e.g.
+// LX0211.3; the number can be left off for the first. `{}` stands for the text that varies,
+// taken in the same order as in English, and `{2}` for the second of it, for a language that
+// needs another order. Blank lines and lines starting with # are ignored. An error keeps the
+// template it was made from and the text for its holes, so it is translated from those, not
+// by reading the English back.
+//
+// What a catalog doesn't translate stays in English: messages without a code or missing from
+// the catalog, the text that varies, which may be English itself, the "[line N] Error at
+// 'x': " a message may start with, and hints such as "did you mean 'x'?". Spanish is built
+// in, as `--lang es`, and its catalog, resources/lang/es.txt, is a place to start on another
+// language.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::codes;
+use crate::error::LoxError;
+
+// BUILT_IN: The catalogs that come with rlox1, by the name --lang knows them by.
+const BUILT_IN: &[(&str, &str)] = &[("es", include_str!("../resources/lang/es.txt"))];
+
+// The catalog diagnostics are translated with, once --lang has chosen one.
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+// Catalog: Translations of messages, by code and which of the code's templates they are of.
+#[derive(Debug, Default)]
+pub struct Catalog {
+    messages: HashMap<(&'static str, usize), String>,
+}
+
+impl Catalog {
+    // parse: The catalog in `text`, read from `file`, or an error about its first line that
+    // isn't right.
+    pub fn parse(file: &str, text: &str) -> Result<Catalog, LoxError> {
+        let mut catalog = Catalog::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let at = format!("{}:{}", file, number + 1);
+            let (key, translation) = line.split_once(':').ok_or_else(|| {
+                lox_error!(LX0713, "{}: Expect a code, then ':' and the message", at)
+            })?;
+            let (name, index) = key.split_once('.').unwrap_or((key, "1"));
+            let code = codes::find(name)
+                .ok_or_else(|| lox_error!(LX0713, "{}: {} isn't a diagnostic code", at, name))?;
+            let template = index
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| index.checked_sub(1))
+                .filter(|&index| index < code.templates().len())
+                .ok_or_else(|| {
+                    lox_error!(LX0713, "{}: {} has no message {}", at, code.code, index)
+                })?;
+            let translation = translation.trim();
+            let holes = code.templates()[template].matches("{}").count();
+            if fill(translation, &vec![""; holes]).is_none() {
+                loxerr!(
+                    LX0713,
+                    "{}: {} uses more parts that vary than the {} there are",
+                    at,
+                    key,
+                    holes
+                )
+            }
+            catalog
+                .messages
+                .insert((code.code, template), translation.to_string());
+        }
+        Ok(catalog)
+    }
+
+    // translate: The message of `err` in the catalog's language, if the catalog has it.
+    pub fn translate(&self, err: &LoxError) -> Option<String> {
+        let template = err.template()?;
+        let holes: Vec<&str> = err.holes().iter().map(String::as_str).collect();
+        // The English is found by filling its template rather than read back from the
+        // message, since what's around it, e.g. its line, may have changed since.
+        let english = fill(template.text(), &holes)?;
+        let message = err.message();
+        let start = message.find(english.as_str())?;
+        let key = (template.code().code, template.index());
+        let translated = fill(self.messages.get(&key)?, &holes)?;
+        let rest = &message[start + english.len()..];
+        Some(format!("{}{}{}", &message[..start], translated, rest))
+    }
+}
+
+// set_language: Translate diagnostics into `lang` from now on: "en", which needs no catalog,
+// the name of one that is built in, or the path of a catalog file.
+pub fn set_language(lang: &str) -> Result<(), LoxError> {
+    let catalog = match BUILT_IN.iter().find(|(name, _)| *name == lang) {
+        _ if lang == "en" => return Ok(()),
+        Some((name, text)) => Catalog::parse(name, text)?,
+        None if Path::new(lang).is_file() => {
+            let text = fs::read_to_string(lang)
+                .map_err(|err| LoxError::from(err).context(&format!("while reading {}", lang)))?;
+            Catalog::parse(lang, &text)?
+        }
+        None => {
+            let names: Vec<_> = BUILT_IN.iter().map(|(name, _)| *name).collect();
+            loxerr!(
+                LX0713,
+                "No catalog for language '{}'; use en, {}, or the path of a catalog file",
+                lang,
+                names.join(", ")
+            )
+        }
+    };
+    // The language is only ever chosen once, from the command line.
+    let _ = CATALOG.set(catalog);
+    Ok(())
+}
+
+// text: `err` as its Display shows it, but in the language chosen, as far as the catalog goes.
+pub fn text(err: &LoxError) -> String {
+    match CATALOG.get().and_then(|catalog| catalog.translate(err)) {
+        Some(message) => err.clone().reworded(&message).to_string(),
+        None => err.to_string(),
+    }
+}
+
+// fill: `translation` with its placeholders replaced by `holes`: `{}` by the next of them, and
+// `{N}` by the Nth. Other braces are kept, as messages quote them, e.g. "Expect '{'". None
+// if it asks for a hole there isn't.
+fn fill(translation: &str, holes: &[&str]) -> Option<String> {
+    let mut out = String::new();
+    let mut next = 0;
+    let mut rest = translation;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        rest = &rest[open + 1..];
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if !rest[digits..].starts_with('}') {
+            out.push('{');
+            continue;
+        }
+        let hole = match rest[..digits].parse::<usize>() {
+            Ok(number) => number.checked_sub(1)?,
+            Err(_) => {
+                next += 1;
+                next - 1
+            }
+        };
+        out.push_str(holes.get(hole)?);
+        rest = &rest[digits + 1..];
+    }
+    out.push_str(rest);
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::catalog::{fill, Catalog, BUILT_IN};
+    use crate::codes;
+    use crate::error::LoxError;
+
+    #[test]
+    fn placeholders_are_filled_in_either_order() {
+        let holes = ["a", "b"];
+        assert_eq!(fill("{} then {}", &holes), Some("a then b".into()));
+        assert_eq!(fill("{2} before {1}", &holes), Some("b before a".into()));
+        assert_eq!(fill("Expect '{' {}", &holes), Some("Expect '{' a".into()));
+        assert_eq!(fill("{} {} {}", &holes), None);
+        assert_eq!(fill("{0}", &holes), None);
+    }
+
+    #[test]
+    fn messages_are_translated_by_code() {
+        let catalog = Catalog::parse(
+            "test.txt",
+            "# A comment\n\nLX0301: Variable '{}' no definida\nLX0303: En {2}, no hay '{1}'\n\
+             LX0211.5: Se esperaba ':' después de la clave\n",
+        )
+        .unwrap();
+        let translate = |err| catalog.translate(&err);
+        assert_eq!(
+            translate(lox_error!(
+                LX0301,
+                "[line {}] Undefined variable '{}'",
+                2,
+                "x"
+            ))
+            .as_deref(),
+            Some("[line 2] Variable 'x' no definida")
+        );
+        let err = lox_error!(
+            LX0303,
+            "[line {}] Undefined property '{}' on {}",
+            2,
+            "y",
+            "A instance"
+        );
+        assert_eq!(
+            translate(err).as_deref(),
+            Some("[line 2] En A instance, no hay 'y'")
+        );
+        let err = LoxError::located(
+            "[line 1] Error at '1': ",
+            message!(LX0211, "Expect ':' after map key"),
+        );
+        assert_eq!(
+            translate(err).as_deref(),
+            Some("[line 1] Error at '1': Se esperaba ':' después de la clave")
+        );
+        // Left in English: a message the catalog doesn't have, one without a code, and one
+        // that only says what a coded one would.
+        let err = LoxError::located(
+            "[line 1] ",
+            message!(LX0211, "Expect '=' after constant name"),
+        );
+        assert_eq!(translate(err), None);
+        assert_eq!(translate(LoxError::exit(1)), None);
+        assert_eq!(
+            translate(LoxError::new("[line 2] Undefined variable 'x'")),
+            None
+        );
+    }
+
+    #[test]
+    fn bad_lines_are_errors() {
+        let error = |text| Catalog::parse("bad.txt", text).unwrap_err().to_string();
+        assert_eq!(
+            error("\nLX0301 Variable"),
+            "bad.txt:2: Expect a code, then ':' and the message."
+        );
+        assert_eq!(
+            error("LX9999: x"),
+            "bad.txt:1: LX9999 isn't a diagnostic code."
+        );
+        assert_eq!(error("LX0301.2: x"), "bad.txt:1: LX0301 has no message 2.");
+        assert_eq!(
+            error("LX0301: {} {}"),
+            "bad.txt:1: LX0301 uses more parts that vary than the 1 there are."
+        );
+    }
+
+    #[test]
+    fn built_in_catalogs_translate_every_message() {
+        for (name, text) in BUILT_IN {
+            let catalog = Catalog::parse(name, text).unwrap();
+            for code in codes::CODES {
+                for template in 0..code.templates().len() {
+                    let key = (code.code, template);
+                    assert!(catalog.messages.contains_key(&key), "{}: {:?}", name, key);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,366 @@
+// Bytecode for the VM, the engine from Part III of the book. A chunk is a flat run of
+// instructions, each an opcode byte followed by its operands, plus the constants the
+// instructions refer to by index and the source line of every byte for error messages.
+//
+// Lines are run-length encoded, the challenge from chapter 14: consecutive bytes from one
+// line share an entry, so the table grows with the number of lines rather than of bytes.
+// Instructions that can fail also note the token they came from, for pointing errors at it.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::error::Span;
+use crate::value::Value;
+
+// The most constants one chunk can hold, since ConstantLong names a constant in three bytes.
+const MAX_CONSTANTS: usize = 1 << 24;
+
+// OpCode: One VM instruction. Operands, where there are any, follow the opcode byte.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum OpCode {
+    // Constant: Push constant [index: u8].
+    Constant,
+    // ConstantLong: Push constant [index: u24], for chunks with more than 256 constants.
+    ConstantLong,
+    Nil,
+    True,
+    False,
+    Pop,
+    // GetLocal, SetLocal: Read or write stack slot [slot: u8]. Setting leaves the value on
+    // the stack, since assignment is an expression.
+    GetLocal,
+    SetLocal,
+    // GetGlobal, DefineGlobal, SetGlobal: Read, create or write the global named by
+    // constant [index: u8].
+    GetGlobal,
+    DefineGlobal,
+    SetGlobal,
+    Equal,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    // Jump, JumpIfFalse: Skip forward [offset: u16] bytes, counted from after the operand.
+    // JumpIfFalse leaves the condition on the stack.
+    Jump,
+    JumpIfFalse,
+    // Loop: Jump back [offset: u16] bytes, counted from after the operand.
+    Loop,
+    Return,
+    // Superinstructions, which the compiler only emits with the `superinstructions` feature.
+    // AddLocals: Push slot [a: u8] + slot [b: u8]; GetLocal a, GetLocal b, Add.
+    AddLocals,
+    // AddConstant: Add constant [index: u8] to the top of the stack; Constant, Add.
+    AddConstant,
+    // SetLocalPop: Pop the top of the stack into slot [slot: u8]; SetLocal, Pop.
+    SetLocalPop,
+}
+
+// Every opcode, in byte order, for decoding.
+const OPCODES: [OpCode; 30] = [
+    OpCode::Constant,
+    OpCode::ConstantLong,
+    OpCode::Nil,
+    OpCode::True,
+    OpCode::False,
+    OpCode::Pop,
+    OpCode::GetLocal,
+    OpCode::SetLocal,
+    OpCode::GetGlobal,
+    OpCode::DefineGlobal,
+    OpCode::SetGlobal,
+    OpCode::Equal,
+    OpCode::Greater,
+    OpCode::GreaterEqual,
+    OpCode::Less,
+    OpCode::LessEqual,
+    OpCode::Add,
+    OpCode::Subtract,
+    OpCode::Multiply,
+    OpCode::Divide,
+    OpCode::Not,
+    OpCode::Negate,
+    OpCode::Print,
+    OpCode::Jump,
+    OpCode::JumpIfFalse,
+    OpCode::Loop,
+    OpCode::Return,
+    OpCode::AddLocals,
+    OpCode::AddConstant,
+    OpCode::SetLocalPop,
+];
+
+impl OpCode {
+    // COUNT: How many opcodes there are.
+    pub const COUNT: usize = OPCODES.len();
+
+    // from_byte: The opcode a byte encodes, or None if it isn't one.
+    #[inline]
+    pub fn from_byte(byte: u8) -> Option<OpCode> {
+        OPCODES.get(byte as usize).copied()
+    }
+
+    // operand_bytes: How many bytes of operands follow the opcode.
+    pub fn operand_bytes(self) -> usize {
+        match self {
+            OpCode::ConstantLong => 3,
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop | OpCode::AddLocals => 2,
+            OpCode::Constant
+            | OpCode::AddConstant
+            | OpCode::GetLocal
+            | OpCode::SetLocal
+            | OpCode::GetGlobal
+            | OpCode::DefineGlobal
+            | OpCode::SetGlobal
+            | OpCode::SetLocalPop => 1,
+            _ => 0,
+        }
+    }
+}
+
+// ConstantKey: What identifies a constant for deduplication. Numbers compare by their bits,
+// so 0 and -0 stay apart and a NaN matches itself.
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Number(u64),
+    Str(String),
+}
+
+// LineRun: Where a run of bytes from one source line starts in the code. A run lasts until
+// the next one starts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineRun {
+    pub start: usize,
+    pub line: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    // The line runs in order of their starts, the first starting at 0.
+    pub lines: Vec<LineRun>,
+    // The span of the token behind each instruction that has one, by where the instruction
+    // starts, in order.
+    pub spans: Vec<(usize, Span)>,
+    pub constants: Vec<Value>,
+    // Where each number and string constant already is, so repeats share one slot.
+    indexes: HashMap<ConstantKey, usize>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Chunk::default()
+    }
+
+    pub fn write(&mut self, byte: u8, line: usize) {
+        if self.lines.last().map(|run| run.line) != Some(line) {
+            self.lines.push(LineRun {
+                start: self.code.len(),
+                line,
+            });
+        }
+        self.code.push(byte);
+    }
+
+    pub fn write_op(&mut self, op: OpCode, line: usize) {
+        self.write(op as u8, line);
+    }
+
+    // line_at: The source line of the byte at `offset`.
+    pub fn line_at(&self, offset: usize) -> usize {
+        let after = self.lines.partition_point(|run| run.start <= offset);
+        after.checked_sub(1).map_or(0, |run| self.lines[run].line)
+    }
+
+    // mark: Note that the instruction about to be written came from the token at `span`.
+    pub fn mark(&mut self, span: Span) {
+        self.spans.push((self.code.len(), span));
+    }
+
+    // span_at: The span of the token behind the instruction starting at `offset`, if any.
+    pub fn span_at(&self, offset: usize) -> Option<Span> {
+        let index = self.spans.binary_search_by_key(&offset, |(start, _)| *start);
+        index.ok().map(|index| self.spans[index].1)
+    }
+
+    // truncate: Drop the code from `length` on, with its lines and spans.
+    pub fn truncate(&mut self, length: usize) {
+        self.code.truncate(length);
+        while self.lines.last().is_some_and(|run| run.start >= length) {
+            self.lines.pop();
+        }
+        while self.spans.last().is_some_and(|(start, _)| *start >= length) {
+            self.spans.pop();
+        }
+    }
+
+    // add_constant: Store a constant, returning the index instructions use to load it, or
+    // None if the chunk is full. A number or string already in the chunk isn't stored twice.
+    pub fn add_constant(&mut self, value: Value) -> Option<usize> {
+        let key = match &value {
+            Value::Number(n) => Some(ConstantKey::Number(n.to_bits())),
+            Value::Str(s) => Some(ConstantKey::Str(s.to_string())),
+            _ => None,
+        };
+        if let Some(index) = key.as_ref().and_then(|key| self.indexes.get(key)) {
+            return Some(*index);
+        }
+        if self.constants.len() >= MAX_CONSTANTS {
+            return None;
+        }
+        let index = self.constants.len();
+        self.constants.push(value);
+        if let Some(key) = key {
+            self.indexes.insert(key, index);
+        }
+        Some(index)
+    }
+
+    // disassemble: A listing of the code, one instruction per line, in the format of clox's
+    // disassembleChunk: the offset, the line (or `|` if it is the previous instruction's),
+    // the opcode and its operands.
+    pub fn disassemble(&self, name: &str) -> String {
+        let mut listing = format!("== {} ==\n", name);
+        let mut at = 0;
+        while at < self.code.len() {
+            at = self.disassemble_instruction(&mut listing, at);
+        }
+        listing
+    }
+
+    // disassemble_instruction: Write the instruction at `at` to the listing, and return
+    // where the next one starts.
+    fn disassemble_instruction(&self, listing: &mut String, at: usize) -> usize {
+        let line = self.line_at(at);
+        if at > 0 && line == self.line_at(at - 1) {
+            let _ = write!(listing, "{:04}    | ", at);
+        } else {
+            let _ = write!(listing, "{:04} {:>4} ", at, line);
+        }
+        let op = match OpCode::from_byte(self.code[at]) {
+            Some(op) => op,
+            None => {
+                let _ = writeln!(listing, "Unknown opcode {}", self.code[at]);
+                return at + 1;
+            }
+        };
+        let next = at + 1 + op.operand_bytes();
+        let operands = match self.code.get(at + 1..next) {
+            Some(operands) => operands,
+            None => {
+                let _ = writeln!(listing, "{:?} (cut short)", op);
+                return self.code.len();
+            }
+        };
+        let number = operands.iter().fold(0, |n, byte| n << 8 | *byte as usize);
+        let name = format!("{:?}", op);
+        let constant = |index: usize| match self.constants.get(index) {
+            Some(value) => format!("{:<16} {:4} '{}'", name, index, value),
+            None => format!("{:<16} {:4} (missing)", name, index),
+        };
+        let text = match op {
+            OpCode::Constant
+            | OpCode::ConstantLong
+            | OpCode::AddConstant
+            | OpCode::GetGlobal
+            | OpCode::DefineGlobal
+            | OpCode::SetGlobal => constant(number),
+            OpCode::GetLocal | OpCode::SetLocal | OpCode::SetLocalPop => {
+                format!("{:<16} {:4}", name, number)
+            }
+            OpCode::AddLocals => format!("{:<16} {:4} {:4}", name, operands[0], operands[1]),
+            OpCode::Jump | OpCode::JumpIfFalse => {
+                format!("{:<16} {:4} -> {}", name, at, next + number)
+            }
+            OpCode::Loop => {
+                format!("{:<16} {:4} -> {}", name, at, next as isize - number as isize)
+            }
+            _ => name,
+        };
+        let _ = writeln!(listing, "{}", text);
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk::{Chunk, LineRun, OpCode, OPCODES};
+use crate::value::Value;
+
+    #[test]
+    fn opcodes_round_trip_through_bytes() {
+        for (byte, op) in OPCODES.iter().enumerate() {
+            assert_eq!(*op as u8 as usize, byte);
+            assert_eq!(OpCode::from_byte(byte as u8), Some(*op));
+        }
+        assert_eq!(OpCode::from_byte(OPCODES.len() as u8), None);
+    }
+
+    #[test]
+    fn constants_are_deduplicated() {
+        let mut chunk = Chunk::new();
+        assert_eq!(chunk.add_constant(Value::Number(1.0)), Some(0));
+        assert_eq!(chunk.add_constant(Value::Str("1".into())), Some(1));
+        assert_eq!(chunk.add_constant(Value::Number(1.0)), Some(0));
+        assert_eq!(chunk.add_constant(Value::Str("1".into())), Some(1));
+        assert_eq!(chunk.add_constant(Value::Number(-0.0)), Some(2));
+        assert_eq!(chunk.add_constant(Value::Number(0.0)), Some(3));
+        assert_eq!(chunk.add_constant(Value::Number(f64::NAN)), Some(4));
+        assert_eq!(chunk.add_constant(Value::Number(f64::NAN)), Some(4));
+        assert_eq!(chunk.constants.len(), 5);
+    }
+
+    #[test]
+    fn constants_go_past_one_byte() {
+        let mut chunk = Chunk::new();
+        for n in 0..300 {
+            assert_eq!(chunk.add_constant(Value::Number(n as f64)), Some(n));
+        }
+    }
+
+    #[test]
+    fn lines_are_run_length_encoded() {
+        let mut chunk = Chunk::new();
+        for (byte, line) in [1, 1, 1, 2, 4, 4].iter().enumerate() {
+            chunk.write(byte as u8, *line);
+        }
+        let run = |start, line| LineRun { start, line };
+        assert_eq!(chunk.lines, [run(0, 1), run(3, 2), run(4, 4)]);
+        let lines: Vec<_> = (0..6).map(|offset| chunk.line_at(offset)).collect();
+        assert_eq!(lines, [1, 1, 1, 2, 4, 4]);
+        chunk.truncate(4);
+        assert_eq!(chunk.lines, [run(0, 1), run(3, 2)]);
+        chunk.write(9, 2);
+        assert_eq!(chunk.lines.len(), 2);
+        assert_eq!(chunk.line_at(4), 2);
+    }
+
+    #[test]
+    fn disassembly_matches_clox() {
+        let mut chunk = Chunk::new();
+        let index = chunk.add_constant(Value::Number(1.2)).unwrap() as u8;
+        chunk.write_op(OpCode::Constant, 123);
+        chunk.write(index, 123);
+        chunk.write_op(OpCode::JumpIfFalse, 123);
+        chunk.write(0, 123);
+        chunk.write(1, 123);
+        chunk.write_op(OpCode::Negate, 124);
+        chunk.write_op(OpCode::Return, 124);
+        assert_eq!(
+            chunk.disassemble("test chunk"),
+            "== test chunk ==\n\
+             0000  123 Constant            0 '1.2'\n\
+             0002    | JumpIfFalse         2 -> 6\n\
+             0005  124 Negate\n\
+             0006    | Return\n"
+        );
+    }
+}
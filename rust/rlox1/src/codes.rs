@@ -0,0 +1,1138 @@
+// Stable codes for diagnostics, in the style of rustc's E0425: LX, then two digits for the
+// area and two for the diagnostic within it.
+//
+//   01  scanning            04  classes, functions and calls    07  files and I/O
+//   02  syntax              05  values at runtime                08  lints
+//   03  names               06  limits and engines               09  rlox1's commands
+//
+// A diagnostic is made from one of the templates here, which the place that raises it names
+// along with its code, e.g. loxerr!(LX0301, "Undefined variable '{}'", name), so the error
+// knows its code from the start, on every engine. `{}` in a template stands for the text that
+// varies. Codes are never reused for something else; `rlox1 explain LX0203` prints the
+// explanation of one.
+
+// Code: A kind of diagnostic: its code, what it is in a few words, the messages that have
+// it, and a longer explanation of what causes it and how to fix it.
+pub struct Code {
+    pub code: &'static str,
+    pub title: &'static str,
+    templates: &'static [&'static str],
+    pub explanation: &'static str,
+}
+
+impl Code {
+    // templates: The messages with this code, `{}` standing for the text that varies.
+    pub fn templates(&self) -> &'static [&'static str] {
+        self.templates
+    }
+}
+
+// Template: One of a code's templates, by where the code is in CODES and where the template
+// is in the code's list, which is small enough for every error to keep the one it was made
+// from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Template {
+    code: u8,
+    index: u8,
+}
+
+impl Template {
+    // of: The template of `code` that `format` is, after the LINE it may start with. It is
+    // meant for constants, as message! uses it, so that a message that isn't one of its code's
+    // templates doesn't compile.
+    pub const fn of(code: &Code, format: &str) -> Template {
+        let mut text = format.as_bytes();
+        if starts_with(text, LINE.as_bytes()) {
+            text = text.split_at(LINE.len()).1;
+        }
+        let mut at = 0;
+        while !equal(CODES[at].code.as_bytes(), code.code.as_bytes()) {
+            at += 1;
+        }
+        let mut index = 0;
+        while index < code.templates.len() {
+            if equal(code.templates[index].as_bytes(), text) {
+                return Template {
+                    code: at as u8,
+                    index: index as u8,
+                };
+            }
+            index += 1;
+        }
+        panic!("the message isn't one of its code's templates")
+    }
+
+    pub fn code(self) -> &'static Code {
+        &CODES[self.code as usize]
+    }
+
+    // index: Where the template is in its code's list, from 0.
+    pub fn index(self) -> usize {
+        self.index as usize
+    }
+
+    pub fn text(self) -> &'static str {
+        self.code().templates[self.index()]
+    }
+}
+
+// LINE: What the format of a runtime error starts with, for the line it happened on, which
+// is where it was rather than part of what it says.
+pub const LINE: &str = "[line {}] ";
+
+const fn starts_with(text: &[u8], prefix: &[u8]) -> bool {
+    text.len() >= prefix.len() && equal(text.split_at(prefix.len()).0, prefix)
+}
+
+const fn equal(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+pub const LX0101: Code = Code {
+    code: "LX0101",
+    title: "Unterminated string",
+    templates: &["Missing end-quote{}"],
+    explanation: "\
+A string was opened with a double quote but the script ended before the closing quote.
+Strings may run over several lines, so the missing quote can be far from where the
+scanner noticed: look for the last string before the end of the script.
+
+    print \"hello;    // the quote after hello is missing",
+};
+
+pub const LX0102: Code = Code {
+    code: "LX0102",
+    title: "Unexpected character",
+    templates: &[
+        "Invalid character on line {}: {}",
+        "An identifier can't start with '{}'",
+    ],
+    explanation: "\
+The script has a character that can't start any token, such as `@`, `#` or `&`. Lox has
+no bitwise operators, and comments start with `//`. Every stray character in a script is
+reported, not just the first. Names start with a letter or `_`.",
+};
+
+pub const LX0103: Code = Code {
+    code: "LX0103",
+    title: "Invalid number",
+    templates: &["Invalid number '{}'"],
+    explanation: "\
+A number is digits with at most one decimal point between them. There are no exponents,
+hexadecimal numbers or separators, and a second point doesn't start a new number.
+
+    print 1.2.3;      // write 1.2 or 1.23",
+};
+
+pub const LX0201: Code = Code {
+    code: "LX0201",
+    title: "Expect expression",
+    templates: &["Expect expression"],
+    explanation: "\
+The parser needed an expression, such as a number, a variable or a call, and found
+something else. This is usually an operator with a missing operand, or a statement
+keyword where a value should be.
+
+    print 1 +;        // what is added to 1?",
+};
+
+pub const LX0202: Code = Code {
+    code: "LX0202",
+    title: "Expect ';'",
+    templates: &["Expect ';' after {}"],
+    explanation: "\
+Statements end with a semicolon, and this one doesn't. The error points at the token
+after where the semicolon should be, which may be on the next line.
+
+    var a = 1
+    print a;          // 'print' is where the ';' was expected",
+};
+
+pub const LX0203: Code = Code {
+    code: "LX0203",
+    title: "Expect ')' after expression",
+    templates: &["Expect ')' after {}"],
+    explanation: "\
+A parenthesis was opened, around an expression, a call's arguments, a function's
+parameters or a statement's condition, and not closed where the parser expected.
+
+    print (1 + 2;     // the ')' is missing
+    f(1, 2;           // so is this one",
+};
+
+pub const LX0204: Code = Code {
+    code: "LX0204",
+    title: "Expect '('",
+    templates: &["Expect '(' after {}"],
+    explanation: "\
+`if`, `for`, `match` and `catch` put what they work on in parentheses, and so do the
+parameters of a function or method, even when there are none.
+
+    if a > 1 print a;   // write if (a > 1)",
+};
+
+pub const LX0205: Code = Code {
+    code: "LX0205",
+    title: "Expect '}'",
+    templates: &["Expect '}' after {}"],
+    explanation: "\
+A block, class body, map or match was opened with `{` and not closed. The error is often
+reported at the end of the script, as the parser reads on looking for the brace.",
+};
+
+pub const LX0206: Code = Code {
+    code: "LX0206",
+    title: "Expect '{'",
+    templates: &["Expect '{' {}"],
+    explanation: "\
+Function and class bodies, `try` and `catch`, and the arms of a match must be written as
+blocks in braces, even when they hold a single statement.
+
+    fun f() return 1;   // write fun f() { return 1; }",
+};
+
+pub const LX0207: Code = Code {
+    code: "LX0207",
+    title: "Expect ']'",
+    templates: &["Expect ']' after {}"],
+    explanation: "\
+A list literal or an index was opened with `[` and not closed.
+
+    var l = [1, 2;
+    print l[0;",
+};
+
+pub const LX0208: Code = Code {
+    code: "LX0208",
+    title: "Expect a name",
+    templates: &["Expect {} name", "Expect property name after '.'"],
+    explanation: "\
+A declaration needs a name where there is none, or something that isn't an identifier.
+Keywords can't be used as names.
+
+    var class = 1;    // 'class' is a keyword
+    fun (a) {}        // a function needs a name",
+};
+
+pub const LX0209: Code = Code {
+    code: "LX0209",
+    title: "Invalid assignment target",
+    templates: &["Invalid assignment target"],
+    explanation: "\
+Only a variable, a property or an element of a list or map can be assigned to. The left
+of `=` is something else, like a call or a sum.
+
+    a + b = c;
+    f() = 1;",
+};
+
+pub const LX0210: Code = Code {
+    code: "LX0210",
+    title: "Too many arguments or parameters",
+    templates: &[
+        "Can't have more than {} arguments",
+        "Can't have more than {} parameters",
+    ],
+    explanation: "\
+A call can pass at most 255 arguments, and a function can take at most 255 parameters, as
+in the book. Pass a list or a map instead.",
+};
+
+pub const LX0211: Code = Code {
+    code: "LX0211",
+    title: "Expect part of a statement",
+    templates: &[
+        "Expect 'in' after loop variable",
+        "Expect 'var' after '(' in for-in loop",
+        "Expect 'catch' after try block",
+        "Expect '=' after constant name",
+        "Expect ':' after map key",
+        "Expect '->' after pattern",
+        "Expect '.' after 'super'",
+    ],
+    explanation: "\
+A statement or expression is missing a keyword or symbol it is made of: `in` and `var` in
+a for-in loop, `catch` after a try block, `=` in a constant, `:` between a map's key and
+value, `->` in a match arm, or `.` after `super`.
+
+    for (x in list) print x;    // write for (var x in list)
+    const limit;                // a constant needs a value",
+};
+
+pub const LX0212: Code = Code {
+    code: "LX0212",
+    title: "Expect pattern",
+    templates: &["Expect pattern", "Expect number after '-' in pattern"],
+    explanation: "\
+A match arm starts with a pattern: a literal, `_`, or a name to bind the value to. A
+negative number is the only expression a pattern may contain.",
+};
+
+pub const LX0213: Code = Code {
+    code: "LX0213",
+    title: "Setters take one parameter",
+    templates: &["A setter must have exactly one parameter"],
+    explanation: "\
+A setter is called with the value being assigned, so it has exactly one parameter.
+
+    class Box { set size(value) { this._size = value; } }",
+};
+
+pub const LX0214: Code = Code {
+    code: "LX0214",
+    title: "Too much nesting",
+    templates: &["Too much nesting"],
+    explanation: "\
+Statements and expressions can be nested at most 256 deep, e.g. parentheses inside
+parentheses or blocks inside blocks, so that running the program can't exhaust the stack.
+Move the inner levels into functions, or build the value a piece at a time.",
+};
+
+pub const LX0301: Code = Code {
+    code: "LX0301",
+    title: "Undefined variable",
+    templates: &["Undefined variable '{}'"],
+    explanation: "\
+The script uses a variable that hasn't been declared, or not yet: globals must be
+declared before the code that uses them runs. Check the spelling, and that the
+declaration isn't in a block that has ended. If a global has a name close to the
+one used, the error asks whether that was meant.
+
+    print count;      // declare it first: var count = 0;",
+};
+
+pub const LX0302: Code = Code {
+    code: "LX0302",
+    title: "Assignment to a constant",
+    templates: &["Can't assign to a constant"],
+    explanation: "\
+A name declared with `const` keeps the value it was declared with. Declare it with `var`
+if it needs to change.",
+};
+
+pub const LX0303: Code = Code {
+    code: "LX0303",
+    title: "Undefined property",
+    templates: &["Undefined property '{}' on {}"],
+    explanation: "\
+The instance has no field of that name, and its class, superclasses and mixins have no
+method of that name. Fields exist once they have been assigned, usually in init().
+If a property has a name close to the one used, the error asks whether that was meant.",
+};
+
+pub const LX0401: Code = Code {
+    code: "LX0401",
+    title: "Return or yield outside a function",
+    templates: &[
+        "Can't return from top-level code",
+        "Can't yield from top-level code",
+    ],
+    explanation: "\
+`return` and `yield` only make sense inside a function. To stop a script early, call
+exit().",
+};
+
+pub const LX0402: Code = Code {
+    code: "LX0402",
+    title: "Return value from an initializer",
+    templates: &[
+        "Can't return a value from an initializer",
+        "Can't yield from an initializer",
+    ],
+    explanation: "\
+init() always returns the new instance, so it can't return anything else, or yield. A
+bare `return;` is allowed, to leave it early.",
+};
+
+pub const LX0403: Code = Code {
+    code: "LX0403",
+    title: "'this' outside a class",
+    templates: &["Can't use 'this' outside of a class"],
+    explanation: "\
+`this` is the instance a method was called on, so it can only be used in methods.",
+};
+
+pub const LX0404: Code = Code {
+    code: "LX0404",
+    title: "'super' without a superclass",
+    templates: &[
+        "Can't use 'super' outside of a class",
+        "Can't use 'super' in a class with no superclass",
+    ],
+    explanation: "\
+`super` calls a method of the superclass, so it can only be used in the methods of a
+class declared with one: class B < A { ... }.",
+};
+
+pub const LX0405: Code = Code {
+    code: "LX0405",
+    title: "Class inherits from itself",
+    templates: &[
+        "A class can't inherit from itself",
+        "A class can't mix in itself",
+    ],
+    explanation: "\
+A class's superclass and mixins must be other classes.
+
+    class A < A {}    // A isn't defined yet, and can't be its own parent",
+};
+
+pub const LX0406: Code = Code {
+    code: "LX0406",
+    title: "Superclass isn't a class",
+    templates: &["Superclass must be a class", "Mixin must be a class"],
+    explanation: "\
+The name after `<` or `with` in a class declaration is bound to something other than a
+class when the declaration runs.",
+};
+
+pub const LX0407: Code = Code {
+    code: "LX0407",
+    title: "Conflicting mixin methods",
+    templates: &["Method '{}' of class {} comes from both {} and {}"],
+    explanation: "\
+Two of a class's mixins, or a mixin and the superclass, both define a method with the
+same name, and there's no telling which one is meant. Define the method in the class
+itself to choose, calling whichever is wanted.",
+};
+
+pub const LX0408: Code = Code {
+    code: "LX0408",
+    title: "Call of something that isn't callable",
+    templates: &["Can only call functions and classes"],
+    explanation: "\
+Only functions, methods, native functions and classes can be called. The value before
+`(` is something else, like a number or nil.
+
+    var f = 1;
+    f();",
+};
+
+pub const LX0409: Code = Code {
+    code: "LX0409",
+    title: "Wrong number of arguments",
+    templates: &["Expected {} arguments but got {}"],
+    explanation: "\
+A function was called with more or fewer arguments than it has parameters. Lox has no
+default or variable arguments. Calling a class calls its init() method, if it has one.",
+};
+
+pub const LX0410: Code = Code {
+    code: "LX0410",
+    title: "Field of something that isn't an instance",
+    templates: &["Only instances have fields"],
+    explanation: "\
+A property was set on, or got from, a value that isn't an instance of a class, such as a
+number or a string. Maps use indexes instead: map[\"key\"] = value.",
+};
+
+pub const LX0411: Code = Code {
+    code: "LX0411",
+    title: "Property with only a getter or a setter",
+    templates: &[
+        "Property '{}' of {} has a getter but no setter",
+        "Property '{}' of {} has a setter but no getter",
+    ],
+    explanation: "\
+The class defines one side of the property, so it can be read but not assigned, or
+assigned but not read. Add the other with a getter, `name { ... }`, or a setter,
+`set name(value) { ... }`.",
+};
+
+pub const LX0412: Code = Code {
+    code: "LX0412",
+    title: "Generator is already running",
+    templates: &["Generator '{}' is already running"],
+    explanation: "\
+A generator was resumed from inside its own body. A generator can only be resumed once
+it has yielded.",
+};
+
+pub const LX0413: Code = Code {
+    code: "LX0413",
+    title: "Method and accessor share a name",
+    templates: &["A method and a {} can't share a name"],
+    explanation: "\
+A class has a method and a getter or setter of the same name, and `obj.name` can only
+mean one of them. Rename one.
+
+    class A { size() { return 1; } size { return 2; } }",
+};
+
+pub const LX0501: Code = Code {
+    code: "LX0501",
+    title: "Operand must be a number",
+    templates: &["Operand of '{}' must be a number, got {}"],
+    explanation: "\
+Negation works on numbers only.
+
+    print -\"5\";     // write -num(\"5\")",
+};
+
+pub const LX0502: Code = Code {
+    code: "LX0502",
+    title: "Operands have the wrong types",
+    templates: &["Operands of '{}' must be {}, got {} and {}"],
+    explanation: "\
+`+` adds two numbers or joins two strings, and the other arithmetic and comparison
+operators work on numbers only. Lox doesn't convert values between types by itself.
+
+    print \"a\" + 1;    // write \"a\" + str(1)",
+};
+
+pub const LX0503: Code = Code {
+    code: "LX0503",
+    title: "Index out of range",
+    templates: &["Index {} out of range for {} of length {}"],
+    explanation: "\
+Lists and strings are indexed from 0 up to one less than their length. A negative index
+counts from the end, so -1 is the last element.",
+};
+
+pub const LX0504: Code = Code {
+    code: "LX0504",
+    title: "Index isn't an integer",
+    templates: &["{} index must be an integer, got {}"],
+    explanation: "\
+Lists and strings are indexed by whole numbers. Use floor() on a computed index.",
+};
+
+pub const LX0505: Code = Code {
+    code: "LX0505",
+    title: "Value can't be indexed",
+    templates: &[
+        "Only lists, maps and strings can be indexed",
+        "Only lists and maps support index assignment",
+        "Only lists and strings can be sliced",
+        "Strings are immutable",
+    ],
+    explanation: "\
+Indexing with [] works on lists, maps and strings, slicing with [a:b] on lists and
+strings, and assigning to an index on lists and maps only. Strings can't be changed in
+place: build a new one instead.",
+};
+
+pub const LX0506: Code = Code {
+    code: "LX0506",
+    title: "Slice runs backwards",
+    templates: &["Slice start {} is past its end {}"],
+    explanation: "\
+In a slice, value[start:end], start must not come after end.",
+};
+
+pub const LX0507: Code = Code {
+    code: "LX0507",
+    title: "Key not found",
+    templates: &["Key {} not found in map"],
+    explanation: "\
+The map has no entry for the key. Check first with map.has(key), or use map.get(key),
+which gives nil for a missing key.",
+};
+
+pub const LX0508: Code = Code {
+    code: "LX0508",
+    title: "Value can't be a map key",
+    templates: &["{} can't be used as a map key"],
+    explanation: "\
+Map keys must be nil, booleans, numbers or strings, and not NaN, which isn't equal to
+itself. Lists, maps and instances can't be keys.",
+};
+
+pub const LX0509: Code = Code {
+    code: "LX0509",
+    title: "Value can't be iterated over",
+    templates: &["Can only iterate over lists, maps, strings and generators, got {}"],
+    explanation: "\
+A for-in loop goes through the elements of a list, the keys of a map, the characters of
+a string, or what a generator yields. Anything else can't be looped over.",
+};
+
+pub const LX0510: Code = Code {
+    code: "LX0510",
+    title: "No match arm",
+    templates: &["No match arm for {}"],
+    explanation: "\
+None of the patterns in a match fit the value. End the match with a `_` arm to catch
+everything else.",
+};
+
+pub const LX0511: Code = Code {
+    code: "LX0511",
+    title: "toString() doesn't return a string",
+    templates: &["toString() must return a string, got {}"],
+    explanation: "\
+An instance is printed by calling its class's toString() method, which must return a
+string.",
+};
+
+pub const LX0512: Code = Code {
+    code: "LX0512",
+    title: "Uncaught exception",
+    templates: &["Uncaught exception: {}"],
+    explanation: "\
+A value was thrown and no try statement around it caught it, so the script stopped.
+
+    try { risky(); } catch (e) { print e; }",
+};
+
+pub const LX0513: Code = Code {
+    code: "LX0513",
+    title: "Wrong type of argument to a native",
+    templates: &["Argument {} to '{}' must be {}, got {}"],
+    explanation: "\
+A function of the standard library was passed a value of a type it doesn't take. The
+message says which argument, counting from 1, and what it must be.",
+};
+
+pub const LX0514: Code = Code {
+    code: "LX0514",
+    title: "Assertion failed",
+    templates: &["Assertion failed: {}"],
+    explanation: "\
+assert() was called with a false condition. The message is the one passed to it.",
+};
+
+pub const LX0515: Code = Code {
+    code: "LX0515",
+    title: "Pop from an empty list",
+    templates: &["Can't pop from an empty list"],
+    explanation: "\
+pop() takes the last element off a list, and the list has none. Check its length first.",
+};
+
+pub const LX0516: Code = Code {
+    code: "LX0516",
+    title: "Argument out of range",
+    templates: &[
+        "Exit code must be a whole number from 0 to 255, got {}",
+        "String.substr({}, {}) is out of range for string of length {}",
+    ],
+    explanation: "\
+A function of the standard library was passed a number of the right type but outside the
+range it takes: an exit code must be from 0 to 255, and the start and end given to
+String.substr must lie within the string.",
+};
+
+pub const LX0517: Code = Code {
+    code: "LX0517",
+    title: "Invalid JSON",
+    templates: &["Invalid JSON at line {}, column {}: {}"],
+    explanation: "\
+jsonParse() was given text that isn't JSON. The message says where in the text the problem
+is and what was expected there instead. Keys must be in double quotes, strings can't hold
+raw newlines, and a list or object can't end with a comma.",
+};
+
+pub const LX0518: Code = Code {
+    code: "LX0518",
+    title: "No JSON for a value",
+    templates: &[
+        "Can't convert a {} that contains itself to JSON",
+        "Can't convert a map with a {} key to JSON",
+        "Can't convert lists and maps nested more than {} deep to JSON",
+        "Can't convert the number {} to JSON",
+        "Can't convert a {} to JSON",
+    ],
+    explanation: "\
+jsonStringify() was given a value that JSON has no way to write. Only nil, bools, numbers,
+strings, lists and maps with string keys can be written, and a list or map can't contain
+itself. NaN and the infinities aren't JSON numbers, and functions, classes and instances
+have no JSON form; convert them to one of the values above first.",
+};
+
+pub const LX0519: Code = Code {
+    code: "LX0519",
+    title: "Error raised by the script",
+    templates: &["{}"],
+    explanation: "\
+The script called error() to stop with an error of its own, saying what went wrong in its
+own words. Like any runtime error, it can be caught with try/catch.
+
+    if (age < 0) error(\"age can't be negative\");",
+};
+
+pub const LX0601: Code = Code {
+    code: "LX0601",
+    title: "Stack overflow",
+    templates: &["Stack overflow"],
+    explanation: "\
+Calls nested deeper than the limit, usually from recursion that never stops. Check the
+base case. --max-call-depth raises the limit, up to what the interpreter's stack allows.",
+};
+
+pub const LX0602: Code = Code {
+    code: "LX0602",
+    title: "Out of memory",
+    templates: &["Out of memory: the limit is {} bytes"],
+    explanation: "\
+The sandbox limits how much memory a script may use, and the script went over it.",
+};
+
+pub const LX0603: Code = Code {
+    code: "LX0603",
+    title: "Interrupted",
+    templates: &["Interrupted"],
+    explanation: "\
+Ctrl-C stopped the entry the REPL was running. A second Ctrl-C, before it stops, exits.",
+};
+
+pub const LX0604: Code = Code {
+    code: "LX0604",
+    title: "Chunk too large",
+    templates: &["Too many {} in one chunk", "Too much code to jump over"],
+    explanation: "\
+The bytecode compilers have fixed limits: 256 constants, locals, globals and registers
+in a chunk, and jumps of 65535 bytes. Split the script up, or run it on the tree-walker.",
+};
+
+pub const LX0605: Code = Code {
+    code: "LX0605",
+    title: "Not supported by the bytecode engine",
+    templates: &["The bytecode engine doesn't support {} yet"],
+    explanation: "\
+The bytecode engines only run part of the language so far: no functions, classes,
+exceptions or for-in loops, and no lists or maps. Run the script on the tree-walker, with
+--engine treewalk.",
+};
+
+pub const LX0606: Code = Code {
+    code: "LX0606",
+    title: "Call depth limited by the sandbox",
+    templates: &["The sandbox limits calls to a depth of {}"],
+    explanation: "\
+--max-call-depth asked for more than the sandbox allows, so the sandbox's limit is used.",
+};
+
+pub const LX0607: Code = Code {
+    code: "LX0607",
+    title: "Internal interpreter error",
+    templates: &[
+        "Internal interpreter error at {}: {}; please report this bug in rlox1",
+        "Internal interpreter error; please report this bug in rlox1",
+    ],
+    explanation: "\
+rlox1 itself went wrong, at the place in its source given, rather than the program it was
+running. Whatever the program did, that is a bug in rlox1: please report it, with the script
+that caused it. The REPL starts again after one, as what was defined before it may have been
+left broken; otherwise rlox1 exits with status 70.",
+};
+
+pub const LX0608: Code = Code {
+    code: "LX0608",
+    title: "Step limit reached",
+    templates: &["The program ran for more than {} steps"],
+    explanation: "\
+The host limits how many statements a program may execute, so that a loop that never ends
+can't hang it; the playground does. Look for a loop or recursion that doesn't stop.",
+};
+
+pub const LX0609: Code = Code {
+    code: "LX0609",
+    title: "Time limit reached",
+    templates: &["The program ran for longer than {}"],
+    explanation: "\
+The host limits how long a program may run, so that one that never ends can't hang it; the
+playground does. Look for a loop or recursion that doesn't stop, or do less work.",
+};
+
+pub const LX0610: Code = Code {
+    code: "LX0610",
+    title: "Not supported by the engine",
+    templates: &[
+        "Only the tree-walker can {}; use --engine treewalk",
+        "The register VM can't be profiled; use --engine vm or treewalk",
+        "Compiled files run on the VM, which can't run in the sandbox",
+        "The {} engine can't run these benchmarks: {}",
+    ],
+    explanation: "\
+What was asked for needs something the selected engine doesn't have. Tracing, debugging
+and the sandbox are only built into the tree-walker, profiling isn't in the register VM,
+and the bytecode engines can't yet run all of the benchmarks. Pick another engine with
+--engine.",
+};
+
+pub const LX0611: Code = Code {
+    code: "LX0611",
+    title: "Memory can't be metered",
+    templates: &["The sandbox can't limit memory until memory::set_meter says how to measure it"],
+    explanation: "\
+The sandbox was given a memory limit, but the host that rlox1 was built into hasn't said
+how to measure the memory used, with memory::set_meter. The rlox1 command always does.",
+};
+
+pub const LX0701: Code = Code {
+    code: "LX0701",
+    title: "Script isn't UTF-8",
+    templates: &["File {} is not UTF-8: there's a stray byte, 0x{}, at line {}, column {}"],
+    explanation: "\
+Scripts must be UTF-8. Convert the file, e.g. with iconv -f latin1 -t utf-8.",
+};
+
+pub const LX0702: Code = Code {
+    code: "LX0702",
+    title: "Script is a binary file",
+    templates: &[
+        "File {} looks like a binary file, not a Lox script; use --force to read it anyway",
+    ],
+    explanation: "\
+The file has a NUL byte near its start, as binary files do and Lox source doesn't. For a
+compiled script, name it .loxc. --force reads the file anyway.",
+};
+
+pub const LX0703: Code = Code {
+    code: "LX0703",
+    title: "Script too large",
+    templates: &["File {} is too large ({} > {}); see --max-source-bytes"],
+    explanation: "\
+--max-source-bytes limits the size of the scripts rlox1 reads, and this one is larger.",
+};
+
+pub const LX0704: Code = Code {
+    code: "LX0704",
+    title: "Script may not be Lox",
+    templates: &["{} doesn't end in .lox, so may not be a Lox script; use --force if it is"],
+    explanation: "\
+Lox scripts are named .lox, and this one isn't, so it may be the wrong file. --force
+runs it without the warning.",
+};
+
+pub const LX0705: Code = Code {
+    code: "LX0705",
+    title: "File can't be read or written",
+    templates: &[
+        "Could not read file '{}': {}",
+        "Could not write file '{}': {}",
+        "Could not record to '{}': {}",
+    ],
+    explanation: "\
+A script's call to read or write a file failed, or the REPL couldn't write the transcript
+that :record asked for. The message ends with why: that the file doesn't exist, say, or
+the permissions don't allow it.",
+};
+
+pub const LX0706: Code = Code {
+    code: "LX0706",
+    title: "Native is disabled",
+    templates: &[
+        "'{}' is disabled; run with --allow-io to enable it",
+        "'{}' is disabled in the sandbox",
+    ],
+    explanation: "\
+Natives that read and write files or set environment variables are off unless rlox1 is
+run with --allow-io, and the sandbox turns them off whatever else was asked.",
+};
+
+pub const LX0707: Code = Code {
+    code: "LX0707",
+    title: "Compiled file can't be loaded",
+    templates: &["Can't load {}: {}"],
+    explanation: "\
+A .loxc file is damaged, isn't a compiled Lox file, or was compiled by a different
+version of rlox1. Compile the script again with rlox1 compile.",
+};
+
+pub const LX0708: Code = Code {
+    code: "LX0708",
+    title: "Invalid environment variable",
+    templates: &[
+        "Invalid environment variable name '{}'",
+        "The value of environment variable '{}' can't contain NUL",
+    ],
+    explanation: "\
+setenv() was given a name that is empty or holds '=' or NUL, or a value that holds NUL,
+none of which an environment variable can have.",
+};
+
+pub const LX0709: Code = Code {
+    code: "LX0709",
+    title: "Constant can't be compiled to a file",
+    templates: &["Can't save a {} constant"],
+    explanation: "\
+A .loxc file holds numbers and strings as constants, and the compiled script has a
+constant of another kind. Run the script from source instead.",
+};
+
+pub const LX0710: Code = Code {
+    code: "LX0710",
+    title: "Compiled file is invalid",
+    templates: &[
+        "Not a compiled Lox file",
+        "Compiled for bytecode format {}, but this rlox1 runs format {}; recompile it",
+        "The file is truncated",
+        "A string constant isn't valid UTF-8",
+        "Unknown constant tag {}",
+        "Unexpected data after the constants",
+        "The line table doesn't match the code",
+        "Invalid opcode {} at offset {}",
+        "The {} instruction at offset {} is cut short",
+        "The constant loaded at offset {} doesn't exist",
+        "The global named at offset {} has no name",
+        "The code doesn't end with a return",
+        "The jump at offset {} doesn't land on an instruction",
+        "The stack is {} deep at offset {} one way and {} another",
+        "The {} instruction at offset {} needs {} values on the stack, but there are {}",
+        "The {} instruction at offset {} uses local slot {}, but the stack only has {}",
+        "The loop at offset {} goes back to code nothing else reaches",
+    ],
+    explanation: "\
+Before running a .loxc file, rlox1 checks that the VM can run its code without going
+wrong: that it is all there, every instruction is one the VM knows, every jump lands on
+an instruction, and the stack always holds what the next instruction needs. This file
+fails a check, so it is damaged, or was written by something other than rlox1 compile.
+Compile the script again.",
+};
+
+pub const LX0711: Code = Code {
+    code: "LX0711",
+    title: "Not a file",
+    templates: &[
+        "Path {} is not a file",
+        "{} is a directory; use --recursive to check the scripts in it",
+    ],
+    explanation: "\
+rlox1 was given the path of something that isn't a file, such as a directory, to read a
+script from. rlox1 check and lint look through a directory with --recursive; rlox1 test
+always does.",
+};
+
+pub const LX0712: Code = Code {
+    code: "LX0712",
+    title: "Invalid source from C",
+    templates: &["Source can't be a null pointer", "Source isn't UTF-8: {}"],
+    explanation: "\
+A program using rlox1's C API passed lox_eval a null pointer, or a string that isn't
+UTF-8. Pass a NUL-terminated UTF-8 string.",
+};
+
+pub const LX0713: Code = Code {
+    code: "LX0713",
+    title: "Message catalog can't be used",
+    templates: &[
+        "No catalog for language '{}'; use en, {}, or the path of a catalog file",
+        "{}: Expect a code, then ':' and the message",
+        "{}: {} isn't a diagnostic code",
+        "{}: {} has no message {}",
+        "{}: {} uses more parts that vary than the {} there are",
+    ],
+    explanation: "\
+--lang names a language rlox1 has no messages for, or a catalog file with a line that
+isn't right, which the error gives as file:line. Each line of a catalog is a code, with
+.2 and so on for the code's later messages, then ':' and the translation, with a `{}`
+for each part of the English that varies. resources/lang/es.txt is an example.",
+};
+
+pub const LX0801: Code = Code {
+    code: "LX0801",
+    title: "Unused variable",
+    templates: &["Unused variable '{}'", "Unused parameter '{}'"],
+    explanation: "\
+A local variable or a parameter is declared but never used, which often means a typo in a
+later use of it, or code left over from a change. Remove it, or start its name with `_` to
+say it is meant to be unused.",
+};
+
+pub const LX0802: Code = Code {
+    code: "LX0802",
+    title: "Shadowed variable",
+    templates: &["Variable '{}' shadows another of the same name"],
+    explanation: "\
+A local variable has the same name as another in the same or an enclosing scope, which it
+hides until its own scope ends. Uses of the name may not mean the variable they seem to.
+
+    fun f(count) {
+      var count = 0;    // the parameter can't be read from here on
+    }",
+};
+
+pub const LX0803: Code = Code {
+    code: "LX0803",
+    title: "Constant condition",
+    templates: &["Condition is always {}"],
+    explanation: "\
+An `if` tests a literal, so one of its branches always runs and the other never does.
+This is usually code left over from debugging.",
+};
+
+pub const LX0804: Code = Code {
+    code: "LX0804",
+    title: "Unreachable code",
+    templates: &["Unreachable code after '{}'"],
+    explanation: "\
+A statement follows a `return` or `throw` in the same block, so it can never run. Either
+the statement or the early exit is probably a mistake.
+
+    fun f() {
+      return 1;
+      print \"never printed\";
+    }",
+};
+
+pub const LX0805: Code = Code {
+    code: "LX0805",
+    title: "Loop that never runs",
+    templates: &[
+        "Loop never runs, as what it iterates over is empty",
+        "Loop never runs, as its condition is always false",
+    ],
+    explanation: "\
+A loop's body can never run: a `for` loop iterates over an empty list, map or string
+written in place, or a `while` or C-style `for` loop's condition is a literal `false`.
+This is usually code left over from debugging.
+
+    for (var x in []) print x;
+    while (false) print \"never printed\";",
+};
+
+pub const LX0901: Code = Code {
+    code: "LX0901",
+    title: "Invalid option value",
+    templates: &["{} expects a whole number, got '{}'"],
+    explanation: "\
+An option that takes a count or a size was given something other than a whole number.
+
+    rlox1 bench --runs 3",
+};
+
+pub const LX0902: Code = Code {
+    code: "LX0902",
+    title: "Unknown diagnostic code",
+    templates: &["{} isn't a diagnostic code; codes look like LX0203"],
+    explanation: "\
+rlox1 explain was asked about a code that no diagnostic has. Codes are LX and four
+digits, as shown in brackets before a message.",
+};
+
+pub const LX0903: Code = Code {
+    code: "LX0903",
+    title: "Unknown benchmark",
+    templates: &["There's no benchmark called '{}'; try {}"],
+    explanation: "\
+rlox1 bench was asked for a benchmark it doesn't have. The message lists the ones it has,
+and with no names at all it runs every one.",
+};
+
+pub const LX0904: Code = Code {
+    code: "LX0904",
+    title: "Script isn't formatted",
+    templates: &["{} isn't formatted; run rlox1 fmt --write to format it"],
+    explanation: "\
+rlox1 fmt --check found a script that formatting would change. Run rlox1 fmt --write on
+it, or rlox1 fmt without --check to see how it would be laid out.",
+};
+
+pub const LX0905: Code = Code {
+    code: "LX0905",
+    title: "Some scripts failed",
+    templates: &[
+        "{} of {} scripts have {}",
+        "{} of {} benchmarks failed",
+        "{} of {} tests failed",
+    ],
+    explanation: "\
+A command that works through many scripts, such as check, lint, test or bench, found a
+problem with some of them. What each one is was reported before this summary.",
+};
+
+pub const LX0906: Code = Code {
+    code: "LX0906",
+    title: "Debugger needs a script file",
+    templates: &["The debugger reads commands from stdin, so the script must be a file"],
+    explanation: "\
+rlox1 debug reads its commands from stdin, so it can't read the script from there too.
+Save the script to a file and debug that.",
+};
+
+pub const LX0907: Code = Code {
+    code: "LX0907",
+    title: "Unknown REPL command",
+    templates: &[
+        "Unknown command '{}'; try :record FILE or :stop",
+        "Nothing is being recorded",
+    ],
+    explanation: "\
+A line starting with ':' at the REPL is a command rather than Lox. :record FILE starts
+writing what is typed to FILE, and :stop stops it; :stop does nothing unless :record was
+used first.",
+};
+
+pub const CODES: &[Code] = &[
+    LX0101, LX0102, LX0103, LX0201, LX0202, LX0203, LX0204, LX0205, LX0206, LX0207, LX0208, LX0209,
+    LX0210, LX0211, LX0212, LX0213, LX0214, LX0301, LX0302, LX0303, LX0401, LX0402, LX0403, LX0404,
+    LX0405, LX0406, LX0407, LX0408, LX0409, LX0410, LX0411, LX0412, LX0413, LX0501, LX0502, LX0503,
+    LX0504, LX0505, LX0506, LX0507, LX0508, LX0509, LX0510, LX0511, LX0512, LX0513, LX0514, LX0515,
+    LX0516, LX0517, LX0518, LX0519, LX0601, LX0602, LX0603, LX0604, LX0605, LX0606, LX0607, LX0608,
+    LX0609, LX0610, LX0611, LX0701, LX0702, LX0703, LX0704, LX0705, LX0706, LX0707, LX0708, LX0709,
+    LX0710, LX0711, LX0712, LX0713, LX0801, LX0802, LX0803, LX0804, LX0805, LX0901, LX0902, LX0903,
+    LX0904, LX0905, LX0906, LX0907,
+];
+
+// find: The code called `code`, e.g. "LX0203", in any case.
+pub fn find(code: &str) -> Option<&'static Code> {
+    CODES.iter().find(|c| c.code.eq_ignore_ascii_case(code))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codes::{find, Template, CODES, LX0211, LX0301};
+    use crate::embed::Lox;
+    use std::collections::HashSet;
+
+    #[test]
+    fn codes_are_unique_and_well_formed() {
+        let mut seen = HashSet::new();
+        for code in CODES {
+            assert!(seen.insert(code.code), "{} is used twice", code.code);
+            assert!(
+                code.code.starts_with("LX") && code.code.len() == 6,
+                "{}",
+                code.code
+            );
+            assert!(!code.templates.is_empty() && !code.explanation.is_empty());
+        }
+    }
+
+    #[test]
+    fn templates_are_found_by_their_text() {
+        let template = Template::of(&LX0211, "Expect ':' after map key");
+        assert_eq!((template.code().code, template.index()), ("LX0211", 4));
+        let template = Template::of(&LX0301, "[line {}] Undefined variable '{}'");
+        assert_eq!(template.text(), "Undefined variable '{}'");
+    }
+
+    #[test]
+    fn errors_have_the_code_they_were_made_with() {
+        let code = |source| Lox::new().eval(source).unwrap_err().code();
+        assert_eq!(code("print \"a;"), Some("LX0101"));
+        assert_eq!(code("print 1 @ 2;"), Some("LX0102"));
+        assert_eq!(code("print 1.2.3;"), Some("LX0103"));
+        assert_eq!(code("print (1;"), Some("LX0203"));
+        assert_eq!(code("const limit;"), Some("LX0211"));
+        assert_eq!(code("print y;"), Some("LX0301"));
+        assert_eq!(code("print nil + 1;"), Some("LX0502"));
+        assert_eq!(code("fun f() { f(); }\nf();"), Some("LX0601"));
+        assert_eq!(code("exit(1);"), None);
+        // The script's own errors have a code of their own, whatever they say.
+        assert_eq!(code("error(\"Undefined variable 'x'\");"), Some("LX0519"));
+        let load = crate::loxc::load(b"not bytecode").unwrap_err();
+        assert_eq!(load.code(), Some("LX0710"));
+    }
+
+    #[test]
+    fn codes_are_found_in_any_case() {
+        assert_eq!(
+            find("lx0301").map(|code| code.title),
+            Some("Undefined variable")
+        );
+        assert!(find("LX9999").is_none());
+    }
+}
@@ -0,0 +1,153 @@
+// Tab completion for the REPL: keywords and globals by name, and after a dot, the fields and
+// methods of the value on its left. That value is found by looking names up in the
+// interpreter's globals, never by running code, so pressing Tab can't have side effects.
+
+use crate::interpreter::Interpreter;
+use crate::scanner::KEYWORDS;
+use crate::stdlib;
+use crate::value::Value;
+
+// complete: Where the word at the end of `line` starts, and every word it could be, sorted.
+// Without an interpreter to look in, only keywords are known.
+pub fn complete(interpreter: Option<&Interpreter>, line: &str) -> (usize, Vec<String>) {
+    let start = word_start(line);
+    let names = match (line[..start].strip_suffix('.'), interpreter) {
+        (Some(receiver), Some(interpreter)) => {
+            lookup(interpreter, receiver).map_or_else(Vec::new, |value| properties(&value))
+        }
+        (Some(_), None) => Vec::new(),
+        (None, _) => KEYWORDS
+            .iter()
+            .copied()
+            .chain(interpreter.into_iter().flat_map(Interpreter::global_names))
+            .map(String::from)
+            .collect(),
+    };
+    let mut words: Vec<String> = names
+        .into_iter()
+        .filter(|name| name.starts_with(&line[start..]))
+        .collect();
+    words.sort();
+    words.dedup();
+    (start, words)
+}
+
+// word_start: Where the identifier characters at the end of `text` begin.
+fn word_start(text: &str) -> usize {
+    text.char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_alphanumeric() || *c == '_')
+        .last()
+        .map_or(text.len(), |(i, _)| i)
+}
+
+// lookup: The value of the dotted names at the end of `text`, e.g. `a.b` in `print a.b`: a
+// global, then fields or namespace members. Getters would run code, so they aren't followed.
+fn lookup(interpreter: &Interpreter, text: &str) -> Option<Value> {
+    let start = word_start(text);
+    let name = &text[start..];
+    if name.is_empty() {
+        return None;
+    }
+    match text[..start].strip_suffix('.') {
+        Some(outer) => match lookup(interpreter, outer)? {
+            Value::Instance(instance) => instance.borrow().fields.get(name).cloned(),
+            Value::Namespace(namespace) => namespace.get(name),
+            _ => None,
+        },
+        None => interpreter.global(name).cloned(),
+    }
+}
+
+// properties: The names that can follow a dot after `value`.
+pub fn properties(value: &Value) -> Vec<String> {
+    let methods: &[&str] = match value {
+        Value::List(_) => &stdlib::list::METHODS,
+        Value::Map(_) => &stdlib::map::METHODS,
+        Value::Generator(_) => &stdlib::generator::METHODS,
+        Value::WeakRef(_) => &stdlib::weakref::METHODS,
+        Value::Namespace(namespace) => return namespace.names().map(String::from).collect(),
+        Value::Instance(instance) => {
+            let instance = instance.borrow();
+            let class = &instance.class;
+            return instance
+                .fields
+                .keys()
+                .cloned()
+                .chain(class.all_getters().into_iter().map(String::from))
+                .chain(class.all_methods().into_keys())
+                .collect();
+        }
+        _ => &[],
+    };
+    methods.iter().map(|name| name.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::complete::complete;
+    use crate::interpreter::Interpreter;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::stdlib;
+    use crate::value::LoxMap;
+
+    fn interpreter(src: &str) -> Interpreter {
+        let mut interpreter = Interpreter::new();
+        stdlib::register(&mut interpreter, &stdlib::Options::default());
+        let tokens = Scanner::new(src).scan_tokens().unwrap().clone();
+        let statements = Parser::new(&tokens).parse().unwrap();
+        interpreter.interpret(&statements).unwrap();
+        interpreter
+    }
+
+    fn words(interpreter: &Interpreter, line: &str) -> Vec<String> {
+        complete(Some(interpreter), line).1
+    }
+
+    #[test]
+    fn names_complete_to_keywords_and_globals() {
+        let interpreter = interpreter("var counter = 1; fun count() {}");
+        assert_eq!(
+            complete(Some(&interpreter), "print cou"),
+            (6, vec!["count".into(), "counter".into()])
+        );
+        assert_eq!(words(&interpreter, "cl"), ["class", "className", "clock"]);
+        assert_eq!(words(&interpreter, "Ma"), ["Map", "Math"]);
+        assert!(words(&interpreter, "zz").is_empty());
+        assert_eq!(complete(None, "cl").1, ["class"]);
+    }
+
+    #[test]
+    fn properties_complete_after_a_dot() {
+        let interpreter = interpreter(
+            "class A { area { return 1; } shout() {} }
+             class B < A { init() { this.size = 2; } }
+             var b = B(); b.inner = [1];",
+        );
+        assert_eq!(
+            words(&interpreter, "b."),
+            ["area", "init", "inner", "shout", "size"]
+        );
+        assert_eq!(words(&interpreter, "b.inner.p"), ["pop", "push"]);
+        assert_eq!(words(&interpreter, "print Math.sq"), ["sqrt"]);
+        assert!(words(&interpreter, "nope.").is_empty());
+        assert!(words(&interpreter, "b.size.").is_empty());
+    }
+
+    // The lists of method names must keep up with the methods.
+    #[test]
+    fn method_names_are_methods() {
+        let list = Rc::new(RefCell::new(Vec::new()));
+        let map = Rc::new(RefCell::new(LoxMap::new()));
+        for name in stdlib::list::METHODS.iter() {
+            assert!(stdlib::list::method(&list, name).is_some(), "{}", name);
+        }
+        for name in stdlib::map::METHODS.iter() {
+            assert!(stdlib::map::method(&map, name).is_some(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,329 @@
+// The source-level debugger behind `rlox1 debug`. The tree-walker hands it each statement
+// before executing it; when a breakpoint or a step says to stop there, it reads commands until
+// one of them says to carry on.
+//
+//   break N, b N     stop at line N; with no line, list the breakpoints
+//   delete N, d N    stop stopping at line N
+//   step, s          run to the next statement, into calls
+//   next, n          run to the next statement in this call or the ones it returns to
+//   continue, c      run to the next breakpoint
+//   print X, p X     show the variable X
+//   locals           show the local variables in scope
+//   backtrace, bt    show the calls in progress
+//   list, l          show the source around the current line
+//   quit, q          stop the program
+//
+// An empty line repeats the last command. The end of the input lets the program run on to the
+// end, without stopping again.
+
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+
+use crate::ast::{Stmt, StmtKind};
+use crate::error::LoxError;
+use crate::interpreter::Interpreter;
+
+const PROMPT: &str = "(debug) ";
+
+// The lines `list` shows either side of the current one.
+const LIST_CONTEXT: usize = 3;
+
+// Mode: When to stop next, apart from at breakpoints.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    // At the next statement, wherever it is.
+    Step,
+    // At the next statement no deeper in calls than this.
+    Next(usize),
+    // Only at breakpoints.
+    Continue,
+    // Never again: the commands have run out.
+    Detached,
+}
+
+pub struct Debugger {
+    source: Vec<String>,
+    breakpoints: BTreeSet<usize>,
+    mode: Mode,
+    // The line of the statement last seen, so a line holding several statements is only
+    // stopped at once per visit.
+    last_line: usize,
+    last_command: String,
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+}
+
+impl Debugger {
+    // new: A debugger for a program with the given source, which stops before its first
+    // statement. Commands come from `input`, and everything it shows goes to `output`.
+    pub fn new(source: &str, input: Box<dyn BufRead>, output: Box<dyn Write>) -> Self {
+        Debugger {
+            source: source.lines().map(String::from).collect(),
+            breakpoints: BTreeSet::new(),
+            mode: Mode::Step,
+            last_line: 0,
+            last_command: String::new(),
+            input,
+            output,
+        }
+    }
+
+    // before: Called by the interpreter before it executes `stmt`. Returns once the program
+    // should go on, or with an exit request if the user quit.
+    pub fn before(&mut self, interpreter: &Interpreter, stmt: &Stmt) -> Result<(), LoxError> {
+        // A block is stopped at by its first statement instead.
+        if let StmtKind::Block(_) = stmt.kind {
+            return Ok(());
+        }
+        let new_line = stmt.line != self.last_line;
+        self.last_line = stmt.line;
+        let stop = match self.mode {
+            Mode::Step => true,
+            Mode::Next(depth) => interpreter.call_depth() <= depth,
+            Mode::Continue => false,
+            Mode::Detached => return Ok(()),
+        };
+        let at_breakpoint = new_line && self.breakpoints.contains(&stmt.line);
+        if !stop && !at_breakpoint {
+            return Ok(());
+        }
+        writeln!(
+            self.output,
+            "[line {}] {}",
+            stmt.line,
+            source_line(&self.source, stmt.line)
+        )?;
+        self.prompt(interpreter, stmt)
+    }
+
+    // prompt: Carry out commands until one of them resumes the program.
+    fn prompt(&mut self, interpreter: &Interpreter, stmt: &Stmt) -> Result<(), LoxError> {
+        loop {
+            write!(self.output, "{}", PROMPT)?;
+            self.output.flush()?;
+            let mut command = String::new();
+            if self.input.read_line(&mut command)? == 0 {
+                self.mode = Mode::Detached;
+                return Ok(());
+            }
+            let mut command = command.trim().to_string();
+            if command.is_empty() {
+                command = self.last_command.clone();
+            } else {
+                self.last_command = command.clone();
+            }
+            let (verb, arg) = match command.split_once(' ') {
+                Some((verb, arg)) => (verb, arg.trim()),
+                None => (command.as_str(), ""),
+            };
+            match verb {
+                "step" | "s" => self.mode = Mode::Step,
+                "next" | "n" => self.mode = Mode::Next(interpreter.call_depth()),
+                "continue" | "c" => self.mode = Mode::Continue,
+                "quit" | "q" => return Err(LoxError::exit(0)),
+                "" => continue,
+                _ => {
+                    self.inspect(interpreter, stmt, verb, arg)?;
+                    continue;
+                }
+            }
+            return Ok(());
+        }
+    }
+
+    // inspect: Carry out a command that looks at the program without resuming it.
+    fn inspect(
+        &mut self,
+        interpreter: &Interpreter,
+        stmt: &Stmt,
+        verb: &str,
+        arg: &str,
+    ) -> Result<(), LoxError> {
+        match verb {
+            "break" | "b" if arg.is_empty() => {
+                if self.breakpoints.is_empty() {
+                    writeln!(self.output, "No breakpoints")?;
+                }
+                for line in &self.breakpoints {
+                    writeln!(self.output, "Breakpoint at line {}", line)?;
+                }
+            }
+            "break" | "b" | "delete" | "d" => match arg.parse::<usize>() {
+                Ok(line) if line >= 1 && line <= self.source.len() => {
+                    if verb.starts_with('b') {
+                        self.breakpoints.insert(line);
+                        writeln!(self.output, "Breakpoint at line {}", line)?;
+                    } else if self.breakpoints.remove(&line) {
+                        writeln!(self.output, "Deleted the breakpoint at line {}", line)?;
+                    } else {
+                        writeln!(self.output, "There's no breakpoint at line {}", line)?;
+                    }
+                }
+                _ => writeln!(
+                    self.output,
+                    "Expected a line number from 1 to {}, got '{}'",
+                    self.source.len(),
+                    arg
+                )?,
+            },
+            "print" | "p" => match interpreter.variable(stmt, arg) {
+                Some(value) => writeln!(self.output, "{} = {}", arg, value.repr())?,
+                None => writeln!(self.output, "There's no variable '{}' here", arg)?,
+            },
+            "locals" => {
+                let locals = interpreter.locals(stmt);
+                if locals.is_empty() {
+                    writeln!(self.output, "No locals")?;
+                }
+                for (name, value) in locals {
+                    writeln!(self.output, "{} = {}", name, value.repr())?;
+                }
+            }
+            "backtrace" | "bt" => {
+                for frame in interpreter.backtrace(stmt.line) {
+                    writeln!(self.output, "{}", frame)?;
+                }
+            }
+            // Listed lines keep their indentation, to show the structure around this one.
+            "list" | "l" => {
+                let first = stmt.line.saturating_sub(LIST_CONTEXT).max(1);
+                let last = (stmt.line + LIST_CONTEXT).min(self.source.len());
+                for number in first..=last {
+                    let marker = if number == stmt.line { ">" } else { " " };
+                    writeln!(
+                        self.output,
+                        "{} {:>4} {}",
+                        marker,
+                        number,
+                        self.source[number - 1]
+                    )?;
+                }
+            }
+            "help" | "h" => writeln!(
+                self.output,
+                "Commands: break N, delete N, step, next, continue, print X, locals, \
+                 backtrace, list, quit"
+            )?,
+            _ => writeln!(self.output, "Unknown command '{}'; try 'help'", verb)?,
+        }
+        Ok(())
+    }
+}
+
+// source_line: Line `number` of the source, without its indentation.
+fn source_line(source: &[String], number: usize) -> &str {
+    number
+        .checked_sub(1)
+        .and_then(|index| source.get(index))
+        .map_or("", |line| line.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::{self, Cursor, Write};
+    use std::rc::Rc;
+
+    use crate::debugger::Debugger;
+    use crate::error::LoxError;
+    use crate::interpreter::Interpreter;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    // Transcript: Output the test can read after the debugger, which owns it, is done.
+    #[derive(Clone, Default)]
+    struct Transcript(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Transcript {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // debug: Run `src` under the debugger with `commands` typed at it, returning what the
+    // debugger showed, without the prompts.
+    fn debug(src: &str, commands: &str) -> Result<String, LoxError> {
+        let transcript = Transcript::default();
+        let debugger = Debugger::new(
+            src,
+            Box::new(Cursor::new(commands.to_string())),
+            Box::new(transcript.clone()),
+        );
+        let statements = Parser::new(&Scanner::new(src).tokens()?).parse()?;
+        let mut interpreter = Interpreter::new();
+        interpreter.debug(debugger);
+        let result = interpreter.interpret(&statements);
+        let shown = String::from_utf8_lossy(&transcript.0.borrow()).replace("(debug) ", "");
+        result.map(|_| shown)
+    }
+
+    const PROGRAM: &str = "var a = 1;\n\
+                           fun add(x, y) {\n\
+                           \x20 var sum = x + y;\n\
+                           \x20 return sum;\n\
+                           }\n\
+                           var b = add(a, 2);\n\
+                           print b;\n";
+
+    #[test]
+    fn stepping_goes_into_calls_and_next_goes_over_them() -> Result<(), LoxError> {
+        let shown = debug(PROGRAM, "s\n\ns\ns\ns\n")?;
+        assert_eq!(
+            shown,
+            "[line 1] var a = 1;\n\
+             [line 2] fun add(x, y) {\n\
+             [line 6] var b = add(a, 2);\n\
+             [line 3] var sum = x + y;\n\
+             [line 4] return sum;\n\
+             [line 7] print b;\n"
+        );
+        let shown = debug(PROGRAM, "n\nn\nn\nn\n")?;
+        assert_eq!(
+            shown,
+            "[line 1] var a = 1;\n\
+             [line 2] fun add(x, y) {\n\
+             [line 6] var b = add(a, 2);\n\
+             [line 7] print b;\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn breakpoints_stop_where_variables_can_be_seen() -> Result<(), LoxError> {
+        let shown = debug(PROGRAM, "b 4\nc\np sum\np a\np nope\nlocals\nbt\nd 4\nc\n")?;
+        assert_eq!(
+            shown,
+            "[line 1] var a = 1;\n\
+             Breakpoint at line 4\n\
+             [line 4] return sum;\n\
+             sum = 3\n\
+             a = 1\n\
+             There's no variable 'nope' here\n\
+             x = 1\n\
+             y = 2\n\
+             sum = 3\n\
+             [line 4] in add()\n\
+             [line 6] in script\n\
+             Deleted the breakpoint at line 4\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn quitting_stops_the_program() {
+        let err = debug(PROGRAM, "q\n").unwrap_err();
+        assert_eq!(err.exit_code(), Some(0));
+        // Bad commands are reported, and the program waits for a good one.
+        assert_eq!(
+            debug("print 1;", "frobnicate\nb 9\nc\n").unwrap(),
+            "[line 1] print 1;\n\
+             Unknown command 'frobnicate'; try 'help'\n\
+             Expected a line number from 1 to 1, got '9'\n"
+        );
+    }
+}
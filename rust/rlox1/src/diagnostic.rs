@@ -0,0 +1,547 @@
+// How rlox1 reports what went wrong: errors from the scanner, parser, resolver and running
+// programs, and warnings about how it was asked to run them. Every diagnostic is laid out the
+// same way, with a header naming its level, and the source and stack trace if the error has
+// them. On a terminal the level picks the color: red for errors, yellow for warnings and blue
+// for notes, such as the frames of a trace. A diagnostic with a code has it in the header,
+// e.g. ERROR[LX0301], which `rlox1 explain LX0301` explains.
+//
+// For editors and CI, `--error-format=json` writes each diagnostic as a JSON object on a line
+// of its own instead:
+//
+//   {"severity":"error","code":"LX0301","message":"[line 2] Undefined variable 'y'.",
+//    "file":"script.lox","span":{"line":2,"column":11,"length":1}}
+//
+// The span is null if the diagnostic isn't about a place in the source, and its column and
+// length are null if only the line is known, and the code is null if the diagnostic has none.
+
+use std::env;
+use std::io::{self, IsTerminal};
+
+use crate::catalog;
+use crate::error::{LoxError, Span};
+use crate::json;
+
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const RESET: &str = "\x1b[0m";
+
+// Level: How serious a diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Level {
+    fn label(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warning => "WARNING",
+            Level::Note => "NOTE",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warning => "warning",
+            Level::Note => "note",
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Level::Error => RED,
+            Level::Warning => YELLOW,
+            Level::Note => BLUE,
+        }
+    }
+}
+
+// ColorChoice: When to color diagnostics, as chosen by --color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    // When stderr is a terminal, and NO_COLOR doesn't say otherwise.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+
+    // enabled: Whether diagnostics written to stderr should be colored.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => io::stderr().is_terminal() && !no_color(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+// Format: How diagnostics are written, as chosen by --error-format.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    // For people, with the source quoted.
+    Human,
+    // A JSON object per diagnostic, for tools.
+    Json,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "human" => Some(Format::Human),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+// no_color: Whether the NO_COLOR environment variable asks for no color. See
+// https://no-color.org: set and not empty means no color.
+pub fn no_color() -> bool {
+    env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+}
+
+// Renderer: Lays diagnostics out as text, in color or not, or as JSON, showing at most
+// `error_limit` of a program's errors, if there is a limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Renderer {
+    color: bool,
+    format: Format,
+    error_limit: Option<usize>,
+}
+
+impl Renderer {
+    pub fn new(color: bool) -> Self {
+        Renderer {
+            color,
+            format: Format::Human,
+            error_limit: None,
+        }
+    }
+
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn error_limit(mut self, limit: Option<usize>) -> Self {
+        self.error_limit = limit;
+        self
+    }
+
+    // render: The report of `err` at `level`, headed by the level and the error's code, e.g.
+    //
+    //   ERROR[LX0301]: [line 2] Undefined variable 'y'.
+    //    --> script.lox:2:11
+    //     |
+    //   2 | print x + y;
+    //     |           ^
+    //   [line 2] in script
+    pub fn render(&self, level: Level, err: &LoxError) -> String {
+        if self.format == Format::Json {
+            return json(level, err);
+        }
+        let label = match err.code() {
+            Some(code) => format!("{}[{}]:", level.label(), code),
+            None => format!("{}:", level.label()),
+        };
+        let label = self.paint(level.color(), &label);
+        format!("{} {}", label, self.body(level, err))
+    }
+
+    // render_in: Like render, for an error in `source`, read from `file`, which the error
+    // may not have been told about, e.g. an entry typed at the REPL. Its line is quoted from
+    // `source` the same way as from a script run from the command line.
+    pub fn render_in(&self, level: Level, err: &LoxError, file: &str, source: &str) -> String {
+        self.render(level, &err.clone().in_source(file, source))
+    }
+
+    // body: The report of `err` without the header's label: its message and context, the
+    // line of source it points at, if that is known, and its stack trace, followed by where in
+    // rlox1 it was made, if RLOX_BACKTRACE asked for that.
+    pub fn body(&self, level: Level, err: &LoxError) -> String {
+        let mut out = self.paint(BOLD, &catalog::text(err));
+        if let Some((span, file, text)) = err.snippet() {
+            out.push_str(&self.snippet(level, span, file, text));
+        }
+        for frame in err.trace() {
+            out.push('\n');
+            out.push_str(&self.paint(Level::Note.color(), &frame.to_string()));
+        }
+        if let Some(backtrace) = err.backtrace() {
+            out.push_str(&format!("\nrlox1 backtrace:\n{}", backtrace));
+        }
+        out
+    }
+
+    // snippet: Line `text` of `file`, which `span` is on, with carets under the span.
+    fn snippet(&self, level: Level, span: Span, file: &str, text: &str) -> String {
+        let number = span.line.to_string();
+        let gutter = " ".repeat(number.len());
+        // Tabs would throw out the carets, which are lined up a character at a time.
+        let text = text.replace('\t', " ");
+        let width = text.chars().count();
+        let start = span.column.saturating_sub(1).min(width);
+        // A span that runs on past the line, like a string with newlines in, stops at its end.
+        let len = span.len.min(width - start).max(1);
+        let bar = self.paint(BLUE, "|");
+        format!(
+            "\n{}{} {}:{}:{}\n{} {}\n{} {}\n{} {} {}{}",
+            gutter,
+            self.paint(BLUE, "-->"),
+            file,
+            span.line,
+            span.column,
+            gutter,
+            bar,
+            self.paint(BLUE, &format!("{} |", number)),
+            text,
+            gutter,
+            bar,
+            " ".repeat(start),
+            self.paint(level.color(), &"^".repeat(len))
+        )
+    }
+
+    // paint: `text` in the given color, if the renderer uses color.
+    fn paint(&self, color: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+// Diagnostics: The errors a phase has found so far. The scanner, parser and resolver carry on
+// past an error, to find the rest, and push each into one of these; it becomes a single
+// LoxError only when the phase is done, if there were any. The resolver also notes warnings
+// here, about code that runs but probably doesn't do what was meant; they don't stop the
+// program, unless --deny-warnings makes errors of them.
+// Recovering from one mistake can make others out of the code after it, so an error at the
+// same place in the same file as one already found is left out, and the first is kept.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    errors: Vec<LoxError>,
+    warnings: Vec<LoxError>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Diagnostics::default()
+    }
+
+    // push: Note `err`, and any errors combined with it, apart from those at a place there
+    // is already an error at.
+    pub fn push(&mut self, err: LoxError) {
+        for err in err.into_errors() {
+            let place = |err: &LoxError| err.span().map(|span| (span.line, span.column));
+            let duplicate = place(&err).is_some()
+                && self
+                    .errors
+                    .iter()
+                    .any(|found| place(found) == place(&err) && found.file() == err.file());
+            if !duplicate {
+                self.errors.push(err);
+            }
+        }
+    }
+
+    pub fn warn(&mut self, warning: LoxError) {
+        self.warnings.push(warning);
+    }
+
+    // take_warnings: The warnings noted so far, leaving none.
+    pub fn take_warnings(&mut self) -> Vec<LoxError> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    // sort: Put the errors, and the warnings, in the order of where they are in the source,
+    // leaving those that aren't about a place in it at the end, in the order they were found.
+    pub fn sort(&mut self) {
+        for diagnostics in [&mut self.errors, &mut self.warnings] {
+            diagnostics.sort_by_key(|err| {
+                let position = match (err.span(), err.line()) {
+                    (Some(span), _) => Some((span.line, span.column)),
+                    (None, line) => line.map(|line| (line, 0)),
+                };
+                (position.is_none(), position)
+            });
+        }
+    }
+
+    // render: The errors, laid out by `renderer` at `level`, one after another, up to its
+    // error limit, with a note of how many more there are after that.
+    pub fn render(&self, renderer: &Renderer, level: Level) -> String {
+        let limit = renderer.error_limit.unwrap_or(usize::MAX);
+        let mut rendered: Vec<_> = self
+            .errors
+            .iter()
+            .take(limit)
+            .map(|err| renderer.render(level, err))
+            .collect();
+        let hidden = self.errors.len().saturating_sub(limit);
+        if hidden > 0 {
+            let plural = if hidden == 1 { "" } else { "s" };
+            let more = LoxError::new(&format!(
+                "And {} more error{}; see --error-limit",
+                hidden, plural
+            ));
+            rendered.push(renderer.render(Level::Note, &more));
+        }
+        rendered.join("\n")
+    }
+
+    // finish: `value` if no errors were found, or else the errors, combined into one.
+    pub fn finish<T>(self, value: T) -> Result<T, LoxError> {
+        match LoxError::combine(self.errors) {
+            Some(err) => Err(err),
+            None => Ok(value),
+        }
+    }
+}
+
+// From: The errors combined in `err`, apart again, e.g. to render them.
+impl From<LoxError> for Diagnostics {
+    fn from(err: LoxError) -> Self {
+        Diagnostics {
+            errors: err.into_errors(),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+// json: The diagnostic for `err` at `level` as a JSON object, on one line.
+fn json(level: Level, err: &LoxError) -> String {
+    let file = err.file().map_or("null".to_string(), json::quote);
+    let span = match (err.span(), err.line()) {
+        (Some(span), _) => format!(
+            r#"{{"line":{},"column":{},"length":{}}}"#,
+            span.line, span.column, span.len
+        ),
+        (None, Some(line)) => format!(r#"{{"line":{},"column":null,"length":null}}"#, line),
+        (None, None) => "null".to_string(),
+    };
+    let code = err.code().map_or("null".to_string(), json::quote);
+    format!(
+        r#"{{"severity":"{}","code":{},"message":{},"file":{},"span":{}}}"#,
+        level.name(),
+        code,
+        json::quote(&catalog::text(err)),
+        file,
+        span
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostic::{Diagnostics, Format, Level, Renderer};
+    use crate::error::{LoxError, Span};
+
+    fn error() -> LoxError {
+        let span = Span {
+            line: 1,
+            column: 7,
+            len: 1,
+        };
+        LoxError::new("[line 1] Bad thing")
+            .at(Some(span))
+            .in_source("a.lox", "print x;")
+            .unwind_from(None, None)
+    }
+
+    #[test]
+    fn plain_diagnostics_have_no_color_codes() {
+        assert_eq!(
+            Renderer::new(false).render(Level::Warning, &error()),
+            "WARNING: [line 1] Bad thing.\n --> a.lox:1:7\n  |\n1 | print x;\n  |       ^\n\
+             [line 1] in script"
+        );
+    }
+
+    #[test]
+    fn errors_can_be_given_their_source() {
+        let span = Span {
+            line: 2,
+            column: 7,
+            len: 3,
+        };
+        let err = LoxError::new("[line 2] Bad thing").at(Some(span));
+        assert_eq!(
+            Renderer::new(false).render_in(Level::Error, &err, "<repl>", "var a;\nprint abc;\n"),
+            "ERROR: [line 2] Bad thing.\n --> <repl>:2:7\n  |\n2 | print abc;\n  |       ^^^"
+        );
+        // Without it, there's nothing to quote.
+        assert_eq!(
+            Renderer::new(false).render(Level::Error, &err),
+            "ERROR: [line 2] Bad thing."
+        );
+    }
+
+    #[test]
+    fn json_has_a_line_per_diagnostic() {
+        let json = Renderer::new(true).format(Format::Json);
+        assert_eq!(
+            json.render(Level::Error, &error()),
+            r#"{"severity":"error","code":null,"message":"[line 1] Bad thing.","file":"a.lox","span":{"line":1,"column":7,"length":1}}"#
+        );
+        let err = LoxError::new("[line 2] Say \"hi\"\tnow").in_source("C:\\a.lox", "");
+        assert_eq!(
+            json.render(Level::Note, &err),
+            r#"{"severity":"note","code":null,"message":"[line 2] Say \"hi\"\tnow.","file":"C:\\a.lox","span":{"line":2,"column":null,"length":null}}"#
+        );
+        assert_eq!(
+            json.render(Level::Warning, &LoxError::new("Careful")),
+            r#"{"severity":"warning","code":null,"message":"Careful.","file":null,"span":null}"#
+        );
+        let err = lox_error!(LX0301, "[line {}] Undefined variable '{}'", 3, "y");
+        assert_eq!(
+            json.render(Level::Error, &err),
+            r#"{"severity":"error","code":"LX0301","message":"[line 3] Undefined variable 'y'.","file":null,"span":{"line":3,"column":null,"length":null}}"#
+        );
+    }
+
+    #[test]
+    fn codes_go_in_the_header() {
+        let err = LoxError::located(
+            "[line 1] Error at ')': ",
+            message!(LX0201, "Expect expression"),
+        );
+        assert_eq!(
+            Renderer::new(false).render(Level::Error, &err),
+            "ERROR[LX0201]: [line 1] Error at ')': Expect expression."
+        );
+        // The code comes from where the error was made, not from what it says.
+        let err = LoxError::new("[line 1] Error at ')': Expect expression");
+        assert_eq!(
+            Renderer::new(false).render(Level::Error, &err),
+            "ERROR: [line 1] Error at ')': Expect expression."
+        );
+    }
+
+    #[test]
+    fn levels_have_colors() {
+        let colored = Renderer::new(true).render(Level::Error, &error());
+        assert_eq!(
+            colored,
+            "\x1b[1;31mERROR:\x1b[0m \x1b[1m[line 1] Bad thing.\x1b[0m\n \
+             \x1b[1;34m-->\x1b[0m a.lox:1:7\n  \x1b[1;34m|\x1b[0m\n\
+             \x1b[1;34m1 |\x1b[0m print x;\n  \x1b[1;34m|\x1b[0m       \x1b[1;31m^\x1b[0m\n\
+             \x1b[1;34m[line 1] in script\x1b[0m"
+        );
+        let warning = Renderer::new(true).render(Level::Warning, &LoxError::new("Careful"));
+        assert_eq!(warning, "\x1b[1;33mWARNING:\x1b[0m \x1b[1mCareful.\x1b[0m");
+    }
+
+    #[test]
+    fn diagnostics_sort_by_where_they_are() {
+        let at = |line, column| {
+            Some(Span {
+                line,
+                column,
+                len: 1,
+            })
+        };
+        let diagnostics = Diagnostics::new();
+        assert!(!diagnostics.has_errors());
+        assert!(diagnostics.finish(()).is_ok());
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.push(LoxError::new("Far").at(at(3, 1)));
+        diagnostics.push(LoxError::new("Nowhere"));
+        diagnostics.push(LoxError::new("[line 2] Line only"));
+        diagnostics.push(LoxError::new("Near").at(at(2, 5)));
+        diagnostics.sort();
+        assert!(diagnostics.has_errors() && diagnostics.len() == 4);
+        assert_eq!(
+            diagnostics.render(&Renderer::new(false), Level::Error),
+            "ERROR: [line 2] Line only.\nERROR: Near.\nERROR: Far.\nERROR: Nowhere."
+        );
+        let errors = diagnostics.finish(()).unwrap_err().into_errors();
+        let messages: Vec<_> = errors.iter().map(LoxError::message).collect();
+        assert_eq!(
+            messages,
+            vec!["[line 2] Line only", "Near", "Far", "Nowhere"]
+        );
+    }
+
+    #[test]
+    fn diagnostics_leave_out_errors_at_the_same_place() {
+        let at = |column| {
+            Some(Span {
+                line: 1,
+                column,
+                len: 1,
+            })
+        };
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.push(LoxError::new("First").at(at(3)));
+        diagnostics.push(LoxError::new("Cascade").at(at(3)));
+        diagnostics.push(LoxError::new("Elsewhere").at(at(5)));
+        diagnostics.push(
+            LoxError::new("In another file")
+                .at(at(3))
+                .in_source("b.lox", ""),
+        );
+        // Errors without a place can't be told apart that way, so they are all kept.
+        diagnostics.push(LoxError::new("Nowhere"));
+        diagnostics.push(LoxError::new("Nowhere"));
+        let messages: Vec<_> = diagnostics.errors.iter().map(LoxError::message).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "First",
+                "Elsewhere",
+                "In another file",
+                "Nowhere",
+                "Nowhere"
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_past_the_limit_are_counted() {
+        let mut diagnostics = Diagnostics::new();
+        for n in 1..=4 {
+            diagnostics.push(LoxError::new(&format!("Error {}", n)));
+        }
+        let render =
+            |limit| diagnostics.render(&Renderer::new(false).error_limit(limit), Level::Error);
+        assert_eq!(
+            render(Some(2)),
+            "ERROR: Error 1.\nERROR: Error 2.\nNOTE: And 2 more errors; see --error-limit."
+        );
+        assert_eq!(
+            render(Some(3)),
+            "ERROR: Error 1.\nERROR: Error 2.\nERROR: Error 3.\n\
+             NOTE: And 1 more error; see --error-limit."
+        );
+        assert_eq!(render(Some(4)).lines().count(), 4);
+        assert_eq!(render(None).lines().count(), 4);
+    }
+}
@@ -0,0 +1,208 @@
+// Lox for a host application: a Lox owns an interpreter with the standard library, and lives
+// for as long as the host keeps it, so scripts can be run into it a piece at a time, each
+// seeing what the ones before defined, and the host can call what they define.
+//
+// Values go back and forth by value: a Value the host keeps is its own reference, which the
+// cycle collector counts as a root, so it stays alive after the script that made it is gone.
+// Like the values, a Lox belongs to the thread that made it.
+
+use crate::error::LoxError;
+use crate::interpreter::Interpreter;
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::stdlib::{self, Options};
+use crate::suggest;
+use crate::value::Value;
+
+// Lox: An interpreter and everything the scripts run in it have defined.
+pub struct Lox {
+    interpreter: Interpreter,
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Lox::new()
+    }
+}
+
+impl Lox {
+    // new: A Lox with the standard library, as `rlox1 run` gives it to scripts by default.
+    pub fn new() -> Self {
+        Lox::with_options(&Options::default())
+    }
+
+    // with_options: A Lox with the standard library as `options` say, e.g. with file access.
+    pub fn with_options(options: &Options) -> Self {
+        let mut interpreter = Interpreter::new();
+        stdlib::register(&mut interpreter, options);
+        Lox { interpreter }
+    }
+
+    // eval: Run `source`, giving back the value of its last statement if that is an
+    // expression, or else nil. What it defines is kept for what runs after it.
+    pub fn eval(&mut self, source: &str) -> Result<Value, LoxError> {
+        let tokens = Scanner::new(source).tokens()?;
+        let statements = Parser::new(&tokens).parse()?;
+        self.interpreter.eval(&statements)
+    }
+
+    // call: Call the global function or class `name` with `args`, giving back its result.
+    pub fn call(&mut self, name: &str, args: &[Value]) -> Result<Value, LoxError> {
+        let callee = match self.interpreter.global(name) {
+            Some(callee) => callee.clone(),
+            None => {
+                let err = lox_error!(LX0301, "Undefined variable '{}'", name).at_runtime();
+                let names = self.interpreter.global_names();
+                return Err(suggest::did_you_mean(err, name, names));
+            }
+        };
+        self.interpreter.call_value(&callee, args)
+    }
+
+    // set_global: Define the global variable `name`, or give it a new value, for the scripts
+    // run after.
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.interpreter.define_global(name, value);
+    }
+
+    // global: The value of the global variable `name`, if one has been defined.
+    pub fn global(&self, name: &str) -> Option<Value> {
+        self.interpreter.global(name).cloned()
+    }
+
+    // interpreter: The interpreter itself, for what a Lox doesn't do, e.g. defining natives
+    // or limiting how deep calls go.
+    pub fn interpreter(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::embed::Lox;
+    use crate::error::ErrorKind;
+    use crate::value::Value;
+
+    #[test]
+    fn eval_gives_the_last_value() {
+        let mut lox = Lox::new();
+        assert_eq!(lox.eval("1 + 2;").unwrap(), Value::Number(3.0));
+        assert_eq!(lox.eval("var x = 4;").unwrap(), Value::Nil);
+        assert_eq!(lox.eval("x = x * 2;\nx;").unwrap(), Value::Number(8.0));
+        assert_eq!(lox.eval("").unwrap(), Value::Nil);
+        assert_eq!(lox.global("x"), Some(Value::Number(8.0)));
+    }
+
+    #[test]
+    fn hosts_call_what_scripts_define() {
+        let mut lox = Lox::new();
+        lox.eval(
+            "fun add(a, b) { return a + b; }\n\
+             class Point { init(x, y) { this.x = x; this.y = y; } }",
+        )
+        .unwrap();
+        let sum = lox.call("add", &[Value::Number(1.0), Value::Number(2.0)]);
+        assert_eq!(sum.unwrap(), Value::Number(3.0));
+        let point = lox.call("Point", &[Value::Number(1.0), Value::Number(2.0)]);
+        lox.set_global("p", point.unwrap());
+        assert_eq!(lox.eval("p.x + p.y;").unwrap(), Value::Number(3.0));
+        let kind = lox.call("type", &[Value::Number(4.0)]);
+        assert_eq!(kind.unwrap(), Value::Str("number".into()));
+    }
+
+    #[test]
+    fn calls_that_cant_be_made_are_errors() {
+        let mut lox = Lox::new();
+        lox.eval("fun add(a, b) { return a + b; }\nvar n = 1;")
+            .unwrap();
+        let error = |lox: &mut Lox, name, args: &[Value]| lox.call(name, args).unwrap_err();
+        let err = error(&mut lox, "addd", &[]);
+        assert_eq!(
+            err.to_string(),
+            "Undefined variable 'addd'; did you mean 'add'?"
+        );
+        assert_eq!(err.kind(), ErrorKind::Runtime);
+        assert_eq!(
+            error(&mut lox, "add", &[Value::Nil]).to_string(),
+            "Expected 2 arguments but got 1."
+        );
+        assert_eq!(
+            error(&mut lox, "n", &[]).to_string(),
+            "Can only call functions and classes."
+        );
+        let err = error(&mut lox, "add", &[Value::Nil, Value::Bool(true)]);
+        assert_eq!(
+            err.to_string(),
+            "[line 1] Operands of '+' must be two numbers or two strings, got nil and bool."
+        );
+        assert_eq!(err.trace()[0].function.as_deref(), Some("add"));
+    }
+
+    // A host's thread has far less stack than the call limit is set for, so running out of it
+    // must stop the recursion first.
+    #[test]
+    fn deep_recursion_on_a_small_stack_is_an_error() {
+        let err = std::thread::Builder::new()
+            .stack_size(2 << 20)
+            .spawn(|| {
+                let mut lox = Lox::new();
+                lox.eval("fun f(n) { return f(n + 1); } f(0);").unwrap_err()
+            })
+            .unwrap()
+            .join()
+            .expect("the host's thread overflowed");
+        assert_eq!(err.to_string(), "[line 1] Stack overflow.");
+    }
+
+    #[test]
+    fn instances_are_shown_by_their_to_string_everywhere() {
+        let mut lox = Lox::new();
+        lox.interpreter().capture_output();
+        lox.eval(
+            "class P { toString() { return \"p\"; } }\n\
+             var p = P();\n\
+             print p;\n\
+             print(p);\n\
+             println([p, {\"k\": p}]);",
+        )
+        .unwrap();
+        assert_eq!(lox.interpreter().take_output(), "p\np\n[p, {\"k\": p}]\n");
+        let joined = lox.eval("str(p) + \"!\" + p;").unwrap();
+        assert_eq!(joined, Value::Str("p!p".into()));
+        let error = |lox: &mut Lox, source| lox.eval(source).unwrap_err().to_string();
+        assert_eq!(error(&mut lox, "error(p);"), "[line 1] p.");
+        assert_eq!(error(&mut lox, "assert(false, [p]);"), "[line 1] Assertion failed: [p].");
+        assert_eq!(error(&mut lox, "throw p;"), "[line 1] Uncaught exception: p.");
+        assert_eq!(error(&mut lox, "({})[p];"), "[line 1] Key p not found in map.");
+    }
+
+    // Nesting, like recursion, is stopped while there is still stack to report it with.
+    #[test]
+    fn deep_nesting_on_a_small_stack_is_an_error() {
+        let err = std::thread::Builder::new()
+            .stack_size(2 << 20)
+            .spawn(|| {
+                let mut lox = Lox::new();
+                let nested = format!("{}1{};", "(".repeat(1000), ")".repeat(1000));
+                lox.eval(&nested).unwrap_err().to_string()
+            })
+            .unwrap()
+            .join()
+            .expect("the host's thread overflowed");
+        assert!(err.contains("Too much nesting"), "{}", err);
+    }
+
+    #[test]
+    fn hosts_capture_what_scripts_print() {
+        let mut lox = Lox::new();
+        lox.interpreter().capture_output();
+        lox.eval("print 1;\nprint(\"two\");").unwrap();
+        assert_eq!(lox.interpreter().take_output(), "1\ntwo\n");
+        lox.eval("println(3);").unwrap();
+        assert_eq!(lox.interpreter().take_output(), "3\n");
+        assert_eq!(lox.interpreter().take_output(), "");
+        // As a value, print leaves the line open and println ends it.
+        lox.eval("var p = print; p(1); p(\"a\"); var q = println; q(2); p(3);").unwrap();
+        assert_eq!(lox.interpreter().take_output(), "1a2\n3");
+    }
+}
@@ -0,0 +1,267 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::error::LoxError;
+use crate::gc;
+use crate::resolver::Slot;
+use crate::scanner::{Token, TokenType};
+use crate::suggest;
+use crate::value::Value;
+
+// Scope: The values of the locals of one block, function call or loop iteration, in the
+// order the resolver numbered them, plus the scope around it. Scopes are shared: a closure
+// keeps the scope it was declared in alive, and sees (and makes) every later change to it.
+#[derive(Default)]
+pub struct Scope {
+    values: Vec<Value>,
+    enclosing: Option<ScopeRef>,
+}
+
+pub type ScopeRef = Rc<RefCell<Scope>>;
+
+impl Scope {
+    // new_ref: A scope holding `values` inside `enclosing`, registered with the cycle
+    // collector. Scopes should always be made through this.
+    pub fn new_ref(values: Vec<Value>, enclosing: Option<ScopeRef>) -> ScopeRef {
+        let scope = Rc::new(RefCell::new(Scope { values, enclosing }));
+        gc::track_scope(&scope);
+        scope
+    }
+
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    pub fn enclosing(&self) -> Option<&ScopeRef> {
+        self.enclosing.as_ref()
+    }
+
+    // clear: Drop everything the scope refers to. Only the cycle collector does this, to
+    // scopes nothing can reach any more.
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.enclosing = None;
+    }
+}
+
+// Environment: Variable bindings. Globals are looked up by name and live as long as the
+// environment does. Locals live in a chain of scopes, innermost first, and are addressed by
+// the slots the resolver worked out.
+pub struct Environment {
+    globals: HashMap<String, Value>,
+    current: Option<ScopeRef>,
+}
+
+// CallerScopes: The scope chain of a caller, set aside while a function body runs.
+pub struct CallerScopes(Option<ScopeRef>);
+
+impl Environment {
+    pub fn new() -> Self {
+        Environment {
+            globals: HashMap::new(),
+            current: None,
+        }
+    }
+
+    // push_scope: Start a scope holding `locals`, e.g. the item of a for-in loop.
+    pub fn push_scope(&mut self, locals: Vec<Value>) {
+        self.current = Some(Scope::new_ref(locals, self.current.take()));
+    }
+
+    pub fn pop_scope(&mut self) {
+        let scope = self.current.take().expect("a local scope");
+        self.current = scope.borrow().enclosing.clone();
+    }
+
+    // capture: The current scope chain, for a closure to hold on to.
+    pub fn capture(&self) -> Option<ScopeRef> {
+        self.current.clone()
+    }
+
+    // begin_call: Set the caller's scopes aside and start a function body with a scope
+    // holding its arguments, inside the scope chain the function closed over.
+    pub fn begin_call(&mut self, closure: Option<ScopeRef>, args: Vec<Value>) -> CallerScopes {
+        let caller = std::mem::replace(&mut self.current, closure);
+        self.push_scope(args);
+        CallerScopes(caller)
+    }
+
+    // resume: Set the caller's scopes aside and carry on in `scope`, the scope chain a
+    // generator paused in.
+    pub fn resume(&mut self, scope: ScopeRef) -> CallerScopes {
+        CallerScopes(self.current.replace(scope))
+    }
+
+    // end_call: Restore the scopes saved by begin_call or resume.
+    pub fn end_call(&mut self, caller: CallerScopes) {
+        self.current = caller.0;
+    }
+
+    // define_local: Set local `index` of the innermost scope, growing the scope if this is
+    // the first time that local has been declared.
+    pub fn define_local(&mut self, index: usize, value: Value) {
+        let mut scope = self.current.as_ref().expect("a local scope").borrow_mut();
+        if index >= scope.values.len() {
+            scope.values.resize(index + 1, Value::Nil);
+        }
+        scope.values[index] = value;
+    }
+
+    pub fn get_local(&self, slot: Slot) -> Value {
+        self.ancestor(slot.depth).borrow().values[slot.index].clone()
+    }
+
+    // find_local: Like get_local, but None rather than a panic if there's nothing at `slot`,
+    // e.g. for a debugger looking at a scope the resolver's view of which may be out of date.
+    pub fn find_local(&self, slot: Slot) -> Option<Value> {
+        let mut scope = self.current.clone()?;
+        for _ in 0..slot.depth {
+            let enclosing = scope.borrow().enclosing.clone()?;
+            scope = enclosing;
+        }
+        let value = scope.borrow().values.get(slot.index).cloned();
+        value
+    }
+
+    pub fn assign_local(&mut self, slot: Slot, value: Value) {
+        self.ancestor(slot.depth).borrow_mut().values[slot.index] = value;
+    }
+
+    // ancestor: The scope `depth` steps out from the innermost one.
+    fn ancestor(&self, depth: usize) -> ScopeRef {
+        let mut scope = self.current.clone().expect("a local scope");
+        for _ in 0..depth {
+            let enclosing = scope.borrow().enclosing.clone();
+            scope = enclosing.expect("an enclosing scope");
+        }
+        scope
+    }
+
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        self.globals.insert(name.to_string(), value);
+    }
+
+    // global: The global called `name`, if there is one.
+    pub fn global(&self, name: &str) -> Option<&Value> {
+        self.globals.get(name)
+    }
+
+    pub fn global_names(&self) -> impl Iterator<Item = &str> {
+        self.globals.keys().map(String::as_str)
+    }
+
+    pub fn get_global(&self, name: &Token) -> Result<Value, LoxError> {
+        let key = name_of(name);
+        match self.globals.get(key) {
+            Some(value) => Ok(value.clone()),
+            None => Err(self.undefined(name, key)),
+        }
+    }
+
+    // assign_global: Update an existing global. Assigning to a name that was never defined is
+    // an error; only `var` creates globals.
+    pub fn assign_global(&mut self, name: &Token, value: Value) -> Result<(), LoxError> {
+        let key = name_of(name);
+        match self.globals.get_mut(key) {
+            Some(slot) => {
+                *slot = value;
+                Ok(())
+            }
+            None => Err(self.undefined(name, key)),
+        }
+    }
+
+    // undefined: The error for using `key`, which isn't a global, suggesting the global most
+    // like it.
+    fn undefined(&self, name: &Token, key: &str) -> LoxError {
+        let err = lox_error!(LX0301, "[line {}] Undefined variable '{}'", name.line, key);
+        suggest::did_you_mean(err, key, self.global_names())
+    }
+}
+
+fn name_of(token: &Token) -> &str {
+    match &token.typ {
+        TokenType::Identifier(name) => name,
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::environment::Environment;
+    use crate::resolver::Slot;
+    use crate::scanner::{Token, TokenType};
+    use crate::value::Value;
+
+    fn ident(name: &str) -> Token {
+        Token::new(TokenType::Identifier(name.to_string()), 1)
+    }
+
+    fn slot(depth: usize, index: usize) -> Slot {
+        Slot { depth, index }
+    }
+
+    #[test]
+    fn define_and_get_global() {
+        let mut env = Environment::new();
+        env.define_global("a", Value::Number(1.0));
+        assert_eq!(env.get_global(&ident("a")).unwrap(), Value::Number(1.0));
+    }
+
+    #[test]
+    fn get_undefined() {
+        let env = Environment::new();
+        let err = env.get_global(&ident("nope")).unwrap_err();
+        assert!(format!("{}", err).contains("Undefined variable 'nope'"));
+    }
+
+    #[test]
+    fn undefined_globals_suggest_the_nearest() {
+        let mut env = Environment::new();
+        env.define_global("counter", Value::Nil);
+        let err = env.assign_global(&ident("countr"), Value::Nil).unwrap_err();
+        assert_eq!(
+            format!("{}", err),
+            "[line 1] Undefined variable 'countr'; did you mean 'counter'?"
+        );
+    }
+
+    #[test]
+    fn assign_global_needs_definition() {
+        let mut env = Environment::new();
+        env.define_global("a", Value::Nil);
+        env.assign_global(&ident("a"), Value::Number(3.0)).unwrap();
+        assert_eq!(env.get_global(&ident("a")).unwrap(), Value::Number(3.0));
+        assert!(env.assign_global(&ident("b"), Value::Nil).is_err());
+    }
+
+    #[test]
+    fn locals_are_addressed_by_slot() {
+        let mut env = Environment::new();
+        env.push_scope(vec![Value::Number(1.0)]);
+        env.define_local(1, Value::Number(2.0));
+        env.push_scope(Vec::new());
+        env.define_local(0, Value::Number(3.0));
+        assert_eq!(env.get_local(slot(1, 1)), Value::Number(2.0));
+        assert_eq!(env.get_local(slot(0, 0)), Value::Number(3.0));
+        env.assign_local(slot(1, 0), Value::Nil);
+        env.pop_scope();
+        assert_eq!(env.get_local(slot(0, 0)), Value::Nil);
+    }
+
+    #[test]
+    fn calls_run_inside_their_closure() {
+        let mut env = Environment::new();
+        env.push_scope(vec![Value::Number(1.0)]);
+        let closure = env.capture();
+        env.push_scope(vec![Value::Number(2.0)]);
+        let caller = env.begin_call(closure, vec![Value::Number(3.0)]);
+        assert_eq!(env.get_local(slot(0, 0)), Value::Number(3.0));
+        assert_eq!(env.get_local(slot(1, 0)), Value::Number(1.0));
+        env.assign_local(slot(1, 0), Value::Nil);
+        env.end_call(caller);
+        assert_eq!(env.get_local(slot(0, 0)), Value::Number(2.0));
+        assert_eq!(env.get_local(slot(1, 0)), Value::Nil);
+    }
+}
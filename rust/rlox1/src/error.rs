@@ -1,40 +1,662 @@
+use std::backtrace::Backtrace;
+use std::env;
+use std::error::Error;
 use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+use crate::codes::{self, Template};
+use crate::diagnostic::{Level, Renderer};
+
+// The process exit statuses the book's interpreters use, from BSD's sysexits.h: EX_DATAERR
+// when a program can't be run at all, e.g. for a syntax error, and EX_SOFTWARE when it fails
+// while running.
+pub const EX_DATAERR: i32 = 65;
+pub const EX_SOFTWARE: i32 = 70;
+
+// ErrorKind: Where an error came from, so that what to do about it can depend on that rather
+// than on its message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    // Scanning the source: a stray character, or a string without its end-quote.
+    Scan,
+    // Parsing, and the checks the resolver and compilers make on what was parsed.
+    Parse,
+    // Running the program, on whichever engine.
+    Runtime,
+    // Reading or writing a file or stream.
+    Io,
+    // Anything else, such as how rlox1 was asked to run a script, or a limit it was given.
+    Other,
+    // A bug in rlox1 itself, such as a panic, rather than in the program it was running.
+    Internal,
+}
+
+// Recovery: What the REPL does after an error. Most leave the session as the entry found it,
+// or as far as the entry got, so what earlier entries defined is still there to use; but a
+// bug in rlox1 may have stopped an engine part way through a call, with its state half
+// changed, and exit() is meant to end the REPL.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Recovery {
+    // Carry on in the same session.
+    Continue,
+    // Start a fresh session, as the one there was can't be trusted.
+    Reset,
+    // Stop the REPL.
+    Stop,
+}
 
 #[derive(Debug, Clone)]
 pub struct LoxError {
     message: String,
+    exit_code: Option<i32>,
+    kind: ErrorKind,
+    // The template of the code the message was made from, if it has one.
+    template: Option<Template>,
+    // The frames a runtime error has unwound out of, innermost first.
+    trace: Vec<Frame>,
+    // Where execution was in the next frame out: the line of the call being unwound.
+    caller_line: Option<usize>,
+    // What in the source the error is about, if that is known, and the errors found with it.
+    // It is boxed, as errors travel back up through every call in the interpreter and are
+    // best kept small.
+    details: Option<Box<Details>>,
+}
+
+// Details: What else is known about an error: where it is, as far as that is known, with the
+// file it came from, the span in it, and the line of source the span is on; the text in the
+// holes of its template, if it was made from one, so that it can be translated; the errors found
+// along with it, in the order they were found, e.g. the rest of a program's syntax errors;
+// what was being done when it happened, innermost first; a hint at how to put it right, if
+// there is one; the error from outside Lox that caused it, if any, such as an io::Error; and
+// where in rlox1 it was made, if RLOX_BACKTRACE asked for that.
+#[derive(Debug, Clone, Default)]
+struct Details {
+    file: Option<String>,
+    span: Option<Span>,
+    text: Option<String>,
+    holes: Vec<String>,
+    others: Vec<LoxError>,
+    context: Vec<String>,
+    help: Option<String>,
+    cause: Option<Arc<dyn Error + Send + Sync>>,
+    backtrace: Option<Arc<Backtrace>>,
+}
+
+// Span: A stretch of a line of source: the line, the column it starts at, both counting from
+// 1, and how many characters it covers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+}
+
+// Frame: A line of a stack trace, in the format clox uses: where execution was in a function,
+// or in the top-level script if `function` is None. A recursion that fails deep down would
+// leave a frame per call, all the same, so they are kept as one, with how many more there were.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub line: usize,
+    pub function: Option<String>,
+    pub repeated: usize,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.function {
+            Some(function) => write!(f, "[line {}] in {}()", self.line, function),
+            None => write!(f, "[line {}] in script", self.line),
+        }?;
+        match self.repeated {
+            0 => Ok(()),
+            1 => write!(f, "\n... previous frame repeated once"),
+            n => write!(f, "\n... previous frame repeated {} times", n),
+        }
+    }
 }
 
 impl LoxError {
+    // new: An error saying `message`. With RLOX_BACKTRACE set, it also captures the Rust
+    // backtrace of where it was made, which is shown after it, for finding bugs in rlox1
+    // itself rather than in the script.
     pub fn new(message: &str) -> LoxError {
+        let details = backtraces().then(|| {
+            Box::new(Details {
+                backtrace: Some(Arc::new(Backtrace::force_capture())),
+                ..Default::default()
+            })
+        });
         LoxError {
             message: message.to_string(),
+            exit_code: None,
+            kind: ErrorKind::Other,
+            template: None,
+            trace: Vec::new(),
+            caller_line: None,
+            details,
         }
     }
+
+    // located: An error saying `message`, at `location`, e.g. "[line 3] Error at 'x': ",
+    // which keeps the template the message was made from and the text in its holes.
+    pub fn located(location: &str, message: Message) -> LoxError {
+        let mut err = LoxError::new(&format!("{}{}", location, message));
+        if message.template.is_some() {
+            // A format's LINE is where the error is, rather than one of the holes.
+            let line = message.format.starts_with(codes::LINE) as usize;
+            let holes = message.args.iter().skip(line).map(ToString::to_string).collect();
+            err.details.get_or_insert_with(Default::default).holes = holes;
+            err.template = message.template;
+        }
+        err
+    }
+
+    // exit: Not a failure, but a request from the script to stop with the given process exit
+    // code. It travels as an error so it unwinds everything between `exit()` and `main`.
+    pub fn exit(code: i32) -> LoxError {
+        LoxError {
+            message: format!("Exit with code {}", code),
+            exit_code: Some(code),
+            kind: ErrorKind::Other,
+            template: None,
+            trace: Vec::new(),
+            caller_line: None,
+            details: None,
+        }
+    }
+
+    // combine: The errors, as one error to report them all, or None if there aren't any.
+    pub fn combine(errors: Vec<LoxError>) -> Option<LoxError> {
+        let mut errors = errors.into_iter();
+        let mut first = errors.next()?;
+        for mut err in errors {
+            let nested = err.details.as_mut().map(|details| std::mem::take(&mut details.others));
+            let others = &mut first.details.get_or_insert_with(Default::default).others;
+            others.push(err);
+            others.extend(nested.into_iter().flatten());
+        }
+        Some(first)
+    }
+
+    // into_errors: This error and the ones found along with it, apart again.
+    pub fn into_errors(mut self) -> Vec<LoxError> {
+        let others = self.details.as_mut().map(|details| std::mem::take(&mut details.others));
+        std::iter::once(self).chain(others.into_iter().flatten()).collect()
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    // reworded: The same error, with everything else about it kept, but saying `message`,
+    // which must say the same thing, e.g. with the line added, or in another language, as
+    // the error keeps its code.
+    pub fn reworded(mut self, message: &str) -> Self {
+        self.message = message.to_string();
+        self
+    }
+
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    // code: The error's stable code, e.g. "LX0301", if it has one; see codes.rs.
+    pub fn code(&self) -> Option<&'static str> {
+        self.template.map(|template| template.code().code)
+    }
+
+    // template: The template of the code the message was made from, if it has one.
+    pub fn template(&self) -> Option<Template> {
+        self.template
+    }
+
+    // holes: The text in each hole of the error's template, in order.
+    pub fn holes(&self) -> &[String] {
+        self.details.as_ref().map_or(&[], |details| &details.holes)
+    }
+
+    // of_kind: Say where the error came from. The scanner, parser, resolver and compilers
+    // mark the errors they report.
+    pub fn of_kind(mut self, kind: ErrorKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    // at_runtime: Mark the error as having stopped a running program, whatever it was to
+    // start with. Each engine marks the errors that escape the program it runs.
+    pub fn at_runtime(self) -> Self {
+        self.of_kind(ErrorKind::Runtime)
+    }
+
+    // status: The status to exit the process with: the code passed to exit(), or the sysexits
+    // status for the kind of error.
+    pub fn status(&self) -> i32 {
+        match (self.exit_code, self.kind) {
+            (Some(code), _) => code,
+            (None, ErrorKind::Runtime) | (None, ErrorKind::Internal) => EX_SOFTWARE,
+            (None, _) => EX_DATAERR,
+        }
+    }
+
+    // recovery: What the REPL should do after the error: carry on after errors in the program,
+    // whether found before it ran or while it ran, start again after a bug in rlox1, and stop
+    // for exit().
+    pub fn recovery(&self) -> Recovery {
+        match (self.exit_code, self.kind) {
+            (Some(_), _) => Recovery::Stop,
+            (None, ErrorKind::Internal) => Recovery::Reset,
+            (None, _) => Recovery::Continue,
+        }
+    }
+
+    // line: The line a runtime error happened on, from its "[line N]" prefix.
+    pub fn line(&self) -> Option<usize> {
+        let rest = self.message.strip_prefix("[line ")?;
+        rest[..rest.find(']')?].parse().ok()
+    }
+
+    // caused_by: Note the error that caused this one, which source() gives back, so that code
+    // embedding rlox1 can downcast it and look inside.
+    pub fn caused_by(mut self, cause: impl Error + Send + Sync + 'static) -> Self {
+        self.details.get_or_insert_with(Default::default).cause = Some(Arc::new(cause));
+        self
+    }
+
+    // context: Note what was being done when the error happened, e.g. "while reading a.lox",
+    // so that an error from deep down, which only knows what went wrong, also says what it
+    // went wrong in. Each caller on the way out can add its own; they are shown after the
+    // message, innermost first. The message itself is kept, so the error's code still is.
+    pub fn context(mut self, context: &str) -> Self {
+        if self.exit_code.is_none() {
+            let details = self.details.get_or_insert_with(Default::default);
+            details.context.push(context.to_string());
+        }
+        self
+    }
+
+    // contexts: What was being done when the error happened, innermost first.
+    pub fn contexts(&self) -> &[String] {
+        self.details.as_ref().map_or(&[], |details| &details.context)
+    }
+
+    // help: Add a hint at how to put the error right, e.g. "did you mean 'counter'?", which
+    // is shown after the message and context.
+    pub fn help(mut self, help: &str) -> Self {
+        self.details.get_or_insert_with(Default::default).help = Some(help.to_string());
+        self
+    }
+
+    // at: Point the error at `span`, if there is one, unless it already points at something
+    // more precise.
+    pub fn at(mut self, span: Option<Span>) -> Self {
+        if let (None, Some(span)) = (self.exit_code, span) {
+            let details = self.details.get_or_insert_with(Default::default);
+            details.span = details.span.or(Some(span));
+        }
+        self
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.details.as_ref().and_then(|details| details.span)
+    }
+
+    // file: The script the error came from, once in_source has said.
+    pub fn file(&self) -> Option<&str> {
+        self.details.as_ref()?.file.as_deref()
+    }
+
+    // snippet: The error's span, with the file and the line of it the span is on, once
+    // in_source has said what they are.
+    pub fn snippet(&self) -> Option<(Span, &str, &str)> {
+        let details = self.details.as_deref()?;
+        match details {
+            Details {
+                file: Some(file),
+                span: Some(span),
+                text: Some(text),
+                ..
+            } => Some((*span, file, text)),
+            _ => None,
+        }
+    }
+
+    // backtrace: Where in rlox1 the error was made, if RLOX_BACKTRACE is set.
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        self.details.as_ref()?.backtrace.as_deref()
+    }
+
+    // trace: The frames the error has unwound out of, innermost first.
+    pub fn trace(&self) -> &[Frame] {
+        &self.trace
+    }
+
+    // in_source: Note that the error came from running `source`, read from `file`, so that
+    // it can say which file and show the line it points at.
+    pub fn in_source(mut self, file: &str, source: &str) -> Self {
+        if self.exit_code.is_some() {
+            return self;
+        }
+        let details = self.details.get_or_insert_with(Default::default);
+        details.others = std::mem::take(&mut details.others)
+            .into_iter()
+            .map(|err| err.in_source(file, source))
+            .collect();
+        if details.file.is_none() {
+            details.file = Some(file.to_string());
+            details.text = details
+                .span
+                .and_then(|span| source.lines().nth(span.line.wrapping_sub(1)))
+                .map(String::from);
+        }
+        self
+    }
+
+    // unwind_from: Add the frame the error is leaving to its trace: a call to `function`
+    // made on `call_line`, or the script when `function` is None. Engines call this as the
+    // error passes out of each frame, innermost first.
+    pub fn unwind_from(mut self, function: Option<&str>, call_line: Option<usize>) -> Self {
+        if self.exit_code.is_some() {
+            return self;
+        }
+        if let Some(line) = self.caller_line.or_else(|| self.line()) {
+            match self.trace.last_mut() {
+                Some(last) if last.line == line && last.function.as_deref() == function => {
+                    last.repeated += 1;
+                }
+                _ => self.trace.push(Frame {
+                    line,
+                    function: function.map(String::from),
+                    repeated: 0,
+                }),
+            }
+        }
+        self.caller_line = call_line;
+        self
+    }
 }
 
+// The message is followed by the error's context, if it has any, e.g. "No such file or
+// directory (os error 2), while reading a.lox.", and then by its help, if it has any, in
+// place of the full stop: "Undefined variable 'countr'; did you mean 'counter'?" The
+// alternate form (`{:#}`) follows that with the line of source it is about, if that is
+// known, and the stack trace: the body of the error's diagnostic, without color.
 impl fmt::Display for LoxError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}.", self.message)
+        if f.alternate() {
+            return write!(f, "{}", Renderer::new(false).body(Level::Error, self));
+        }
+        write!(f, "{}", self.message)?;
+        for context in self.contexts() {
+            write!(f, ", {}", context)?;
+        }
+        match self.details.as_ref().and_then(|details| details.help.as_ref()) {
+            Some(help) => write!(f, "; {}", help),
+            None => write!(f, "."),
+        }
     }
 }
 
-impl std::error::Error for LoxError {}
+impl Error for LoxError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        let cause = self.details.as_ref()?.cause.as_ref()?;
+        Some(cause.as_ref())
+    }
+}
+
+impl<'a> From<Message<'a>> for LoxError {
+    fn from(message: Message<'a>) -> Self {
+        LoxError::located("", message)
+    }
+}
 
 impl From<std::io::Error> for LoxError {
     fn from(other: std::io::Error) -> Self {
-        LoxError {
-            message: format!("{}", other),
+        LoxError::new(&format!("{}", other))
+            .of_kind(ErrorKind::Io)
+            .caused_by(other)
+    }
+}
+
+// Message: What a diagnostic says, before it is made into an error: `format`, with each `{}`
+// in it standing for the next of `args`, and the template of the code it has, which the format
+// is, if it has one. Other braces are kept as they are, as messages quote them, e.g. "Expect
+// '{'". message! makes one, which the parser, resolver or compiler then say where it is.
+#[derive(Clone, Copy)]
+pub struct Message<'a> {
+    template: Option<Template>,
+    format: &'a str,
+    args: &'a [&'a dyn fmt::Display],
+}
+
+impl<'a> Message<'a> {
+    pub fn new(
+        template: Option<Template>,
+        format: &'a str,
+        args: &'a [&'a dyn fmt::Display],
+    ) -> Self {
+        Message {
+            template,
+            format,
+            args,
+        }
+    }
+}
+
+impl fmt::Display for Message<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut args = self.args.iter();
+        let mut pieces = self.format.split("{}");
+        write!(f, "{}", pieces.next().unwrap_or(""))?;
+        for piece in pieces {
+            match args.next() {
+                Some(arg) => write!(f, "{}{}", arg, piece)?,
+                None => write!(f, "{{}}{}", piece)?,
+            }
         }
+        Ok(())
     }
 }
 
+// backtraces: Whether errors should capture backtraces: whether RLOX_BACKTRACE is set, and not
+// to "0", as for RUST_BACKTRACE. It is only read once, as errors are made often.
+fn backtraces() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| {
+        env::var_os("RLOX_BACKTRACE").is_some_and(|value| !value.is_empty() && value != "0")
+    })
+}
+
+// loxerr: Return early with a LoxError built from a format string. The error is converted
+// with `into`, so the macro also works in functions whose error type wraps LoxError. A
+// diagnostic with a code names it first, and its format must then be one of the code's
+// templates, perhaps after "[line {}] ", with only `{}` for the text that varies:
+//
+//   loxerr!(LX0301, "[line {}] Undefined variable '{}'", line, name)
 #[macro_export]
 macro_rules! loxerr {
+    ( $code:ident, $format:literal $( , $args:expr )* ) => {
+        return Err($crate::lox_error!($code, $format $( , $args )*).into())
+    };
     ( $e:expr ) => {
-        return Err(LoxError::new(&format!("{}", $e)))
+        return Err(LoxError::new(&format!("{}", $e)).into())
     };
     ( $fmt:expr, $( $params:expr ),+ ) => {
-        return Err(LoxError::new(&format!($fmt, $( $params ),+ )))
+        return Err(LoxError::new(&format!($fmt, $( $params ),+ )).into())
+    };
+}
+
+// lox_error: The LoxError loxerr! returns for a diagnostic with a code, without returning.
+#[macro_export]
+macro_rules! lox_error {
+    ( $code:ident, $format:literal $( , $args:expr )* ) => {
+        $crate::error::LoxError::from($crate::message!($code, $format $( , $args )*))
     };
 }
+
+// message: The Message for a diagnostic with a code, checked as loxerr! checks it, for the
+// parser, resolver and compiler to say where it is.
+#[macro_export]
+macro_rules! message {
+    ( $code:ident, $format:literal $( , $args:expr )* ) => {
+        $crate::error::Message::new(
+            {
+                const TEMPLATE: $crate::codes::Template =
+                    $crate::codes::Template::of(&$crate::codes::$code, $format);
+                Some(TEMPLATE)
+            },
+            $format,
+            &[ $( &$args as &dyn std::fmt::Display ),* ],
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{ErrorKind, LoxError, Recovery, Span, EX_DATAERR, EX_SOFTWARE};
+
+    #[test]
+    fn line_comes_from_the_message() {
+        assert_eq!(LoxError::new("[line 12] Bad thing").line(), Some(12));
+        assert_eq!(LoxError::new("Bad thing").line(), None);
+        assert_eq!(LoxError::new("[line x] Bad thing").line(), None);
+    }
+
+    #[test]
+    fn errors_keep_the_text_of_their_holes() {
+        let err = lox_error!(LX0301, "[line {}] Undefined variable '{}'", 2, "x");
+        assert_eq!(err.holes(), ["x"]);
+        assert_eq!(err.template().map(|t| t.index()), Some(0));
+        assert!(LoxError::new("[line 2] Bad thing").holes().is_empty());
+    }
+
+    #[test]
+    fn traces_show_in_the_alternate_form() {
+        let err = LoxError::new("[line 3] Bad thing")
+            .unwind_from(Some("inner"), Some(7))
+            .unwind_from(Some("outer"), Some(9))
+            .unwind_from(None, None);
+        assert_eq!(format!("{}", err), "[line 3] Bad thing.");
+        assert_eq!(
+            format!("{:#}", err),
+            "[line 3] Bad thing.\n[line 3] in inner()\n[line 7] in outer()\n[line 9] in script"
+        );
+        let mut err = LoxError::new("[line 2] Stack overflow").unwind_from(Some("f"), Some(2));
+        for _ in 0..996 {
+            err = err.unwind_from(Some("f"), Some(2));
+        }
+        let err = err.unwind_from(Some("f"), Some(5)).unwind_from(None, None);
+        assert_eq!(err.trace().len(), 2);
+        assert_eq!(
+            format!("{:#}", err),
+            "[line 2] Stack overflow.\n[line 2] in f()\n... previous frame repeated 997 times\n\
+             [line 5] in script"
+        );
+        let exit = LoxError::exit(1).unwind_from(None, None);
+        assert_eq!(format!("{:#}", exit), "Exit with code 1.");
+    }
+
+    #[test]
+    fn snippets_underline_the_span() {
+        let span = Span {
+            line: 10,
+            column: 7,
+            len: 3,
+        };
+        let err = LoxError::new("[line 10] Bad thing")
+            .at(Some(span))
+            .in_source("a.lox", &("x\n".repeat(9) + "print\tfoo;"))
+            .unwind_from(None, None);
+        assert_eq!(format!("{}", err), "[line 10] Bad thing.");
+        assert_eq!(
+            format!("{:#}", err),
+            "[line 10] Bad thing.\n  --> a.lox:10:7\n   |\n10 | print foo;\n   |       ^^^\n\
+             [line 10] in script"
+        );
+        // The first span an error is given is the one it keeps.
+        let other = Span { column: 1, ..span };
+        assert_eq!(err.at(Some(other)).span(), Some(span));
+        // Without the source, there's nothing to quote.
+        let err = LoxError::new("[line 1] Bad thing").at(Some(other));
+        assert_eq!(format!("{:#}", err), "[line 1] Bad thing.");
+    }
+
+    #[test]
+    fn combined_errors_keep_their_order() {
+        assert!(LoxError::combine(Vec::new()).is_none());
+        let span = Span {
+            line: 2,
+            column: 1,
+            len: 1,
+        };
+        let first = LoxError::combine(vec![LoxError::new("a"), LoxError::new("b")]).unwrap();
+        let err = LoxError::combine(vec![first, LoxError::new("c").at(Some(span))])
+            .unwrap()
+            .in_source("a.lox", "x\ny");
+        let errors = err.into_errors();
+        let messages: Vec<_> = errors.iter().map(LoxError::message).collect();
+        assert_eq!(messages, vec!["a", "b", "c"]);
+        // Each of them is shown with its own line of source.
+        let last = errors.last().unwrap();
+        assert_eq!(last.snippet(), Some((span, "a.lox", "y")));
+    }
+
+    #[test]
+    fn causes_can_be_downcast() {
+        use std::error::Error;
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "gone");
+        let err = LoxError::from(io);
+        let cause = err.source().and_then(|cause| cause.downcast_ref::<std::io::Error>());
+        assert_eq!(cause.map(std::io::Error::kind), Some(std::io::ErrorKind::NotFound));
+        // The cause may itself be a LoxError with its own, and survives being combined.
+        let outer = LoxError::new("Can't load a.loxc").caused_by(err);
+        let inner = outer.source().and_then(|cause| cause.downcast_ref::<LoxError>());
+        assert!(inner.and_then(Error::source).is_some());
+        let combined = LoxError::combine(vec![outer.clone(), LoxError::new("b")]).unwrap();
+        assert!(combined.source().is_some());
+        assert!(LoxError::new("a").source().is_none());
+    }
+
+    #[test]
+    fn context_follows_the_message() {
+        let err = LoxError::new("[line 4] A number was expected but not found")
+            .context("while parsing arguments to f")
+            .context("while loading a.lox");
+        assert_eq!(
+            format!("{}", err),
+            "[line 4] A number was expected but not found, while parsing arguments to f, \
+             while loading a.lox."
+        );
+        // The message is unchanged, and so is what it says about the error.
+        assert_eq!(err.message(), "[line 4] A number was expected but not found");
+        assert_eq!(err.line(), Some(4));
+        // Exit requests aren't errors, and have no context.
+        let exit = LoxError::exit(2).context("while running");
+        assert_eq!(format!("{}", exit), "Exit with code 2.");
+    }
+
+    #[test]
+    fn status_depends_on_when_the_error_happened() {
+        assert_eq!(LoxError::new("Expect ';'").status(), EX_DATAERR);
+        assert_eq!(
+            LoxError::new("Bad thing").at_runtime().status(),
+            EX_SOFTWARE
+        );
+        assert_eq!(LoxError::exit(3).at_runtime().status(), 3);
+    }
+
+    #[test]
+    fn only_bugs_in_rlox1_and_exits_end_a_session() {
+        let recovery = |err: LoxError| err.recovery();
+        assert_eq!(recovery(LoxError::new("Expect ';'")), Recovery::Continue);
+        assert_eq!(recovery(LoxError::new("Oops").at_runtime()), Recovery::Continue);
+        assert_eq!(
+            recovery(LoxError::new("Oops").of_kind(ErrorKind::Internal)),
+            Recovery::Reset
+        );
+        assert_eq!(recovery(LoxError::exit(0).at_runtime()), Recovery::Stop);
+    }
+}
@@ -1,97 +1,1076 @@
+use std::collections::HashSet;
+use std::fmt;
 use std::fs::{self, File};
-use std::io::prelude::*;
-use std::io::{self, BufReader};
+use std::io::{self, BufReader, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::time::Instant;
 
-use crate::error::LoxError;
+use crate::ast::{Stmt, StmtKind};
+use crate::bench::{self, Timings};
+use crate::chunk::Chunk;
+use crate::compiler::Compiler;
+use crate::complete::complete;
+use crate::debugger::Debugger;
+use crate::diagnostic::{self, Diagnostics, Level, Renderer};
+use crate::error::{ErrorKind, LoxError, Recovery};
+use crate::format;
+use crate::harness;
+use crate::highlight::highlight;
+use crate::interpreter::{Interpreter, DEFAULT_MAX_CALL_DEPTH};
+use crate::interrupt;
+use crate::lineedit::{Input, LineEditor};
+use crate::log::{self, Verbosity};
+use crate::loxc;
+use crate::memory;
+use crate::parser::Parser;
+use crate::profile::Profile;
+use crate::register::{RegisterCompiler, RegisterVm};
+use crate::resolver::Resolver;
 use crate::scanner::*;
+use crate::stdlib;
+use crate::vm::Vm;
 
-const MAX_SOURCE_FILE_SIZE: u64 = 65535;
+// The limits a sandboxed script runs under.
+pub const SANDBOX_MAX_MEMORY: usize = 64 * 1024 * 1024;
+pub const SANDBOX_MAX_CALL_DEPTH: usize = 200;
 
-pub struct Executor;
+// How much of a script to look through for a NUL byte, which means a binary file, as git does.
+const BINARY_SNIFF_BYTES: usize = 8000;
+
+// U+FEFF in UTF-8, the byte order mark a script may start with.
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+// What errors in what is typed at the REPL call where it came from, as stdin is "<stdin>".
+const REPL: &str = "<repl>";
+
+// Engine: What runs programs once they are parsed: the tree-walking interpreter from Part II
+// of the book, the bytecode VM from Part III, or an experimental register VM.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Engine {
+    TreeWalk,
+    Vm,
+    Register,
+}
+
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Engine::TreeWalk => write!(f, "treewalk"),
+            Engine::Vm => write!(f, "vm"),
+            Engine::Register => write!(f, "register"),
+        }
+    }
+}
+
+pub struct Executor {
+    stdlib_options: stdlib::Options,
+    max_call_depth: usize,
+    engine: Engine,
+    profile: bool,
+    // What to print to stderr while running, for debugging rlox1 or a script.
+    dump_tokens: bool,
+    dump_ast: bool,
+    trace: bool,
+    trace_exec: bool,
+    // Whether the REPL highlights what is typed.
+    color: bool,
+    // How the REPL reports errors, which don't stop it.
+    diagnostics: Renderer,
+    // The largest script to accept, in bytes, if there is a limit.
+    max_source_bytes: Option<u64>,
+    sandbox: bool,
+    // Whether a script stops at its first runtime error, rather than carrying on with the
+    // next top-level statement.
+    fail_fast: bool,
+    // Whether scripts run together each get their own globals.
+    isolate: bool,
+    // Whether to read scripts that don't look like Lox, without a warning or an error.
+    force: bool,
+    // Whether warnings are errors, which stop a script before it runs.
+    deny_warnings: bool,
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Executor::new()
+    }
+}
 
 impl Executor {
     pub fn new() -> Self {
-        Executor {}
+        Executor {
+            stdlib_options: stdlib::Options::default(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            engine: Engine::TreeWalk,
+            profile: false,
+            dump_tokens: false,
+            dump_ast: false,
+            trace: false,
+            trace_exec: false,
+            color: true,
+            diagnostics: Renderer::new(false),
+            max_source_bytes: None,
+            sandbox: false,
+            fail_fast: false,
+            isolate: false,
+            force: false,
+            deny_warnings: false,
+        }
+    }
+
+    pub fn engine(mut self, engine: Engine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    // profile: Report what each program executed, and where the time went, once it stops.
+    pub fn profile(mut self, profile: bool) -> Self {
+        self.profile = profile;
+        self
     }
-    // display_prompt: Display a prompt and flush to stdout.
-    fn display_prompt(&self, prompt: &str) {
-        print!("{}", prompt);
-        io::stdout().flush().expect("Failed to write to stdout!");
+
+    // dump_tokens: Print the tokens of every program parsed, one per line.
+    pub fn dump_tokens(mut self, dump: bool) -> Self {
+        self.dump_tokens = dump;
+        self
     }
 
-    // read_file: Read lines from a file. Line termination is stripped.
+    // dump_ast: Print every program parsed, a statement per line.
+    pub fn dump_ast(mut self, dump: bool) -> Self {
+        self.dump_ast = dump;
+        self
+    }
+
+    // trace: Print each statement before the tree-walker executes it.
+    pub fn trace(mut self, trace: bool) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    // trace_exec: Print each statement with its line, and each call with its arguments and
+    // result, as the tree-walker executes them.
+    pub fn trace_exec(mut self, trace: bool) -> Self {
+        self.trace_exec = trace;
+        self
+    }
+
+    // color: Highlight REPL input, unless the NO_COLOR environment variable says not to.
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    // diagnostics: Lay out warnings, and the errors the REPL reports, with `renderer`.
+    pub fn diagnostics(mut self, renderer: Renderer) -> Self {
+        self.diagnostics = renderer;
+        self
+    }
+
+    // allow_io: Let scripts use the file natives from the standard library, and setenv().
+    pub fn allow_io(mut self, allow: bool) -> Self {
+        self.stdlib_options.allow_io = allow;
+        self
+    }
+
+    // print_natives: Whether print() and println() are defined alongside the print statement.
+    pub fn print_natives(mut self, define: bool) -> Self {
+        self.stdlib_options.print_natives = define;
+        self
+    }
+
+    // sandbox: Run scripts that can't be trusted: no file or environment natives, whatever
+    // else was allowed, and limits on memory and on how deeply calls nest.
+    pub fn sandbox(mut self, sandbox: bool) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    // script_args: The arguments a script sees in args() and argc.
+    pub fn script_args(mut self, args: Vec<String>) -> Self {
+        self.stdlib_options.args = args;
+        self
+    }
+
+    // max_call_depth: How deeply Lox calls may nest before a "Stack overflow" error.
+    pub fn max_call_depth(mut self, depth: usize) -> Self {
+        self.max_call_depth = depth;
+        self
+    }
+
+    // fail_fast: Stop a script at its first runtime error, as the book's interpreters do.
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    // isolate: Start each of several scripts run together from fresh globals, rather than
+    // letting each see what the ones before it defined.
+    pub fn isolate(mut self, isolate: bool) -> Self {
+        self.isolate = isolate;
+        self
+    }
+
+    // max_source_bytes: Refuse scripts bigger than this. There is no limit by default.
+    pub fn max_source_bytes(mut self, bytes: u64) -> Self {
+        self.max_source_bytes = Some(bytes);
+        self
+    }
+
+    // force: Read scripts that don't look like Lox: binary files, which are otherwise
+    // refused, and files without a .lox extension, which are otherwise warned about.
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    pub fn deny_warnings(mut self, deny_warnings: bool) -> Self {
+        self.deny_warnings = deny_warnings;
+        self
+    }
+
+    // read_file: Read a whole source file, newlines and all, so that multi-line constructs
+    // and comments work and errors report the right line.
     fn read_file(&self, filename: &str) -> Result<String, LoxError> {
+        // An io::Error doesn't say which file it is about.
+        let reading =
+            |err: io::Error| LoxError::from(err).context(&format!("while reading {}", filename));
         // Confirm the file isn't too big before opening.
-        let attr = fs::metadata(filename)?;
+        let attr = fs::metadata(filename).map_err(reading)?;
         if !attr.is_file() {
-            return Err(LoxError::new(&format!("Path {} is not a file.", filename)));
-        } else if attr.len() > MAX_SOURCE_FILE_SIZE {
-            return Err(LoxError::new(&format!(
-                "File {} is too large ({} > {}).",
+            loxerr!(LX0711, "Path {} is not a file", filename)
+        }
+        self.check_size(filename, attr.len())?;
+        trace!("read: {} bytes from {}", attr.len(), filename);
+        self.decode(filename, fs::read(filename).map_err(reading)?)
+    }
+
+    // read_script: Read a script from a file, warning if its name says it may not be Lox.
+    fn read_script(&self, filename: &str) -> Result<String, LoxError> {
+        let source = self.read_file(filename)?;
+        if !self.force && Path::new(filename).extension() != Some("lox".as_ref()) {
+            self.warn(lox_error!(
+                LX0704,
+                "{} doesn't end in .lox, so may not be a Lox script; use --force if it is",
+                filename
+            ))?;
+        }
+        Ok(source)
+    }
+
+    // decode: The text of a script, read as `bytes` from `filename`. A NUL byte near the
+    // start means a binary file, which is refused unless forced, as it won't be Lox; and the
+    // text must be UTF-8, or the error says where it stops being. A byte order mark, which
+    // some Windows editors start a file with, isn't part of the text, so it is left off.
+    fn decode(&self, filename: &str, mut bytes: Vec<u8>) -> Result<String, LoxError> {
+        if bytes.starts_with(UTF8_BOM) {
+            bytes.drain(..UTF8_BOM.len());
+        }
+        if !self.force && bytes.iter().take(BINARY_SNIFF_BYTES).any(|&byte| byte == 0) {
+            loxerr!(
+                LX0702,
+                "File {} looks like a binary file, not a Lox script; use --force to read it anyway",
+                filename
+            )
+        }
+        String::from_utf8(bytes).or_else(|err| {
+            let valid = &err.as_bytes()[..err.utf8_error().valid_up_to()];
+            let bad = err.as_bytes()[valid.len()];
+            // The text up to the bad byte is valid, so its lines and characters can be counted.
+            let valid = std::str::from_utf8(valid).expect("the valid prefix");
+            let line = valid.matches('\n').count() + 1;
+            let column = valid
+                .rsplit('\n')
+                .next()
+                .map_or(0, |text| text.chars().count())
+                + 1;
+            loxerr!(
+                LX0701,
+                "File {} is not UTF-8: there's a stray byte, 0x{}, at line {}, column {}",
                 filename,
-                attr.len(),
-                MAX_SOURCE_FILE_SIZE
-            )));
-        }
-        let f = File::open(filename)?;
-        let reader = BufReader::new(f);
-        let mut buffer = String::new();
-        //let mut lines = Vec::new();
-        for line in reader.lines() {
-            buffer.push_str(&line?);
-        }
-        Ok(buffer)
-    }
-
-    // run: Runs some Lox code. This is where the magic happens.
-    fn run(&self, buffer: String) -> Result<(), LoxError> {
-        let mut scanner_ = Scanner::new(&buffer);
-        let tokens = scanner_.scan_tokens()?;
-        eprintln!("{} tokens found.", tokens.len());
-        for token in tokens {
-            eprintln!("Token: {}", token);
-        }
-        if scanner_.errors_found() {
-            loxerr!("Errors found while parsing {}.", buffer)
+                format!("{:02x}", bad),
+                line,
+                column
+            )
+        })
+    }
+
+    // warn: Report something that may be wrong, but doesn't stop rlox1, unless warnings are
+    // denied, when it is an error instead.
+    fn warn(&self, warning: LoxError) -> Result<(), LoxError> {
+        if self.deny_warnings {
+            return Err(warning);
+        }
+        if log::enabled(Verbosity::Normal) {
+            eprintln!("{}", self.diagnostics.render(Level::Warning, &warning));
+        }
+        Ok(())
+    }
+
+    // lint: Resolve a program before it runs, for its static errors and the warnings about
+    // it, which are reported then. Denied warnings are all reported together, as errors.
+    fn lint(&self, statements: &[Stmt], filename: &str, source: &str) -> Result<(), LoxError> {
+        let started = Instant::now();
+        let warnings = Resolver::new().check(statements)?;
+        debug!(
+            "resolve: {} statements in {:?}",
+            statements.len(),
+            started.elapsed()
+        );
+        let mut denied = Diagnostics::new();
+        for warning in warnings {
+            if let Err(err) = self.warn(in_source(warning, filename, source)) {
+                denied.push(err);
+            }
+        }
+        denied.finish(())
+    }
+
+    // check_size: Make sure a script of `size` bytes is within the limit, if there is one.
+    fn check_size(&self, filename: &str, size: u64) -> Result<(), LoxError> {
+        match self.max_source_bytes {
+            Some(max) if size > max => loxerr!(
+                LX0703,
+                "File {} is too large ({} > {}); see --max-source-bytes",
+                filename,
+                size,
+                max
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    // parse: Scan and parse some Lox code.
+    fn parse(&self, buffer: &str) -> Result<Vec<Stmt>, LoxError> {
+        let started = Instant::now();
+        let tokens = Scanner::new(buffer).tokens()?;
+        debug!("scan: {} tokens in {:?}", tokens.len(), started.elapsed());
+        if self.dump_tokens {
+            for token in &tokens {
+                eprintln!("token: {}", token);
+            }
+        }
+        let started = Instant::now();
+        let statements = Parser::new(&tokens).parse()?;
+        debug!(
+            "parse: {} statements in {:?}",
+            statements.len(),
+            started.elapsed()
+        );
+        if self.dump_ast {
+            for stmt in &statements {
+                eprintln!("ast: {}", stmt);
+            }
+        }
+        Ok(statements)
+    }
+
+    // run: Runs some Lox code, from the script `filename`. This is where the magic happens.
+    fn run(&self, filename: &str, source: &str) -> Result<(), LoxError> {
+        let mut session = self.session()?;
+        let result = self.run_in(&mut session, filename, source);
+        report(session.take_profile());
+        result
+    }
+
+    // run_in: Run the script `filename` in `session`, with whatever globals it already has.
+    fn run_in(&self, session: &mut Session, filename: &str, source: &str) -> Result<(), LoxError> {
+        self.parse(source)
+            .and_then(|statements| {
+                self.lint(&statements, filename, source)?;
+                let started = Instant::now();
+                let result = if self.fail_fast {
+                    session.execute(&statements)
+                } else {
+                    session.execute_each(&statements)
+                };
+                debug!(
+                    "execute: {} on the {} engine in {:?}",
+                    display_name(filename),
+                    self.engine,
+                    started.elapsed()
+                );
+                result
+            })
+            .map_err(|err| in_source(err, filename, source))
+    }
+
+    // execute: Run parsed statements on the selected engine, starting from fresh globals.
+    fn execute(&self, statements: &[Stmt]) -> Result<(), LoxError> {
+        let mut session = self.session()?;
+        let result = session.execute(statements);
+        report(session.take_profile());
+        result
+    }
+
+    // session: The selected engine, with fresh globals.
+    fn session(&self) -> Result<Session, LoxError> {
+        if (self.trace || self.trace_exec) && self.engine != Engine::TreeWalk {
+            loxerr!(LX0610, "Only the tree-walker can {}; use --engine treewalk", "trace")
+        }
+        if self.sandbox && self.engine != Engine::TreeWalk {
+            loxerr!(
+                LX0610,
+                "Only the tree-walker can {}; use --engine treewalk",
+                "run in the sandbox"
+            )
+        }
+        if self.sandbox && memory::in_use().is_none() {
+            loxerr!(LX0611, "The sandbox can't limit memory until memory::set_meter says how to measure it")
+        }
+        Ok(match self.engine {
+            Engine::TreeWalk => Session::TreeWalk(Box::new(self.interpreter())),
+            Engine::Vm => Session::Vm(self.vm()),
+            Engine::Register if self.profile => {
+                loxerr!(LX0610, "The register VM can't be profiled; use --engine vm or treewalk")
+            }
+            Engine::Register => Session::Register(RegisterVm::new()),
+        })
+    }
+
+    // interpreter: A fresh tree-walking interpreter with the standard library, set up as asked.
+    fn interpreter(&self) -> Interpreter {
+        let mut interpreter = Interpreter::new();
+        let mut options = self.stdlib_options.clone();
+        if self.sandbox {
+            options.allow_io = false;
+            options.allow_env = false;
+            interpreter.set_max_memory(SANDBOX_MAX_MEMORY);
+            interpreter.set_max_call_depth(self.max_call_depth.min(SANDBOX_MAX_CALL_DEPTH));
+        } else {
+            interpreter.set_max_call_depth(self.max_call_depth);
+        }
+        stdlib::register(&mut interpreter, &options);
+        if self.profile {
+            interpreter.profile();
+        }
+        if self.trace {
+            interpreter.trace();
+        }
+        if self.trace_exec {
+            interpreter.trace_exec();
+        }
+        interpreter
+    }
+
+    // vm: A fresh VM, profiling if asked to.
+    fn vm(&self) -> Vm {
+        let mut vm = Vm::new();
+        if self.profile {
+            vm.profile();
+        }
+        vm
+    }
+
+    // run_files: Run scripts one after another, each as one program, e.g. a prelude and then
+    // the script that uses it. They share globals unless isolated. An error in one script
+    // doesn't stop the ones after it, unless failing fast or on exit(), and the errors of all
+    // of them are reported together. `-` is stdin.
+    // Compiled files are recognised by their extension, and always run on a VM of their own.
+    pub fn run_files(&self, filenames: &[&str]) -> Result<(), LoxError> {
+        let mut session = self.session()?;
+        let mut errors = Diagnostics::new();
+        for (i, &filename) in filenames.iter().enumerate() {
+            if self.isolate && i > 0 {
+                report(session.take_profile());
+                session = self.session()?;
+            }
+            let result = if Path::new(filename).extension() == Some(loxc::EXTENSION.as_ref()) {
+                self.run_compiled(filename)
+            } else {
+                self.source(filename)
+                    .and_then(|source| self.run_in(&mut session, filename, &source))
+            };
+            if let Err(err) = result {
+                let exit = err.exit_code().is_some();
+                if !exit || !errors.has_errors() {
+                    errors.push(err);
+                }
+                if exit || self.fail_fast {
+                    break;
+                }
+            }
+        }
+        report(session.take_profile());
+        errors.finish(())
+    }
+
+    // run_stdin: Read all of stdin and run it as one program, e.g. from a pipe or heredoc.
+    pub fn run_stdin(&self) -> Result<(), LoxError> {
+        let contents = self.source("-")?;
+        self.run("-", &contents)
+    }
+
+    // debug_file: Run a script under the debugger, which takes its commands from stdin.
+    pub fn debug_file(&self, filename: &str) -> Result<(), LoxError> {
+        if filename == "-" {
+            loxerr!(LX0906, "The debugger reads commands from stdin, so the script must be a file")
+        }
+        if self.engine != Engine::TreeWalk {
+            loxerr!(LX0610, "Only the tree-walker can {}; use --engine treewalk", "debug")
+        }
+        let source = self.read_script(filename)?;
+        let statements = self
+            .parse(&source)
+            .map_err(|err| in_source(err, filename, &source))?;
+        let mut interpreter = self.interpreter();
+        let input = Box::new(BufReader::new(io::stdin()));
+        interpreter.debug(Debugger::new(&source, input, Box::new(io::stdout())));
+        let result = interpreter.interpret(&statements);
+        report(interpreter.take_profile());
+        result.map_err(|err| in_source(err, filename, &source))
+    }
+
+    // source: The source of a script, or of stdin if the name is `-`.
+    fn source(&self, filename: &str) -> Result<String, LoxError> {
+        if filename != "-" {
+            return self.read_script(filename);
+        }
+        // Stdin's size isn't known until it has been read, so read no more than one byte past
+        // the limit.
+        let mut contents = Vec::new();
+        let limit = self.max_source_bytes.map_or(u64::MAX, |max| max + 1);
+        let size = io::stdin().take(limit).read_to_end(&mut contents)?;
+        self.check_size("stdin", size as u64)?;
+        self.decode("stdin", contents)
+    }
+
+    // tokenize_file: Print the tokens a script scans to, one per line.
+    pub fn tokenize_file(&self, filename: &str) -> Result<(), LoxError> {
+        let source = self.source(filename)?;
+        for token in Scanner::new(&source).scan_tokens()? {
+            println!("{}", token);
+        }
+        Ok(())
+    }
+
+    // parse_file: Print the syntax tree of a script, a statement per line.
+    pub fn parse_file(&self, filename: &str) -> Result<(), LoxError> {
+        for stmt in self.parse(&self.source(filename)?)? {
+            println!("{}", stmt);
+        }
+        Ok(())
+    }
+
+    // check_file: Report the errors a script has before it runs, without running it.
+    pub fn check_file(&self, filename: &str) -> Result<(), LoxError> {
+        let source = self.source(filename)?;
+        self.parse(&source)
+            .and_then(|statements| self.lint(&statements, filename, &source))
+            .map_err(|err| in_source(err, filename, &source))
+    }
+
+    // check_files: Check several scripts, and with `recursive`, every .lox file in the
+    // directories among them, however deep. Each script's errors are reported as it is
+    // checked, and then a line per script says how it did.
+    pub fn check_files(&self, paths: &[&str], recursive: bool) -> Result<(), LoxError> {
+        let check = |script: &str| self.check_file(script).map(|()| 0);
+        self.check_scripts(paths, recursive, "errors", check)
+    }
+
+    // lint_files: Like check_files, but the warnings about each script are reported and
+    // count against it as well, so that only scripts with nothing to warn about pass.
+    pub fn lint_files(&self, paths: &[&str], recursive: bool) -> Result<(), LoxError> {
+        let lint = |script: &str| self.lint_file(script);
+        self.check_scripts(paths, recursive, "errors or warnings", lint)
+    }
+
+    // lint_file: Report the errors and warnings a script has before it runs, giving back how
+    // many warnings there were.
+    fn lint_file(&self, filename: &str) -> Result<usize, LoxError> {
+        let source = self.source(filename)?;
+        let warnings = self
+            .parse(&source)
+            .and_then(|statements| Resolver::new().check(&statements))
+            .map_err(|err| in_source(err, filename, &source))?;
+        for warning in &warnings {
+            let warning = in_source(warning.clone(), filename, &source);
+            eprintln!("{}", self.diagnostics.render(Level::Warning, &warning));
+        }
+        Ok(warnings.len())
+    }
+
+    // check_scripts: Run `check` on the scripts at `paths`, which gives back how many
+    // warnings count against a script, then say how each did, and whether any had `what`.
+    fn check_scripts<F>(
+        &self,
+        paths: &[&str],
+        recursive: bool,
+        what: &str,
+        check: F,
+    ) -> Result<(), LoxError>
+    where
+        F: Fn(&str) -> Result<usize, LoxError>,
+    {
+        if let (false, Some(dir)) = (
+            recursive,
+            paths.iter().find(|path| Path::new(path).is_dir()),
+        ) {
+            loxerr!(
+                LX0711,
+                "{} is a directory; use --recursive to check the scripts in it",
+                dir
+            )
+        }
+        let scripts = harness::scripts(paths)?;
+        let mut summary = Vec::new();
+        let mut failed = 0;
+        for script in &scripts {
+            let script = script.display().to_string();
+            match check(&script) {
+                Ok(0) => summary.push(format!("{}: ok", script)),
+                Ok(count) => {
+                    let plural = if count == 1 { "" } else { "s" };
+                    summary.push(format!("{}: {} warning{}", script, count, plural));
+                    failed += 1;
+                }
+                Err(err) => {
+                    let errors = Diagnostics::from(err);
+                    eprintln!("{}", errors.render(&self.diagnostics, Level::Error));
+                    let count = errors.len();
+                    let plural = if count == 1 { "" } else { "s" };
+                    summary.push(format!("{}: {} error{}", script, count, plural));
+                    failed += 1;
+                }
+            }
+        }
+        for line in summary {
+            println!("{}", line);
+        }
+        println!(
+            "{} scripts checked, {} with {}",
+            scripts.len(),
+            failed,
+            what
+        );
+        if failed > 0 {
+            loxerr!(LX0905, "{} of {} scripts have {}", failed, scripts.len(), what)
+        }
+        Ok(())
+    }
+
+    // format_file: Print a script laid out in the standard way, or rewrite the file with it.
+    // With `check`, it does neither, but fails if the script isn't laid out that way already.
+    pub fn format_file(&self, filename: &str, write: bool, check: bool) -> Result<(), LoxError> {
+        let source = self.source(filename)?;
+        let mut scanner_ = Scanner::new(&source);
+        let tokens = scanner_.scan_tokens()?.clone();
+        let mut parser = Parser::new(&tokens).with_comments(scanner_.comments());
+        let statements = parser.parse()?;
+        let formatted = format::format(&statements, &parser.end_comments());
+        if check {
+            if formatted != source {
+                loxerr!(
+                    LX0904,
+                    "{} isn't formatted; run rlox1 fmt --write to format it",
+                    display_name(filename)
+                )
+            }
+        } else if write && filename != "-" {
+            fs::write(filename, formatted)?;
         } else {
-            Ok(())
+            print!("{}", formatted);
+        }
+        Ok(())
+    }
+
+    // test: Run the scripts at `paths`, or in the directories there, on the selected engine,
+    // checking each against its expect comments.
+    pub fn test(&self, paths: &[&str]) -> Result<(), LoxError> {
+        harness::run(paths, self.engine)
+    }
+
+    // run_compiled: Load a file written by compile_file and run it on the VM.
+    fn run_compiled(&self, filename: &str) -> Result<(), LoxError> {
+        if self.sandbox {
+            loxerr!(LX0610, "Compiled files run on the VM, which can't run in the sandbox")
         }
+        let chunk = self.load(filename)?;
+        let mut vm = self.vm();
+        let result = vm.run(&chunk);
+        report(vm.take_profile());
+        result.map(|_| ())
     }
 
-    // run_file: Run the supplied file based on filename.
-    // We iterate through each line of the file and attempt to execute it.
-    // TODO: collect errors from execution, so we can see if multiple errors are encountered.
-    pub fn run_file(&self, filename: &str) -> Result<(), LoxError> {
-        let contents = self.read_file(filename)?;
-        self.run(contents)
+    // compile_file: Compile a script to bytecode and save it to `output`, to be run later
+    // without parsing it again.
+    pub fn compile_file(&self, filename: &str, output: &str) -> Result<(), LoxError> {
+        fs::write(output, loxc::save(&self.compile(filename)?)?)?;
+        Ok(())
+    }
+
+    // disassemble_file: List the bytecode a script compiles to, or that a compiled file holds.
+    pub fn disassemble_file(&self, filename: &str) -> Result<(), LoxError> {
+        let chunk = if Path::new(filename).extension() == Some(loxc::EXTENSION.as_ref()) {
+            self.load(filename)?
+        } else {
+            self.compile(filename)?
+        };
+        print!("{}", chunk.disassemble(filename));
+        Ok(())
+    }
+
+    fn compile(&self, filename: &str) -> Result<Chunk, LoxError> {
+        let statements = self.parse(&self.read_script(filename)?)?;
+        Resolver::new().resolve(&statements)?;
+        Compiler::compile(&statements)
+    }
+
+    fn load(&self, filename: &str) -> Result<Chunk, LoxError> {
+        let bytes = fs::read(filename)?;
+        loxc::load(&bytes).map_err(|err| {
+            lox_error!(LX0707, "Can't load {}: {}", filename, err.message()).caused_by(err)
+        })
+    }
+
+    // bench: Run each of the named built-in benchmarks, or all of them, `runs` times on the
+    // selected engine, and print the mean and standard deviation of their times. An engine
+    // that can't compile one of them is refused before anything runs. A benchmark that fails
+    // is reported and skipped, and makes the whole run an error.
+    pub fn bench(&self, names: &[&str], runs: usize) -> Result<(), LoxError> {
+        let mut benchmarks = Vec::new();
+        for name in names {
+            match bench::find(name) {
+                Some(benchmark) => benchmarks.push(benchmark),
+                None => loxerr!(
+                    LX0903,
+                    "There's no benchmark called '{}'; try {}",
+                    name,
+                    bench::names().join(", ")
+                ),
+            }
+        }
+        if benchmarks.is_empty() {
+            benchmarks.extend(bench::BENCHMARKS.iter());
+        }
+        let session = self.session()?;
+        let mut programs = Vec::new();
+        let mut unsupported = Vec::new();
+        for benchmark in &benchmarks {
+            let statements = self.parse(benchmark.source)?;
+            match session.check(&statements) {
+                Ok(()) => programs.push((benchmark, statements)),
+                Err(err) => unsupported.push(format!("{} ({})", benchmark.name, err.message())),
+            }
+        }
+        if !unsupported.is_empty() {
+            loxerr!(
+                LX0610,
+                "The {} engine can't run these benchmarks: {}",
+                self.engine,
+                unsupported.join(", ")
+            )
+        }
+        println!(
+            "{} runs of each benchmark on the {} engine",
+            runs, self.engine
+        );
+        let mut failed = 0;
+        for (benchmark, statements) in &programs {
+            let mut times = Vec::with_capacity(runs);
+            for _ in 0..runs {
+                let started = Instant::now();
+                if let Err(err) = self.execute(statements) {
+                    println!("{:<16} failed: {}", benchmark.name, err);
+                    failed += 1;
+                    break;
+                }
+                times.push(started.elapsed());
+            }
+            if times.len() == runs && runs > 0 {
+                println!("{:<16} {}", benchmark.name, Timings(times));
+            }
+        }
+        if failed > 0 {
+            loxerr!(LX0905, "{} of {} benchmarks failed", failed, benchmarks.len())
+        }
+        Ok(())
     }
 
     // run_repl: Read a line, execute it, repeat.
-    pub fn run_repl(&self) -> Result<(), LoxError> {
-        let mut line = String::new();
+    // An entry that stops partway through a statement is continued on the next line, after a
+    // `... ` prompt, until it parses; a blank line runs it as it is. The value of the last
+    // expression statement to run is kept in `_`.
+    // With `replay`, the lines of that transcript are run first, shown as if typed, before
+    // reading any more.
+    pub fn run_repl(&self, replay: Option<&str>) -> Result<(), LoxError> {
+        let mut editor = LineEditor::new();
+        // Every entry runs in the same session, so each sees what the ones before defined.
+        let mut session = self.session()?;
+        let mut source = String::new();
+        let color = self.color && !diagnostic::no_color();
+        let transcript = match replay {
+            Some(filename) => self.read_file(filename)?,
+            None => String::new(),
+        };
+        let mut transcript = transcript.lines();
+        // Where what is typed is being recorded, after `:record`.
+        let mut recording = None;
+        // Ctrl-C stops the entry running, not the REPL.
+        interrupt::install();
         loop {
-            line.clear();
-            self.display_prompt("> ");
-            if io::stdin().read_line(&mut line).expect("Error on stdin!") == 0 {
-                break; // EOF reached.
-            } else {
-                let line = line.trim();
-                // Skip empty lines. Display and continue on error.
-                if !line.is_empty() {
-                    if let Err(err) = self.run(line.to_string()) {
-                        eprintln!("{}", err);
+            let prompt = if source.is_empty() { "> " } else { "... " };
+            let complete = |line: &str| session.complete(line);
+            let highlight = |line: &str| {
+                if color {
+                    highlight(&source, line)
+                } else {
+                    line.to_string()
+                }
+            };
+            let line = match transcript.next() {
+                Some(line) => {
+                    println!("{}{}", prompt, line);
+                    line.to_string()
+                }
+                None => match editor.read_line(prompt, &complete, &highlight)? {
+                    Input::Line(line) => {
+                        editor.add_history(&line);
+                        line
                     }
+                    // Ctrl-C abandons the entry so far.
+                    Input::Interrupt => {
+                        source.clear();
+                        continue;
+                    }
+                    Input::End => break,
+                },
+            };
+            let blank = line.trim().is_empty();
+            // Skip empty lines.
+            if source.is_empty() && blank {
+                continue;
+            }
+            // No statement starts with a colon, so one starts a command to the REPL itself.
+            if source.is_empty() && line.trim_start().starts_with(':') {
+                if let Err(err) = command(line.trim(), &mut recording) {
+                    eprintln!("{}", self.diagnostics.render(Level::Error, &err));
                 }
+                continue;
+            }
+            if let Some(file) = &mut recording {
+                writeln!(file, "{}", line)?;
+            }
+            source.push_str(&line);
+            source.push('\n');
+            if !blank && is_incomplete(&source) {
+                continue;
+            }
+            // Display and continue on error, but start again after a bug in rlox1, and stop on
+            // exit(); see Recovery.
+            let entry = std::mem::take(&mut source);
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                self.parse(&entry)
+                    .and_then(|statements| session.execute(&keep_results(statements)))
+            }));
+            // A Ctrl-C is for the entry it came during, or the one about to run if it came
+            // between them.
+            interrupt::clear();
+            let recovery = match result {
+                Ok(Ok(())) => Recovery::Continue,
+                Ok(Err(err)) if err.recovery() == Recovery::Stop => {
+                    report(session.take_profile());
+                    return Err(err);
+                }
+                Ok(Err(err)) => {
+                    // An error in a function may be about an earlier entry, where the
+                    // function was defined, so only one in the entry's own top-level code
+                    // quotes it.
+                    if err.trace().iter().all(|frame| frame.function.is_none()) {
+                        let rendered = self.diagnostics.render_in(Level::Error, &err, REPL, &entry);
+                        eprintln!("{}", rendered);
+                    } else {
+                        eprintln!("{}", self.diagnostics.render(Level::Error, &err));
+                    }
+                    err.recovery()
+                }
+                // A panic, which the panic hook in main.rs has reported.
+                Err(_) => Recovery::Reset,
+            };
+            if recovery == Recovery::Reset {
+                report(session.take_profile());
+                session = self.session()?;
+                let note = LoxError::new(
+                    "The REPL has started again, without what earlier entries defined",
+                );
+                eprintln!("{}", self.diagnostics.render(Level::Note, &note));
             }
         }
+        report(session.take_profile());
         Ok(())
     }
 }
 
+// command: Carry out a command to the REPL: `:record FILE` saves what is typed from then on
+// to FILE, as a transcript that `rlox1 repl --replay FILE` runs again, until `:stop`.
+fn command(line: &str, recording: &mut Option<File>) -> Result<(), LoxError> {
+    let words: Vec<_> = line.split_whitespace().collect();
+    match words[..] {
+        [":record", filename] => match File::create(filename) {
+            Ok(file) => *recording = Some(file),
+            Err(err) => {
+                let message = lox_error!(LX0705, "Could not record to '{}': {}", filename, err);
+                return Err(message.caused_by(err));
+            }
+        },
+        [":stop"] if recording.is_some() => *recording = None,
+        [":stop"] => loxerr!(LX0907, "Nothing is being recorded"),
+        _ => loxerr!(LX0907, "Unknown command '{}'; try :record FILE or :stop", line),
+    }
+    Ok(())
+}
+
+// keep_results: Turn the top-level expression statements of a REPL entry into definitions of
+// `_`, so that each value is there to use in the next entry. An expression that fails leaves
+// `_` as it was.
+fn keep_results(statements: Vec<Stmt>) -> Vec<Stmt> {
+    statements
+        .into_iter()
+        .map(|stmt| match stmt.kind {
+            StmtKind::Expression(expr) => {
+                let mut define = Stmt::new(StmtKind::Var {
+                    name: Token::new(TokenType::Identifier("_".to_string()), stmt.line),
+                    initializer: Some(expr),
+                    constant: false,
+                });
+                define.line = stmt.line;
+                define
+            }
+            _ => stmt,
+        })
+        .collect()
+}
+
+// in_source: Let an error show the line of the script it points at.
+fn in_source(err: LoxError, filename: &str, source: &str) -> LoxError {
+    err.in_source(display_name(filename), source)
+}
+
+// display_name: What to call the script `filename` in messages. Stdin goes by "<stdin>".
+fn display_name(filename: &str) -> &str {
+    if filename == "-" {
+        "<stdin>"
+    } else {
+        filename
+    }
+}
+
+// Session: An engine and its globals, which last from one program it runs to the next.
+enum Session {
+    TreeWalk(Box<Interpreter>),
+    Vm(Vm),
+    Register(RegisterVm),
+}
+
+impl Session {
+    // check: Find the errors the compiler stops a whole program for on the bytecode engines,
+    // without running any of it. The resolver's have been found already, by lint.
+    fn check(&self, statements: &[Stmt]) -> Result<(), LoxError> {
+        match self {
+            Session::TreeWalk(_) => {}
+            Session::Vm(_) => {
+                Compiler::compile(statements)?;
+            }
+            Session::Register(_) => {
+                RegisterCompiler::compile(statements)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, statements: &[Stmt]) -> Result<(), LoxError> {
+        match self {
+            Session::TreeWalk(interpreter) => interpreter.interpret(statements),
+            Session::Vm(vm) => vm.interpret(statements),
+            Session::Register(vm) => vm.interpret(statements),
+        }
+    }
+
+    // execute_each: Run parsed statements like execute, but a top-level statement at a time,
+    // so that after a runtime error the rest of the program still runs, and every error is
+    // reported. The whole program is checked first, so a static error stops it before any of
+    // it runs. An exit() stops it too, though not to hide the errors before it. A variable
+    // whose declaration failed is left undefined, but its uses aren't reported as well, as
+    // they only repeat the error that left it so.
+    fn execute_each(&mut self, statements: &[Stmt]) -> Result<(), LoxError> {
+        self.check(statements)?;
+        let mut errors = Diagnostics::new();
+        let mut poisoned = HashSet::new();
+        for stmt in statements {
+            let result = self.execute(std::slice::from_ref(stmt));
+            if let StmtKind::Var { name, .. } = &stmt.kind {
+                if let TokenType::Identifier(name) = &name.typ {
+                    if result.is_err() {
+                        poisoned.insert(name.clone());
+                    } else {
+                        poisoned.remove(name);
+                    }
+                }
+            }
+            match result {
+                Ok(()) => {}
+                Err(err) if err.code() == Some("LX0301") && poisons(&err, &poisoned) => {}
+                Err(err) if err.kind() == ErrorKind::Runtime && err.exit_code().is_none() => {
+                    errors.push(err)
+                }
+                Err(err) => {
+                    if !errors.has_errors() {
+                        errors.push(err);
+                    }
+                    break;
+                }
+            }
+        }
+        errors.finish(())
+    }
+
+    fn take_profile(&mut self) -> Option<Profile> {
+        match self {
+            Session::TreeWalk(interpreter) => interpreter.take_profile(),
+            Session::Vm(vm) => vm.take_profile(),
+            Session::Register(_) => None,
+        }
+    }
+
+    // complete: Tab completion. Only the tree-walker can say what its globals are, so the VMs
+    // just complete keywords.
+    fn complete(&self, line: &str) -> (usize, Vec<String>) {
+        match self {
+            Session::TreeWalk(interpreter) => complete(Some(interpreter), line),
+            _ => complete(None, line),
+        }
+    }
+}
+
+// poisons: Whether `err` is about one of the variables in `poisoned`.
+fn poisons(err: &LoxError, poisoned: &HashSet<String>) -> bool {
+    err.holes().iter().any(|hole| poisoned.contains(hole))
+}
+
+// is_incomplete: Whether `source` ends partway through a statement: inside a string, or
+// where the parser still expects more, e.g. with a brace or parenthesis left open.
+fn is_incomplete(source: &str) -> bool {
+    let mut scanner = Scanner::new(source);
+    match scanner.scan_tokens() {
+        Ok(tokens) => match Parser::new(tokens).parse() {
+            Ok(_) => false,
+            Err(err) => err.message().contains("Error at end:"),
+        },
+        Err(err) => err.message().starts_with("Missing end-quote"),
+    }
+}
+
+// report: Print a profile to stderr, out of the way of the program's own output.
+fn report(profile: Option<Profile>) {
+    if let Some(profile) = profile {
+        eprint!("{}", profile);
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::error::LoxError;
-    use crate::executive::Executor;
+    use crate::error::{ErrorKind, LoxError};
+    use crate::executive::{is_incomplete, keep_results, Engine, Executor};
     use std::path::PathBuf;
 
     macro_rules! assert_error_contains {
@@ -112,7 +1091,7 @@ mod tests {
     macro_rules! assert_run_file {
         ( $fn:expr, $ct:expr ) => {{
             let e = Executor::new();
-            let result = e.run_file(&get_resource($fn));
+            let result = e.run_files(&[&get_resource($fn)]);
             eprintln!("assert_run_file: ERROR: {:?} {}", result, $ct);
             assert_error_contains!(result, $ct)
         }};
@@ -132,12 +1111,15 @@ mod tests {
 
     #[test]
     fn load_non_existent_file() -> Result<(), LoxError> {
-        assert_run_file!("not-a-file.file", "No such file")
+        assert_run_file!("not-a-file.file", "No such file")?;
+        // The io::Error doesn't say which file, so the context does.
+        assert_run_file!("not-a-file.file", "not-a-file.file.")
     }
 
     #[test]
     fn load_too_big_file() -> Result<(), LoxError> {
-        assert_run_file!("large.file", "is too large")
+        let e = Executor::new().max_source_bytes(65535);
+        assert_error_contains!(e.run_files(&[&get_resource("large.file")]), "is too large")
     }
 
     #[test]
@@ -145,6 +1127,89 @@ mod tests {
         assert_run_file!(".", "is not a file")
     }
 
+    #[test]
+    fn unfinished_statements_are_incomplete() {
+        for source in &[
+            "{ print 1;",
+            "fun f() {\n",
+            "print (1 +",
+            "\"abc",
+            "print 1",
+            "if (a)",
+        ] {
+            assert!(is_incomplete(source), "{:?}", source);
+        }
+        for source in &["print 1;", "{ print 1; }", "1 +;", "}", "print \"a\nb\";"] {
+            assert!(!is_incomplete(source), "{:?}", source);
+        }
+    }
+
+    // Each REPL entry is parsed and run separately, in one session.
+    #[test]
+    fn sessions_keep_their_globals() -> Result<(), LoxError> {
+        for &engine in &[Engine::TreeWalk, Engine::Vm, Engine::Register] {
+            let e = Executor::new().engine(engine);
+            let mut session = e.session()?;
+            for entry in &["var a = 1;", "var b = a + 1;", "a = b * 10;"] {
+                session.execute(&e.parse(entry)?)?;
+            }
+            let defined = session.execute(&e.parse("a + b; undefined;")?);
+            assert_error_contains!(defined, "Undefined variable 'undefined'")?;
+        }
+        let e = Executor::new();
+        let mut session = e.session()?;
+        session.execute(&e.parse("fun twice(x) { return 2 * x; }")?)?;
+        session.execute(&e.parse("assert(twice(2) == 4, \"twice\");")?)
+    }
+
+    #[test]
+    fn underscore_holds_the_last_result() -> Result<(), LoxError> {
+        for &engine in &[Engine::TreeWalk, Engine::Vm, Engine::Register] {
+            let e = Executor::new().engine(engine);
+            let mut session = e.session()?;
+            for entry in &["1 + 2;", "_ * 2; var b = 0;", "nope;", "var a = _ + 1;"] {
+                let _ = session.execute(&keep_results(e.parse(entry)?));
+            }
+            // The VMs can't call assert() yet, so a wrong value shows as an undefined variable.
+            let checked = session.execute(&e.parse("a == 7 or wrong;")?);
+            assert!(checked.is_ok(), "{}: {:?}", engine, checked);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn errors_say_where_they_came_from() -> Result<(), LoxError> {
+        let e = Executor::new();
+        let kind = |result: Result<(), LoxError>| result.map_err(|err| err.kind()).err();
+        assert_eq!(kind(e.parse("print @;").map(|_| ())), Some(ErrorKind::Scan));
+        assert_eq!(
+            kind(e.parse("print 1 +;").map(|_| ())),
+            Some(ErrorKind::Parse)
+        );
+        for &engine in &[Engine::TreeWalk, Engine::Vm, Engine::Register] {
+            let mut session = Executor::new().engine(engine).session()?;
+            let static_error = session.execute(&e.parse("print this;")?);
+            assert_eq!(kind(static_error), Some(ErrorKind::Parse), "{}", engine);
+            let runtime_error = session.execute(&e.parse("-nil;")?);
+            assert_eq!(kind(runtime_error), Some(ErrorKind::Runtime), "{}", engine);
+        }
+        let missing = e.run_files(&[&get_resource("not-a-file.lox")]);
+        assert_eq!(kind(missing), Some(ErrorKind::Io));
+        assert_eq!(kind(e.run_files(&["."])), Some(ErrorKind::Other));
+        Ok(())
+    }
+
+    #[test]
+    fn files_run_as_one_program() {
+        let e = Executor::new();
+        assert!(e.run_files(&[&get_resource("multiline.lox")]).is_ok());
+    }
+
+    #[test]
+    fn errors_report_their_line_in_the_file() -> Result<(), LoxError> {
+        assert_run_file!("error-line.lox", "[line 3] Operands of '+'")
+    }
+
     // #[test]
     // fn load_file_with_bad_statement() -> Result<(), LoxError> {
     //     assert_run_file!("test-bad.lox", "Invalid character")
@@ -0,0 +1,547 @@
+// Source formatting for `rlox1 fmt`: a parsed program printed back as Lox in one layout: two
+// spaces of indentation, a statement per line, opening braces at the end of the line, and a
+// blank line around declarations of functions and classes. The tree keeps parentheses as
+// groupings, so the output parses to the same tree as the input.
+//
+// Comments come from the parser's trivia-keeping mode, which hangs them on the statements in
+// blocks and on methods. Each is printed on a line of its own above what it was attached to,
+// or at the end of its last line if it was there already, so a comment from the middle of an
+// expression, or from an empty block, moves up to above its statement.
+
+use crate::ast::{
+    Comments, Expr, ExprKind, FunctionDecl, Literal, MatchArm, Pattern, Stmt, StmtKind,
+};
+
+const INDENT: &str = "  ";
+
+// format: The statements as formatted source, then the comments at the end of the file,
+// ending with a newline.
+pub fn format(statements: &[Stmt], end_comments: &[String]) -> String {
+    let mut formatter = Formatter {
+        out: String::new(),
+        depth: 0,
+    };
+    formatter.statements(statements);
+    for comment in end_comments {
+        formatter.push(comment);
+        formatter.out.push('\n');
+    }
+    formatter.out
+}
+
+struct Formatter {
+    out: String,
+    // How many levels the current line is indented.
+    depth: usize,
+}
+
+impl Formatter {
+    fn push(&mut self, text: &str) {
+        self.out.push_str(text);
+    }
+
+    // new_line: End the line, and indent the next one.
+    fn new_line(&mut self) {
+        self.out.push('\n');
+        self.out.push_str(&INDENT.repeat(self.depth));
+    }
+
+    // statements: Each statement on its own line, starting on the current one.
+    fn statements(&mut self, statements: &[Stmt]) {
+        for (i, stmt) in statements.iter().enumerate() {
+            if i > 0 {
+                if is_declaration(stmt) || is_declaration(&statements[i - 1]) {
+                    self.out.push('\n');
+                }
+                self.new_line();
+            }
+            self.comments_before(stmt.comments.as_deref());
+            self.statement(stmt);
+            self.comments_after(stmt.comments.as_deref());
+        }
+        if self.depth == 0 && !statements.is_empty() {
+            self.out.push('\n');
+        }
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Expression(expr) => {
+                self.expr(expr);
+                self.push(";");
+            }
+            StmtKind::Print(expr) => {
+                self.push("print ");
+                self.expr(expr);
+                self.push(";");
+            }
+            StmtKind::Var {
+                name,
+                initializer,
+                constant,
+            } => {
+                self.push(if *constant { "const " } else { "var " });
+                self.push(&name.typ.to_string());
+                if let Some(initializer) = initializer {
+                    self.push(" = ");
+                    self.expr(initializer);
+                }
+                self.push(";");
+            }
+            StmtKind::Function(decl) => {
+                self.push("fun ");
+                self.function(decl);
+            }
+            StmtKind::Class {
+                name,
+                superclass,
+                mixins,
+                methods,
+                getters,
+                setters,
+            } => {
+                self.push(&format!("class {}", name.typ));
+                if let Some(superclass) = superclass {
+                    self.push(" < ");
+                    self.expr(superclass);
+                }
+                for (i, mixin) in mixins.iter().enumerate() {
+                    self.push(if i == 0 { " with " } else { ", " });
+                    self.expr(mixin);
+                }
+                // The tree keeps each kind of member apart; put them back in source order.
+                let mut members: Vec<_> = methods
+                    .iter()
+                    .map(|decl| (decl, ""))
+                    .chain(getters.iter().map(|decl| (decl, "getter")))
+                    .chain(setters.iter().map(|decl| (decl, "set ")))
+                    .collect();
+                members.sort_by_key(|(decl, _)| decl.name.line);
+                if members.is_empty() {
+                    self.push(" {}");
+                    return;
+                }
+                self.push(" {");
+                self.depth += 1;
+                for (i, (decl, kind)) in members.into_iter().enumerate() {
+                    if i > 0 {
+                        self.out.push('\n');
+                    }
+                    self.new_line();
+                    self.comments_before(decl.comments.as_deref());
+                    match kind {
+                        "getter" => {
+                            self.push(&format!("{} ", decl.name.typ));
+                            self.block(&decl.body);
+                        }
+                        kind => {
+                            self.push(kind);
+                            self.function(decl);
+                        }
+                    }
+                    self.comments_after(decl.comments.as_deref());
+                }
+                self.depth -= 1;
+                self.new_line();
+                self.push("}");
+            }
+            StmtKind::Block(statements) => self.block(statements),
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.push("if (");
+                self.expr(condition);
+                self.push(") ");
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    match then_branch.kind {
+                        StmtKind::Block(_) => self.push(" "),
+                        _ => self.new_line(),
+                    }
+                    self.push("else ");
+                    self.statement(else_branch);
+                }
+            }
+            StmtKind::Return { value, .. } => self.keyword("return", value.as_ref()),
+            StmtKind::Yield { value, .. } => self.keyword("yield", value.as_ref()),
+            StmtKind::Throw { value, .. } => self.keyword("throw", Some(value)),
+            StmtKind::Try {
+                body,
+                name,
+                handler,
+            } => {
+                self.push("try ");
+                self.block(body);
+                self.push(&format!(" catch ({}) ", name.typ));
+                self.block(handler);
+            }
+            StmtKind::ForIn {
+                name,
+                iterable,
+                body,
+                ..
+            } => {
+                self.push(&format!("for (var {} in ", name.typ));
+                self.expr(iterable);
+                self.push(") ");
+                self.statement(body);
+            }
+            StmtKind::While {
+                condition, body, ..
+            } => {
+                self.push("while (");
+                self.expr(condition);
+                self.push(") ");
+                self.statement(body);
+            }
+            StmtKind::For {
+                initializer,
+                condition,
+                increment,
+                body,
+                ..
+            } => {
+                self.push("for (");
+                match initializer {
+                    Some(initializer) => self.statement(initializer),
+                    None => self.push(";"),
+                }
+                if let Some(condition) = condition {
+                    self.push(" ");
+                    self.expr(condition);
+                }
+                self.push(";");
+                if let Some(increment) = increment {
+                    self.push(" ");
+                    self.expr(increment);
+                }
+                self.push(") ");
+                self.statement(body);
+            }
+        }
+    }
+
+    // comments_before: The comments above a statement or method, a line each, leaving the
+    // line after them to start it on.
+    fn comments_before(&mut self, comments: Option<&Comments>) {
+        for comment in comments.map_or(&[][..], |comments| &comments.before) {
+            self.push(comment);
+            self.new_line();
+        }
+    }
+
+    // comments_after: The comment trailing a statement or method, and those on the lines
+    // after it.
+    fn comments_after(&mut self, comments: Option<&Comments>) {
+        if let Some(comments) = comments {
+            if let Some(trailing) = &comments.trailing {
+                self.push(" ");
+                self.push(trailing);
+            }
+            for comment in &comments.after {
+                self.new_line();
+                self.push(comment);
+            }
+        }
+    }
+
+    // keyword: A statement of a keyword and an optional value, e.g. `return x;`.
+    fn keyword(&mut self, keyword: &str, value: Option<&Expr>) {
+        self.push(keyword);
+        if let Some(value) = value {
+            self.push(" ");
+            self.expr(value);
+        }
+        self.push(";");
+    }
+
+    fn block(&mut self, statements: &[Stmt]) {
+        if statements.is_empty() {
+            self.push("{}");
+            return;
+        }
+        self.push("{");
+        self.depth += 1;
+        self.new_line();
+        self.statements(statements);
+        self.depth -= 1;
+        self.new_line();
+        self.push("}");
+    }
+
+    // function: A function or method from its name on.
+    fn function(&mut self, decl: &FunctionDecl) {
+        let params: Vec<_> = decl.params.iter().map(|p| p.typ.to_string()).collect();
+        self.push(&format!("{}({}) ", decl.name.typ, params.join(", ")));
+        self.block(&decl.body);
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Literal(literal) => self.push(&show_literal(literal)),
+            ExprKind::Grouping(inner) => {
+                self.push("(");
+                self.expr(inner);
+                self.push(")");
+            }
+            ExprKind::List(items) => {
+                self.push("[");
+                self.list(items);
+                self.push("]");
+            }
+            ExprKind::Map { entries, .. } => {
+                self.push("{");
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        self.push(", ");
+                    }
+                    self.expr(key);
+                    self.push(": ");
+                    self.expr(value);
+                }
+                self.push("}");
+            }
+            ExprKind::Variable(name) => self.push(&name.typ.to_string()),
+            ExprKind::Logical { left, op, right } | ExprKind::Binary { left, op, right } => {
+                self.expr(left);
+                self.push(&format!(" {} ", op.typ));
+                self.expr(right);
+            }
+            ExprKind::Assign { name, value } => {
+                self.push(&format!("{} = ", name.typ));
+                self.expr(value);
+            }
+            ExprKind::Unary { op, right } => {
+                self.push(&op.typ.to_string());
+                self.expr(right);
+            }
+            ExprKind::Call {
+                callee, arguments, ..
+            } => {
+                self.expr(callee);
+                self.push("(");
+                self.list(arguments);
+                self.push(")");
+            }
+            ExprKind::Get { object, name } => {
+                self.expr(object);
+                self.push(&format!(".{}", name.typ));
+            }
+            ExprKind::Set {
+                object,
+                name,
+                value,
+            } => {
+                self.expr(object);
+                self.push(&format!(".{} = ", name.typ));
+                self.expr(value);
+            }
+            ExprKind::This(_) => self.push("this"),
+            ExprKind::Super { method, .. } => self.push(&format!("super.{}", method.typ)),
+            ExprKind::Index { object, index, .. } => {
+                self.expr(object);
+                self.push("[");
+                self.expr(index);
+                self.push("]");
+            }
+            ExprKind::Slice {
+                object, start, end, ..
+            } => {
+                self.expr(object);
+                self.push("[");
+                if let Some(start) = start {
+                    self.expr(start);
+                }
+                self.push(":");
+                if let Some(end) = end {
+                    self.expr(end);
+                }
+                self.push("]");
+            }
+            ExprKind::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.expr(object);
+                self.push("[");
+                self.expr(index);
+                self.push("] = ");
+                self.expr(value);
+            }
+            ExprKind::Match { subject, arms, .. } => {
+                self.push("match (");
+                self.expr(subject);
+                self.push(") { ");
+                for (i, MatchArm { pattern, body }) in arms.iter().enumerate() {
+                    if i > 0 {
+                        self.push(", ");
+                    }
+                    match pattern {
+                        Pattern::Literal(literal) => self.push(&show_literal(literal)),
+                        Pattern::Binding(name) => self.push(&name.typ.to_string()),
+                        Pattern::Wildcard => self.push("_"),
+                    }
+                    self.push(" -> ");
+                    self.expr(body);
+                }
+                self.push(" }");
+            }
+        }
+    }
+
+    // list: Expressions separated by commas, as in arguments and list literals.
+    fn list(&mut self, items: &[Expr]) {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.push(", ");
+            }
+            self.expr(item);
+        }
+    }
+}
+
+// is_declaration: Whether a statement declares a function or class, which get a blank line
+// either side.
+fn is_declaration(stmt: &Stmt) -> bool {
+    matches!(stmt.kind, StmtKind::Function(_) | StmtKind::Class { .. })
+}
+
+// show_literal: A literal as it is written. Strings are kept as scanned, escapes and all.
+fn show_literal(literal: &Literal) -> String {
+    match literal {
+        Literal::Str(s) => format!("\"{}\"", s),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use crate::error::LoxError;
+    use crate::format::format;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn reformat(src: &str) -> Result<String, LoxError> {
+        let mut scanner = Scanner::new(src);
+        let tokens = scanner.scan_tokens()?.clone();
+        let mut parser = Parser::new(&tokens).with_comments(scanner.comments());
+        let statements = parser.parse()?;
+        Ok(format(&statements, &parser.end_comments()))
+    }
+
+    // comments: The text of every comment in the program, in order.
+    fn comments(src: &str) -> Result<Vec<String>, LoxError> {
+        let mut scanner = Scanner::new(src);
+        scanner.scan_tokens()?;
+        Ok(scanner.comments().iter().map(|c| c.text.clone()).collect())
+    }
+
+    // tree: The program as the AST prints it, to check formatting doesn't change its meaning.
+    fn tree(src: &str) -> Result<Vec<String>, LoxError> {
+        let tokens = Scanner::new(src).scan_tokens()?.clone();
+        let statements = Parser::new(&tokens).parse()?;
+        Ok(statements.iter().map(|stmt| stmt.to_string()).collect())
+    }
+
+    #[test]
+    fn statements_are_laid_out() -> Result<(), LoxError> {
+        let src = "var a=1;fun f(x,y){if(x<y)return -x;else{print (x+y)*2;}}\
+                   class B<A with M{get(){return super.get();}size{return 1;}set size(v){}}\
+                   for(var i in [1,2,{\"k\":nil}])f(i,a[1:]);";
+        let expected = "\
+var a = 1;
+
+fun f(x, y) {
+  if (x < y) return -x;
+  else {
+    print (x + y) * 2;
+  }
+}
+
+class B < A with M {
+  get() {
+    return super.get();
+  }
+
+  size {
+    return 1;
+  }
+
+  set size(v) {}
+}
+
+for (var i in [1, 2, {\"k\": nil}]) f(i, a[1:]);
+";
+        assert_eq!(reformat(src)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn expressions_keep_their_shape() -> Result<(), LoxError> {
+        let src = "x = !(a and b) or c; y.z = match (n) { 0 -> \"zero\", -1 -> m, _ -> n[0] };";
+        assert_eq!(
+            reformat(src)?,
+            "x = !(a and b) or c;\ny.z = match (n) { 0 -> \"zero\", -1 -> m, _ -> n[0] };\n"
+        );
+        assert_eq!(
+            reformat("fun g() { try { throw 1; } catch (e) { yield e; } }")?,
+            "fun g() {\n  try {\n    throw 1;\n  } catch (e) {\n    yield e;\n  }\n}\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn comments_are_kept() -> Result<(), LoxError> {
+        let src = "// A class.\nclass A {\n// Says hi.\nhi() { print \"hi\"; } // inline\n\
+                   // The end of the class.\n}\n{ var a = 1; // one\n// after\n}\n\
+                   var b = 1 + // mid\n2;\nfun f() {\n// empty\n}\n// The end.\n";
+        let expected = "\
+// A class.
+class A {
+  // Says hi.
+  hi() {
+    print \"hi\";
+  } // inline
+  // The end of the class.
+}
+
+{
+  var a = 1; // one
+  // after
+}
+// mid
+var b = 1 + 2;
+
+// empty
+fun f() {}
+// The end.
+";
+        assert_eq!(reformat(src)?, expected);
+        assert_eq!(reformat(expected)?, expected);
+        assert_eq!(reformat("// Only a comment.\n")?, "// Only a comment.\n");
+        Ok(())
+    }
+
+    // Formatting the engine test scripts must not change what they mean, and formatting again
+    // must change nothing.
+    #[test]
+    fn formatting_keeps_the_program() -> Result<(), LoxError> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/engines");
+        for entry in fs::read_dir(dir)? {
+            let src = fs::read_to_string(entry?.path())?;
+            let formatted = reformat(&src)?;
+            assert_eq!(tree(&formatted)?, tree(&src)?, "{}", formatted);
+            assert_eq!(comments(&formatted)?, comments(&src)?, "{}", formatted);
+            assert_eq!(reformat(&formatted)?, formatted);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,478 @@
+// Cycle collection for the tree-walk interpreter.
+//
+// Values are reference counted, which frees almost everything promptly but leaks cycles: a
+// closure stored in the scope it captured, or a list that contains itself. The collector
+// finds such cycles by trial deletion, the scheme CPython uses. Every container (scope,
+// list, map, function, class, instance, generator) is tracked, and a collection:
+//
+//   1. Counts, for each tracked object, the references it gets from other tracked objects.
+//   2. Treats any object with more strong references than that as a root: something outside
+//      the tracked graph owns it, such as the globals, the interpreter's current scope or a
+//      temporary on the Rust stack.
+//   3. Marks everything reachable from the roots. Whatever is left can only be reached from
+//      other garbage, so its contents are cleared, which breaks the cycles and lets the
+//      reference counts free it.
+//
+// No roots need to be registered, so the interpreter may collect at any statement boundary.
+// The heap is per thread, like the values in it.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::{Rc, Weak};
+use std::time::Instant;
+
+use crate::environment::{Scope, ScopeRef};
+use crate::value::{
+    GeneratorState, LoxClass, LoxFunction, LoxGenerator, LoxInstance, LoxMap, ResumePoint, Value,
+};
+
+// Collections start after this many new objects, or twice the objects that survived the
+// last collection if that is more, so the cost of collecting stays proportional to work
+// done.
+const MIN_THRESHOLD: usize = 10_000;
+
+// GcStats: Counters reported by `--gc-stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GcStats {
+    pub collections: usize,
+    pub allocated: usize,
+    pub freed: usize,
+    pub live: usize,
+}
+
+impl fmt::Display for GcStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "gc: {} collections, {} objects allocated, {} freed in cycles, {} tracked",
+            self.collections, self.allocated, self.freed, self.live
+        )
+    }
+}
+
+#[derive(Default)]
+struct Heap {
+    scopes: Vec<Weak<RefCell<Scope>>>,
+    lists: Vec<Weak<RefCell<Vec<Value>>>>,
+    maps: Vec<Weak<RefCell<LoxMap>>>,
+    functions: Vec<Weak<LoxFunction>>,
+    classes: Vec<Weak<LoxClass>>,
+    instances: Vec<Weak<RefCell<LoxInstance>>>,
+    generators: Vec<Weak<RefCell<LoxGenerator>>>,
+    since_last: usize,
+    stats: GcStats,
+}
+
+thread_local! {
+    static HEAP: RefCell<Heap> = RefCell::new(Heap::default());
+}
+
+fn track<F>(add: F)
+where
+    F: FnOnce(&mut Heap),
+{
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        add(&mut heap);
+        heap.since_last += 1;
+        heap.stats.allocated += 1;
+    });
+}
+
+pub fn track_scope(scope: &ScopeRef) {
+    track(|heap| heap.scopes.push(Rc::downgrade(scope)));
+}
+
+pub fn track_list(list: &Rc<RefCell<Vec<Value>>>) {
+    track(|heap| heap.lists.push(Rc::downgrade(list)));
+}
+
+pub fn track_map(map: &Rc<RefCell<LoxMap>>) {
+    track(|heap| heap.maps.push(Rc::downgrade(map)));
+}
+
+pub fn track_function(function: &Rc<LoxFunction>) {
+    track(|heap| heap.functions.push(Rc::downgrade(function)));
+}
+
+pub fn track_class(class: &Rc<LoxClass>) {
+    track(|heap| heap.classes.push(Rc::downgrade(class)));
+}
+
+pub fn track_instance(instance: &Rc<RefCell<LoxInstance>>) {
+    track(|heap| heap.instances.push(Rc::downgrade(instance)));
+}
+
+pub fn track_generator(generator: &Rc<RefCell<LoxGenerator>>) {
+    track(|heap| heap.generators.push(Rc::downgrade(generator)));
+}
+
+// maybe_collect: Collect if enough objects have been allocated since the last collection.
+pub fn maybe_collect() {
+    let (since_last, threshold) = HEAP.with(|heap| {
+        let heap = heap.borrow();
+        (heap.since_last, MIN_THRESHOLD.max(2 * heap.stats.live))
+    });
+    if since_last >= threshold {
+        trace!("gc: {} new objects since the last collection, so collecting", since_last);
+        collect();
+    }
+}
+
+pub fn stats() -> GcStats {
+    HEAP.with(|heap| heap.borrow().stats)
+}
+
+// Object: A tracked object, held strongly for the duration of a collection.
+enum Object {
+    Scope(ScopeRef),
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<LoxMap>>),
+    Function(Rc<LoxFunction>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
+    Generator(Rc<RefCell<LoxGenerator>>),
+}
+
+impl Object {
+    fn address(&self) -> usize {
+        match self {
+            Object::Scope(rc) => Rc::as_ptr(rc) as *const () as usize,
+            Object::List(rc) => Rc::as_ptr(rc) as *const () as usize,
+            Object::Map(rc) => Rc::as_ptr(rc) as *const () as usize,
+            Object::Function(rc) => Rc::as_ptr(rc) as *const () as usize,
+            Object::Class(rc) => Rc::as_ptr(rc) as *const () as usize,
+            Object::Instance(rc) => Rc::as_ptr(rc) as *const () as usize,
+            Object::Generator(rc) => Rc::as_ptr(rc) as *const () as usize,
+        }
+    }
+
+    // strong_count: References to the object, not counting the one the collector holds.
+    fn strong_count(&self) -> usize {
+        let count = match self {
+            Object::Scope(rc) => Rc::strong_count(rc),
+            Object::List(rc) => Rc::strong_count(rc),
+            Object::Map(rc) => Rc::strong_count(rc),
+            Object::Function(rc) => Rc::strong_count(rc),
+            Object::Class(rc) => Rc::strong_count(rc),
+            Object::Instance(rc) => Rc::strong_count(rc),
+            Object::Generator(rc) => Rc::strong_count(rc),
+        };
+        count - 1
+    }
+
+    // children: The addresses of the tracked objects this one refers to, once per reference.
+    // Returns None if the object is borrowed, in which case it can't be inspected safely.
+    fn children(&self) -> Option<Vec<usize>> {
+        let mut children = Vec::new();
+        match self {
+            Object::Scope(rc) => {
+                let scope = rc.try_borrow().ok()?;
+                scope
+                    .values()
+                    .iter()
+                    .for_each(|v| value_child(v, &mut children));
+                if let Some(enclosing) = scope.enclosing() {
+                    children.push(Rc::as_ptr(enclosing) as *const () as usize);
+                }
+            }
+            Object::List(rc) => {
+                let items = rc.try_borrow().ok()?;
+                items.iter().for_each(|v| value_child(v, &mut children));
+            }
+            Object::Map(rc) => {
+                // Keys can be instances, so both keys and values can refer to objects.
+                let map = rc.try_borrow().ok()?;
+                map.keys()
+                    .iter()
+                    .for_each(|k| value_child(k, &mut children));
+                map.values().for_each(|v| value_child(v, &mut children));
+            }
+            Object::Function(rc) => {
+                if let Some(closure) = &rc.closure {
+                    children.push(Rc::as_ptr(closure) as *const () as usize);
+                }
+            }
+            Object::Class(rc) => {
+                let accessors = rc.getters.values().chain(rc.setters.values());
+                for method in rc.methods.values().chain(accessors) {
+                    children.push(Rc::as_ptr(method) as *const () as usize);
+                }
+                if let Some(superclass) = &rc.superclass {
+                    children.push(Rc::as_ptr(superclass) as *const () as usize);
+                }
+            }
+            Object::Instance(rc) => {
+                let instance = rc.try_borrow().ok()?;
+                children.push(Rc::as_ptr(&instance.class) as *const () as usize);
+                instance
+                    .fields
+                    .values()
+                    .for_each(|v| value_child(v, &mut children));
+            }
+            Object::Generator(rc) => {
+                // A running generator's scopes are the interpreter's current ones, which are
+                // roots anyway.
+                let generator = rc.try_borrow().ok()?;
+                if let GeneratorState::Suspended { scope, path } = &generator.state {
+                    children.push(Rc::as_ptr(scope) as *const () as usize);
+                    for point in path {
+                        if let ResumePoint::ForIn { iterable, .. } = point {
+                            value_child(iterable, &mut children);
+                        }
+                    }
+                }
+            }
+        }
+        Some(children)
+    }
+
+    // clear: Drop the object's references. Functions and classes are immutable, so any
+    // cycle through one also runs through a scope or instance, and clearing that is enough.
+    fn clear(&self) {
+        match self {
+            Object::Scope(rc) => rc.borrow_mut().clear(),
+            Object::List(rc) => rc.borrow_mut().clear(),
+            Object::Map(rc) => rc.borrow_mut().clear(),
+            Object::Instance(rc) => rc.borrow_mut().fields.clear(),
+            Object::Generator(rc) => rc.borrow_mut().state = GeneratorState::Done,
+            Object::Function(_) | Object::Class(_) => {}
+        }
+    }
+}
+
+fn value_child(value: &Value, children: &mut Vec<usize>) {
+    let address = match value {
+        Value::List(rc) => Rc::as_ptr(rc) as *const () as usize,
+        Value::Map(rc) => Rc::as_ptr(rc) as *const () as usize,
+        Value::Function(rc) => Rc::as_ptr(rc) as *const () as usize,
+        Value::Class(rc) => Rc::as_ptr(rc) as *const () as usize,
+        Value::Instance(rc) => Rc::as_ptr(rc) as *const () as usize,
+        Value::Generator(rc) => Rc::as_ptr(rc) as *const () as usize,
+        _ => return,
+    };
+    children.push(address);
+}
+
+// collect: Free every tracked object that is only reachable from cycles, returning how many
+// were freed.
+pub fn collect() -> usize {
+    let started = Instant::now();
+    let objects = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        let mut objects = Vec::new();
+        heap.scopes
+            .retain(|weak| upgrade(weak, Object::Scope, &mut objects));
+        heap.lists
+            .retain(|weak| upgrade(weak, Object::List, &mut objects));
+        heap.maps
+            .retain(|weak| upgrade(weak, Object::Map, &mut objects));
+        heap.functions
+            .retain(|weak| upgrade(weak, Object::Function, &mut objects));
+        heap.classes
+            .retain(|weak| upgrade(weak, Object::Class, &mut objects));
+        heap.instances
+            .retain(|weak| upgrade(weak, Object::Instance, &mut objects));
+        heap.generators
+            .retain(|weak| upgrade(weak, Object::Generator, &mut objects));
+        objects
+    });
+
+    let garbage = find_garbage(&objects);
+    // `objects` holds every garbage object until all of them are cleared, so nothing is
+    // freed while another object is still borrowed.
+    for &i in &garbage {
+        objects[i].clear();
+    }
+    let freed = garbage.len();
+    let live = objects.len() - freed;
+    drop(objects);
+    debug!(
+        "gc: freed {} of the {} objects tracked in {:?}",
+        freed,
+        live + freed,
+        started.elapsed()
+    );
+
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.since_last = 0;
+        heap.stats.collections += 1;
+        heap.stats.freed += freed;
+        heap.stats.live = live;
+    });
+    freed
+}
+
+fn upgrade<T, F>(weak: &Weak<T>, wrap: F, objects: &mut Vec<Object>) -> bool
+where
+    F: Fn(Rc<T>) -> Object,
+{
+    match weak.upgrade() {
+        Some(rc) => {
+            objects.push(wrap(rc));
+            true
+        }
+        None => false,
+    }
+}
+
+// find_garbage: The indexes of the objects that can't be reached from outside the tracked
+// graph. If any object can't be inspected, nothing is treated as garbage.
+fn find_garbage(objects: &[Object]) -> Vec<usize> {
+    let index: HashMap<usize, usize> = objects
+        .iter()
+        .enumerate()
+        .map(|(i, object)| (object.address(), i))
+        .collect();
+    let mut children = Vec::with_capacity(objects.len());
+    for object in objects {
+        match object.children() {
+            Some(found) => children.push(found),
+            None => return Vec::new(),
+        }
+    }
+
+    // Subtract the references that come from inside the graph; what remains comes from
+    // outside it.
+    let mut outside: Vec<usize> = objects.iter().map(Object::strong_count).collect();
+    for found in &children {
+        for child in found {
+            if let Some(&i) = index.get(child) {
+                outside[i] -= 1;
+            }
+        }
+    }
+
+    let mut reachable = vec![false; objects.len()];
+    let mut pending: Vec<usize> = (0..objects.len()).filter(|&i| outside[i] > 0).collect();
+    while let Some(i) = pending.pop() {
+        if reachable[i] {
+            continue;
+        }
+        reachable[i] = true;
+        for child in &children[i] {
+            if let Some(&c) = index.get(child) {
+                pending.push(c);
+            }
+        }
+    }
+    (0..objects.len()).filter(|&i| !reachable[i]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::error::LoxError;
+    use crate::gc;
+    use crate::interpreter::Interpreter;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::value::Value;
+
+    fn run(interpreter: &mut Interpreter, src: &str) -> Result<(), LoxError> {
+        let mut scanner = Scanner::new(src);
+        let statements = Parser::new(scanner.scan_tokens()?).parse()?;
+        interpreter.interpret(&statements)
+    }
+
+    // The heap is per thread, and tests may share a thread, so each test first collects
+    // whatever earlier tests left behind.
+
+    #[test]
+    fn frees_a_list_that_contains_itself() {
+        gc::collect();
+        let list = Value::list(Vec::new());
+        let weak = match &list {
+            Value::List(items) => {
+                items.borrow_mut().push(list.clone());
+                Rc::downgrade(items)
+            }
+            _ => unreachable!(),
+        };
+        assert_eq!(gc::collect(), 0, "the list is still referenced");
+        drop(list);
+        assert!(weak.upgrade().is_some(), "the cycle keeps it alive");
+        assert_eq!(gc::collect(), 1);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn frees_closure_cycles() -> Result<(), LoxError> {
+        gc::collect();
+        let mut interpreter = Interpreter::new();
+        // `f` lives in the block's scope, and that scope is f's closure.
+        run(&mut interpreter, "{ fun f() { return f; } }")?;
+        assert_eq!(gc::collect(), 2);
+        assert_eq!(gc::stats().live, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn frees_instance_and_class_cycles() -> Result<(), LoxError> {
+        gc::collect();
+        let mut interpreter = Interpreter::new();
+        // An instance holding itself, and a class whose methods close over the block that
+        // holds the class.
+        let src = "class A {} { var a = A(); a.me = a; class B { f() { return B; } } }";
+        run(&mut interpreter, src)?;
+        // The block scope, a, B and B's method f. The global class A stays.
+        assert_eq!(gc::collect(), 4);
+        assert_eq!(gc::stats().live, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn frees_cycles_through_map_keys() -> Result<(), LoxError> {
+        gc::collect();
+        let mut interpreter = Interpreter::new();
+        let src = "{ class K {} var k = K(); var m = {}; m[k] = 1; k.m = m; }";
+        run(&mut interpreter, src)?;
+        // The block scope, k and m; K goes once k no longer holds it.
+        assert_eq!(gc::collect(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn frees_paused_generators() -> Result<(), LoxError> {
+        gc::collect();
+        let mut interpreter = Interpreter::new();
+        let src = "{ fun g() { yield 1; } var it = g(); it.next(); }";
+        run(&mut interpreter, src)?;
+        // The block scope, g, the generator, and the scope it paused in, whose enclosing
+        // scope is the block's.
+        assert_eq!(gc::collect(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_cycles_reachable_from_globals() -> Result<(), LoxError> {
+        gc::collect();
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var l = []; l.push(l); var m = {}; m[1] = [m];",
+        )?;
+        assert_eq!(gc::collect(), 0);
+        run(&mut interpreter, "l[0].push(1); m[1][0][2] = 3;")?;
+        drop(interpreter);
+        assert_eq!(gc::collect(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn collects_while_running() -> Result<(), LoxError> {
+        gc::collect();
+        let before = gc::stats();
+        let mut interpreter = Interpreter::new();
+        interpreter.define_global("items", Value::list(vec![Value::Nil; 30_000]));
+        let src = "var kept = []; for (var i in items) { fun f() { return f; } kept = [f]; }";
+        run(&mut interpreter, src)?;
+        let after = gc::stats();
+        assert!(after.collections > before.collections, "{}", after);
+        assert!(after.freed - before.freed > 30_000, "{}", after);
+        run(&mut interpreter, "kept[0]();")
+    }
+}
@@ -0,0 +1,218 @@
+// The test runner behind `rlox1 test`: Lox scripts that say in comments what they should do,
+// in the style of the book's test suite, run one by one and checked.
+//
+//   print 1 + 2; // expect: 3                       a line the script prints, in order
+//   print nope;  // expect runtime error: Undefined variable 'nope'.
+//   print 1 +;   // Error at ';': Expect expression.
+//
+// A runtime error must end the script with status 70, a compile error ("Error ...", maybe
+// after "[line N] ") with status 65, and in both cases the message must appear on stderr.
+// Scripts run with --fail-fast, so that, as in the book, nothing runs after a runtime error.
+// Each script runs in a separate rlox1 process, so output, exit() and crashes stay apart.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::{LoxError, EX_DATAERR, EX_SOFTWARE};
+use crate::executive::Engine;
+
+const EXPECT: &str = "// expect: ";
+const EXPECT_RUNTIME_ERROR: &str = "// expect runtime error: ";
+
+// Expectations: What a script says it does.
+#[derive(Debug, Default, PartialEq)]
+struct Expectations {
+    output: Vec<String>,
+    // The error the script should stop with, if any, and the status it exits with.
+    error: Option<(String, i32)>,
+}
+
+// expectations: Read the expect comments of a script.
+fn expectations(source: &str) -> Expectations {
+    let mut expected = Expectations::default();
+    for line in source.lines() {
+        if let Some(output) = after(line, EXPECT) {
+            expected.output.push(output.to_string());
+        } else if let Some(message) = after(line, EXPECT_RUNTIME_ERROR) {
+            expected.error = Some((message.to_string(), EX_SOFTWARE));
+        } else if let Some(comment) = after(line, "// ") {
+            let message = match comment.strip_prefix("[line ") {
+                Some(rest) => rest
+                    .split_once("] ")
+                    .map_or(comment, |(_, message)| message),
+                None => comment,
+            };
+            if message.starts_with("Error") {
+                expected.error = Some((message.to_string(), EX_DATAERR));
+            }
+        }
+    }
+    expected
+}
+
+// after: What follows `marker` on a line, trimmed, if it's there.
+fn after<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    line.find(marker)
+        .map(|start| line[start + marker.len()..].trim())
+}
+
+// check: Run a script on an engine, and list how it fell short of its expectations.
+fn check(script: &Path, engine: Engine) -> Result<Vec<String>, LoxError> {
+    let expected = expectations(&fs::read_to_string(script)?);
+    let output = Command::new(env::current_exe()?)
+        .arg("run")
+        .arg("--fail-fast")
+        .arg(format!("--engine={}", engine))
+        .arg(script)
+        .output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut failures = Vec::new();
+    let printed: Vec<_> = stdout.lines().collect();
+    if printed != expected.output {
+        failures.push(format!(
+            "expected output {:?}, got {:?}",
+            expected.output, printed
+        ));
+    }
+    let status = output.status.code().unwrap_or(-1);
+    match &expected.error {
+        Some((message, code)) => {
+            if status != *code {
+                failures.push(format!("expected exit status {}, got {}", code, status));
+            }
+            // The book ends messages with a full stop, which rlox1 doesn't always.
+            if !stderr.contains(message.trim_end_matches('.')) {
+                failures.push(format!(
+                    "expected error {:?}, got {:?}",
+                    message,
+                    stderr.trim()
+                ));
+            }
+        }
+        None if status != 0 => failures.push(format!(
+            "expected success, got status {}: {}",
+            status,
+            stderr.trim()
+        )),
+        None => (),
+    }
+    Ok(failures)
+}
+
+// scripts: The scripts at `paths`, looking through directories for .lox files, in order. Links
+// to directories found along the way aren't followed, so a link back up the tree can't make the
+// search go round forever; a path given by name is looked through even if it is a link.
+pub fn scripts(paths: &[&str]) -> Result<Vec<PathBuf>, LoxError> {
+    let mut scripts = Vec::new();
+    for path in paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            find(path, &mut scripts)?;
+        } else {
+            scripts.push(path.to_path_buf());
+        }
+    }
+    Ok(scripts)
+}
+
+// find: Add the .lox files in the directory at `path`, and in the ones below it, to `scripts`.
+fn find(path: &Path, scripts: &mut Vec<PathBuf>) -> Result<(), LoxError> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        // DirEntry::file_type doesn't follow links, so a link to a directory isn't one here.
+        entries.push((entry.path(), entry.file_type()?.is_dir()));
+    }
+    entries.sort();
+    for (entry, is_dir) in entries {
+        if is_dir {
+            find(&entry, scripts)?;
+        } else if entry.extension().is_some_and(|ext| ext == "lox") && entry.is_file() {
+            scripts.push(entry);
+        }
+    }
+    Ok(())
+}
+
+// run: Check every script at `paths` on `engine`, printing each failure and then a summary.
+// Any failure makes the whole run an error.
+pub fn run(paths: &[&str], engine: Engine) -> Result<(), LoxError> {
+    let scripts = scripts(paths)?;
+    let mut failed = 0;
+    for script in &scripts {
+        let failures = check(script, engine)?;
+        if !failures.is_empty() {
+            failed += 1;
+            println!("FAIL {}", script.display());
+            for failure in failures {
+                println!("  {}", failure);
+            }
+        }
+    }
+    println!("{} passed, {} failed", scripts.len() - failed, failed);
+    if failed > 0 {
+        loxerr!(LX0905, "{} of {} tests failed", failed, scripts.len())
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{EX_DATAERR, EX_SOFTWARE};
+    use crate::harness::{expectations, scripts, Expectations};
+
+    #[test]
+    fn comments_give_expectations() {
+        let source = "print 1; // expect: 1\n\
+                      // A comment.\n\
+                      print \"a b\"; // expect: a b\n\
+                      print x; // expect runtime error: Undefined variable 'x'.\n";
+        assert_eq!(
+            expectations(source),
+            Expectations {
+                output: vec!["1".into(), "a b".into()],
+                error: Some(("Undefined variable 'x'.".into(), EX_SOFTWARE)),
+            }
+        );
+        for source in &[
+            "print 1 +; // Error at ';': Expect expression.",
+            "// [line 3] Error at ';': Expect expression.",
+        ] {
+            assert_eq!(
+                expectations(source).error,
+                Some(("Error at ';': Expect expression.".into(), EX_DATAERR))
+            );
+        }
+        assert_eq!(expectations("print 2;"), Expectations::default());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_to_directories_are_not_followed() {
+        use std::os::unix::fs::symlink;
+        use std::path::PathBuf;
+        use std::{env, fs};
+
+        let root = env::temp_dir().join(format!("rlox1-links-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::write(root.join("a/one.lox"), "print 1;").unwrap();
+        fs::write(root.join("two.lox"), "print 2;").unwrap();
+        symlink(&root, root.join("a/up")).unwrap();
+        symlink(root.join("two.lox"), root.join("a/three.lox")).unwrap();
+        let found = scripts(&[root.to_str().unwrap()]);
+        let _ = fs::remove_dir_all(&root);
+        let names: Vec<_> = found
+            .unwrap()
+            .iter()
+            .map(|path| path.strip_prefix(&root).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(
+            names,
+            ["a/one.lox", "a/three.lox", "two.lox"].map(PathBuf::from)
+        );
+    }
+}
@@ -0,0 +1,79 @@
+// Syntax highlighting for the REPL: keywords, strings, numbers and comments in color, as the
+// line is typed. The colors come from the scanner's own lexemes, so they always agree with how
+// the line will scan; a stretch that won't scan, like an unfinished string, is left plain.
+
+use crate::scanner::{Lexeme, Scanner, TokenType};
+
+const KEYWORD: &str = "\x1b[35m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[33m";
+const COMMENT: &str = "\x1b[90m";
+const RESET: &str = "\x1b[0m";
+
+// highlight: `line` with terminal color codes. `before` is what was entered on earlier lines
+// of the same entry, which is scanned along with the line, e.g. so the end of a string that
+// started there is a string.
+pub fn highlight(before: &str, line: &str) -> String {
+    let source = format!("{}{}", before, line);
+    let chars: Vec<char> = source.chars().collect();
+    let mut at = before.chars().count();
+    let mut out = String::new();
+    for (range, lexeme) in Scanner::new(&source).lexemes() {
+        let color = match lexeme {
+            Lexeme::Token(TokenType::QuotedString(_)) => STRING,
+            Lexeme::Token(TokenType::Number(_)) => NUMBER,
+            Lexeme::Token(typ) if typ.is_keyword() => KEYWORD,
+            Lexeme::Comment => COMMENT,
+            _ => continue,
+        };
+        let start = range.start.max(at);
+        if start >= range.end {
+            continue;
+        }
+        out.extend(&chars[at..start]);
+        out.push_str(color);
+        out.extend(&chars[start..range.end]);
+        out.push_str(RESET);
+        at = range.end;
+    }
+    out.extend(&chars[at..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::highlight::highlight;
+
+    // plain: Show the colors as tags, to keep the expected strings readable.
+    fn plain(highlighted: &str) -> String {
+        highlighted
+            .replace("\x1b[35m", "<k>")
+            .replace("\x1b[32m", "<s>")
+            .replace("\x1b[33m", "<n>")
+            .replace("\x1b[90m", "<c>")
+            .replace("\x1b[0m", "</>")
+    }
+
+    #[test]
+    fn keywords_strings_and_numbers_are_colored() {
+        assert_eq!(
+            plain(&highlight("", "if (x) print \"hi\" + 1.5; // done")),
+            "<k>if</> (x) <k>print</> <s>\"hi\"</> + <n>1.5</>; <c>// done</>"
+        );
+        // Names that only start like keywords are names.
+        assert_eq!(
+            plain(&highlight("", "var format = nils;")),
+            "<k>var</> format = nils;"
+        );
+    }
+
+    #[test]
+    fn earlier_lines_carry_over() {
+        assert_eq!(
+            plain(&highlight("var s = \"one\n", "two\"; s")),
+            "<s>two\"</>; s"
+        );
+        // An unfinished string doesn't scan, so it isn't colored yet.
+        assert_eq!(plain(&highlight("", "print \"one")), "<k>print</> \"one");
+    }
+}
@@ -0,0 +1,2573 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::ast::{Expr, ExprKind, FunctionDecl, Literal, NodeId, Pattern, Stmt, StmtKind};
+use crate::complete;
+use crate::debugger::Debugger;
+use crate::environment::{Environment, Scope, ScopeRef};
+use crate::error::{ErrorKind, Frame, LoxError};
+use crate::gc;
+use crate::interrupt;
+use crate::memory;
+use crate::profile::Profile;
+use crate::resolver::{Names, Resolver, Slot, Slots};
+use crate::scanner::{Token, TokenType};
+use crate::stack;
+use crate::stdlib;
+use crate::suggest;
+use crate::value::{
+    GeneratorState, LoxClass, LoxFunction, LoxGenerator, LoxInstance, LoxMap, MapKey, Namespace,
+    NativeFunction, ResumePoint, Value,
+};
+
+// The default limit on nested Lox calls, and the stack the interpreter's thread needs to
+// reach it. Lox calls recurse on the Rust stack, and one costs up to ~50KB of it in a debug
+// build, so the limit is chosen to fit STACK_SIZE with room to spare. A program that runs
+// deeply recursive Lox should do it on a thread with STACK_SIZE of stack, as rlox1 does; on a
+// thread with less, e.g. a host's own, the stack left stops the recursion first (see
+// stack.rs), with the same "Stack overflow" error.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+pub const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+// The most characters of a statement `--trace` shows.
+const TRACE_WIDTH: usize = 72;
+
+// How many statements run between looks at the clock, under a time limit, as a clock may be
+// slow to read, e.g. a browser's.
+const CLOCK_STEPS: u64 = 1024;
+
+// Output: Where printing goes, for the print statement and the print natives alike: stdout,
+// or a buffer the host takes the text from, once it has asked for the output to be captured,
+// e.g. to show it in a web page. Copies share the one destination.
+#[derive(Debug, Clone, Default)]
+pub struct Output {
+    captured: Rc<RefCell<Option<String>>>,
+}
+
+impl Output {
+    // line: Print `text` as a line of its own.
+    pub fn line(&self, text: &str) {
+        match &mut *self.captured.borrow_mut() {
+            Some(buffer) => {
+                buffer.push_str(text);
+                buffer.push('\n');
+            }
+            None => println!("{}", text),
+        }
+    }
+
+    // text: Print `text` without ending the line, flushing it so that it shows straight away.
+    pub fn text(&self, text: &str) {
+        match &mut *self.captured.borrow_mut() {
+            Some(buffer) => buffer.push_str(text),
+            None => {
+                print!("{}", text);
+                let _ = io::stdout().flush();
+            }
+        }
+    }
+}
+
+// Interpreter: Executes statements against an environment it owns. Globals defined by one
+// call to `interpret` are visible to the next, so a single interpreter can be fed a program
+// piece by piece.
+pub struct Interpreter {
+    environment: Environment,
+    // Where each local variable use and declaration lives in the program running, from the
+    // resolver. A function keeps its own program's, so they go once nothing can run them.
+    slots: Rc<Slots>,
+    call_depth: usize,
+    max_call_depth: usize,
+    // The way back into a generator being resumed, outermost statement last. Each statement
+    // on the way takes its own point off the end as execution passes through it.
+    resuming: Vec<ResumePoint>,
+    // What has run so far, when profiling.
+    profile: Option<Profile>,
+    // Whether to print each statement before executing it.
+    trace: bool,
+    // Whether to print each statement with its line, and each call with its arguments and
+    // what it returned.
+    trace_exec: bool,
+    // The most memory the program may use, in bytes, over what the process was using when the
+    // limit was set. It is checked before each statement, call and loop pass, after each call
+    // to a native, and as values are shown as text.
+    max_memory: Option<usize>,
+    memory_base: usize,
+    // The most statements the program may execute, how many it has, and how long it may run.
+    max_steps: Option<u64>,
+    steps: u64,
+    time_limit: Option<TimeLimit>,
+    // What to ask before each statement, when debugging, along with what it needs to show
+    // the program: the names of the locals at each statement, the line being executed, and
+    // the calls in progress, each with the line it was made from.
+    debugger: Option<Debugger>,
+    names: Names,
+    line: usize,
+    calls: Vec<(String, usize)>,
+    output: Output,
+}
+
+// Unwind: Why evaluation stopped early: a runtime error, a `return` carrying its value back
+// out to the call being executed, a `yield` pausing a generator, a thrown value looking for a
+// `catch`, or a call to `exit()`.
+enum Unwind {
+    Error(LoxError),
+    Return(Value),
+    Yield(Box<Suspension>),
+    Throw { value: Value, line: usize },
+    Exit(i32),
+}
+
+// Suspension: A generator pausing at `yield`. Each statement the unwind passes through on the
+// way out of the generator's body adds the point to resume it at to `path`.
+struct Suspension {
+    value: Value,
+    scope: ScopeRef,
+    path: Vec<ResumePoint>,
+}
+
+// TimeLimit: How long a program may run, the host's clock, which gives the time since any
+// point it likes, and what the clock said when the limit was set.
+struct TimeLimit {
+    limit: Duration,
+    clock: fn() -> Duration,
+    started: Duration,
+}
+
+impl Unwind {
+    // into_error: The error to report when an unwind escapes to the top level.
+    fn into_error(self) -> LoxError {
+        match self {
+            Unwind::Error(err) => err,
+            // The parser rejects `return` outside a function, so this means the statements
+            // did not come from the parser.
+            Unwind::Return(_) => lox_error!(LX0401, "Can't return from top-level code"),
+            Unwind::Yield(_) => lox_error!(LX0401, "Can't yield from top-level code"),
+            Unwind::Throw { value, line } => {
+                lox_error!(LX0512, "[line {}] Uncaught exception: {}", line, value)
+            }
+            Unwind::Exit(code) => LoxError::exit(code),
+        }
+    }
+
+    // escaped: The error to report when an unwind escapes a program: into_error's, with the
+    // script as the outermost frame of its trace, marked as having happened at runtime.
+    fn escaped(self) -> LoxError {
+        let err = match self {
+            Unwind::Error(err) => err.unwind_from(None, None),
+            other => other.into_error(),
+        };
+        err.at_runtime()
+    }
+}
+
+impl From<LoxError> for Unwind {
+    fn from(err: LoxError) -> Self {
+        match err.exit_code() {
+            Some(code) => Unwind::Exit(code),
+            None => Unwind::Error(err),
+        }
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        let mut interpreter = Interpreter {
+            environment: Environment::new(),
+            slots: Rc::default(),
+            call_depth: 0,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            resuming: Vec::new(),
+            profile: None,
+            trace: false,
+            trace_exec: false,
+            max_memory: None,
+            memory_base: 0,
+            max_steps: None,
+            steps: 0,
+            time_limit: None,
+            debugger: None,
+            names: Names::new(),
+            line: 0,
+            calls: Vec::new(),
+            output: Output::default(),
+        };
+        interpreter.define_native("clock", 0, |_| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|err| LoxError::new(&format!("{}", err)).caused_by(err))?;
+            Ok(Value::Number(now.as_secs_f64()))
+        });
+        interpreter
+    }
+
+    // define_global: Bind a global variable, e.g. a constant provided by the host.
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        self.environment.define_global(name, value);
+    }
+
+    // global: The value of the global variable `name`, if it has been defined.
+    pub fn global(&self, name: &str) -> Option<&Value> {
+        self.environment.global(name)
+    }
+
+    // global_names: The name of every global variable, natives included.
+    pub fn global_names(&self) -> impl Iterator<Item = &str> {
+        self.environment.global_names()
+    }
+
+    // define_native: Make a Rust function callable from Lox as a global named `name`.
+    pub fn define_native<F>(&mut self, name: &str, arity: usize, func: F)
+    where
+        F: Fn(&[Value]) -> Result<Value, LoxError> + 'static,
+    {
+        self.define_global(name, NativeFunction::from_fn(name, arity, func));
+    }
+
+    // define_native_with: Like define_native, for a native that needs the interpreter, e.g. to
+    // show a value as print does.
+    pub fn define_native_with<F>(&mut self, name: &str, arity: usize, func: F)
+    where
+        F: Fn(&mut Interpreter, &[Value]) -> Result<Value, LoxError> + 'static,
+    {
+        let native = NativeFunction {
+            name: name.to_string(),
+            arity,
+            func: Box::new(func),
+        };
+        self.define_global(name, Value::Native(Rc::new(native)));
+    }
+
+    // define_namespace: Bind a namespace of natives as a global under its own name.
+    pub fn define_namespace(&mut self, namespace: Namespace) {
+        let name = namespace.name.clone();
+        self.define_global(&name, Value::Namespace(Rc::new(namespace)));
+    }
+
+    // set_max_call_depth: Limit how deeply Lox calls may nest. Going deeper is a runtime
+    // error rather than a crash of the whole process.
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    // set_max_memory: Stop the program with a runtime error once it uses more than `bytes` of
+    // memory that a collection can't free, as memory::set_meter measures it. What the process
+    // is using already, e.g. for the host, doesn't count against the program.
+    pub fn set_max_memory(&mut self, bytes: usize) {
+        self.max_memory = Some(bytes);
+        self.memory_base = memory::in_use().unwrap_or(0);
+    }
+
+    // set_max_steps: Stop the program with a runtime error once it has executed more than
+    // `steps` statements from now on, so that a loop that never ends can't hang the host.
+    pub fn set_max_steps(&mut self, steps: u64) {
+        self.max_steps = Some(steps);
+        self.steps = 0;
+    }
+
+    // set_time_limit: Stop the program with a runtime error once it has run for longer than
+    // `limit` from now on, as `clock` tells the time. The host gives the clock, since std
+    // can't tell the time everywhere, e.g. in a browser.
+    pub fn set_time_limit(&mut self, limit: Duration, clock: fn() -> Duration) {
+        self.time_limit = Some(TimeLimit {
+            limit,
+            clock,
+            started: clock(),
+        });
+        self.steps = 0;
+    }
+
+    // check_memory: An error if the program is over the limit set_max_memory gave it, once
+    // garbage has been collected. A native that builds a big value a piece at a time can call
+    // it as it goes, rather than leave the check to when it returns.
+    pub fn check_memory(&self) -> Result<(), LoxError> {
+        let max = match self.max_memory {
+            Some(max) => max,
+            None => return Ok(()),
+        };
+        let used = || memory::in_use().map_or(0, |bytes| bytes.saturating_sub(self.memory_base));
+        if used() > max {
+            // Cycles may be holding memory the collector hasn't got to yet.
+            gc::collect();
+            if used() > max {
+                loxerr!(LX0602, "Out of memory: the limit is {} bytes", max)
+            }
+        }
+        Ok(())
+    }
+
+    // profile: Count every node evaluated from now on, and time every function call.
+    pub fn profile(&mut self) {
+        self.profile = Some(Profile::new("nodes"));
+    }
+
+    // trace: Print every statement to stderr from now on, as it is about to be executed,
+    // indented by how deeply calls are nested.
+    pub fn trace(&mut self) {
+        self.trace = true;
+    }
+
+    // debug: Let `debugger` see every statement from now on before it is executed, and stop
+    // the program there if it wants to.
+    pub fn debug(&mut self, debugger: Debugger) {
+        self.debugger = Some(debugger);
+    }
+
+    // output: Where printing goes, for natives that print.
+    pub fn output(&self) -> Output {
+        self.output.clone()
+    }
+
+    // capture_output: Keep what is printed from now on, for take_output, rather than writing
+    // it to stdout.
+    pub fn capture_output(&mut self) {
+        self.output.captured.borrow_mut().get_or_insert_with(String::new);
+    }
+
+    // take_output: What has been printed since output was captured or last taken.
+    pub fn take_output(&mut self) -> String {
+        let mut captured = self.output.captured.borrow_mut();
+        captured.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    // call_depth: How many Lox calls are in progress.
+    pub fn call_depth(&self) -> usize {
+        self.call_depth
+    }
+
+    // variable: The value of the variable `name` as `stmt` would see it, while debugging.
+    pub fn variable(&self, stmt: &Stmt, name: &str) -> Option<Value> {
+        let locals = self.names.get(&stmt.id).into_iter().flatten();
+        match locals.into_iter().find(|(local, _)| local == name) {
+            Some((_, slot)) => self.environment.find_local(*slot),
+            None => self.global(name).cloned(),
+        }
+    }
+
+    // locals: The local variables `stmt` can see and their values, innermost scope first,
+    // while debugging. Shadowed variables are left out.
+    pub fn locals(&self, stmt: &Stmt) -> Vec<(String, Value)> {
+        let mut locals: Vec<(String, Value)> = Vec::new();
+        for (name, slot) in self.names.get(&stmt.id).into_iter().flatten() {
+            if locals.iter().all(|(seen, _)| seen != name) {
+                if let Some(value) = self.environment.find_local(*slot) {
+                    locals.push((name.clone(), value));
+                }
+            }
+        }
+        locals
+    }
+
+    // backtrace: The calls in progress while debugging, innermost first, starting from
+    // `line` in the innermost.
+    pub fn backtrace(&self, line: usize) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        let mut line = line;
+        for (function, called_from) in self.calls.iter().rev() {
+            frames.push(Frame {
+                line,
+                function: Some(function.clone()),
+                repeated: 0,
+            });
+            line = *called_from;
+        }
+        frames.push(Frame {
+            line,
+            function: None,
+            repeated: 0,
+        });
+        frames
+    }
+
+    // trace_exec: Like trace, but with the line of each statement, and with every call of a
+    // Lox function shown as it starts and ends, along with its arguments and result.
+    pub fn trace_exec(&mut self) {
+        self.trace_exec = true;
+    }
+
+    // take_profile: What has run since `profile` was called.
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profile.take()
+    }
+
+    // interpret: Resolve the statements, then execute them in order, stopping at the first
+    // runtime error. Nothing runs if the resolver finds an error.
+    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<(), LoxError> {
+        self.resolve(statements)?;
+        self.execute_all(statements)
+            .map_err(|unwind| self.uncaught(unwind).escaped())
+    }
+
+    // eval: Like interpret, but give back the value of the last statement, if it is an
+    // expression, or else nil, for a host that wants the result of what it ran.
+    pub fn eval(&mut self, statements: &[Stmt]) -> Result<Value, LoxError> {
+        self.resolve(statements)?;
+        let result = match statements.split_last() {
+            Some((last, rest)) => match &last.kind {
+                StmtKind::Expression(expr) => {
+                    self.execute_all(rest).and_then(|()| self.evaluate(expr))
+                }
+                _ => self.execute_all(statements).map(|()| Value::Nil),
+            },
+            None => Ok(Value::Nil),
+        };
+        result.map_err(|unwind| self.uncaught(unwind).escaped())
+    }
+
+    // call_value: Call `callee` for the host, e.g. a function that a script defined, and give
+    // back what it returns. No Lox code made the call, so an error about the call itself has
+    // no line, and the trace of one from inside it ends in the callee.
+    pub fn call_value(&mut self, callee: &Value, args: &[Value]) -> Result<Value, LoxError> {
+        let arity = match callee {
+            Value::Native(native) if native.accepts(args.len()) => args.len(),
+            Value::Native(native) => native.arity,
+            Value::Function(function) => function.arity(),
+            Value::Class(class) => class.find_method("init").map_or(0, |init| init.arity()),
+            _ => loxerr!(LX0408, "Can only call functions and classes"),
+        };
+        if args.len() != arity {
+            loxerr!(LX0409, "Expected {} arguments but got {}", arity, args.len())
+        }
+        // A function's name, where it was declared, stands in for the call site.
+        let site = match callee {
+            Value::Native(native) => return (native.func)(self, args),
+            Value::Function(function) => function.declaration.name.clone(),
+            _ => Token::new(TokenType::Identifier(format!("{}", callee)), 0),
+        };
+        self.call(callee, &site, args)
+            .map_err(|unwind| self.uncaught(unwind).into_error().at_runtime())
+    }
+
+    // resolve: Resolve the statements, keeping where each of their variables is found, and
+    // the names in each scope for the debugger.
+    fn resolve(&mut self, statements: &[Stmt]) -> Result<(), LoxError> {
+        if self.debugger.is_some() {
+            let (slots, names) = Resolver::new().resolve_with_names(statements)?;
+            self.slots = Rc::new(slots);
+            self.names.extend(names);
+        } else {
+            self.slots = Rc::new(Resolver::new().resolve(statements)?);
+        }
+        Ok(())
+    }
+
+    fn execute_all(&mut self, statements: &[Stmt]) -> Result<(), Unwind> {
+        let start = match self.resuming.pop() {
+            Some(ResumePoint::Statement(index)) => index,
+            Some(_) => unreachable!("resuming a statement list at a statement"),
+            None => 0,
+        };
+        for (index, stmt) in statements.iter().enumerate().skip(start) {
+            suspend_at(self.execute(stmt), || ResumePoint::Statement(index))?;
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, stmt: &Stmt) -> Result<(), Unwind> {
+        gc::maybe_collect();
+        self.count_step()?;
+        if interrupt::requested() {
+            loxerr!(LX0603, "Interrupted")
+        }
+        if self.debugger.is_some() {
+            self.pause(stmt)?;
+        }
+        if let Some(profile) = &mut self.profile {
+            profile.count(stmt.kind.name());
+        }
+        if self.trace {
+            eprintln!("trace: {}{}", "  ".repeat(self.call_depth), shorten(stmt));
+        }
+        if self.trace_exec {
+            self.trace_statement(stmt);
+        }
+        match &stmt.kind {
+            StmtKind::Expression(expr) => {
+                self.evaluate(expr)?;
+            }
+            StmtKind::Print(expr) => {
+                let value = self.evaluate(expr)?;
+                let text = self.stringify(&value)?;
+                self.output.line(&text);
+            }
+            StmtKind::Var {
+                name, initializer, ..
+            } => {
+                let value = match initializer {
+                    Some(initializer) => self.evaluate(initializer)?,
+                    None => Value::Nil,
+                };
+                self.declare(stmt.id, name, value);
+            }
+            StmtKind::Function(decl) => self.declare_function(stmt.id, decl),
+            StmtKind::Class {
+                name,
+                superclass,
+                mixins,
+                methods,
+                getters,
+                setters,
+            } => {
+                let superclass = match superclass {
+                    Some(expr) => match self.evaluate(expr)? {
+                        Value::Class(class) => Some(class),
+                        _ => loxerr!(LX0406, "[line {}] Superclass must be a class", name.line),
+                    },
+                    None => None,
+                };
+                let mut mixed_in = Vec::new();
+                for mixin in mixins {
+                    match self.evaluate(mixin)? {
+                        Value::Class(class) => mixed_in.push(class),
+                        _ => loxerr!(LX0406, "[line {}] Mixin must be a class", name.line),
+                    }
+                }
+                // Methods close over a scope holding the superclass, where `super` finds it.
+                if let Some(superclass) = &superclass {
+                    self.environment
+                        .push_scope(vec![Value::Class(superclass.clone())]);
+                }
+                let closure = self.environment.capture();
+                if superclass.is_some() {
+                    self.environment.pop_scope();
+                }
+                let functions = |decls: &[Rc<FunctionDecl>], methods: bool| {
+                    decls
+                        .iter()
+                        .map(|decl| {
+                            let function_name = format!("{}", decl.name.typ);
+                            let function = Rc::new(LoxFunction {
+                                declaration: decl.clone(),
+                                closure: closure.clone(),
+                                slots: self.slots.clone(),
+                                is_initializer: methods && function_name == "init",
+                            });
+                            gc::track_function(&function);
+                            (function_name, function)
+                        })
+                        .collect::<Vec<_>>()
+                };
+                let mut all_methods = mix_in(name, &mixed_in, methods)?;
+                all_methods.extend(functions(methods, true));
+                let class = LoxClass {
+                    name: format!("{}", name.typ),
+                    superclass,
+                    methods: all_methods,
+                    getters: functions(getters, false).into_iter().collect(),
+                    setters: functions(setters, false).into_iter().collect(),
+                };
+                self.declare(stmt.id, name, Value::class(class));
+            }
+            StmtKind::Block(statements) => {
+                // A resumed block's scope is already in the chain the generator paused in.
+                if self.resuming.is_empty() {
+                    self.environment.push_scope(Vec::new());
+                }
+                let result = self.execute_all(statements);
+                self.environment.pop_scope();
+                result?;
+            }
+            StmtKind::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let then = match self.resuming.pop() {
+                    Some(ResumePoint::If { then }) => then,
+                    Some(_) => unreachable!("resuming an if statement at a branch"),
+                    None => self.evaluate(condition)?.is_truthy(),
+                };
+                if then {
+                    suspend_at(self.execute(then_branch), || ResumePoint::If { then })?;
+                } else if let Some(else_branch) = else_branch {
+                    suspend_at(self.execute(else_branch), || ResumePoint::If { then })?;
+                }
+            }
+            StmtKind::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => self.evaluate(value)?,
+                    None => Value::Nil,
+                };
+                return Err(Unwind::Return(value));
+            }
+            StmtKind::Throw { keyword, value } => {
+                let value = self.evaluate(value)?;
+                return Err(Unwind::Throw {
+                    value,
+                    line: keyword.line,
+                });
+            }
+            StmtKind::Try { body, handler, .. } => {
+                let resumed = match self.resuming.pop() {
+                    Some(ResumePoint::Try { in_handler }) => Some(in_handler),
+                    Some(_) => unreachable!("resuming a try statement in its body or handler"),
+                    None => None,
+                };
+                if resumed != Some(true) {
+                    if resumed.is_none() {
+                        self.environment.push_scope(Vec::new());
+                    }
+                    let result = self.execute_all(body);
+                    self.environment.pop_scope();
+                    // Runtime errors are catchable too; the handler sees their message. A
+                    // call to exit() is not, so it still ends the script, and nor is Ctrl-C.
+                    let caught = match result {
+                        Err(Unwind::Throw { value, .. }) => value,
+                        Err(Unwind::Error(err)) if !interrupt::requested() => {
+                            Value::Str(err.message().into())
+                        }
+                        other => {
+                            return suspend_at(other, || ResumePoint::Try { in_handler: false })
+                        }
+                    };
+                    self.environment.push_scope(vec![caught]);
+                }
+                let result = self.execute_all(handler);
+                self.environment.pop_scope();
+                suspend_at(result, || ResumePoint::Try { in_handler: true })?;
+            }
+            StmtKind::ForIn {
+                keyword,
+                iterable,
+                body,
+                ..
+            } => {
+                let (iterable, mut position, mut resumed) = match self.resuming.pop() {
+                    Some(ResumePoint::ForIn { iterable, position }) => (iterable, position, true),
+                    Some(_) => unreachable!("resuming a for-in loop in one of its passes"),
+                    None => (self.evaluate(iterable)?, 0, false),
+                };
+                // Each pass gets a fresh scope, so every iteration has its own variable. A
+                // resumed pass already has one.
+                loop {
+                    self.check_memory()?;
+                    if !resumed {
+                        match self.iteration_item(&iterable, position, keyword)? {
+                            Some(item) => self.environment.push_scope(vec![item]),
+                            None => break,
+                        }
+                    }
+                    resumed = false;
+                    let result = self.execute(body);
+                    self.environment.pop_scope();
+                    suspend_at(result, || ResumePoint::ForIn {
+                        iterable: iterable.clone(),
+                        position,
+                    })?;
+                    position += 1;
+                }
+            }
+            StmtKind::While { .. } | StmtKind::For { .. } => return self.run_loop(&stmt.kind),
+            StmtKind::Yield { value, .. } => {
+                if let Some(point) = self.resuming.pop() {
+                    debug_assert!(matches!(point, ResumePoint::Yield));
+                    return Ok(());
+                }
+                let value = match value {
+                    Some(value) => self.evaluate(value)?,
+                    None => Value::Nil,
+                };
+                return Err(Unwind::Yield(Box::new(Suspension {
+                    value,
+                    scope: self.environment.capture().expect("a generator's scope"),
+                    path: vec![ResumePoint::Yield],
+                })));
+            }
+        }
+        Ok(())
+    }
+
+    // run_loop: Run a while or C-style for loop. A for loop's initializer declares its variable
+    // in a scope of its own that every pass shares, as in C, so a closure made in the body sees
+    // it change. A resumed loop's scope is already in the chain the generator paused in. Like
+    // run_body, this is kept out of execute, whose stack every nested statement takes.
+    #[inline(never)]
+    fn run_loop(&mut self, kind: &StmtKind) -> Result<(), Unwind> {
+        let (initializer, condition, increment, body) = match kind {
+            StmtKind::While {
+                condition, body, ..
+            } => return self.loop_passes(Some(condition), None, body),
+            StmtKind::For {
+                initializer,
+                condition,
+                increment,
+                body,
+                ..
+            } => (initializer, condition.as_ref(), increment.as_ref(), body),
+            _ => unreachable!("running a statement that isn't a loop as one"),
+        };
+        let mut result = Ok(());
+        if self.resuming.is_empty() {
+            self.environment.push_scope(Vec::new());
+            if let Some(initializer) = initializer {
+                result = self.execute(initializer);
+            }
+        }
+        if result.is_ok() {
+            result = self.loop_passes(condition, increment, body);
+        }
+        self.environment.pop_scope();
+        result
+    }
+
+    // loop_passes: Run the passes of a loop, until `condition` is false. With no condition,
+    // it loops until the body returns or throws.
+    fn loop_passes(
+        &mut self,
+        condition: Option<&Expr>,
+        increment: Option<&Expr>,
+        body: &Stmt,
+    ) -> Result<(), Unwind> {
+        let mut resumed = match self.resuming.pop() {
+            Some(ResumePoint::Loop) => true,
+            Some(_) => unreachable!("resuming a loop in one of its passes"),
+            None => false,
+        };
+        loop {
+            if !resumed {
+                self.check_memory()?;
+                if let Some(condition) = condition {
+                    if !self.evaluate(condition)?.is_truthy() {
+                        return Ok(());
+                    }
+                }
+            }
+            resumed = false;
+            suspend_at(self.execute(body), || ResumePoint::Loop)?;
+            if let Some(increment) = increment {
+                self.evaluate(increment)?;
+            }
+        }
+    }
+
+    // declare_function: Declare the function `decl`, closing over the scope it is declared
+    // in. It is kept out of execute, whose stack every nested statement takes.
+    #[inline(never)]
+    fn declare_function(&mut self, id: NodeId, decl: &Rc<FunctionDecl>) {
+        let function = LoxFunction {
+            declaration: decl.clone(),
+            closure: self.environment.capture(),
+            slots: self.slots.clone(),
+            is_initializer: false,
+        };
+        self.declare(id, &decl.name, Value::function(function));
+    }
+
+    // declare: Bind a declared name, in the local slot the resolver gave it or else as a
+    // global.
+    fn declare(&mut self, id: NodeId, name: &Token, value: Value) {
+        match self.slots.get(&id) {
+            Some(slot) => self.environment.define_local(slot.index, value),
+            None => self
+                .environment
+                .define_global(&format!("{}", name.typ), value),
+        }
+    }
+
+    // iteration_item: The item at `position` of a for-in loop over `iterable`, or None when
+    // the loop is done. Maps yield their keys in insertion order. Collections are re-checked
+    // on every pass, so a loop sees changes made by its own body.
+    fn iteration_item(
+        &mut self,
+        iterable: &Value,
+        position: usize,
+        keyword: &Token,
+    ) -> Result<Option<Value>, Unwind> {
+        match iterable {
+            Value::Generator(generator) => self.resume(generator),
+            Value::List(items) => Ok(items.borrow().get(position).cloned()),
+            Value::Map(map) => Ok(map.borrow().key_at(position)),
+            Value::Str(s) => Ok(s
+                .chars()
+                .nth(position)
+                .map(|c| Value::Str(c.to_string().into()))),
+            other => loxerr!(
+                LX0509,
+                "[line {}] Can only iterate over lists, maps, strings and generators, got {}",
+                keyword.line,
+                other.type_name()
+            ),
+        }
+    }
+
+    // evaluate: Work out the value of an expression. A runtime error that doesn't say yet where
+    // on its line it happened is pointed at the expression's token, e.g. the operator of a
+    // binary expression, as long as that is on the line the error is reported on.
+    fn evaluate(&mut self, expr: &Expr) -> Result<Value, Unwind> {
+        match self.evaluate_node(expr) {
+            Err(Unwind::Error(err)) if err.span().is_none() => Err(Unwind::Error(locate(err, expr))),
+            result => result,
+        }
+    }
+
+    fn evaluate_node(&mut self, expr: &Expr) -> Result<Value, Unwind> {
+        if let Some(profile) = &mut self.profile {
+            profile.count(expr.kind.name());
+        }
+        match &expr.kind {
+            ExprKind::Literal(lit) => Ok(literal_value(lit)),
+            ExprKind::Grouping(inner) => self.evaluate(inner),
+            ExprKind::Variable(name) => match self.slots.get(&expr.id) {
+                Some(slot) => Ok(self.environment.get_local(*slot)),
+                None => Ok(self.environment.get_global(name)?),
+            },
+            ExprKind::Logical { left, op, right } => {
+                let left = self.evaluate(left)?;
+                // `or` stops at the first truthy operand, `and` at the first falsey one.
+                if (op.typ == TokenType::Or) == left.is_truthy() {
+                    Ok(left)
+                } else {
+                    self.evaluate(right)
+                }
+            }
+            ExprKind::Assign { name, value } => {
+                let value = self.evaluate(value)?;
+                match self.slots.get(&expr.id) {
+                    Some(slot) => self.environment.assign_local(*slot, value.clone()),
+                    None => self.environment.assign_global(name, value.clone())?,
+                }
+                Ok(value)
+            }
+            ExprKind::List(elements) => {
+                let mut items = Vec::with_capacity(elements.len());
+                for element in elements {
+                    items.push(self.evaluate(element)?);
+                }
+                Ok(Value::list(items))
+            }
+            ExprKind::Map { brace, entries } => {
+                let map = Rc::new(RefCell::new(LoxMap::new()));
+                for (key, value) in entries {
+                    let key = self.evaluate(key)?;
+                    let value = self.evaluate(value)?;
+                    self.map_set(&map, key, value)
+                        .map_err(|err| at_line(brace, err))?;
+                }
+                // Lox code can't see the map until it is built, so nothing else holds it.
+                let map = Rc::try_unwrap(map).expect("an unshared map").into_inner();
+                Ok(Value::map(map))
+            }
+            ExprKind::Unary { op, right } => {
+                let right = self.evaluate(right)?;
+                match op.typ {
+                    TokenType::Bang => Ok(Value::Bool(!right.is_truthy())),
+                    TokenType::Minus => match right {
+                        Value::Number(n) => Ok(Value::Number(-n)),
+                        other => loxerr!(
+                            LX0501,
+                            "[line {}] Operand of '{}' must be a number, got {}",
+                            op.line,
+                            op.typ,
+                            other.type_name()
+                        ),
+                    },
+                    _ => Err(invalid_operator(op).into()),
+                }
+            }
+            ExprKind::Binary { left, op, right } => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+                self.binary(left, op, right)
+            }
+            ExprKind::Call {
+                callee,
+                paren,
+                arguments,
+            } => {
+                let callee = self.evaluate(callee)?;
+                let mut args = Vec::with_capacity(arguments.len());
+                for argument in arguments {
+                    args.push(self.evaluate(argument)?);
+                }
+                self.call(&callee, paren, &args)
+            }
+            ExprKind::Get { object, name } => {
+                let object = self.evaluate(object)?;
+                let key = format!("{}", name.typ);
+                let property = match &object {
+                    Value::List(items) => stdlib::list::method(items, &key),
+                    Value::Map(map) => stdlib::map::method(map, &key),
+                    Value::Namespace(namespace) => namespace.get(&key),
+                    Value::WeakRef(weak) => stdlib::weakref::method(weak, &key),
+                    Value::Generator(generator) => stdlib::generator::method(generator, &key),
+                    // Fields shadow getters, which shadow methods.
+                    Value::Instance(instance) => {
+                        let class = instance.borrow().class.clone();
+                        let field = instance.borrow().fields.get(&key).cloned();
+                        if field.is_some() {
+                            field
+                        } else if let Some(getter) = class.find_getter(&key) {
+                            return self.call_accessor(&getter, &object, name, &[]);
+                        } else if class.find_setter(&key).is_some() {
+                            loxerr!(
+                                LX0411,
+                                "[line {}] Property '{}' of {} has a setter but no getter",
+                                name.line,
+                                key,
+                                describe(&object)
+                            )
+                        } else {
+                            class
+                                .find_method(&key)
+                                .map(|method| method.bind(object.clone()))
+                        }
+                    }
+                    _ => None,
+                };
+                match property {
+                    Some(property) => Ok(property),
+                    None => Err(undefined_property(name, &key, &object).into()),
+                }
+            }
+            ExprKind::Set {
+                object,
+                name,
+                value,
+            } => {
+                let object = self.evaluate(object)?;
+                let value = self.evaluate(value)?;
+                match &object {
+                    Value::Instance(instance) => {
+                        let key = format!("{}", name.typ);
+                        let class = instance.borrow().class.clone();
+                        if let Some(setter) = class.find_setter(&key) {
+                            self.call_accessor(
+                                &setter,
+                                &object,
+                                name,
+                                std::slice::from_ref(&value),
+                            )?;
+                        } else if class.find_getter(&key).is_some() {
+                            loxerr!(
+                                LX0411,
+                                "[line {}] Property '{}' of {} has a getter but no setter",
+                                name.line,
+                                key,
+                                describe(&object)
+                            )
+                        } else {
+                            instance.borrow_mut().fields.insert(key, value.clone());
+                        }
+                        Ok(value)
+                    }
+                    _ => loxerr!(LX0410, "[line {}] Only instances have fields", name.line),
+                }
+            }
+            ExprKind::This(_) => Ok(self.environment.get_local(self.slots[&expr.id])),
+            ExprKind::Super { method, .. } => {
+                // The resolver put `this` in the scope just inside the one holding `super`.
+                let slot = self.slots[&expr.id];
+                let superclass = self.environment.get_local(slot);
+                let this = self.environment.get_local(Slot {
+                    depth: slot.depth - 1,
+                    index: 0,
+                });
+                let key = format!("{}", method.typ);
+                let found = match &superclass {
+                    Value::Class(class) => class.find_method(&key),
+                    _ => None,
+                };
+                match found {
+                    Some(found) => Ok(found.bind(this)),
+                    None => Err(undefined_super_method(method, &key, &superclass).into()),
+                }
+            }
+            ExprKind::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                Ok(self.index(&object, bracket, &index)?)
+            }
+            ExprKind::Slice {
+                object,
+                bracket,
+                start,
+                end,
+            } => {
+                let object = self.evaluate(object)?;
+                let start = match start {
+                    Some(start) => Some(self.evaluate(start)?),
+                    None => None,
+                };
+                let end = match end {
+                    Some(end) => Some(self.evaluate(end)?),
+                    None => None,
+                };
+                Ok(self.slice(&object, bracket, start.as_ref(), end.as_ref())?)
+            }
+            ExprKind::SetIndex {
+                object,
+                bracket,
+                index,
+                value,
+            } => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                let value = self.evaluate(value)?;
+                match &object {
+                    Value::List(items) => {
+                        let i = to_index(&index, items.borrow().len(), "list", bracket)?;
+                        items.borrow_mut()[i] = value.clone();
+                        Ok(value)
+                    }
+                    Value::Map(map) => {
+                        self.map_set(map, index, value.clone())
+                            .map_err(|err| at_line(bracket, err))?;
+                        Ok(value)
+                    }
+                    Value::Str(_) => {
+                        loxerr!(LX0505, "[line {}] Strings are immutable", bracket.line)
+                    }
+                    _ => loxerr!(
+                        LX0505,
+                        "[line {}] Only lists and maps support index assignment",
+                        bracket.line
+                    ),
+                }
+            }
+            ExprKind::Match {
+                keyword,
+                subject,
+                arms,
+            } => {
+                let subject = self.evaluate(subject)?;
+                for arm in arms {
+                    match &arm.pattern {
+                        Pattern::Literal(lit) if literal_value(lit) == subject => {
+                            return self.evaluate(&arm.body)
+                        }
+                        Pattern::Literal(_) => {}
+                        Pattern::Wildcard => return self.evaluate(&arm.body),
+                        Pattern::Binding(_) => {
+                            self.environment.push_scope(vec![subject]);
+                            let result = self.evaluate(&arm.body);
+                            self.environment.pop_scope();
+                            return result;
+                        }
+                    }
+                }
+                let subject = self.repr(&subject)?;
+                loxerr!(
+                    LX0510,
+                    "[line {}] No match arm for {}",
+                    keyword.line,
+                    subject
+                )
+            }
+        }
+    }
+
+    // show: `value` as text, the way print shows it, for a native.
+    pub fn show(&mut self, value: &Value) -> Result<String, LoxError> {
+        self.stringify(value)
+            .map_err(|unwind| self.uncaught(unwind).into_error())
+    }
+
+    // stringify: How a value is shown as text: by print and the natives that print or make
+    // strings, by `+` with a string, and in errors. An instance whose class has a `toString()`
+    // method is shown as the string that method returns, in a list or map as well; everything
+    // else is shown as it displays. Errors in `toString()` are reported at its declaration, as
+    // there is no call site.
+    fn stringify(&mut self, value: &Value) -> Result<String, Unwind> {
+        // The elements are copied out, so that `toString()` is free to change the collection.
+        let (open, items, close) = match value {
+            Value::List(items) => ("[", items.borrow().clone(), "]"),
+            Value::Map(map) => {
+                let map = map.borrow();
+                let entries = map.keys().into_iter().zip(map.values().cloned());
+                ("{", entries.flat_map(|(key, value)| vec![key, value]).collect(), "}")
+            }
+            _ => return self.stringify_instance(value),
+        };
+        let mut shown = Vec::new();
+        for item in &items {
+            self.check_memory()?;
+            shown.push(self.repr(item)?);
+        }
+        let parts: Vec<String> = match value {
+            Value::Map(_) => shown.chunks(2).map(|entry| entry.join(": ")).collect(),
+            _ => shown,
+        };
+        Ok(format!("{}{}{}", open, parts.join(", "), close))
+    }
+
+    // repr: Like stringify, but with strings quoted, as a collection or an error shows them, so
+    // that `[1, "1"]` does not show as `[1, 1]`.
+    fn repr(&mut self, value: &Value) -> Result<String, Unwind> {
+        match value {
+            Value::Str(_) => Ok(value.repr()),
+            _ => self.stringify(value),
+        }
+    }
+
+    fn stringify_instance(&mut self, value: &Value) -> Result<String, Unwind> {
+        match self.call_method(value, "toString", &[])? {
+            Some((Value::Str(s), _)) => Ok(s.to_string()),
+            Some((other, name)) => loxerr!(
+                LX0511,
+                "[line {}] toString() must return a string, got {}",
+                name.line,
+                other.type_name()
+            ),
+            None => Ok(format!("{}", value)),
+        }
+    }
+
+    // uncaught: `unwind` as it leaves the program or a native, with a value it throws shown the
+    // way print shows it.
+    fn uncaught(&mut self, unwind: Unwind) -> Unwind {
+        match unwind {
+            Unwind::Throw { value, line } => match self.stringify(&value) {
+                Ok(text) => Unwind::Error(lox_error!(
+                    LX0512,
+                    "[line {}] Uncaught exception: {}",
+                    line,
+                    text
+                )),
+                Err(err) => err,
+            },
+            other => other,
+        }
+    }
+
+    // call_method: Call the method `name` of `value`, if it is an instance whose class has
+    // one, for one of the protocols the interpreter runs on its own. Returns the result along
+    // with the method's name, which stands in for a call site in errors.
+    fn call_method(
+        &mut self,
+        value: &Value,
+        name: &str,
+        args: &[Value],
+    ) -> Result<Option<(Value, Token)>, Unwind> {
+        match method_of(value, name) {
+            Some(Value::Function(method)) => {
+                let name = method.declaration.name.clone();
+                let result = self.call_function(&method, &name, args)?;
+                Ok(Some((result, name)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // call_accessor: Run a getter or setter of `instance` for the property `name`.
+    fn call_accessor(
+        &mut self,
+        accessor: &LoxFunction,
+        instance: &Value,
+        name: &Token,
+        args: &[Value],
+    ) -> Result<Value, Unwind> {
+        match accessor.bind(instance.clone()) {
+            Value::Function(bound) => self.call_function(&bound, name, args),
+            _ => unreachable!("a bound method is a function"),
+        }
+    }
+
+    // map_get, map_set, map_remove: Map operations for the interpreter and natives. Keys that
+    // are instances hash by identity, unless their class has `hash()` and `equals()` methods.
+    pub fn map_get(
+        &mut self,
+        map: &Rc<RefCell<LoxMap>>,
+        key: &Value,
+    ) -> Result<Option<Value>, LoxError> {
+        let (_, position) = self.map_find(map, key).map_err(|unwind| self.uncaught(unwind).into_error())?;
+        Ok(position.map(|i| map.borrow().value_at(i)))
+    }
+
+    pub fn map_set(
+        &mut self,
+        map: &Rc<RefCell<LoxMap>>,
+        key: Value,
+        value: Value,
+    ) -> Result<(), LoxError> {
+        let (hashed, position) = self.map_find(map, &key).map_err(|unwind| self.uncaught(unwind).into_error())?;
+        match position {
+            Some(i) => map.borrow_mut().set_at(i, value),
+            None => map.borrow_mut().insert(hashed, key, value),
+        }
+        Ok(())
+    }
+
+    pub fn map_remove(
+        &mut self,
+        map: &Rc<RefCell<LoxMap>>,
+        key: &Value,
+    ) -> Result<Option<Value>, LoxError> {
+        let (_, position) = self.map_find(map, key).map_err(|unwind| self.uncaught(unwind).into_error())?;
+        Ok(position.map(|i| map.borrow_mut().remove_at(i)))
+    }
+
+    // map_find: The hashed form of `key`, and the position of its entry in `map` if it has
+    // one. An instance whose class has `hash()` hashes as the key its result makes, and is
+    // compared with the keys sharing that hash the way `==` would compare them.
+    fn map_find(
+        &mut self,
+        map: &Rc<RefCell<LoxMap>>,
+        key: &Value,
+    ) -> Result<(MapKey, Option<usize>), Unwind> {
+        let hashed = match self.call_method(key, "hash", &[])? {
+            Some((hash, name)) => MapKey::Hashed(Box::new(
+                MapKey::from_value(&hash).map_err(|err| at_line(&name, err))?,
+            )),
+            None => MapKey::from_value(key)?,
+        };
+        // The candidates are copied out, so `equals()` is free to use the map.
+        let candidates = map.borrow().candidates(&hashed);
+        for (position, other) in candidates {
+            let same = match hashed {
+                MapKey::Hashed(_) => {
+                    match self.call_method(key, "equals", std::slice::from_ref(&other))? {
+                        Some((result, _)) => result.is_truthy(),
+                        None => *key == other,
+                    }
+                }
+                _ => true,
+            };
+            if same {
+                return Ok((hashed, Some(position)));
+            }
+        }
+        Ok((hashed, None))
+    }
+
+    fn call(&mut self, callee: &Value, paren: &Token, args: &[Value]) -> Result<Value, Unwind> {
+        match callee {
+            Value::Native(native) => {
+                if !native.accepts(args.len()) {
+                    loxerr!(
+                        LX0409,
+                        "[line {}] Expected {} arguments but got {}",
+                        paren.line,
+                        native.arity,
+                        args.len()
+                    )
+                }
+                let result = (native.func)(self, args);
+                let result = result.and_then(|value| self.check_memory().map(|_| value));
+                Ok(result.map_err(|err| at_line(paren, err))?)
+            }
+            Value::Function(function) => self.call_function(function, paren, args),
+            // Calling a class makes an instance and runs its initializer, if it has one.
+            Value::Class(class) => {
+                let instance = Value::instance(LoxInstance::new(class.clone()));
+                match class.find_method("init") {
+                    Some(init) => {
+                        if let Value::Function(init) = init.bind(instance.clone()) {
+                            self.call_function(&init, paren, args)?;
+                        }
+                    }
+                    None if !args.is_empty() => loxerr!(
+                        LX0409,
+                        "[line {}] Expected {} arguments but got {}",
+                        paren.line,
+                        0,
+                        args.len()
+                    ),
+                    None => {}
+                }
+                Ok(instance)
+            }
+            _ => loxerr!(LX0408, "[line {}] Can only call functions and classes", paren.line),
+        }
+    }
+
+    fn call_function(
+        &mut self,
+        function: &LoxFunction,
+        paren: &Token,
+        args: &[Value],
+    ) -> Result<Value, Unwind> {
+        if args.len() != function.arity() {
+            loxerr!(
+                LX0409,
+                "[line {}] Expected {} arguments but got {}",
+                paren.line,
+                function.arity(),
+                args.len()
+            )
+        }
+        // A generator's body doesn't run until something asks for its first value.
+        if function.declaration.is_generator {
+            return Ok(generator(function, args));
+        }
+        self.check_call(paren)?;
+        if self.trace_exec {
+            self.trace_call(&function.declaration.name, args);
+        }
+        self.call_depth += 1;
+        self.enter(&function.declaration.name);
+        let caller = self
+            .environment
+            .begin_call(function.closure.clone(), args.to_vec());
+        let timed = self.profile.as_mut().map(|profile| {
+            let name = &function.declaration.name;
+            let name = format!("{} (line {})", name.typ, name.line);
+            let started = profile.enter(&name);
+            (name, started)
+        });
+        let result = self.run_body(&function.slots, &function.declaration.body);
+        if let (Some(profile), Some((name, started))) = (&mut self.profile, timed) {
+            profile.exit(&name, started);
+        }
+        // An initializer returns `this`, which is in the scope its closure starts with.
+        let this = if function.is_initializer {
+            Some(self.environment.get_local(Slot { depth: 1, index: 0 }))
+        } else {
+            None
+        };
+        self.environment.end_call(caller);
+        self.call_depth -= 1;
+        self.leave();
+        let result = match (result, this) {
+            (Ok(()), Some(this)) | (Err(Unwind::Return(_)), Some(this)) => Ok(this),
+            (Ok(()), None) => Ok(Value::Nil),
+            (Err(Unwind::Return(value)), None) => Ok(value),
+            (Err(Unwind::Error(err)), _) => {
+                let name = format!("{}", function.declaration.name.typ);
+                Err(Unwind::Error(
+                    err.unwind_from(Some(&name), Some(paren.line)),
+                ))
+            }
+            (Err(unwind), _) => Err(unwind),
+        };
+        if self.trace_exec {
+            self.trace_return(&function.declaration.name, &result);
+        }
+        result
+    }
+
+    // The tracing and debugging hooks are kept out of line: execute and the call functions
+    // recurse for every Lox call, and all of their locals take up stack each time.
+
+    // pause: Let the debugger look at `stmt`, and stop there if it wants to.
+    fn pause(&mut self, stmt: &Stmt) -> Result<(), LoxError> {
+        let mut debugger = self.debugger.take().expect("a debugger");
+        self.line = stmt.line;
+        let paused = debugger.before(self, stmt);
+        self.debugger = Some(debugger);
+        paused
+    }
+
+    // trace_statement: Show a statement about to run, for --trace-exec.
+    fn trace_statement(&self, stmt: &Stmt) {
+        let indent = "  ".repeat(self.call_depth);
+        eprintln!("exec: {}[line {}] {}", indent, stmt.line, shorten(stmt));
+    }
+
+    // trace_call: Show a call starting, for --trace-exec, at the depth of its caller.
+    fn trace_call(&self, name: &Token, args: &[Value]) {
+        let args: Vec<_> = args.iter().map(Value::repr).collect();
+        let indent = "  ".repeat(self.call_depth);
+        eprintln!("exec: {}-> {}({})", indent, name.typ, args.join(", "));
+    }
+
+    // trace_return: Show how a call ended, for --trace-exec.
+    fn trace_return(&self, name: &Token, result: &Result<Value, Unwind>) {
+        let outcome = match result {
+            Ok(value) => format!("returned {}", value.repr()),
+            Err(Unwind::Throw { value, .. }) => format!("threw {}", value.repr()),
+            Err(_) => "stopped".to_string(),
+        };
+        eprintln!("exec: {}<- {} {}", "  ".repeat(self.call_depth), name.typ, outcome);
+    }
+
+    // trace_resume: Show a generator being resumed, for --trace-exec.
+    fn trace_resume(&self, name: &Token) {
+        eprintln!("exec: {}-> {} resumed", "  ".repeat(self.call_depth), name.typ);
+    }
+
+    // trace_pause: Show how a generator stopped running, for --trace-exec.
+    fn trace_pause(&self, name: &Token, result: &Result<(), Unwind>) {
+        let outcome = match result {
+            Err(Unwind::Yield(suspension)) => format!("yielded {}", suspension.value.repr()),
+            Ok(()) | Err(Unwind::Return(_)) => "finished".to_string(),
+            Err(Unwind::Throw { value, .. }) => format!("threw {}", value.repr()),
+            Err(_) => "stopped".to_string(),
+        };
+        eprintln!("exec: {}<- {} {}", "  ".repeat(self.call_depth), name.typ, outcome);
+    }
+
+    // count_step: Check the memory limit before a statement, and count the statement against
+    // the step and time limits. It is kept out of execute, like check_call, so that the stack
+    // each statement takes stays the same.
+    #[inline(never)]
+    fn count_step(&mut self) -> Result<(), LoxError> {
+        self.check_memory()?;
+        if self.max_steps.is_none() && self.time_limit.is_none() {
+            return Ok(());
+        }
+        self.steps += 1;
+        if let Some(max) = self.max_steps.filter(|&max| self.steps > max) {
+            loxerr!(LX0608, "The program ran for more than {} steps", max)
+        }
+        if let Some(time) = self.time_limit.as_ref().filter(|_| self.steps.is_multiple_of(CLOCK_STEPS)) {
+            if (time.clock)().saturating_sub(time.started) > time.limit {
+                let limit = format!("{:?}", time.limit);
+                loxerr!(LX0609, "The program ran for longer than {}", limit)
+            }
+        }
+        Ok(())
+    }
+
+    // run_body: Execute the body of a function with `slots`, those of the program it was
+    // declared in, and then go back to the slots of the code that called it. Like check_call,
+    // it is kept out of call_function, to keep the stack each Lox call takes down.
+    #[inline(never)]
+    fn run_body(&mut self, slots: &Rc<Slots>, body: &[Stmt]) -> Result<(), Unwind> {
+        let caller = std::mem::replace(&mut self.slots, slots.clone());
+        let result = self.execute_all(body);
+        self.slots = caller;
+        result
+    }
+
+    // check_call: An error if a call made at `paren` would nest too deeply, or if the program
+    // is out of memory. It is kept out of call_function so that its error doesn't add to the
+    // stack each Lox call takes.
+    #[inline(never)]
+    fn check_call(&self, paren: &Token) -> Result<(), LoxError> {
+        if self.call_depth >= self.max_call_depth || stack::exhausted() {
+            loxerr!(LX0601, "[line {}] Stack overflow", paren.line)
+        }
+        self.check_memory().map_err(|err| at_line(paren, err))
+    }
+
+    // enter: Note a call of the function `name` for the debugger's backtrace, if debugging.
+    fn enter(&mut self, name: &Token) {
+        if self.debugger.is_some() {
+            self.calls.push((format!("{}", name.typ), self.line));
+        }
+    }
+
+    // leave: Forget the call noted last, going back to the line it was made from.
+    fn leave(&mut self) {
+        if self.debugger.is_some() {
+            if let Some((_, line)) = self.calls.pop() {
+                self.line = line;
+            }
+        }
+    }
+
+    // generator_next: The next value of a generator, or nil once it has finished.
+    pub fn generator_next(
+        &mut self,
+        generator: &Rc<RefCell<LoxGenerator>>,
+    ) -> Result<Value, LoxError> {
+        let next = self.resume(generator).map_err(|unwind| self.uncaught(unwind).into_error())?;
+        Ok(next.unwrap_or(Value::Nil))
+    }
+
+    // resume: Run a generator's body up to its next `yield`, returning the value yielded, or
+    // None if the body finished instead. Once finished, a generator stays finished.
+    fn resume(&mut self, generator: &Rc<RefCell<LoxGenerator>>) -> Result<Option<Value>, Unwind> {
+        let (declaration, slots, scope, path) = {
+            let mut generator = generator.borrow_mut();
+            let name = &generator.declaration.name;
+            if let GeneratorState::Running = generator.state {
+                loxerr!(
+                    LX0412,
+                    "[line {}] Generator '{}' is already running",
+                    name.line,
+                    name.typ
+                )
+            }
+            if self.call_depth >= self.max_call_depth || stack::exhausted() {
+                loxerr!(LX0601, "[line {}] Stack overflow", name.line)
+            }
+            match std::mem::replace(&mut generator.state, GeneratorState::Running) {
+                GeneratorState::Suspended { scope, path } => {
+                    let slots = generator.slots.clone();
+                    (generator.declaration.clone(), slots, scope, path)
+                }
+                _ => {
+                    generator.state = GeneratorState::Done;
+                    return Ok(None);
+                }
+            }
+        };
+        if self.trace_exec {
+            self.trace_resume(&declaration.name);
+        }
+        self.call_depth += 1;
+        self.enter(&declaration.name);
+        self.resuming = path;
+        let caller = self.environment.resume(scope);
+        let result = self.run_body(&slots, &declaration.body);
+        self.environment.end_call(caller);
+        self.call_depth -= 1;
+        self.leave();
+        if self.trace_exec {
+            self.trace_pause(&declaration.name, &result);
+        }
+        let mut generator = generator.borrow_mut();
+        match result {
+            Err(Unwind::Yield(suspension)) => {
+                let Suspension { value, scope, path } = *suspension;
+                generator.state = GeneratorState::Suspended { scope, path };
+                Ok(Some(value))
+            }
+            result => {
+                generator.state = GeneratorState::Done;
+                match result {
+                    Ok(()) | Err(Unwind::Return(_)) => Ok(None),
+                    Err(unwind) => Err(unwind),
+                }
+            }
+        }
+    }
+
+    fn index(&mut self, object: &Value, bracket: &Token, index: &Value) -> Result<Value, LoxError> {
+        match object {
+            Value::List(items) => {
+                let items = items.borrow();
+                let i = to_index(index, items.len(), "list", bracket)?;
+                Ok(items[i].clone())
+            }
+            Value::Str(s) => {
+                let i = to_index(index, s.chars().count(), "string", bracket)?;
+                Ok(Value::Str(s.chars().nth(i).unwrap().to_string().into()))
+            }
+            Value::Map(map) => match self
+                .map_get(map, index)
+                .map_err(|err| at_line(bracket, err))?
+            {
+                Some(value) => Ok(value),
+                None => {
+                    let key = self
+                        .repr(index)
+                        .map_err(|unwind| self.uncaught(unwind).into_error())?;
+                    loxerr!(
+                        LX0507,
+                        "[line {}] Key {} not found in map",
+                        bracket.line,
+                        key
+                    )
+                }
+            },
+            _ => loxerr!(
+                LX0505,
+                "[line {}] Only lists, maps and strings can be indexed",
+                bracket.line
+            ),
+        }
+    }
+
+    fn slice(
+        &mut self,
+        object: &Value,
+        bracket: &Token,
+        start: Option<&Value>,
+        end: Option<&Value>,
+    ) -> Result<Value, LoxError> {
+        let (len, what) = match object {
+            Value::List(items) => (items.borrow().len(), "list"),
+            Value::Str(s) => (s.chars().count(), "string"),
+            _ => loxerr!(
+                LX0505,
+                "[line {}] Only lists and strings can be sliced",
+                bracket.line
+            ),
+        };
+        let start = match start {
+            Some(start) => to_bound(start, len, what, bracket)?,
+            None => 0,
+        };
+        let end = match end {
+            Some(end) => to_bound(end, len, what, bracket)?,
+            None => len,
+        };
+        if start > end {
+            loxerr!(
+                LX0506,
+                "[line {}] Slice start {} is past its end {}",
+                bracket.line,
+                start,
+                end
+            )
+        }
+        match object {
+            Value::List(items) => Ok(Value::list(items.borrow()[start..end].to_vec())),
+            Value::Str(s) => Ok(Value::Str(
+                s.chars()
+                    .skip(start)
+                    .take(end - start)
+                    .collect::<String>()
+                    .into(),
+            )),
+            _ => unreachable!(),
+        }
+    }
+
+    // binary: Numbers follow IEEE 754 throughout. Dividing by zero gives an infinity, or NaN
+    // for 0/0, rather than an error. NaN is unequal to everything, itself included, and every
+    // ordering comparison against it is false. -0 and 0 are equal.
+    fn binary(&mut self, left: Value, op: &Token, right: Value) -> Result<Value, Unwind> {
+        if let Some(result) = self.overloaded(&left, op, &right)? {
+            return Ok(result);
+        }
+        Ok(match op.typ {
+            TokenType::EqualEqual => Value::Bool(left == right),
+            TokenType::BangEqual => Value::Bool(left != right),
+            TokenType::Plus => match (left, right) {
+                (Value::Number(a), Value::Number(b)) => Value::Number(a + b),
+                (Value::Str(a), Value::Str(b)) => Value::Str(format!("{}{}", a, b).into()),
+                // A string joins with an instance that says how it is shown.
+                (Value::Str(a), b) if method_of(&b, "toString").is_some() => {
+                    Value::Str(format!("{}{}", a, self.stringify(&b)?).into())
+                }
+                (a, Value::Str(b)) if method_of(&a, "toString").is_some() => {
+                    Value::Str(format!("{}{}", self.stringify(&a)?, b).into())
+                }
+                (a, b) => {
+                    return Err(operand_error(
+                        op.line,
+                        &op.typ.to_string(),
+                        "two numbers or two strings",
+                        &a,
+                        &b,
+                    )
+                    .into())
+                }
+            },
+            TokenType::Minus | TokenType::Star | TokenType::Slash => match (left, right) {
+                (Value::Number(a), Value::Number(b)) => Value::Number(match op.typ {
+                    TokenType::Minus => a - b,
+                    TokenType::Star => a * b,
+                    _ => a / b,
+                }),
+                (a, b) => {
+                    return Err(
+                        operand_error(op.line, &op.typ.to_string(), "numbers", &a, &b).into(),
+                    )
+                }
+            },
+            TokenType::Greater
+            | TokenType::GreaterEqual
+            | TokenType::Less
+            | TokenType::LessEqual => match (left, right) {
+                (Value::Number(a), Value::Number(b)) => Value::Bool(match op.typ {
+                    TokenType::Greater => a > b,
+                    TokenType::GreaterEqual => a >= b,
+                    TokenType::Less => a < b,
+                    _ => a <= b,
+                }),
+                (a, b) => {
+                    return Err(
+                        operand_error(op.line, &op.typ.to_string(), "numbers", &a, &b).into(),
+                    )
+                }
+            },
+            _ => return Err(invalid_operator(op).into()),
+        })
+    }
+
+    // overloaded: Apply an operator overloaded by a class, or None if neither operand's class
+    // overloads it. Instances overload `+` and `-` with `plus(other)` and `minus(other)`, called
+    // on the left operand. `==` calls `equals(other)`, on the left operand if it can and
+    // otherwise on the right, and `!=` is its negation. The comparisons all come from
+    // `less(other)`: `a < b` is `a.less(b)`, `a > b` is `b.less(a)`, `a <= b` is `!b.less(a)`
+    // and `a >= b` is `!a.less(b)`.
+    fn overloaded(
+        &mut self,
+        left: &Value,
+        op: &Token,
+        right: &Value,
+    ) -> Result<Option<Value>, Unwind> {
+        let (receiver, name, arg, negate) = match op.typ {
+            TokenType::Plus => (left, "plus", right, None),
+            TokenType::Minus => (left, "minus", right, None),
+            TokenType::EqualEqual => (left, "equals", right, Some(false)),
+            TokenType::BangEqual => (left, "equals", right, Some(true)),
+            TokenType::Less => (left, "less", right, Some(false)),
+            TokenType::Greater => (right, "less", left, Some(false)),
+            TokenType::LessEqual => (right, "less", left, Some(true)),
+            TokenType::GreaterEqual => (left, "less", right, Some(true)),
+            _ => return Ok(None),
+        };
+        let mut found = method_of(receiver, name).map(|method| (method, arg));
+        if found.is_none() && name == "equals" {
+            found = method_of(right, name).map(|method| (method, left));
+        }
+        let (method, arg) = match found {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        let result = self.call(&method, op, std::slice::from_ref(arg))?;
+        // Comparisons always give a bool; `+` and `-` give whatever the method returns.
+        Ok(Some(match negate {
+            Some(negate) => Value::Bool(result.is_truthy() != negate),
+            None => result,
+        }))
+    }
+}
+
+// mix_in: The methods the mixins of class `name` give it. Two mixins offering different
+// methods with the same name is an error, unless the class settles it by declaring that
+// method itself; its own methods are added afterwards and replace mixed-in ones.
+fn mix_in(
+    name: &Token,
+    mixins: &[Rc<LoxClass>],
+    declared: &[Rc<FunctionDecl>],
+) -> Result<HashMap<String, Rc<LoxFunction>>, LoxError> {
+    let mut methods: HashMap<String, (Rc<LoxFunction>, &str)> = HashMap::new();
+    for mixin in mixins {
+        let mut offered: Vec<_> = mixin.all_methods().into_iter().collect();
+        offered.sort_by(|a, b| a.0.cmp(&b.0));
+        for (method_name, method) in offered {
+            if let Some((other, from)) = methods.get(&method_name) {
+                let overridden = declared
+                    .iter()
+                    .any(|decl| format!("{}", decl.name.typ) == method_name);
+                if !Rc::ptr_eq(other, &method) && !overridden {
+                    loxerr!(
+                        LX0407,
+                        "[line {}] Method '{}' of class {} comes from both {} and {}",
+                        name.line,
+                        method_name,
+                        name.typ,
+                        from,
+                        mixin.name
+                    )
+                }
+            }
+            methods.insert(method_name, (method, &mixin.name));
+        }
+    }
+    Ok(methods
+        .into_iter()
+        .map(|(method_name, (method, _))| (method_name, method))
+        .collect())
+}
+
+// generator: What calling the generator function `function` with `args` makes, paused before
+// its first statement. It is kept out of call_function, as check_call is.
+#[inline(never)]
+fn generator(function: &LoxFunction, args: &[Value]) -> Value {
+    let scope = Scope::new_ref(args.to_vec(), function.closure.clone());
+    Value::generator(LoxGenerator {
+        declaration: function.declaration.clone(),
+        slots: function.slots.clone(),
+        state: GeneratorState::Suspended {
+            scope,
+            path: Vec::new(),
+        },
+    })
+}
+
+fn literal_value(lit: &Literal) -> Value {
+    match lit {
+        Literal::Nil => Value::Nil,
+        Literal::Bool(b) => Value::Bool(*b),
+        Literal::Number(n) => Value::Number(*n),
+        Literal::Str(s) => Value::Str(s.as_str().into()),
+    }
+}
+
+// method_of: The method `name` of `value` bound to it, if `value` is an instance whose class
+// has one.
+fn method_of(value: &Value, name: &str) -> Option<Value> {
+    match value {
+        Value::Instance(instance) => {
+            let method = instance.borrow().class.find_method(name)?;
+            Some(method.bind(value.clone()))
+        }
+        _ => None,
+    }
+}
+
+// locate: Point an error at the token of `expr`, if it is on the error's line.
+fn locate(err: LoxError, expr: &Expr) -> LoxError {
+    let token = expr.token().filter(|token| err.line() == Some(token.line));
+    err.at(token.and_then(Token::span))
+}
+
+// suspend_at: Add `point` to the way back into a generator, if `result` is it pausing.
+fn suspend_at<F>(result: Result<(), Unwind>, point: F) -> Result<(), Unwind>
+where
+    F: FnOnce() -> ResumePoint,
+{
+    match result {
+        Err(Unwind::Yield(mut suspension)) => {
+            suspension.path.push(point());
+            Err(Unwind::Yield(suspension))
+        }
+        other => other,
+    }
+}
+
+// undefined_property: The error for reading `key` from `object`, which has no such property,
+// suggesting the one most like it. Kept out of evaluate, whose frame every nested expression
+// adds to the stack.
+#[cold]
+#[inline(never)]
+fn undefined_property(name: &Token, key: &str, object: &Value) -> LoxError {
+    let err = lox_error!(
+        LX0303,
+        "[line {}] Undefined property '{}' on {}",
+        name.line,
+        key,
+        describe(object)
+    );
+    let names = complete::properties(object);
+    suggest::did_you_mean(err, key, names.iter().map(String::as_str))
+}
+
+// undefined_super_method: Like undefined_property, for `super.key` where `superclass` has no
+// such method.
+#[cold]
+#[inline(never)]
+fn undefined_super_method(method: &Token, key: &str, superclass: &Value) -> LoxError {
+    let err = lox_error!(
+        LX0303,
+        "[line {}] Undefined property '{}' on {}",
+        method.line,
+        key,
+        "superclass"
+    );
+    let names = match superclass {
+        Value::Class(class) => class.all_methods().into_keys().collect(),
+        _ => Vec::new(),
+    };
+    suggest::did_you_mean(err, key, names.iter().map(String::as_str))
+}
+
+// describe: What a value is called in errors about its properties. Instances go by their
+// class.
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Instance(instance) => format!("{} instance", instance.borrow().class.name),
+        Value::Namespace(namespace) => format!("namespace {}", namespace.name),
+        other => other.type_name().to_string(),
+    }
+}
+
+// operand_error: A binary operator was given operands of the wrong types, e.g. "Operands of
+// '>' must be numbers, got string and nil". The VM reports the same errors.
+// shorten: A statement as the trace shows it, cut short if it is long, e.g. a block or a
+// function with its whole body.
+fn shorten(stmt: &Stmt) -> String {
+    let mut shown = stmt.to_string();
+    if let Some((end, _)) = shown.char_indices().nth(TRACE_WIDTH) {
+        shown.truncate(end);
+        shown.push_str("...");
+    }
+    shown
+}
+
+// invalid_operator: The error for an expression with an operator the parser never gives it,
+// which is a bug in rlox1.
+#[cold]
+fn invalid_operator(op: &Token) -> LoxError {
+    lox_error!(
+        LX0607,
+        "Internal interpreter error at {}: {}; please report this bug in rlox1",
+        concat!(file!(), ":", line!()),
+        format!("invalid operator '{}' on line {}", op.typ, op.line)
+    )
+    .of_kind(ErrorKind::Internal)
+}
+
+pub fn operand_error(
+    line: usize,
+    op: &str,
+    expected: &str,
+    left: &Value,
+    right: &Value,
+) -> LoxError {
+    lox_error!(
+        LX0502,
+        "[line {}] Operands of '{}' must be {}, got {} and {}",
+        line,
+        op,
+        expected,
+        left.type_name(),
+        right.type_name()
+    )
+}
+
+// at_line: Tag an error raised without position information with the line of `token`. Exit
+// requests aren't errors, and errors from Lox code run by a native already have a line; both
+// pass through unchanged.
+fn at_line(token: &Token, err: LoxError) -> LoxError {
+    if err.exit_code().is_some() || err.message().starts_with("[line ") {
+        return err;
+    }
+    let message = format!("[line {}] {}", token.line, err.message());
+    err.reworded(&message)
+}
+
+// to_index: Check that `index` is a whole number in `0..len` and convert it.
+fn to_index(index: &Value, len: usize, what: &str, bracket: &Token) -> Result<usize, LoxError> {
+    checked_index(index, len, len, what, bracket)
+}
+
+// to_bound: Like to_index, but a slice bound may also be one past the end.
+fn to_bound(index: &Value, len: usize, what: &str, bracket: &Token) -> Result<usize, LoxError> {
+    checked_index(index, len + 1, len, what, bracket)
+}
+
+fn checked_index(
+    index: &Value,
+    limit: usize,
+    len: usize,
+    what: &str,
+    bracket: &Token,
+) -> Result<usize, LoxError> {
+    match index {
+        Value::Number(n) if n.fract() == 0.0 => {
+            if *n >= 0.0 && (*n as usize) < limit {
+                Ok(*n as usize)
+            } else {
+                loxerr!(
+                    LX0503,
+                    "[line {}] Index {} out of range for {} of length {}",
+                    bracket.line,
+                    n,
+                    what,
+                    len
+                )
+            }
+        }
+        _ => loxerr!(
+            LX0504,
+            "[line {}] {} index must be an integer, got {}",
+            bracket.line,
+            what,
+            index
+        ),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::ast::StmtKind;
+    use crate::error::LoxError;
+    use crate::interpreter::{Interpreter, Unwind, STACK_SIZE};
+    use crate::memory;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::value::Value;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // eval_with: Like eval, but lets the caller register natives first.
+    pub fn eval_with<F>(src: &str, setup: F) -> Result<String, LoxError>
+    where
+        F: FnOnce(&mut Interpreter),
+    {
+        let mut scanner = Scanner::new(src);
+        let tokens = scanner.scan_tokens()?;
+        let mut statements = Parser::new(tokens).parse()?;
+        let last = statements.pop().expect("at least one statement");
+        let mut interpreter = Interpreter::new();
+        setup(&mut interpreter);
+        interpreter.interpret(&statements)?;
+        match &last.kind {
+            StmtKind::Expression(expr) => match interpreter.evaluate(expr) {
+                Ok(value) => interpreter.stringify(&value).map_err(Unwind::into_error),
+                Err(unwind) => Err(unwind.into_error()),
+            },
+            _ => panic!("last statement must be an expression"),
+        }
+    }
+
+    // eval: Run a program whose last statement is an expression, and return the printed
+    // value of that expression.
+    fn eval(src: &str) -> Result<String, LoxError> {
+        eval_with(src, |_| {})
+    }
+
+    macro_rules! eval_test {
+        ( FAIL: $name:ident, $src:expr, $ct:expr ) => {
+            #[test]
+            fn $name() {
+                match eval($src) {
+                    Ok(value) => panic!("{:?} should fail, got {}", $src, value),
+                    Err(err) => assert!(format!("{}", err).contains($ct), "{}", err),
+                }
+            }
+        };
+        ( $name:ident, $src:expr => $printed:expr ) => {
+            #[test]
+            fn $name() -> Result<(), LoxError> {
+                assert_eq!(eval($src)?, $printed, "Input was {:?}", $src);
+                Ok(())
+            }
+        };
+    }
+
+    eval_test!(eval_arithmetic, "1 + 2 * 3 - 4 / 2;" => "5");
+    eval_test!(eval_grouping, "(1 + 2) * 3;" => "9");
+    eval_test!(eval_negate, "-(2 - 5);" => "3");
+    eval_test!(eval_not, "!nil;" => "true");
+    eval_test!(eval_concat, "\"foo\" + \"bar\";" => "foobar");
+    eval_test!(eval_comparison, "1 < 2;" => "true");
+    eval_test!(eval_equality, "1 == 1 != false;" => "true");
+    eval_test!(eval_mixed_equality, "1 == \"1\";" => "false");
+    eval_test!(eval_nil_equality, "nil == nil;" => "true");
+
+    eval_test!(eval_list, "[1, \"two\", 1 + 2];" => "[1, \"two\", 3]");
+    eval_test!(eval_empty_list, "[];" => "[]");
+    eval_test!(eval_list_trailing_comma, "[1, 2,];" => "[1, 2]");
+    eval_test!(eval_nested_list, "[[], [nil]];" => "[[], [nil]]");
+    eval_test!(eval_list_identity, "[] == [];" => "false");
+
+    eval_test!(eval_index_list, "[1, 2, 3][1];" => "2");
+    eval_test!(eval_index_nested, "[[1], [2, 3]][1][0];" => "2");
+    eval_test!(eval_index_string, "\"héllo\"[1];" => "é");
+    eval_test!(eval_set_index, "[1, 2][0] = \"x\";" => "x");
+    eval_test!(eval_slice_list, "[1, 2, 3, 4][1:3];" => "[2, 3]");
+    eval_test!(eval_slice_open_start, "[1, 2, 3][:1];" => "[1]");
+    eval_test!(eval_slice_open_end, "[1, 2, 3][1:];" => "[2, 3]");
+    eval_test!(eval_slice_whole, "[1, 2, 3][:];" => "[1, 2, 3]");
+    eval_test!(eval_slice_empty, "[1, 2, 3][3:];" => "[]");
+    eval_test!(eval_slice_string, "\"hello\"[1:4];" => "ell");
+
+    eval_test!(FAIL: eval_index_out_of_range, "[1, 2]\n[2];", "[line 2] Index 2 out of range for list of length 2");
+    eval_test!(FAIL: eval_index_negative, "[1][-1];", "Index -1 out of range");
+    eval_test!(FAIL: eval_index_fraction, "[1][0.5];", "list index must be an integer, got 0.5");
+    eval_test!(FAIL: eval_index_string_out_of_range, "\"ab\"[5];", "out of range for string of length 2");
+    eval_test!(FAIL: eval_index_number, "1[0];", "Only lists, maps and strings can be indexed");
+    eval_test!(FAIL: eval_set_index_string, "\"ab\"[0] = \"c\";", "Strings are immutable");
+    eval_test!(FAIL: eval_set_index_out_of_range, "[][0] = 1;", "Index 0 out of range");
+    eval_test!(FAIL: eval_slice_past_end, "[1][0:2];", "Index 2 out of range for list of length 1");
+    eval_test!(FAIL: eval_slice_backwards, "[1, 2][2:1];", "Slice start 2 is past its end 1");
+
+    eval_test!(eval_global, "var a = 1; a + 1;" => "2");
+    eval_test!(eval_uninitialized, "var a; a;" => "nil");
+    eval_test!(eval_assign, "var a = 1; a = a + 1; a;" => "2");
+    eval_test!(eval_assign_is_expression, "var a; var b; a = b = 3; a + b;" => "6");
+    eval_test!(eval_redefine_global, "var a = 1; var a = \"x\"; a;" => "x");
+    eval_test!(eval_block_shadows, "var a = 1; { var a = 2; } a;" => "1");
+    eval_test!(eval_block_assigns_outer, "var a = 1; { a = 2; } a;" => "2");
+    eval_test!(eval_nested_blocks, "var a = 1; { var a = a + 1; { a = a * 10; } a = a + 1; } a;" => "1");
+    eval_test!(eval_index_variable, "var l = [1, 2]; l[0] = 5; l;" => "[5, 2]");
+    eval_test!(eval_lists_are_shared, "var a = [1]; var b = a; b[0] = 2; a;" => "[2]");
+
+    eval_test!(FAIL: eval_undefined, "\nx;", "[line 2] Undefined variable 'x'");
+    eval_test!(FAIL: eval_undefined_suggests, "var counter = 1;\ncountr;", "[line 2] Undefined variable 'countr'; did you mean 'counter'?");
+    eval_test!(FAIL: eval_assign_undefined, "x = 1; 1;", "Undefined variable 'x'");
+    eval_test!(FAIL: eval_block_scope_ends, "{ var a = 1; } a;", "Undefined variable 'a'");
+
+    eval_test!(eval_clock, "clock() > 0;" => "true");
+    eval_test!(eval_native_value, "clock;" => "<native fn clock>");
+    eval_test!(eval_native_identity, "clock == clock;" => "true");
+
+    eval_test!(FAIL: eval_native_arity, "clock(1);", "[line 1] Expected 0 arguments but got 1");
+    eval_test!(FAIL: eval_call_number, "\n1();", "[line 2] Can only call functions");
+
+    #[test]
+    fn define_native_function() -> Result<(), LoxError> {
+        let got = eval_with("var x = 2; add(x, 3) * 2;", |interp| {
+            interp.define_native("add", 2, |args| match (&args[0], &args[1]) {
+                (Value::Number(a), Value::Number(b)) => Ok(Value::Number(a + b)),
+                _ => Err(LoxError::new("add expects numbers")),
+            });
+        })?;
+        assert_eq!(got, "10");
+        Ok(())
+    }
+
+    #[test]
+    fn profile_counts_nodes_and_calls() -> Result<(), LoxError> {
+        let mut scanner = Scanner::new("fun f(n) { if (n > 0) f(n - 1); } f(2);");
+        let statements = Parser::new(scanner.scan_tokens()?).parse()?;
+        let mut interpreter = Interpreter::new();
+        interpreter.profile();
+        interpreter.interpret(&statements)?;
+        let report = format!("{}", interpreter.take_profile().unwrap());
+        assert!(report.contains("3  10.34%  Call"), "{}", report);
+        assert!(report.contains("8  27.59%  Variable"), "{}", report);
+        assert!(report.contains("3 calls  f (line 1)"), "{}", report);
+        Ok(())
+    }
+
+    #[test]
+    fn errors_carry_a_stack_trace() -> Result<(), LoxError> {
+        let src = "fun inner(x) {\n  return x + nil;\n}\n\
+                   fun outer() {\n  return inner(1);\n}\n\nouter();";
+        let mut scanner = Scanner::new(src);
+        let statements = Parser::new(scanner.scan_tokens()?).parse()?;
+        let err = Interpreter::new().interpret(&statements).unwrap_err();
+        let trace: Vec<_> = format!("{:#}", err)
+            .lines()
+            .skip(1)
+            .map(String::from)
+            .collect();
+        assert_eq!(
+            trace,
+            [
+                "[line 2] in inner()",
+                "[line 5] in outer()",
+                "[line 8] in script"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn native_errors_get_a_line() {
+        let err = eval_with("\nfail();", |interp| {
+            interp.define_native("fail", 0, |_| Err(LoxError::new("it broke")));
+        })
+        .unwrap_err();
+        assert_eq!(format!("{}", err), "[line 2] it broke.");
+    }
+
+    #[test]
+    fn natives_can_capture_host_state() -> Result<(), LoxError> {
+        use std::cell::Cell;
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        eval_with("tick(); tick(); 1;", move |interp| {
+            interp.define_native("tick", 0, move |_| {
+                counter.set(counter.get() + 1);
+                Ok(Value::Nil)
+            });
+        })?;
+        assert_eq!(calls.get(), 2);
+        Ok(())
+    }
+
+    eval_test!(eval_for_in_string, "var out = \"\"; for (var c in \"abc\") out = c + out; out;" => "cba");
+    eval_test!(eval_for_in_scope, "var x = \"outer\"; for (var x in [1, 2]) {} x;" => "outer");
+    eval_test!(eval_while, "var n = 0; var r = []; while (n < 3) { r.push(n); n = n + 1; } r;" => "[0, 1, 2]");
+    eval_test!(eval_for, "var r = []; for (var i = 0; i < 3; i = i + 1) r.push(i); r;" => "[0, 1, 2]");
+    eval_test!(eval_for_scope, "var i = \"outer\"; for (var i = 0; i < 2; i = i + 1) {} i;" => "outer");
+    eval_test!(eval_for_without_clauses, "fun f() { var n = 0; for (;;) { n = n + 1; if (n == 4) return n; } } f();" => "4");
+    eval_test!(eval_for_existing_variable, "var i; for (i = 5; i > 1; i = i - 2) {} i;" => "1");
+    eval_test!(eval_for_in_empty, "var n = 0; for (var x in []) n = 1; n;" => "0");
+
+    eval_test!(FAIL: eval_for_in_number, "for (var x in 3) print x; 1;", "[line 1] Can only iterate over lists, maps, strings and generators, got number");
+    eval_test!(FAIL: eval_property_on_number, "\n(1).foo;", "[line 2] Undefined property 'foo' on number");
+
+    eval_test!(eval_map_literal, "({\"a\": 1, \"b\": [2],});" => "{\"a\": 1, \"b\": [2]}");
+    eval_test!(eval_empty_map, "({});" => "{}");
+    eval_test!(eval_map_index, "var m = {1: \"one\", \"two\": 2}; [m[1], m[\"two\"]];" => "[\"one\", 2]");
+    eval_test!(eval_map_set_index, "var m = {}; m[\"k\"] = 1; m[\"k\"] = m[\"k\"] + 1; m;" => "{\"k\": 2}");
+    eval_test!(eval_map_duplicate_keys, "({1: \"a\", 1: \"b\"});" => "{1: \"b\"}");
+    eval_test!(eval_for_in_map, "var ks = []; for (var k in {\"x\": 1, \"y\": 2}) ks.push(k); ks;" => "[\"x\", \"y\"]");
+
+    eval_test!(FAIL: eval_map_missing_key, "({})\n[\"k\"];", "[line 2] Key \"k\" not found in map");
+    eval_test!(FAIL: eval_map_bad_key, "({[]: 1});", "[line 1] A list can't be used as a map key");
+
+    eval_test!(eval_if, "var a = 1; if (a == 1) a = 2; else a = 3; a;" => "2");
+    eval_test!(eval_else, "var a = 1; if (nil) a = 2; else a = 3; a;" => "3");
+    eval_test!(eval_or, "[nil or \"x\", 1 or 2, false or false];" => "[\"x\", 1, false]");
+    eval_test!(eval_and, "[nil and 1, 1 and 2, true and nil];" => "[nil, 2, nil]");
+    eval_test!(eval_logical_short_circuit, "var a = 1; false and (a = 2); true or (a = 3); a;" => "1");
+
+    eval_test!(eval_fun, "fun add(a, b) { return a + b; } add(1, 2);" => "3");
+    eval_test!(eval_fun_value, "fun f() {} f;" => "<fn f>");
+    eval_test!(eval_fun_without_return, "fun f() { 1; } f();" => "nil");
+    eval_test!(eval_bare_return, "fun f() { return; } f();" => "nil");
+    eval_test!(eval_return_from_nested_block, "fun f(l) { for (var x in l) { if (x > 1) return x; } return -1; } [f([1, 2, 3]), f([])];" => "[2, -1]");
+    eval_test!(eval_recursion, "fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); } fib(15);" => "610");
+    eval_test!(eval_fun_sees_globals, "var g = 1; fun f() { g = g + 1; return g; } f(); g;" => "2");
+    eval_test!(eval_params_are_local, "var a = 1; fun f(a) { a = 5; } f(2); a;" => "1");
+    eval_test!(eval_fun_identity, "fun f() {} var g = f; [f == g, f == clock];" => "[true, false]");
+
+    eval_test!(eval_redeclare_local, "var r; { var a = 1; var a = a + 1; r = a; } r;" => "2");
+    eval_test!(eval_loop_body_locals, "var r = []; for (var x in [1, 2]) { var y = x * 10; r.push(y); } r;" => "[10, 20]");
+    eval_test!(eval_locals_per_call, "fun f(n) { var m = n; if (n > 0) f(n - 1); return m; } f(3);" => "3");
+    eval_test!(eval_params_in_nested_blocks, "fun f(a) { { var b = 1; { return a + b; } } } f(2);" => "3");
+    eval_test!(eval_local_fun, "var r; { fun sq(x) { return x * x; } r = sq(3); } r;" => "9");
+
+    eval_test!(eval_closure_counter, "fun make() { var n = 0; fun inc() { n = n + 1; return n; } return inc; } var c = make(); c(); c();" => "2");
+    eval_test!(eval_closures_are_independent, "fun make() { var n = 0; fun inc() { n = n + 1; return n; } return inc; } var a = make(); var b = make(); a(); a(); b();" => "1");
+    eval_test!(eval_closures_share_a_variable, "var get; var set; { var v = 1; fun g() { return v; } fun s(x) { v = x; } get = g; set = s; } set(5); get();" => "5");
+    eval_test!(eval_closure_sees_later_assignment, "var f; { var a = 1; fun g() { return a; } f = g; a = 2; } f();" => "2");
+    eval_test!(eval_closure_per_iteration, "var fs = []; for (var x in [1, 2, 3]) { fun f() { return x; } fs.push(f); } [fs[0](), fs[2]()];" => "[1, 3]");
+    eval_test!(eval_closure_binding_is_static, "var a = \"global\"; var r = []; { fun show() { r.push(a); } show(); var a = \"block\"; show(); } r;" => "[\"global\", \"global\"]");
+    eval_test!(eval_local_recursion, "var r; { fun down(n) { if (n == 0) return \"done\"; return down(n - 1); } r = down(3); } r;" => "done");
+    eval_test!(eval_nested_closures, "fun a(x) { fun b(y) { fun c(z) { return x + y + z; } return c; } return b; } a(1)(2)(3);" => "6");
+
+    // closure_test: The closure cases from the book's test suite (test/closure), the classic
+    // places where tree-walkers get scoping wrong. Each `print x;` became `out.push(x);` so
+    // the output can be compared with the book's `// expect:` lines.
+    macro_rules! closure_test {
+        ( $name:ident, $src:expr => $( $expected:expr ),* ) => {
+            #[test]
+            fn $name() -> Result<(), LoxError> {
+                let src = format!("var out = [];\n{}\nout;", $src);
+                let expected: Vec<String> = vec![ $( format!("{:?}", $expected) ),* ];
+                let expected = format!("[{}]", expected.join(", "));
+                assert_eq!(eval(&src)?, expected, "Input was {}", $src);
+                Ok(())
+            }
+        };
+    }
+
+    closure_test!(closure_block_capture_is_static, "
+        var a = \"global\";
+        {
+          fun showA() { out.push(a); }
+          showA();
+          var a = \"block\";
+          showA();
+        }" => "global", "global");
+    closure_test!(closure_assign_to_shadowed_later, "
+        var a = \"global\";
+        {
+          fun assign() { a = \"assigned\"; }
+          var a = \"inner\";
+          assign();
+          out.push(a);
+        }
+        out.push(a);" => "inner", "assigned");
+    closure_test!(closure_assign_to_closure, "
+        var f;
+        var g;
+        {
+          var local = \"local\";
+          fun f_() { out.push(local); local = \"after f\"; out.push(local); }
+          f = f_;
+          fun g_() { out.push(local); local = \"after g\"; out.push(local); }
+          g = g_;
+        }
+        f();
+        g();" => "local", "after f", "after f", "after g");
+    closure_test!(closure_over_function_parameter, "
+        var f;
+        fun foo(param) {
+          fun f_() { out.push(param); }
+          f = f_;
+        }
+        foo(\"param\");
+        f();" => "param");
+    closure_test!(closure_over_later_variable, "
+        fun f() {
+          var a = \"a\";
+          var b = \"b\";
+          fun g() { out.push(b); out.push(a); }
+          g();
+        }
+        f();" => "b", "a");
+    closure_test!(closure_closed_in_function, "
+        var f;
+        {
+          var local = \"local\";
+          fun f_() { out.push(local); }
+          f = f_;
+        }
+        f();" => "local");
+    closure_test!(closure_nested, "
+        var f;
+        fun f1() {
+          var a = \"a\";
+          fun f2() {
+            var b = \"b\";
+            fun f3() {
+              var c = \"c\";
+              fun f4() { out.push(a); out.push(b); out.push(c); }
+              f = f4;
+            }
+            f3();
+          }
+          f2();
+        }
+        f1();
+        f();" => "a", "b", "c");
+    closure_test!(closure_open_in_function, "
+        {
+          var local = \"local\";
+          fun f() { out.push(local); }
+          f();
+        }" => "local");
+    closure_test!(closure_referenced_multiple_times, "
+        var f;
+        {
+          var a = \"a\";
+          fun f_() { out.push(a); out.push(a); }
+          f = f_;
+        }
+        f();" => "a", "a");
+    closure_test!(closure_reuse_slot, "
+        {
+          var f;
+          {
+            var a = \"a\";
+            fun f_() { out.push(a); }
+            f = f_;
+          }
+          {
+            var b = \"b\";
+            f();
+          }
+        }" => "a");
+    closure_test!(closure_shadowed_by_local, "
+        {
+          var foo = \"closure\";
+          fun f() {
+            {
+              out.push(foo);
+              var foo = \"shadow\";
+              out.push(foo);
+            }
+            out.push(foo);
+          }
+          f();
+        }" => "closure", "shadow", "closure");
+    closure_test!(closure_counters_share_state, "
+        fun makeCounter() {
+          var i = 0;
+          fun count() { i = i + 1; out.push(i); }
+          return count;
+        }
+        var counter = makeCounter();
+        var other = makeCounter();
+        counter();
+        counter();
+        other();" => 1, 2, 1);
+    closure_test!(closure_loop_variable_per_iteration, "
+        var fs = [];
+        for (var i in [1, 2, 3]) {
+          var doubled = i * 2;
+          fun f() { out.push(i); out.push(doubled); }
+          fs.push(f);
+        }
+        for (var f in fs) f();" => 1, 2, 2, 4, 3, 6);
+    // The book's test/for/closure_in_body.lox: a C-style for loop has one variable for all its
+    // passes, which the closures share, while the body's own locals are fresh each pass.
+    closure_test!(closure_c_style_loop_variable_is_shared, "
+        var fs = [];
+        for (var i = 1; i < 4; i = i + 1) {
+          var j = i;
+          fun f() { out.push(i); out.push(j); }
+          fs.push(f);
+        }
+        for (var f in fs) f();" => 4, 1, 4, 2, 4, 3);
+
+    eval_test!(eval_class_value, "class A {} [A, A()];" => "[<class A>, <A instance>]");
+    eval_test!(eval_instance_fields, "class A {} var a = A(); a.x = 1; a.y = a.x + 1; [a.x, a.y];" => "[1, 2]");
+    eval_test!(eval_set_returns_value, "class A {} var a = A(); a.x = 3;" => "3");
+    eval_test!(eval_method_uses_this, "class A { get() { return this.v; } } var a = A(); a.v = 4; a.get();" => "4");
+    eval_test!(eval_initializer, "class P { init(x, y) { this.x = x; this.y = y; } } var p = P(1, 2); [p.x, p.y];" => "[1, 2]");
+    eval_test!(eval_init_returns_this, "class A { init() { this.n = 1; return; } } var a = A(); a.init() == a;" => "true");
+    eval_test!(eval_bound_method_keeps_this, "class A { init(n) { this.n = n; } get() { return this.n; } } var g = A(7).get; g();" => "7");
+    eval_test!(eval_fields_shadow_methods, "class A { m() { return 1; } } var a = A(); fun two() { return 2; } a.m = two; a.m();" => "2");
+    eval_test!(eval_this_in_closure, "class A { init() { this.v = 5; } f() { fun g() { return this.v; } return g; } } A().f()();" => "5");
+    eval_test!(eval_instances_are_distinct, "class A {} [A() == A(), A() != A()];" => "[false, true]");
+    eval_test!(eval_inherited_method, "class A { f() { return \"A\"; } } class B < A {} B().f();" => "A");
+    eval_test!(eval_override_and_super, "class A { f() { return \"A\"; } } class B < A { f() { return \"B\" + super.f(); } } B().f();" => "BA");
+    eval_test!(eval_super_binds_this, "class A { name() { return this.n; } } class B < A { init() { this.n = \"b\"; } name() { return \"<\" + super.name() + \">\"; } } B().name();" => "<b>");
+    eval_test!(eval_super_skips_own_class, "class A { f() { return 1; } } class B < A { f() { return 2; } g() { return super.f(); } } class C < B {} C().g();" => "1");
+    eval_test!(eval_inherited_init, "class A { init(x) { this.x = x; } } class B < A {} B(3).x;" => "3");
+    eval_test!(eval_local_class, "var r; { class A { f() { return A; } } r = A().f(); } r;" => "<class A>");
+
+    eval_test!(FAIL: eval_class_arity, "class A { init(a) {} } A();", "Expected 1 arguments but got 0");
+    eval_test!(FAIL: eval_class_without_init_arity, "class A {} A(1);", "Expected 0 arguments but got 1");
+    eval_test!(FAIL: eval_undefined_property, "class A {}\nA().nope;", "[line 2] Undefined property 'nope' on A instance");
+    eval_test!(FAIL: eval_undefined_property_suggests, "class A { total() {} }\nA().totl;", "on A instance; did you mean 'total'?");
+    eval_test!(FAIL: eval_undefined_list_method_suggests, "[1].lenn;", "on list; did you mean 'len'?");
+    eval_test!(FAIL: eval_field_on_number, "var n = 1; n.x = 2;", "Only instances have fields");
+    eval_test!(FAIL: eval_inherit_from_non_class, "var A = 1; class B < A {} 1;", "[line 1] Superclass must be a class");
+    eval_test!(FAIL: eval_undefined_super_method, "class A {} class B < A { f() { return super.f(); } } B().f();", "Undefined property 'f' on superclass");
+
+    eval_test!(eval_overload_plus, "class V { init(x) { this.x = x; } plus(o) { return V(this.x + o.x); } } (V(1) + V(2)).x;" => "3");
+    eval_test!(eval_overload_minus, "class V { init(x) { this.x = x; } minus(o) { return V(this.x - o); } } (V(5) - 2).x;" => "3");
+    eval_test!(eval_overload_equals, "class V { init(x) { this.x = x; } equals(o) { return this.x == o.x; } } [V(1) == V(1), V(1) == V(2), V(1) != V(1)];" => "[true, false, false]");
+    eval_test!(eval_overload_equals_right_operand, "class V { equals(o) { return o == 1; } } [1 == V(), V() == 1];" => "[true, true]");
+    eval_test!(eval_default_equals_is_identity, "class V {} var v = V(); [v == v, v == V()];" => "[true, false]");
+    eval_test!(eval_overload_less, "class V { init(x) { this.x = x; } less(o) { return this.x < o.x; } } var a = V(1); var b = V(2); [a < b, a > b, a <= b, a >= b, b <= b];" => "[true, false, true, false, true]");
+    eval_test!(eval_overload_result_is_bool, "class V { less(o) { return 1; } } V() < V();" => "true");
+    eval_test!(eval_overload_inherited, "class A { plus(o) { return \"A+\"; } } class B < A {} B() + 1;" => "A+");
+
+    eval_test!(FAIL: eval_plus_without_overload, "class V {}\nV() + 1;", "[line 2] Operands of '+' must be two numbers or two strings, got instance and number");
+    eval_test!(FAIL: eval_overload_needs_receiver, "class V { less(o) { return true; } } V() > 1;", "Operands of '>' must be numbers, got instance and number");
+    eval_test!(FAIL: eval_overload_error_has_line, "class V { plus(o) { return o.nope; } }\nV() + V();", "[line 1] Undefined property 'nope' on V instance");
+
+    eval_test!(eval_mixin_methods, "class Named { name() { return this.n; } } class Greeter { greet() { return \"hi \" + this.name(); } } class P with Named, Greeter { init() { this.n = \"p\"; } } P().greet();" => "hi p");
+    eval_test!(eval_mixin_with_superclass, "class A { f() { return \"A\"; } } class X { g() { return \"X\"; } } class B < A with X {} var b = B(); b.f() + b.g();" => "AX");
+    eval_test!(eval_mixin_inherited_methods, "class Base { f() { return 1; } } class X < Base {} class C with X {} C().f();" => "1");
+    eval_test!(eval_mixin_overrides_superclass, "class A { f() { return \"A\"; } } class X { f() { return \"X\"; } } class B < A with X {} B().f();" => "X");
+    eval_test!(eval_own_method_settles_conflict, "class X { f() { return 1; } } class Y { f() { return 2; } } class C with X, Y { f() { return 3; } } C().f();" => "3");
+    eval_test!(eval_shared_method_is_no_conflict, "class Base { f() { return 1; } } class X < Base {} class Y < Base {} class C with X, Y {} C().f();" => "1");
+    eval_test!(FAIL: eval_mixin_conflict, "class X { f() {} } class Y { f() {} }\nclass C with X, Y {} 1;", "[line 2] Method 'f' of class C comes from both X and Y");
+    eval_test!(FAIL: eval_mixin_must_be_class, "var X = 1;\nclass C with X {} 1;", "[line 2] Mixin must be a class");
+    eval_test!(eval_match_literals, "[match (2) { 1 -> \"one\", 2 -> \"two\", _ -> \"many\" }, match (\"b\") { \"a\" -> 1, \"b\" -> 2 }, match (nil) { false -> 1, nil -> 2 }];" => "[\"two\", 2, 2]");
+    eval_test!(eval_match_negative_number, "match (-1) { 1 -> \"pos\", -1 -> \"neg\" };" => "neg");
+    eval_test!(eval_match_first_arm_wins, "match (1) { 1 -> \"first\", 1 -> \"second\", _ -> \"any\" };" => "first");
+    eval_test!(eval_match_compares_types, "match (\"1\") { 1 -> \"number\", _ -> \"other\" };" => "other");
+    eval_test!(eval_match_binding, "var r = match (5) { 0 -> 0, n -> n * 2 }; r;" => "10");
+    eval_test!(eval_match_binding_shadows, "var n = 1; var r = [match (2) { n -> n }, n]; r;" => "[2, 1]");
+    eval_test!(eval_match_binding_in_function, "fun f(x) { var k = 10; return match (x) { 0 -> k, n -> n + k }; } [f(0), f(5)];" => "[10, 15]");
+    eval_test!(eval_match_only_runs_chosen_arm, "var log = []; fun say(s) { log.push(s); return s; } match (2) { 1 -> say(1), 2 -> say(2), _ -> say(3) }; log;" => "[2]");
+    eval_test!(eval_match_subject_evaluated_once, "var n = 0; fun next() { n = n + 1; return n; } match (next()) { 2 -> 2, 3 -> 3, _ -> n };" => "1");
+    eval_test!(FAIL: eval_match_without_arm, "1;\nmatch ([1]) { 1 -> 1 };", "[line 2] No match arm for [1]");
+    eval_test!(eval_generator_for_in, "fun g() { yield 1; yield 2; } var r = []; for (var x in g()) r.push(x); r;" => "[1, 2]");
+    eval_test!(eval_generator_next, "fun g() { yield 1; } var it = g(); [it.next(), it.next(), it.next()];" => "[1, nil, nil]");
+    eval_test!(eval_generator_is_lazy, "var log = []; fun g() { log.push(1); yield 2; } var it = g(); var n = log.len(); it.next(); [n, log.len()];" => "[0, 1]");
+    eval_test!(eval_generator_keeps_locals, "fun sums(xs) { var total = 0; for (var x in xs) { total = total + x; yield total; } } var r = []; for (var s in sums([1, 2, 3])) r.push(s); r;" => "[1, 3, 6]");
+    eval_test!(eval_generator_resumes_branches, "fun g(flag) { if (flag) { yield 1; } else yield 2; try { yield 3; throw 4; } catch (e) { yield e; } yield 5; } var r = []; for (var x in g(true)) r.push(x); for (var x in g(false)) r.push(x); r;" => "[1, 3, 4, 5, 2, 3, 4, 5]");
+    eval_test!(eval_generator_delegates, "fun inner() { yield 1; yield 2; } fun outer() { for (var x in inner()) yield x * 10; yield 0; } var r = []; for (var x in outer()) r.push(x); r;" => "[10, 20, 0]");
+    eval_test!(eval_generator_infinite, "fun from(n) { yield n; for (var x in from(n + 1)) yield x; } var it = from(1); [it.next(), it.next(), it.next()];" => "[1, 2, 3]");
+    eval_test!(eval_generator_return_ends_it, "fun g() { yield 1; return; yield 2; } var r = []; for (var x in g()) r.push(x); r;" => "[1]");
+    eval_test!(eval_generator_passes_have_own_variables, "fun g() { for (var x in [1, 2]) { fun f() { return x; } yield f; } } var fs = []; for (var f in g()) fs.push(f); [fs[0](), fs[1]()];" => "[1, 2]");
+    eval_test!(eval_generator_resumes_loops, "fun g() { var n = 0; while (n < 2) { yield n; n = n + 1; } for (var i = 10; i < 12; i = i + 1) yield i; } var r = []; for (var x in g()) r.push(x); r;" => "[0, 1, 10, 11]");
+    eval_test!(eval_generator_method, "class R { init(n) { this.n = n; } items() { for (var x in [1, 2]) yield x * this.n; } } var r = []; for (var v in R(3).items()) r.push(v); r;" => "[3, 6]");
+    eval_test!(eval_generator_shared_progress, "fun g() { yield 1; yield 2; yield 3; } var it = g(); it.next(); var r = []; for (var x in it) r.push(x); r;" => "[2, 3]");
+    eval_test!(eval_generator_throw_reaches_caller, "fun g() { yield 1; throw \"boom\"; } var r = []; try { for (var x in g()) r.push(x); } catch (e) { r.push(e); } r;" => "[1, \"boom\"]");
+    eval_test!(eval_generator_value, "fun g() { yield; } g();" => "<generator g>");
+    eval_test!(FAIL: eval_generator_error, "fun g() {\n  yield -nil; }\nfor (var x in g()) x; 1;", "[line 2] Operand of '-' must be a number, got nil");
+    eval_test!(FAIL: eval_generator_already_running, "fun g() { yield it.next(); }\nvar it = g(); it.next();", "[line 1] Generator 'g' is already running");
+    eval_test!(eval_getter, "class Sq { init(s) { this.s = s; } area { return this.s * this.s; } } Sq(3).area;" => "9");
+    eval_test!(eval_setter, "class Sq { init(s) { this.s = s; } area { return this.s * this.s; } set area(a) { this.s = a / 2; } } var q = Sq(1); var r = [q.area = 8, q.s, q.area]; r;" => "[8, 4, 16]");
+    eval_test!(eval_accessors_inherited, "class A { x { return this.v; } set x(v) { this.v = v + 1; } } class B < A {} var b = B(); b.x = 1; b.x;" => "2");
+    eval_test!(FAIL: eval_getter_without_setter, "class A { x { return 1; } }
+A().x = 2;", "[line 2] Property 'x' of A instance has a getter but no setter");
+    eval_test!(FAIL: eval_setter_without_getter, "class A { set x(v) {} }
+A().x;", "[line 2] Property 'x' of A instance has a setter but no getter");
+    eval_test!(FAIL: eval_error_in_getter, "class A { x {
+  return -nil; } }
+A().x;", "[line 2] Operand of '-' must be a number, got nil");
+    eval_test!(eval_to_string, "class P { init(x) { this.x = x; } toString() { return \"P(\" + this.x + \")\"; } } P(\"a\");" => "P(a)");
+    eval_test!(eval_to_string_inherited, "class A { toString() { return \"an A\"; } } class B < A {} B();" => "an A");
+    eval_test!(eval_without_to_string, "class A {} A();" => "<A instance>");
+    eval_test!(eval_to_string_in_collections, "class A { toString() { return \"an A\"; } } [A(), \"s\", {A(): [A()]}];" => "[an A, \"s\", {an A: [an A]}]");
+    eval_test!(eval_to_string_joins_strings, "class A { toString() { return \"A\"; } } \"<\" + A() + \">\";" => "<A>");
+    eval_test!(FAIL: eval_to_string_needed_to_join, "class A {} \"<\" + A();", "[line 1] Operands of '+' must be two numbers or two strings, got string and instance");
+    eval_test!(FAIL: eval_to_string_of_uncaught, "class A { toString() { return \"an A\"; } }\nthrow A(); 1;", "[line 2] Uncaught exception: an A");
+    eval_test!(FAIL: eval_to_string_of_unmatched, "class A { toString() { return \"an A\"; } } match (A()) { 1 -> 2 };", "[line 1] No match arm for an A");
+    eval_test!(eval_to_string_only_for_instances, "class A { toString() { return \"x\"; } } A;" => "<class A>");
+    eval_test!(FAIL: eval_to_string_must_return_string, "class A {\n  toString() { return 1; } } A();", "[line 2] toString() must return a string, got number");
+    eval_test!(FAIL: eval_to_string_arity, "class A {\n  toString(x) { return x; } } A();", "[line 2] Expected 1 arguments but got 0");
+
+    eval_test!(FAIL: eval_fun_arity, "fun f(a) {} f(1, 2);", "[line 1] Expected 1 arguments but got 2");
+    eval_test!(FAIL: eval_fun_hides_caller_locals, "fun f() { return x; } { var x = 1; f(); } 1;", "Undefined variable 'x'");
+    eval_test!(FAIL: eval_error_inside_fun, "fun f() {\n  return -nil; } f();", "[line 2] Operand of '-' must be a number, got nil");
+
+    #[test]
+    fn stack_overflow_is_an_error() {
+        let err = eval_with("fun f() { f(); }\nf();", |interp| {
+            interp.set_max_call_depth(50)
+        })
+        .unwrap_err();
+        assert_eq!(format!("{}", err), "[line 1] Stack overflow.");
+    }
+
+    #[test]
+    fn stack_overflow_is_catchable() -> Result<(), LoxError> {
+        let src = "fun f() { f(); } var got; try { f(); } catch (e) { got = e; } got;";
+        let got = eval_with(src, |interp| interp.set_max_call_depth(50))?;
+        assert_eq!(got, "[line 1] Stack overflow");
+        Ok(())
+    }
+
+    #[test]
+    fn call_depth_limit_is_exact() -> Result<(), LoxError> {
+        let src = "fun down(n) { if (n > 1) return down(n - 1); return 0; }";
+        let limit = |interp: &mut Interpreter| interp.set_max_call_depth(10);
+        assert_eq!(eval_with(&format!("{} down(10);", src), limit)?, "0");
+        assert!(eval_with(&format!("{} down(11);", src), limit).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn call_depth_unwinds_after_overflow() -> Result<(), LoxError> {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_max_call_depth(20);
+        let mut scanner = Scanner::new("fun f() { f(); } f();");
+        let statements = Parser::new(scanner.scan_tokens()?).parse()?;
+        for _ in 0..3 {
+            let err = interpreter.interpret(&statements).unwrap_err();
+            assert!(format!("{}", err).contains("Stack overflow"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn slots_go_with_the_last_function_of_their_program() -> Result<(), LoxError> {
+        fn run(interpreter: &mut Interpreter, src: &str) -> Result<Value, LoxError> {
+            let mut scanner = Scanner::new(src);
+            interpreter.eval(&Parser::new(scanner.scan_tokens()?).parse()?)
+        }
+        let mut interpreter = Interpreter::new();
+        run(&mut interpreter, "fun f(a) { var b = a; return b; }")?;
+        let slots = Rc::downgrade(&interpreter.slots);
+        run(&mut interpreter, "var got; { var c = 3; got = f(2) + c; }")?;
+        assert_eq!(run(&mut interpreter, "got;")?, Value::Number(5.0));
+        // The function still needs its program's slots; nothing does once it is gone.
+        assert!(slots.upgrade().is_some());
+        run(&mut interpreter, "f = nil;")?;
+        assert!(slots.upgrade().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn default_limit_fits_the_interpreter_stack() {
+        let deep = std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(|| eval("fun f(n) { return f(n + 1); } f(0);").unwrap_err())
+            .unwrap();
+        let err = deep.join().expect("the interpreter thread overflowed");
+        assert!(format!("{}", err).contains("Stack overflow"), "{}", err);
+    }
+
+    #[test]
+    fn memory_is_checked_within_a_statement() -> Result<(), LoxError> {
+        // The meter reads a count that `grow` moves by hand. No other test sets one, and only
+        // an interpreter with a limit reads it.
+        static USED: AtomicUsize = AtomicUsize::new(5000);
+        memory::set_meter(|| USED.load(Ordering::Relaxed));
+        let limit = |interp: &mut Interpreter| {
+            interp.set_max_memory(100);
+            interp.define_native("grow", 0, |_| {
+                USED.fetch_add(200, Ordering::Relaxed);
+                Ok(Value::Nil)
+            });
+        };
+        // What was in use when the limit was set doesn't count.
+        assert_eq!(eval_with("var a = [1]; a;", limit)?, "[1]");
+        let err = eval_with("var a = 1;
+[a, grow(), 2];", limit).unwrap_err();
+        assert_eq!(err.to_string(), "[line 2] Out of memory: the limit is 100 bytes.");
+        Ok(())
+    }
+
+    eval_test!(eval_try_without_throw, "var a = 1; try { a = 2; } catch (e) { a = 3; } a;" => "2");
+    eval_test!(eval_catch_thrown_value, "var got; try { throw [1, 2]; } catch (e) { got = e; } got;" => "[1, 2]");
+    eval_test!(eval_throw_skips_rest, "var a = 1; try { throw nil; a = 2; } catch (e) {} a;" => "1");
+    eval_test!(eval_throw_through_calls, "fun f() { throw \"deep\"; } fun g() { f(); return 1; } var got; try { g(); } catch (e) { got = e; } got;" => "deep");
+    eval_test!(eval_rethrow, "var got; try { try { throw 1; } catch (e) { throw e + 1; } } catch (e) { got = e; } got;" => "2");
+    eval_test!(eval_catch_runtime_error, "var got; try { -nil; } catch (e) { got = e; } got;" => "[line 1] Operand of '-' must be a number, got nil");
+    eval_test!(eval_catch_scope, "var e = \"outer\"; try { throw 1; } catch (e) {} e;" => "outer");
+    eval_test!(eval_try_block_scope, "var a = 1; try { var a = 2; } catch (e) {} a;" => "1");
+    eval_test!(eval_return_through_try, "fun f() { try { return 1; } catch (e) { return 2; } } f();" => "1");
+
+    eval_test!(FAIL: eval_uncaught_throw, "\nthrow \"boom\"; 1;", "[line 2] Uncaught exception: boom");
+    eval_test!(FAIL: eval_error_in_handler, "try { throw 1; } catch (e) { e(); } 1;", "Can only call functions");
+
+    eval_test!(eval_const_global, "const a = 1; a + 1;" => "2");
+    eval_test!(eval_const_local, "var got; { const a = [1]; a.push(2); got = a; } got;" => "[1, 2]");
+    eval_test!(eval_const_captured, "fun make() { const n = 3; fun get() { return n; } return get; } make()();" => "3");
+    eval_test!(FAIL: eval_const_assign, "const a = 1;\nprint \"not run\";\na = 2; 1;", "[line 3] Error at 'a': Can't assign to a constant");
+
+    eval_test!(eval_zero_over_zero, "0 / 0;" => "NaN");
+    eval_test!(eval_divide_by_zero, "[1 / 0, -1 / 0];" => "[inf, -inf]");
+    eval_test!(eval_nan_is_not_equal, "var n = 0 / 0; [n == n, n != n, [n] == [n]];" => "[false, true, false]");
+    eval_test!(eval_nan_comparisons, "var n = 0 / 0; [n < 1, n > 1, n <= n, n >= n];" => "[false, false, false, false]");
+    eval_test!(eval_nan_propagates, "var n = 0 / 0; [n + 1, n * 0, -n];" => "[NaN, NaN, NaN]");
+    eval_test!(eval_nan_is_truthy, "var a = 1; if (0 / 0) a = 2; a;" => "2");
+    eval_test!(eval_negative_zero, "[-0 == 0, -0, 1 / -0];" => "[true, -0, -inf]");
+
+    eval_test!(FAIL: eval_negate_string, "-\"a\";", "[line 1] Operand of '-' must be a number, got string");
+    eval_test!(FAIL: eval_add_mixed, "1 + \"a\";", "[line 1] Operands of '+' must be two numbers or two strings, got number and string");
+    eval_test!(FAIL: eval_subtract_list, "\n[] - 1;", "[line 2] Operands of '-' must be numbers, got list and number");
+    eval_test!(FAIL: eval_divide_nil, "1 / nil;", "Operands of '/' must be numbers, got number and nil");
+    eval_test!(FAIL: eval_compare_strings, "\"a\" < \"b\";", "[line 1] Operands of '<' must be numbers, got string and string");
+    eval_test!(FAIL: eval_compare_mixed, "\n\n\n\"a\" > nil;", "[line 4] Operands of '>' must be numbers, got string and nil");
+    eval_test!(FAIL: eval_error_in_list, "[1, -nil];", "[line 1] Operand of '-' must be a number, got nil");
+}
@@ -0,0 +1,46 @@
+// Ctrl-C in the REPL. While an entry runs, SIGINT doesn't kill the process but sets a flag,
+// which the tree-walker checks before each statement, so a runaway script stops with an
+// "Interrupted" error and the REPL carries on. A second Ctrl-C before the entry stops exits
+// after all, in case the script is stuck somewhere the flag isn't checked. The VMs have no
+// loops or calls to run away in, so only the tree-walker checks.
+//
+// At the prompt itself the line editor keeps the terminal in raw mode, where Ctrl-C is read
+// as a key rather than sent as a signal.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// The status a shell gives a process killed by SIGINT.
+#[cfg(unix)]
+const EXIT_INTERRUPTED: i32 = 130;
+
+// install: Catch SIGINT from now on, rather than be killed by it.
+#[cfg(unix)]
+pub fn install() {
+    extern "C" fn on_sigint(_: libc::c_int) {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            // Only async-signal-safe calls here: _exit, not exit.
+            unsafe { libc::_exit(EXIT_INTERRUPTED) }
+        }
+    }
+    let handler = on_sigint as extern "C" fn(libc::c_int);
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+}
+
+// Elsewhere Ctrl-C keeps its default behavior and ends the process.
+#[cfg(not(unix))]
+pub fn install() {}
+
+// clear: Forget any Ctrl-C so far, once what it was meant to stop has stopped.
+pub fn clear() {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+}
+
+// requested: Whether Ctrl-C has been pressed since the last clear. It stays set until then,
+// so that a `try` can't catch the interruption and carry on.
+pub fn requested() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
@@ -0,0 +1,457 @@
+// JSON, for scripts that read configuration and data files, and hosts that pass structured
+// data in and out: jsonParse() and jsonStringify() in Lox, Value::from_json() and to_json()
+// in Rust. JSON's null, booleans, numbers, strings, arrays and objects are nil, bools,
+// numbers, strings, lists and maps with string keys, which keep the order of the object's
+// keys, and the other way round. Nothing else has a JSON form: not functions, classes or
+// instances, nor the numbers JSON can't write, NaN and the infinities, nor maps with keys
+// that aren't strings, nor lists and maps that contain themselves.
+
+use std::rc::Rc;
+
+use crate::error::LoxError;
+use crate::value::{LoxMap, MapKey, Value};
+
+// The deepest that arrays and objects may nest, reading or writing, so that neither can
+// overflow the stack.
+const MAX_DEPTH: usize = 512;
+
+// parse: The value that `text` is the JSON for.
+pub fn parse(text: &str) -> Result<Value, LoxError> {
+    let mut reader = Reader {
+        text,
+        position: 0,
+        depth: 0,
+    };
+    let value = reader.value()?;
+    reader.skip_whitespace();
+    if reader.position < text.len() {
+        return Err(reader.invalid("expected the end of the text"));
+    }
+    Ok(value)
+}
+
+// stringify: The JSON for `value`, on one line.
+pub fn stringify(value: &Value) -> Result<String, LoxError> {
+    let mut out = String::new();
+    write(value, &mut out, &mut Vec::new())?;
+    Ok(out)
+}
+
+// quote: `s` as a JSON string literal.
+pub fn quote(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// write: Add the JSON for `value` to `out`. `open` holds the lists and maps being written, by
+// address, to catch one that contains itself.
+fn write(value: &Value, out: &mut String, open: &mut Vec<usize>) -> Result<(), LoxError> {
+    match value {
+        Value::Nil => out.push_str("null"),
+        Value::Bool(b) => out.push_str(&b.to_string()),
+        Value::Number(n) if n.is_finite() => out.push_str(&n.to_string()),
+        Value::Number(n) => loxerr!(LX0518, "Can't convert the number {} to JSON", n),
+        Value::Str(s) => out.push_str(&quote(s)),
+        Value::List(items) => {
+            enter(open, Rc::as_ptr(items) as usize, "list")?;
+            out.push('[');
+            for (i, item) in items.borrow().iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write(item, out, open)?;
+            }
+            out.push(']');
+            open.pop();
+        }
+        Value::Map(map) => {
+            enter(open, Rc::as_ptr(map) as usize, "map")?;
+            out.push('{');
+            let map = map.borrow();
+            for (i, (key, value)) in map.keys().iter().zip(map.values()).enumerate() {
+                let key = match key {
+                    Value::Str(key) => key,
+                    other => loxerr!(
+                        LX0518,
+                        "Can't convert a map with a {} key to JSON",
+                        other.type_name()
+                    ),
+                };
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&quote(key));
+                out.push(':');
+                write(value, out, open)?;
+            }
+            out.push('}');
+            open.pop();
+        }
+        other => loxerr!(LX0518, "Can't convert a {} to JSON", other.type_name()),
+    }
+    Ok(())
+}
+
+// enter: Note that the list or map at `address` is being written, unless it already is or
+// too many are.
+fn enter(open: &mut Vec<usize>, address: usize, what: &str) -> Result<(), LoxError> {
+    if open.contains(&address) {
+        loxerr!(
+            LX0518,
+            "Can't convert a {} that contains itself to JSON",
+            what
+        )
+    }
+    if open.len() == MAX_DEPTH {
+        loxerr!(
+            LX0518,
+            "Can't convert lists and maps nested more than {} deep to JSON",
+            MAX_DEPTH
+        )
+    }
+    open.push(address);
+    Ok(())
+}
+
+// Reader: A parser of JSON text, at `position` in it, inside `depth` arrays and objects.
+struct Reader<'a> {
+    text: &'a str,
+    position: usize,
+    depth: usize,
+}
+
+impl Reader<'_> {
+    fn value(&mut self) -> Result<Value, LoxError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.nested(Reader::object),
+            Some('[') => self.nested(Reader::array),
+            Some('"') => Ok(Value::Str(self.string()?.into())),
+            Some('-') | Some('0'..='9') => self.number(),
+            Some(_) if self.keyword("null") => Ok(Value::Nil),
+            Some(_) if self.keyword("true") => Ok(Value::Bool(true)),
+            Some(_) if self.keyword("false") => Ok(Value::Bool(false)),
+            _ => Err(self.invalid("expected a value")),
+        }
+    }
+
+    // nested: Read an array or object with `read`, one level deeper.
+    fn nested(
+        &mut self,
+        read: fn(&mut Self) -> Result<Value, LoxError>,
+    ) -> Result<Value, LoxError> {
+        if self.depth == MAX_DEPTH {
+            let why = format!("arrays and objects nest more than {} deep", MAX_DEPTH);
+            return Err(self.invalid(&why));
+        }
+        self.depth += 1;
+        let value = read(self)?;
+        self.depth -= 1;
+        Ok(value)
+    }
+
+    fn array(&mut self) -> Result<Value, LoxError> {
+        self.position += 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if !self.eat(']') {
+            loop {
+                items.push(self.value()?);
+                self.skip_whitespace();
+                if self.eat(']') {
+                    break;
+                }
+                if !self.eat(',') {
+                    return Err(self.invalid("expected ',' or ']'"));
+                }
+            }
+        }
+        Ok(Value::list(items))
+    }
+
+    // object: An object, as a map. A key given twice has the value it was given last.
+    fn object(&mut self) -> Result<Value, LoxError> {
+        self.position += 1;
+        let mut map = LoxMap::new();
+        self.skip_whitespace();
+        if !self.eat('}') {
+            loop {
+                self.skip_whitespace();
+                if self.peek() != Some('"') {
+                    return Err(self.invalid("expected a string as a key"));
+                }
+                let key: Rc<str> = self.string()?.into();
+                self.skip_whitespace();
+                if !self.eat(':') {
+                    return Err(self.invalid("expected ':' after a key"));
+                }
+                let value = self.value()?;
+                let hashed = MapKey::Str(key.clone());
+                match map.candidates(&hashed).first() {
+                    Some(&(position, _)) => map.set_at(position, value),
+                    None => map.insert(hashed, Value::Str(key), value),
+                }
+                self.skip_whitespace();
+                if self.eat('}') {
+                    break;
+                }
+                if !self.eat(',') {
+                    return Err(self.invalid("expected ',' or '}'"));
+                }
+            }
+        }
+        Ok(Value::map(map))
+    }
+
+    fn string(&mut self) -> Result<String, LoxError> {
+        self.position += 1;
+        let mut out = String::new();
+        loop {
+            let c = self
+                .next()
+                .ok_or_else(|| self.invalid("unterminated string"))?;
+            match c {
+                '"' => return Ok(out),
+                '\\' => out.push(self.escape()?),
+                c if (c as u32) < 0x20 => {
+                    self.position -= 1;
+                    return Err(self.invalid("control character in a string"));
+                }
+                c => out.push(c),
+            }
+        }
+    }
+
+    // escape: The character a backslash escape stands for, after the backslash. A character
+    // outside the Basic Multilingual Plane is written as a pair of \u escapes, for the two
+    // halves of its UTF-16 encoding.
+    fn escape(&mut self) -> Result<char, LoxError> {
+        let c = match self.next() {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('/') => '/',
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('u') => {
+                let high = self.hex()?;
+                let code = if (0xd800..0xdc00).contains(&high) {
+                    if !(self.eat('\\') && self.eat('u')) {
+                        return Err(self.invalid("expected the second half of a surrogate pair"));
+                    }
+                    let low = self.hex()?;
+                    if !(0xdc00..0xe000).contains(&low) {
+                        return Err(self.invalid("expected the second half of a surrogate pair"));
+                    }
+                    0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                } else {
+                    high
+                };
+                return char::from_u32(code).ok_or_else(|| self.invalid("unpaired surrogate"));
+            }
+            _ => {
+                self.position -= 1;
+                return Err(self.invalid("invalid escape"));
+            }
+        };
+        Ok(c)
+    }
+
+    // hex: The four hex digits of a \u escape.
+    fn hex(&mut self) -> Result<u32, LoxError> {
+        let digits = self.text[self.position..].get(..4).unwrap_or("");
+        match u32::from_str_radix(digits, 16) {
+            Ok(code) if digits.chars().all(|c| c.is_ascii_hexdigit()) => {
+                self.position += 4;
+                Ok(code)
+            }
+            _ => Err(self.invalid("expected four hex digits after \\u")),
+        }
+    }
+
+    // number: A number, which JSON writes as an optional minus sign, an integer part without
+    // leading zeros, then an optional fraction and exponent.
+    fn number(&mut self) -> Result<Value, LoxError> {
+        let start = self.position;
+        self.eat('-');
+        if !self.eat('0') && self.digits() == 0 {
+            return Err(self.invalid("expected a digit"));
+        }
+        if self.eat('.') && self.digits() == 0 {
+            return Err(self.invalid("expected a digit after '.'"));
+        }
+        if self.eat('e') || self.eat('E') {
+            let _ = self.eat('+') || self.eat('-');
+            if self.digits() == 0 {
+                return Err(self.invalid("expected a digit in the exponent"));
+            }
+        }
+        let number = &self.text[start..self.position];
+        let n = number.parse().expect("JSON numbers are Rust floats");
+        Ok(Value::Number(n))
+    }
+
+    // digits: Skip the digits ahead, returning how many there were.
+    fn digits(&mut self) -> usize {
+        let rest = &self.text[self.position..];
+        let count = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        self.position += count;
+        count
+    }
+
+    // keyword: Skip `word` if it is next.
+    fn keyword(&mut self, word: &str) -> bool {
+        let found = self.text[self.position..].starts_with(word);
+        if found {
+            self.position += word.len();
+        }
+        found
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.position..];
+        let trimmed = rest.trim_start_matches([' ', '\t', '\n', '\r']);
+        self.position += rest.len() - trimmed.len();
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    // eat: Skip `c` if it is next.
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    // invalid: An error saying what is wrong with the text at the position reached, which it
+    // gives as a line and column, both counting from 1.
+    fn invalid(&self, why: &str) -> LoxError {
+        let before = &self.text[..self.position];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+        lox_error!(
+            LX0517,
+            "Invalid JSON at line {}, column {}: {}",
+            line,
+            column,
+            why
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::json::{parse, quote, stringify, MAX_DEPTH};
+    use crate::value::{LoxMap, Value};
+
+    fn round_trip(text: &str) -> String {
+        stringify(&parse(text).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn json_reads_and_writes_every_kind_of_value() {
+        assert_eq!(
+            round_trip(r#" {"b": [1, 2.5, -3e2, true, null], "a": {"s": "x"}} "#),
+            r#"{"b":[1,2.5,-300,true,null],"a":{"s":"x"}}"#
+        );
+        assert_eq!(round_trip("[]"), "[]");
+        assert_eq!(round_trip("{}"), "{}");
+        assert_eq!(round_trip(r#"{"k": 1, "k": 2}"#), r#"{"k":2}"#);
+        let text = parse(r#""tab\t \"q\" é 😀 \/""#).unwrap();
+        assert_eq!(text.to_string(), "tab\t \"q\" é 😀 /");
+        assert_eq!(quote("a\"b\\\n\u{1}"), r#""a\"b\\\n\u0001""#);
+    }
+
+    #[test]
+    fn bad_json_says_where() {
+        let error = |text| parse(text).unwrap_err().to_string();
+        assert_eq!(
+            error("[1,\n 2,]"),
+            "Invalid JSON at line 2, column 4: expected a value."
+        );
+        assert_eq!(
+            error(r#"{"a" 1}"#),
+            "Invalid JSON at line 1, column 6: expected ':' after a key."
+        );
+        assert_eq!(
+            error("[1 2]"),
+            "Invalid JSON at line 1, column 4: expected ',' or ']'."
+        );
+        assert_eq!(
+            error("01"),
+            "Invalid JSON at line 1, column 2: expected the end of the text."
+        );
+        assert_eq!(
+            error(r#""abc"#),
+            "Invalid JSON at line 1, column 5: unterminated string."
+        );
+        assert_eq!(
+            error(r#""\x""#),
+            "Invalid JSON at line 1, column 3: invalid escape."
+        );
+        assert_eq!(
+            error(""),
+            "Invalid JSON at line 1, column 1: expected a value."
+        );
+        let deep = "[".repeat(MAX_DEPTH + 1);
+        assert!(error(&deep).ends_with("arrays and objects nest more than 512 deep."));
+    }
+
+    #[test]
+    fn only_some_values_have_json() {
+        let error = |value: Value| stringify(&value).unwrap_err().to_string();
+        assert_eq!(
+            error(Value::Number(f64::NAN)),
+            "Can't convert the number NaN to JSON."
+        );
+        let list = Value::list(Vec::new());
+        if let Value::List(items) = &list {
+            items.borrow_mut().push(list.clone());
+        }
+        assert_eq!(
+            error(list.clone()),
+            "Can't convert a list that contains itself to JSON."
+        );
+        if let Value::List(items) = &list {
+            items.borrow_mut().clear();
+        }
+        let mut map = LoxMap::new();
+        map.insert(
+            crate::value::MapKey::Number(0),
+            Value::Number(0.0),
+            Value::Nil,
+        );
+        assert_eq!(
+            error(Value::map(map)),
+            "Can't convert a map with a number key to JSON."
+        );
+        // The same list twice over is fine, as long as it isn't inside itself.
+        let shared = Value::list(vec![Value::Nil]);
+        let twice = Value::list(vec![shared.clone(), shared]);
+        assert_eq!(stringify(&twice).unwrap(), "[[null],[null]]");
+    }
+}
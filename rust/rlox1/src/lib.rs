@@ -0,0 +1,58 @@
+// rlox1 as a library, for Rust programs that run Lox. The simplest way is a `Lox`, which
+// evaluates source, calls what it defines, and sets globals for it; see embed.rs. Underneath,
+// source is scanned and parsed with `scanner` and `parser`, and what was parsed is run with an
+// `interpreter::Interpreter`, after `stdlib::register` has given it the standard library, if
+// it is to have one. Results come back as `value::Value`s, and failures as
+// `error::LoxError`s. `lox_native!` binds Rust functions as natives; see native.rs.
+// `executive` runs scripts the way the rlox1 command does, on any of the engines, and the
+// command itself is a thin wrapper around it, in main.rs. With the `wasm` feature,
+// `playground::run` runs a program for a web page, keeping what it prints, and with `capi`,
+// capi.rs gives C hosts a Lox.
+//
+// The interpreter's values aren't Send, so each thread that runs Lox needs its own.
+
+#[macro_use]
+pub mod error;
+#[macro_use]
+pub mod log;
+pub mod ast;
+pub mod bench;
+pub mod catalog;
+#[cfg(feature = "capi")]
+pub mod capi;
+mod chunk;
+pub mod codes;
+mod compiler;
+mod complete;
+mod debugger;
+pub mod diagnostic;
+mod embed;
+mod environment;
+pub mod executive;
+mod format;
+pub mod gc;
+mod harness;
+mod highlight;
+pub mod interpreter;
+mod interrupt;
+mod json;
+mod lineedit;
+pub mod loxc;
+pub mod memory;
+pub mod native;
+#[cfg(feature = "nan-boxing")]
+mod nanbox;
+pub mod parser;
+#[cfg(feature = "wasm")]
+pub mod playground;
+mod profile;
+mod register;
+mod resolver;
+pub mod scanner;
+mod stack;
+pub mod stdlib;
+mod suggest;
+pub mod value;
+mod vm;
+
+pub use embed::Lox;
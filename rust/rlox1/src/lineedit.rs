@@ -0,0 +1,585 @@
+// Line editing for the REPL: the arrow keys and the usual Emacs keys, a history that is kept
+// between sessions, Ctrl-R to search it, Tab to complete words, and highlighting as the line
+// is typed. rlox1 depends on little beyond clap, so rather than pull in a line-editing crate
+// this puts the terminal into raw mode itself and handles the keys. When stdin isn't a
+// terminal, lines are read as they come.
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+const MAX_HISTORY: usize = 1000;
+
+// Complete: Given the line up to the cursor, where the word being completed starts and the
+// words it could be.
+pub type Complete<'a> = &'a dyn Fn(&str) -> (usize, Vec<String>);
+
+// Highlight: The line as it should be shown, e.g. with color codes added.
+pub type Highlight<'a> = &'a dyn Fn(&str) -> String;
+
+// Input: What the user entered at a prompt.
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    Line(String),
+    // Ctrl-C: abandon what has been entered so far.
+    Interrupt,
+    // Ctrl-D on an empty line, or the end of stdin.
+    End,
+}
+
+pub struct LineEditor {
+    history: Vec<String>,
+    // Where the history is saved, if there is anywhere to save it.
+    file: Option<PathBuf>,
+}
+
+impl LineEditor {
+    // new: An editor with the history saved by earlier sessions.
+    pub fn new() -> Self {
+        let file = history_file();
+        let mut history: Vec<String> = file
+            .as_ref()
+            .and_then(|file| fs::read_to_string(file).ok())
+            .map(|text| text.lines().map(String::from).collect())
+            .unwrap_or_default();
+        let oldest = history.len().saturating_sub(MAX_HISTORY);
+        history.drain(..oldest);
+        LineEditor { history, file }
+    }
+
+    // read_line: Prompt for a line, which is returned without its newline.
+    pub fn read_line(
+        &mut self,
+        prompt: &str,
+        complete: Complete,
+        highlight: Highlight,
+    ) -> io::Result<Input> {
+        if let Some(_raw) = terminal::RawMode::enable() {
+            let (mut input, mut output) = (io::stdin().lock(), io::stdout().lock());
+            return self.edit(prompt, complete, highlight, &mut input, &mut output);
+        }
+        print!("{}", prompt);
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line)? == 0 {
+            return Ok(Input::End);
+        }
+        let end = line.trim_end_matches(&['\r', '\n'][..]).len();
+        line.truncate(end);
+        Ok(Input::Line(line))
+    }
+
+    // add_history: Remember an entry, unless it is blank or repeats the one before.
+    pub fn add_history(&mut self, entry: &str) {
+        if entry.trim().is_empty() || self.history.last().is_some_and(|last| last == entry) {
+            return;
+        }
+        self.history.push(entry.to_string());
+        if self.history.len() > MAX_HISTORY {
+            self.history.remove(0);
+        }
+        // History is a convenience, so failing to save it isn't worth interrupting the user.
+        if let Some(file) = &self.file {
+            let _ = append(file, entry);
+        }
+    }
+
+    // edit: Read keys from `input` and echo the line being edited to `output`, which the
+    // terminal must be in raw mode for.
+    fn edit<R: Read, W: Write>(
+        &self,
+        prompt: &str,
+        complete: Complete,
+        highlight: Highlight,
+        input: &mut R,
+        output: &mut W,
+    ) -> io::Result<Input> {
+        let mut keys = Keys(input);
+        let mut line = Line::default();
+        // Which history entry is shown: history.len() is the new line, kept in `draft` while
+        // the user looks back through the history.
+        let mut index = self.history.len();
+        let mut draft = String::new();
+        // A key that ended a search, to be handled as usual.
+        let mut pending = None;
+        line.draw(prompt, highlight, output)?;
+        loop {
+            let key = match pending.take() {
+                Some(key) => key,
+                None => match keys.next()? {
+                    Some(key) => key,
+                    None => return Ok(Input::End),
+                },
+            };
+            match key {
+                Key::Enter => {
+                    write!(output, "\r\n")?;
+                    return Ok(Input::Line(line.text()));
+                }
+                Key::Ctrl('c') => {
+                    write!(output, "^C\r\n")?;
+                    return Ok(Input::Interrupt);
+                }
+                Key::Ctrl('d') if line.chars.is_empty() => {
+                    write!(output, "\r\n")?;
+                    return Ok(Input::End);
+                }
+                Key::Ctrl('d') | Key::Delete => line.delete(),
+                Key::Backspace => line.backspace(),
+                Key::Left | Key::Ctrl('b') => line.cursor = line.cursor.saturating_sub(1),
+                Key::Right | Key::Ctrl('f') => {
+                    line.cursor = (line.cursor + 1).min(line.chars.len())
+                }
+                Key::Home | Key::Ctrl('a') => line.cursor = 0,
+                Key::End | Key::Ctrl('e') => line.cursor = line.chars.len(),
+                Key::Ctrl('k') => line.chars.truncate(line.cursor),
+                Key::Ctrl('u') => {
+                    line.chars.drain(..line.cursor);
+                    line.cursor = 0;
+                }
+                Key::Up | Key::Ctrl('p') if index > 0 => {
+                    if index == self.history.len() {
+                        draft = line.text();
+                    }
+                    index -= 1;
+                    line = Line::from(&self.history[index]);
+                }
+                Key::Down | Key::Ctrl('n') if index < self.history.len() => {
+                    index += 1;
+                    line = match self.history.get(index) {
+                        Some(entry) => Line::from(entry),
+                        None => Line::from(&draft),
+                    };
+                }
+                Key::Ctrl('r') => {
+                    let (found, key) = self.search(&mut keys, output)?;
+                    if let Some(entry) = found {
+                        line = Line::from(&entry);
+                    }
+                    pending = key;
+                }
+                Key::Ctrl('i') => {
+                    let before: String = line.chars[..line.cursor].iter().collect();
+                    let (start, words) = complete(&before);
+                    let prefix = common_prefix(&words);
+                    let typed = before[start..].chars().count();
+                    if prefix.chars().count() > typed {
+                        prefix.chars().skip(typed).for_each(|c| line.insert(c));
+                    } else if words.len() > 1 {
+                        write!(output, "\r\n{}\r\n", words.join("  "))?;
+                    }
+                }
+                Key::Char(c) => line.insert(c),
+                _ => {}
+            }
+            line.draw(prompt, highlight, output)?;
+        }
+    }
+
+    // search: Ctrl-R. Search back through the history as the user types, until a key that
+    // isn't part of the search ends it. Returns the entry found, if any, and that key.
+    // Ctrl-R again finds an older match; Ctrl-G or Ctrl-C gives up, leaving the line as it was.
+    fn search<R: Read, W: Write>(
+        &self,
+        keys: &mut Keys<R>,
+        output: &mut W,
+    ) -> io::Result<(Option<String>, Option<Key>)> {
+        let mut query = String::new();
+        let mut found: Option<usize> = None;
+        loop {
+            let entry = found.map_or("", |i| self.history[i].as_str());
+            write!(output, "\r(reverse-i-search)`{}': {}\x1b[K", query, entry)?;
+            output.flush()?;
+            match keys.next()? {
+                Some(Key::Char(c)) => {
+                    query.push(c);
+                    // The match so far may still match.
+                    let before = found.map_or(self.history.len(), |i| i + 1);
+                    found = self.find(&query, before);
+                }
+                Some(Key::Backspace) => {
+                    query.pop();
+                    found = self.find(&query, self.history.len());
+                }
+                Some(Key::Ctrl('r')) => {
+                    if let Some(i) = found {
+                        found = self.find(&query, i).or(found);
+                    }
+                }
+                Some(Key::Ctrl('g')) | Some(Key::Ctrl('c')) => return Ok((None, None)),
+                key => return Ok((found.map(|i| self.history[i].clone()), key)),
+            }
+        }
+    }
+
+    // find: The newest history entry before `before` that contains `query`.
+    fn find(&self, query: &str, before: usize) -> Option<usize> {
+        if query.is_empty() {
+            return None;
+        }
+        self.history[..before]
+            .iter()
+            .rposition(|entry| entry.contains(query))
+    }
+}
+
+// common_prefix: The longest start that all of `words` share.
+fn common_prefix(words: &[String]) -> &str {
+    let first = match words.first() {
+        Some(first) => first,
+        None => return "",
+    };
+    let mut end = 0;
+    for (i, c) in first.char_indices() {
+        if !words
+            .iter()
+            .all(|word| word.get(i..).is_some_and(|rest| rest.starts_with(c)))
+        {
+            break;
+        }
+        end = i + c.len_utf8();
+    }
+    &first[..end]
+}
+
+// history_file: Where the history is kept: rlox1/history in the user's data directory.
+fn history_file() -> Option<PathBuf> {
+    let data = match env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".local/share"),
+    };
+    Some(data.join("rlox1").join("history"))
+}
+
+fn append(file: &Path, entry: &str) -> io::Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(file)?;
+    writeln!(file, "{}", entry)
+}
+
+// Line: The line being edited, and where the cursor is in it.
+#[derive(Default)]
+struct Line {
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl Line {
+    fn from(text: &str) -> Line {
+        let chars: Vec<char> = text.chars().collect();
+        Line {
+            cursor: chars.len(),
+            chars,
+        }
+    }
+
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    fn insert(&mut self, c: char) {
+        self.chars.insert(self.cursor, c);
+        self.cursor += 1;
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.cursor < self.chars.len() {
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    // draw: Redraw the prompt and line, clear whatever was after it, and put the cursor back.
+    fn draw<W: Write>(&self, prompt: &str, highlight: Highlight, output: &mut W) -> io::Result<()> {
+        write!(output, "\r{}{}\x1b[K", prompt, highlight(&self.text()))?;
+        let back = self.chars.len() - self.cursor;
+        if back > 0 {
+            write!(output, "\x1b[{}D", back)?;
+        }
+        output.flush()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Key {
+    Char(char),
+    Ctrl(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    Unknown,
+}
+
+// Keys: Decodes keys from the bytes a terminal sends: UTF-8 characters, control characters,
+// and the escape sequences for the arrow and editing keys.
+struct Keys<'a, R>(&'a mut R);
+
+impl<'a, R: Read> Keys<'a, R> {
+    // next: The next key, or None at the end of the input.
+    fn next(&mut self) -> io::Result<Option<Key>> {
+        let byte = match self.byte()? {
+            Some(byte) => byte,
+            None => return Ok(None),
+        };
+        let key = match byte {
+            b'\r' | b'\n' => Key::Enter,
+            127 | 8 => Key::Backspace,
+            0x1b => self.escape()?,
+            1..=26 => Key::Ctrl((b'a' + byte - 1) as char),
+            0..=31 => Key::Unknown,
+            32..=126 => Key::Char(byte as char),
+            _ => self.utf8(byte)?,
+        };
+        Ok(Some(key))
+    }
+
+    // escape: ESC [ or ESC O, then a letter, or digits and a tilde.
+    fn escape(&mut self) -> io::Result<Key> {
+        match self.byte()? {
+            Some(b'[') | Some(b'O') => {}
+            _ => return Ok(Key::Unknown),
+        }
+        let key = match self.byte()? {
+            Some(b'A') => Key::Up,
+            Some(b'B') => Key::Down,
+            Some(b'C') => Key::Right,
+            Some(b'D') => Key::Left,
+            Some(b'H') => Key::Home,
+            Some(b'F') => Key::End,
+            Some(digit @ b'0'..=b'9') => {
+                let mut number = vec![digit];
+                loop {
+                    match self.byte()? {
+                        Some(b'~') => break,
+                        Some(byte) if byte.is_ascii_digit() || byte == b';' => number.push(byte),
+                        _ => return Ok(Key::Unknown),
+                    }
+                }
+                match &number[..] {
+                    b"1" | b"7" => Key::Home,
+                    b"3" => Key::Delete,
+                    b"4" | b"8" => Key::End,
+                    _ => Key::Unknown,
+                }
+            }
+            _ => Key::Unknown,
+        };
+        Ok(key)
+    }
+
+    // utf8: The character that starts with `first`.
+    fn utf8(&mut self, first: u8) -> io::Result<Key> {
+        let len = match first {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => return Ok(Key::Unknown),
+        };
+        let mut bytes = vec![first];
+        while bytes.len() < len {
+            match self.byte()? {
+                Some(byte) => bytes.push(byte),
+                None => return Ok(Key::Unknown),
+            }
+        }
+        Ok(std::str::from_utf8(&bytes)
+            .ok()
+            .and_then(|s| s.chars().next())
+            .map_or(Key::Unknown, Key::Char))
+    }
+
+    fn byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        loop {
+            match self.0.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+mod terminal {
+    use std::mem;
+
+    // RawMode: The terminal in raw mode for as long as this lives, so that keys arrive as
+    // they are pressed and aren't echoed.
+    pub struct RawMode(libc::termios);
+
+    impl RawMode {
+        // enable: Switch to raw mode, if stdin and stdout are both a terminal.
+        pub fn enable() -> Option<RawMode> {
+            unsafe {
+                if libc::isatty(libc::STDIN_FILENO) == 0 || libc::isatty(libc::STDOUT_FILENO) == 0 {
+                    return None;
+                }
+                let mut saved: libc::termios = mem::zeroed();
+                if libc::tcgetattr(libc::STDIN_FILENO, &mut saved) != 0 {
+                    return None;
+                }
+                let mut raw = saved;
+                raw.c_iflag &=
+                    !(libc::ICRNL | libc::IXON | libc::BRKINT | libc::INPCK | libc::ISTRIP);
+                raw.c_lflag &= !(libc::ECHO | libc::ICANON | libc::IEXTEN | libc::ISIG);
+                raw.c_cc[libc::VMIN] = 1;
+                raw.c_cc[libc::VTIME] = 0;
+                if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &raw) != 0 {
+                    return None;
+                }
+                Some(RawMode(saved))
+            }
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.0);
+            }
+        }
+    }
+}
+
+// Elsewhere, lines are read without editing.
+#[cfg(not(unix))]
+mod terminal {
+    pub struct RawMode;
+
+    impl RawMode {
+        pub fn enable() -> Option<RawMode> {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lineedit::{Input, LineEditor};
+
+    fn editor(history: &[&str]) -> LineEditor {
+        LineEditor {
+            history: history.iter().map(|entry| entry.to_string()).collect(),
+            file: None,
+        }
+    }
+
+    fn type_keys(editor: &LineEditor, keys: &str) -> Input {
+        let words = ["print", "println", "push"];
+        type_keys_output(editor, keys, &words).0
+    }
+
+    // type_keys_output: Type `keys`, completing from `words` and "highlighting" in capitals,
+    // and return the result along with what was echoed.
+    fn type_keys_output(editor: &LineEditor, keys: &str, words: &[&str]) -> (Input, String) {
+        let complete = |line: &str| {
+            let start = line.rfind(' ').map_or(0, |i| i + 1);
+            let matches = words.iter().filter(|word| word.starts_with(&line[start..]));
+            (start, matches.map(|word| word.to_string()).collect())
+        };
+        let highlight = |line: &str| line.to_uppercase();
+        let mut output = Vec::new();
+        let input = editor
+            .edit(
+                "> ",
+                &complete,
+                &highlight,
+                &mut keys.as_bytes(),
+                &mut output,
+            )
+            .expect("in-memory editing can't fail");
+        (input, String::from_utf8(output).expect("the echo is UTF-8"))
+    }
+
+    fn line(text: &str) -> Input {
+        Input::Line(text.to_string())
+    }
+
+    #[test]
+    fn keys_edit_the_line() {
+        let editor = editor(&[]);
+        assert_eq!(type_keys(&editor, "print 1;\r"), line("print 1;"));
+        // Left, insert, then Home and delete the first character.
+        assert_eq!(type_keys(&editor, "ac\x1b[Db\x1b[H\x1b[3~\r"), line("bc"));
+        assert_eq!(type_keys(&editor, "abc\x7f\x7fé\r"), line("aé"));
+        // Ctrl-A, Ctrl-K; Ctrl-E, Ctrl-U.
+        assert_eq!(type_keys(&editor, "abc\x01\x06\x0b\r"), line("a"));
+        assert_eq!(type_keys(&editor, "abc\x02\x05\x15x\r"), line("x"));
+    }
+
+    #[test]
+    fn ctrl_c_and_ctrl_d_end_input() {
+        let editor = editor(&[]);
+        assert_eq!(type_keys(&editor, "abc\x03"), Input::Interrupt);
+        assert_eq!(type_keys(&editor, "\x04"), Input::End);
+        assert_eq!(type_keys(&editor, "ab\x01\x04\r"), line("b"));
+        assert_eq!(type_keys(&editor, "ab"), Input::End);
+    }
+
+    #[test]
+    fn arrows_move_through_history() {
+        let mut editor = editor(&[]);
+        editor.add_history("var a = 1;");
+        editor.add_history("print a;");
+        editor.add_history("print a;");
+        editor.add_history("  ");
+        assert_eq!(editor.history, ["var a = 1;", "print a;"]);
+        assert_eq!(type_keys(&editor, "\x1b[A\r"), line("print a;"));
+        assert_eq!(
+            type_keys(&editor, "\x1b[A\x1b[A\x1b[A\r"),
+            line("var a = 1;")
+        );
+        // Down past the newest entry returns to the line being written.
+        assert_eq!(
+            type_keys(&editor, "dr\x1b[A\x1b[B\x1b[Baft\r"),
+            line("draft")
+        );
+    }
+
+    #[test]
+    fn ctrl_r_searches_history() {
+        let editor = editor(&["var a = 1;", "print a;", "var b = 2;"]);
+        assert_eq!(type_keys(&editor, "\x12var\r"), line("var b = 2;"));
+        assert_eq!(type_keys(&editor, "\x12var\x12\r"), line("var a = 1;"));
+        // Another key accepts the match for editing.
+        assert_eq!(type_keys(&editor, "\x12pri\x1b[D\x7f\r"), line("print ;"));
+        // Ctrl-G gives up, leaving the line as it was.
+        assert_eq!(type_keys(&editor, "x\x12var\x07\r"), line("x"));
+    }
+
+    #[test]
+    fn tab_completes_words() {
+        let editor = editor(&[]);
+        assert_eq!(type_keys(&editor, "pu\t 1;\r"), line("push 1;"));
+        assert_eq!(type_keys(&editor, "x = pri\t\r"), line("x = print"));
+        // With no more in common, a second Tab lists the choices.
+        let (input, echo) = type_keys_output(&editor, "print\t\r", &["print", "println"]);
+        assert_eq!(input, line("print"));
+        assert!(echo.contains("\r\nprint  println\r\n"), "{:?}", echo);
+        assert_eq!(type_keys(&editor, "zz\t\r"), line("zz"));
+    }
+
+    #[test]
+    fn lines_are_drawn_highlighted() {
+        let (input, echo) = type_keys_output(&editor(&[]), "print x\x1b[D\r", &[]);
+        assert_eq!(input, line("print x"));
+        assert!(echo.ends_with("\r> PRINT X\x1b[K\x1b[1D\r\n"), "{:?}", echo);
+    }
+}
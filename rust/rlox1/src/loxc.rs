@@ -0,0 +1,529 @@
+// Compiled bytecode files (.loxc): a chunk saved by `rlox1 compile`, so running a large
+// script later skips scanning, parsing and compiling. The layout, with integers in little
+// endian, is:
+//
+//     "LOXC"  magic
+//     u16     format version
+//     u32     code length, then the code bytes
+//     u32     line run count, then each run as the u32 offset it starts at and its u32 line
+//     u32     constant count, then each constant as a tag byte and its contents:
+//             0 = number (f64 bits), 1 = string (u32 length, then UTF-8)
+//
+// Loading checks the whole file before the VM sees it: every opcode must be valid, operands
+// must be in bounds, global names must be strings, jumps and loops must land on an
+// instruction, the code must end with a return and every byte must have a line. The stack is
+// followed through the code as well: no instruction may take more values than are on it or
+// use a local slot past its top, and every way of reaching an instruction must leave it
+// equally deep.
+
+use crate::chunk::{Chunk, LineRun, OpCode};
+use crate::error::LoxError;
+use crate::value::Value;
+
+pub const EXTENSION: &str = "loxc";
+
+const MAGIC: &[u8; 4] = b"LOXC";
+
+// The version of the layout and the instruction set. Bump it whenever either changes, so
+// that older files are refused rather than misread.
+const FORMAT_VERSION: u16 = 4;
+
+const NUMBER: u8 = 0;
+const STRING: u8 = 1;
+
+// save: Serialize a chunk.
+pub fn save(chunk: &Chunk) -> Result<Vec<u8>, LoxError> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(&FORMAT_VERSION.to_le_bytes());
+    bytes.extend(&(chunk.code.len() as u32).to_le_bytes());
+    bytes.extend(&chunk.code);
+    bytes.extend(&(chunk.lines.len() as u32).to_le_bytes());
+    for run in &chunk.lines {
+        bytes.extend(&(run.start as u32).to_le_bytes());
+        bytes.extend(&(run.line as u32).to_le_bytes());
+    }
+    bytes.extend(&(chunk.constants.len() as u32).to_le_bytes());
+    for constant in &chunk.constants {
+        match constant {
+            Value::Number(n) => {
+                bytes.push(NUMBER);
+                bytes.extend(&n.to_bits().to_le_bytes());
+            }
+            Value::Str(s) => {
+                bytes.push(STRING);
+                bytes.extend(&(s.len() as u32).to_le_bytes());
+                bytes.extend(s.as_bytes());
+            }
+            other => loxerr!(LX0709, "Can't save a {} constant", other.type_name()),
+        }
+    }
+    Ok(bytes)
+}
+
+// load: Deserialize and validate a chunk.
+pub fn load(bytes: &[u8]) -> Result<Chunk, LoxError> {
+    let mut reader = Reader { bytes, at: 0 };
+    if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+        loxerr!(LX0710, "Not a compiled Lox file")
+    }
+    let version = reader.u16()?;
+    if version != FORMAT_VERSION {
+        loxerr!(
+            LX0710,
+            "Compiled for bytecode format {}, but this rlox1 runs format {}; recompile it",
+            version,
+            FORMAT_VERSION
+        )
+    }
+    let mut chunk = Chunk::new();
+    let length = reader.u32()? as usize;
+    chunk.code = reader.take(length)?.to_vec();
+    let runs = reader.u32()?;
+    for _ in 0..runs {
+        let start = reader.u32()? as usize;
+        let line = reader.u32()? as usize;
+        chunk.lines.push(LineRun { start, line });
+    }
+    let count = reader.u32()?;
+    for _ in 0..count {
+        let constant = match reader.u8()? {
+            NUMBER => Value::Number(f64::from_bits(reader.u64()?)),
+            STRING => {
+                let length = reader.u32()? as usize;
+                match String::from_utf8(reader.take(length)?.to_vec()) {
+                    Ok(s) => Value::Str(s.into()),
+                    Err(_) => loxerr!(LX0710, "A string constant isn't valid UTF-8"),
+                }
+            }
+            tag => loxerr!(LX0710, "Unknown constant tag {}", tag),
+        };
+        chunk.constants.push(constant);
+    }
+    if reader.at != bytes.len() {
+        loxerr!(LX0710, "Unexpected data after the constants")
+    }
+    validate(&chunk)?;
+    Ok(chunk)
+}
+
+// validate: Check that the VM can run a chunk without reading out of bounds.
+fn validate(chunk: &Chunk) -> Result<(), LoxError> {
+    let code = &chunk.code;
+    let starts = chunk.lines.iter().map(|run| run.start);
+    if chunk.lines.first().map(|run| run.start) != Some(0)
+        || starts.clone().zip(starts.skip(1)).any(|(a, b)| a >= b)
+        || chunk
+            .lines
+            .last()
+            .is_some_and(|run| run.start >= code.len())
+    {
+        loxerr!(LX0710, "The line table doesn't match the code")
+    }
+    let mut starts = vec![false; code.len()];
+    let mut jumps = Vec::new();
+    let mut last = None;
+    let mut at = 0;
+    while at < code.len() {
+        let op = match OpCode::from_byte(code[at]) {
+            Some(op) => op,
+            None => loxerr!(LX0710, "Invalid opcode {} at offset {}", code[at], at),
+        };
+        starts[at] = true;
+        let operands = at + 1;
+        let next = operands + op.operand_bytes();
+        if next > code.len() {
+            loxerr!(
+                LX0710,
+                "The {} instruction at offset {} is cut short",
+                format!("{:?}", op),
+                at
+            )
+        }
+        let operand = |width: usize| {
+            code[operands..operands + width]
+                .iter()
+                .fold(0, |n, byte| n << 8 | *byte as usize)
+        };
+        match op {
+            OpCode::Constant | OpCode::ConstantLong | OpCode::AddConstant
+                if operand(op.operand_bytes()) >= chunk.constants.len() =>
+            {
+                loxerr!(LX0710, "The constant loaded at offset {} doesn't exist", at)
+            }
+            OpCode::GetGlobal | OpCode::DefineGlobal | OpCode::SetGlobal => {
+                match chunk.constants.get(operand(1)) {
+                    Some(Value::Str(_)) => {}
+                    _ => loxerr!(LX0710, "The global named at offset {} has no name", at),
+                }
+            }
+            OpCode::Jump | OpCode::JumpIfFalse => jumps.push((at, next + operand(2))),
+            OpCode::Loop => jumps.push((at, next.wrapping_sub(operand(2)))),
+            _ => {}
+        }
+        last = Some(op);
+        at = next;
+    }
+    if last != Some(OpCode::Return) {
+        loxerr!(LX0710, "The code doesn't end with a return")
+    }
+    for (at, target) in jumps {
+        if !starts.get(target).copied().unwrap_or(false) {
+            loxerr!(
+                LX0710,
+                "The jump at offset {} doesn't land on an instruction",
+                at
+            )
+        }
+    }
+    check_stack(chunk)
+}
+
+// check_stack: Check that the VM's stack never runs short, running through the code with the
+// stack's depth rather than its values. Jumps only go forward, so one pass sees every way
+// into an instruction before reaching it, except for loops, which go back to an instruction
+// already passed and must find the stack as deep as it was there. Code no way reaches isn't
+// checked, as it can't run.
+fn check_stack(chunk: &Chunk) -> Result<(), LoxError> {
+    let code = &chunk.code;
+    // The depth a jump leaves the stack at, at each offset jumped to.
+    let mut arriving: Vec<Option<usize>> = vec![None; code.len()];
+    // The depth before each instruction passed so far, for the loops back to it.
+    let mut passed: Vec<Option<usize>> = vec![None; code.len()];
+    // The depth before the instruction at `at`, if the one before it falls through to it.
+    let mut depth = Some(0);
+    let mut at = 0;
+    while at < code.len() {
+        let op = OpCode::from_byte(code[at]).expect("an opcode validate has checked");
+        let operands = at + 1;
+        let next = operands + op.operand_bytes();
+        depth = match (depth, arriving[at]) {
+            (Some(a), Some(b)) if a != b => loxerr!(
+                LX0710,
+                "The stack is {} deep at offset {} one way and {} another",
+                a,
+                at,
+                b
+            ),
+            (depth, arriving) => depth.or(arriving),
+        };
+        let height = match depth {
+            Some(height) => height,
+            None => {
+                at = next;
+                continue;
+            }
+        };
+        passed[at] = Some(height);
+        // (values taken, values left) and the local slots used, which must be below the
+        // top once the values are taken.
+        let slot = |index: usize| code[operands + index] as usize;
+        let (takes, leaves, slots) = match op {
+            OpCode::Constant
+            | OpCode::ConstantLong
+            | OpCode::Nil
+            | OpCode::True
+            | OpCode::False
+            | OpCode::GetGlobal => (0, 1, vec![]),
+            OpCode::GetLocal => (0, 1, vec![slot(0)]),
+            OpCode::AddLocals => (0, 1, vec![slot(0), slot(1)]),
+            OpCode::SetLocal => (1, 1, vec![slot(0)]),
+            OpCode::SetLocalPop => (1, 0, vec![slot(0)]),
+            OpCode::Pop | OpCode::DefineGlobal | OpCode::Print => (1, 0, vec![]),
+            OpCode::SetGlobal
+            | OpCode::Not
+            | OpCode::Negate
+            | OpCode::AddConstant
+            | OpCode::JumpIfFalse => (1, 1, vec![]),
+            OpCode::Equal
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide => (2, 1, vec![]),
+            // The VM returns nil from an empty stack.
+            OpCode::Return | OpCode::Jump | OpCode::Loop => (0, 0, vec![]),
+        };
+        if takes > height {
+            loxerr!(
+                LX0710,
+                "The {} instruction at offset {} needs {} values on the stack, but there are {}",
+                format!("{:?}", op),
+                at,
+                takes,
+                height
+            )
+        }
+        // SetLocal writes its slot while the value is still on top, so the slot may be it.
+        let below = if op == OpCode::SetLocal {
+            height
+        } else {
+            height - takes
+        };
+        if let Some(slot) = slots.into_iter().find(|&slot| slot >= below) {
+            loxerr!(
+                LX0710,
+                "The {} instruction at offset {} uses local slot {}, but the stack only has {}",
+                format!("{:?}", op),
+                at,
+                slot,
+                below
+            )
+        }
+        let after = height - takes + leaves;
+        if let OpCode::Jump | OpCode::JumpIfFalse = op {
+            let target = next + ((code[operands] as usize) << 8 | code[operands + 1] as usize);
+            match arriving[target] {
+                Some(other) if other != after => loxerr!(
+                    LX0710,
+                    "The stack is {} deep at offset {} one way and {} another",
+                    after,
+                    target,
+                    other
+                ),
+                _ => arriving[target] = Some(after),
+            }
+        }
+        if op == OpCode::Loop {
+            let target = next - ((code[operands] as usize) << 8 | code[operands + 1] as usize);
+            match passed[target] {
+                Some(other) if other == after => {}
+                Some(other) => loxerr!(
+                    LX0710,
+                    "The stack is {} deep at offset {} one way and {} another",
+                    after,
+                    target,
+                    other
+                ),
+                None => loxerr!(
+                    LX0710,
+                    "The loop at offset {} goes back to code nothing else reaches",
+                    at
+                ),
+            }
+        }
+        depth = match op {
+            OpCode::Jump | OpCode::Loop | OpCode::Return => None,
+            _ => Some(after),
+        };
+        at = next;
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], LoxError> {
+        match self.bytes.get(self.at..self.at + length) {
+            Some(taken) => {
+                self.at += length;
+                Ok(taken)
+            }
+            None => loxerr!(LX0710, "The file is truncated"),
+        }
+    }
+
+    fn u8(&mut self) -> Result<u8, LoxError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, LoxError> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.take(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    fn u32(&mut self) -> Result<u32, LoxError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, LoxError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk::{Chunk, OpCode};
+    use crate::compiler::Compiler;
+    use crate::error::LoxError;
+    use crate::loxc::{load, save};
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::value::Value;
+    use crate::vm::Vm;
+
+    fn compile(src: &str) -> Result<Chunk, LoxError> {
+        let mut scanner = Scanner::new(src);
+        Compiler::compile(&Parser::new(scanner.scan_tokens()?).parse()?)
+    }
+
+    fn load_err(bytes: &[u8]) -> String {
+        match load(bytes) {
+            Ok(_) => panic!("{:?} should not load", bytes),
+            Err(err) => format!("{}", err),
+        }
+    }
+
+    // saved: A small saved chunk, `1;`, to corrupt.
+    fn saved() -> Vec<u8> {
+        save(&compile("1;").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn round_trip() -> Result<(), LoxError> {
+        let chunk = compile("var a = \"x\"; { var b = 2.5; a = a + \"y\"; }\nb or a;")?;
+        let loaded = load(&save(&chunk)?)?;
+        assert_eq!(loaded.code, chunk.code);
+        assert_eq!(loaded.lines, chunk.lines);
+        assert_eq!(loaded.constants, chunk.constants);
+        Ok(())
+    }
+
+    #[test]
+    fn loaded_chunks_run() -> Result<(), LoxError> {
+        let mut chunk = compile("var a = 2; { var b = a * 3; a = b - 0.5; }")?;
+        chunk.truncate(chunk.code.len() - 1);
+        let get = chunk.add_constant(Value::Str("a".into())).unwrap() as u8;
+        chunk.write_op(OpCode::GetGlobal, 1);
+        chunk.write(get, 1);
+        chunk.write_op(OpCode::Return, 1);
+        let loaded = load(&save(&chunk)?)?;
+        assert_eq!(format!("{}", Vm::new().run(&loaded)?), "5.5");
+        Ok(())
+    }
+
+    #[test]
+    fn header_is_checked() {
+        assert!(load_err(b"").contains("Not a compiled Lox file"));
+        assert!(load_err(b"#!/usr/bin/env rlox1").contains("Not a compiled Lox file"));
+        let mut bytes = saved();
+        bytes[4] = 99;
+        assert!(load_err(&bytes).contains("Compiled for bytecode format 99"));
+    }
+
+    #[test]
+    fn truncation_is_detected() {
+        let bytes = saved();
+        for length in 4..bytes.len() {
+            let err = load_err(&bytes[..length]);
+            assert!(
+                err.contains("truncated") || err.contains("format"),
+                "{}",
+                err
+            );
+        }
+        let mut bytes = saved();
+        bytes.push(0);
+        assert!(load_err(&bytes).contains("Unexpected data"));
+    }
+
+    #[test]
+    fn code_is_validated() -> Result<(), LoxError> {
+        let corrupt = |src: &str, f: &dyn Fn(&mut Chunk)| -> Result<String, LoxError> {
+            let mut chunk = compile(src)?;
+            f(&mut chunk);
+            Ok(load_err(&save(&chunk)?))
+        };
+        let err = corrupt("1;", &|chunk| chunk.code[0] = 200)?;
+        assert!(err.contains("Invalid opcode 200 at offset 0"), "{}", err);
+        let err = corrupt("1;", &|chunk| chunk.code[1] = 7)?;
+        assert!(
+            err.contains("constant loaded at offset 0 doesn't exist"),
+            "{}",
+            err
+        );
+        let err = corrupt("nil;", &|chunk| chunk.truncate(2))?;
+        assert!(err.contains("doesn't end with a return"), "{}", err);
+        let err = corrupt("nil or 1;", &|chunk| chunk.code[3] = 1)?;
+        assert!(err.contains("jump at offset 1 doesn't land"), "{}", err);
+        let err = corrupt("a;", &|chunk| chunk.constants[0] = Value::Number(1.0))?;
+        assert!(
+            err.contains("global named at offset 0 has no name"),
+            "{}",
+            err
+        );
+        let err = corrupt("a;\nb;", &|chunk| chunk.lines[1].start = 9)?;
+        assert!(err.contains("line table doesn't match"), "{}", err);
+        let err = corrupt("a;\nb;", &|chunk| chunk.lines[1].start = 0)?;
+        assert!(err.contains("line table doesn't match"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn stack_is_validated() -> Result<(), LoxError> {
+        // build: A chunk of `ops`, each an opcode and its operand bytes.
+        let build = |ops: &[(OpCode, &[u8])]| -> Result<String, LoxError> {
+            let mut chunk = Chunk::new();
+            for (op, operands) in ops {
+                chunk.write_op(*op, 1);
+                for byte in *operands {
+                    chunk.write(*byte, 1);
+                }
+            }
+            Ok(load_err(&save(&chunk)?))
+        };
+        let err = build(&[(OpCode::Pop, &[]), (OpCode::Return, &[])])?;
+        assert!(
+            err.contains(
+                "Pop instruction at offset 0 needs 1 values on the stack, but there are 0"
+            ),
+            "{}",
+            err
+        );
+        let err = build(&[
+            (OpCode::Nil, &[]),
+            (OpCode::GetLocal, &[1]),
+            (OpCode::Return, &[]),
+        ])?;
+        assert!(
+            err.contains(
+                "GetLocal instruction at offset 1 uses local slot 1, but the stack only has 1"
+            ),
+            "{}",
+            err
+        );
+        let err = build(&[
+            (OpCode::Nil, &[]),
+            (OpCode::SetLocalPop, &[0]),
+            (OpCode::Return, &[]),
+        ])?;
+        assert!(err.contains("uses local slot 0"), "{}", err);
+        // The jump skips the second Nil, so the Return is reached one deep and two deep.
+        let err = build(&[
+            (OpCode::Nil, &[]),
+            (OpCode::JumpIfFalse, &[0, 1]),
+            (OpCode::Nil, &[]),
+            (OpCode::Return, &[]),
+        ])?;
+        assert!(
+            err.contains("stack is 2 deep at offset 5 one way and 1 another"),
+            "{}",
+            err
+        );
+        // Each pass of the loop leaves one more value behind.
+        let err = build(&[
+            (OpCode::Nil, &[]),
+            (OpCode::Loop, &[0, 4]),
+            (OpCode::Return, &[]),
+        ])?;
+        assert!(
+            err.contains("stack is 1 deep at offset 0 one way and 0 another"),
+            "{}",
+            err
+        );
+        let err = build(&[(OpCode::Loop, &[0, 9]), (OpCode::Return, &[])])?;
+        assert!(err.contains("jump at offset 0 doesn't land"), "{}", err);
+        Ok(())
+    }
+}
@@ -1,31 +1,611 @@
-use clap::{App, Arg};
+use std::io::{self, IsTerminal, Write};
+use std::panic;
+use std::path::Path;
+use std::process;
+use std::thread;
 
-// TODO: Add documentation.
+use clap::{App, AppSettings, Arg, ArgMatches, Shell, SubCommand};
 
-#[macro_use]
-mod error;
-mod executive;
-mod scanner;
+// The rlox1 command: its command line, and what it reports and exits with. The interpreter
+// itself is the library in lib.rs.
 
-use executive::Executor;
+use rlox1::diagnostic::{ColorChoice, Diagnostics, Format, Level, Renderer};
+use rlox1::error::{self, ErrorKind, LoxError};
+use rlox1::executive::{Engine, Executor, SANDBOX_MAX_CALL_DEPTH};
+use rlox1::log::{self, Verbosity};
+use rlox1::memory::{self, Counting};
+use rlox1::{bench, catalog, codes, gc, interpreter, lox_error, loxc, loxerr};
+
+// Every allocation is counted, for --sandbox's memory limit.
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
 
 // ------------------------------------------------------------------------------------------------
 // Main
 // ------------------------------------------------------------------------------------------------
 
 fn main() {
-    let matches = App::new("rlox1: Lox in Rust.")
+    let matches = app().get_matches();
+    let renderer = renderer(&matches);
+    report_panics(renderer);
+    memory::set_meter(Counting::in_use);
+    log::set_verbosity(verbosity(&matches));
+    // Lox calls recurse on the Rust stack, so run on a thread big enough for the call limit.
+    let interpreter = thread::Builder::new()
+        .stack_size(interpreter::STACK_SIZE)
+        .spawn(move || {
+            let result = dispatch(&matches);
+            // The collector's heap belongs to this thread, so report from here. A last
+            // collection first shows what would otherwise have leaked.
+            if matches.subcommand().1.is_some_and(|sub| sub.is_present("gc-stats")) {
+                gc::collect();
+                eprintln!("{}", gc::stats());
+            }
+            result
+        })
+        .expect("Failed to start the interpreter thread!");
+    let result = match interpreter.join() {
+        Ok(result) => result,
+        // The panic hook has already said what went wrong.
+        Err(_) => process::exit(error::EX_SOFTWARE),
+    };
+    // Exit with 65 if the program couldn't be run, 70 if it failed while running, or the code
+    // it passed to exit(), which is the only one of these that isn't reported. When there was
+    // more than one error, a note at the end sums up.
+    if let Err(err) = result {
+        let status = err.status();
+        let exit = err.exit_code().is_some();
+        let errors = Diagnostics::from(err);
+        if !exit {
+            eprintln!("{}", errors.render(&renderer, Level::Error));
+        }
+        if errors.len() > 1 {
+            let summary = LoxError::new(&format!(
+                "{} errors; exiting with status {}",
+                errors.len(),
+                status
+            ));
+            eprintln!("{}", renderer.render(Level::Note, &summary));
+        }
+        process::exit(status);
+    }
+}
+
+// report_panics: Report a panic, which is a bug in rlox1 rather than in the script, as a
+// diagnostic saying where in rlox1 it happened, instead of Rust's dump of it. With
+// RLOX_BACKTRACE set, the diagnostic has the backtrace too.
+fn report_panics(renderer: Renderer) {
+    panic::set_hook(Box::new(move |info| {
+        let payload = info.payload();
+        let what = match payload.downcast_ref::<&str>() {
+            Some(what) => what.to_string(),
+            None => payload.downcast_ref::<String>().cloned().unwrap_or_default(),
+        };
+        let place = info.location().map_or_else(
+            || "an unknown place".to_string(),
+            |at| format!("{}:{}:{}", at.file(), at.line(), at.column()),
+        );
+        let err = lox_error!(
+            LX0607,
+            "Internal interpreter error at {}: {}; please report this bug in rlox1",
+            place,
+            what
+        )
+        .of_kind(ErrorKind::Internal);
+        // eprintln! would panic again inside the hook if stderr is closed, aborting rlox1.
+        let _ = writeln!(io::stderr(), "{}", renderer.render(Level::Error, &err));
+    }));
+}
+
+// app: The command line: rlox1's commands and their flags.
+fn app() -> App<'static, 'static> {
+    App::new("rlox1: Lox in Rust.")
         .version("v0.1.0")
         .author("Brian King <brian@jenashcal.net>")
         .about("Implementation of Lox from Part II of Crafting Interpreters by Robert Nystrum.")
-        .arg(Arg::with_name("script").index(1))
-        .get_matches();
-    let exec = Executor::new();
-    let result = match matches.value_of("script") {
-        None => exec.run_repl(),
-        Some(script) => exec.run_file(script),
-    };
-    if let Err(err) = result {
-        eprintln!("ERROR: {}", err);
+        .after_help("With no command, run the program on stdin, or start a REPL on a terminal.")
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .value_name("WHEN")
+                .possible_values(&["auto", "always", "never"])
+                .default_value("auto")
+                .global(true)
+                .help("Color errors and warnings: always, never, or on a terminal"),
+        )
+        .arg(
+            Arg::with_name("error-format")
+                .long("error-format")
+                .value_name("FORMAT")
+                .possible_values(&["human", "json"])
+                .default_value("human")
+                .global(true)
+                .help("Write errors and warnings for people, or as a JSON object per line"),
+        )
+        .arg(
+            Arg::with_name("error-limit")
+                .long("error-limit")
+                .value_name("N")
+                .default_value("20")
+                .validator(|n| match n.parse::<usize>() {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(format!("expects a whole number, got '{}'", n)),
+                })
+                .global(true)
+                .help("Show at most N of a program's errors, or all of them with 0"),
+        )
+        .arg(
+            Arg::with_name("lang")
+                .long("lang")
+                .value_name("LANG")
+                .default_value("en")
+                .global(true)
+                .help("The language of errors and warnings: en, es, or a catalog file's path"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .multiple(true)
+                .global(true)
+                .help("Log what rlox1 does as it runs a script; -vv logs more"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .conflicts_with("verbose")
+                .global(true)
+                .help("Report errors only, not warnings"),
+        )
+        .arg(
+            Arg::with_name("deny-warnings")
+                .long("deny-warnings")
+                .global(true)
+                .help("Treat warnings as errors, so a script with any doesn't run"),
+        )
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Run a script, or several in turn")
+                .arg(
+                    script_arg()
+                        .multiple(true)
+                        .help("The scripts to run, in order, or - for stdin [default: -]"),
+                )
+                .arg(script_args_arg())
+                .args(&source_args())
+                .arg(
+                    Arg::with_name("fail-fast")
+                        .long("fail-fast")
+                        .help("Stop at the first runtime error, rather than running the rest"),
+                )
+                .arg(
+                    Arg::with_name("isolate")
+                        .long("isolate")
+                        .help("Run each script with fresh globals, rather than sharing them"),
+                )
+                .args(&engine_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("debug")
+                .about("Run a script in the debugger, which takes commands on stdin; try 'help'")
+                .arg(script_arg().required(true))
+                .arg(script_args_arg())
+                .args(&source_args())
+                .args(&engine_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("repl")
+                .about("Start a REPL, which runs each entry as it is typed")
+                .arg(
+                    Arg::with_name("no-color")
+                        .long("no-color")
+                        .help("Don't highlight what is typed; setting NO_COLOR does the same"),
+                )
+                .arg(
+                    Arg::with_name("replay")
+                        .long("replay")
+                        .value_name("FILE")
+                        .help("Run a transcript saved with :record first, showing it as typed"),
+                )
+                .args(&engine_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("tokenize")
+                .about("Print the tokens a script scans to")
+                .arg(script_arg().required(true))
+                .args(&source_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("parse")
+                .about("Print the syntax tree of a script, a statement per line")
+                .arg(script_arg().required(true))
+                .args(&source_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Report the errors scripts have before they run, without running them")
+                .arg(
+                    script_arg()
+                        .required(true)
+                        .multiple(true)
+                        .help("The scripts to check, or - for stdin"),
+                )
+                .arg(
+                    Arg::with_name("recursive")
+                        .short("r")
+                        .long("recursive")
+                        .help("Check every .lox file in the directories given, however deep"),
+                )
+                .args(&source_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("lint")
+                .about("Report the warnings and errors scripts have, without running them")
+                .arg(
+                    script_arg()
+                        .required(true)
+                        .multiple(true)
+                        .help("The scripts to lint, or - for stdin"),
+                )
+                .arg(
+                    Arg::with_name("recursive")
+                        .short("r")
+                        .long("recursive")
+                        .help("Lint every .lox file in the directories given, however deep"),
+                )
+                .args(&source_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("fmt")
+                .about("Print a script laid out in the standard way, unless it has comments")
+                .arg(script_arg().required(true))
+                .args(&source_args())
+                .arg(
+                    Arg::with_name("write")
+                        .short("w")
+                        .long("write")
+                        .help("Rewrite the script in place instead of printing it"),
+                )
+                .arg(
+                    Arg::with_name("check")
+                        .long("check")
+                        .conflicts_with("write")
+                        .help("Print nothing, but fail if the script isn't formatted"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("compile")
+                .about("Compile a script to a bytecode file the VM can run directly")
+                .arg(script_arg().required(true))
+                .args(&source_args())
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Where to write the bytecode [default: the script's name, as .loxc]"),
+                )
+                .arg(
+                    Arg::with_name("disassemble")
+                        .long("disassemble")
+                        .help("List the bytecode instead of saving it; works on .loxc files too"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("test")
+                .about("Run scripts and check them against their '// expect: ' comments")
+                .arg(
+                    Arg::with_name("paths")
+                        .index(1)
+                        .multiple(true)
+                        .required(true)
+                        .help("The scripts to test, or directories to look through for them"),
+                )
+                .arg(engine_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("Time the built-in benchmark programs on the selected engine")
+                .arg(
+                    Arg::with_name("benchmark")
+                        .index(1)
+                        .multiple(true)
+                        .possible_values(&bench::names())
+                        .help("The benchmarks to run [default: all of them]"),
+                )
+                .arg(
+                    Arg::with_name("runs")
+                        .short("n")
+                        .long("runs")
+                        .value_name("N")
+                        .default_value("10")
+                        .help("How many times to run each benchmark"),
+                )
+                .args(&engine_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("completions")
+                .about("Print a script that completes rlox1's commands and flags in a shell")
+                .arg(
+                    Arg::with_name("shell")
+                        .index(1)
+                        .required(true)
+                        .possible_values(&Shell::variants())
+                        .help("The shell to complete in"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("explain")
+                .about("Explain an error or warning by its code, e.g. LX0203")
+                .arg(
+                    Arg::with_name("code")
+                        .index(1)
+                        .required(true)
+                        .help("The code, from the diagnostic's header"),
+                ),
+        )
+}
+
+// dispatch: Carry out the command given on the command line.
+fn dispatch(matches: &ArgMatches) -> Result<(), LoxError> {
+    catalog::set_language(lang(matches))?;
+    match matches.subcommand() {
+        ("run", Some(run)) => match run.values_of("script") {
+            None => executor(run)?.run_stdin(),
+            Some(scripts) => executor(run)?.run_files(&scripts.collect::<Vec<_>>()),
+        },
+        ("debug", Some(debug)) => executor(debug)?.debug_file(script(debug)),
+        ("repl", Some(repl)) => executor(repl)?.run_repl(repl.value_of("replay")),
+        ("tokenize", Some(tokenize)) => executor(tokenize)?.tokenize_file(script(tokenize)),
+        ("parse", Some(parse)) => executor(parse)?.parse_file(script(parse)),
+        ("check", Some(check)) => {
+            let paths: Vec<_> = check.values_of("script").into_iter().flatten().collect();
+            match (&paths[..], check.is_present("recursive")) {
+                ([script], false) if !Path::new(script).is_dir() => {
+                    executor(check)?.check_file(script)
+                }
+                (_, recursive) => executor(check)?.check_files(&paths, recursive),
+            }
+        }
+        ("lint", Some(lint)) => {
+            let paths: Vec<_> = lint.values_of("script").into_iter().flatten().collect();
+            executor(lint)?.lint_files(&paths, lint.is_present("recursive"))
+        }
+        ("fmt", Some(fmt)) => {
+            let (write, check) = (fmt.is_present("write"), fmt.is_present("check"));
+            executor(fmt)?.format_file(script(fmt), write, check)
+        }
+        ("compile", Some(compile)) => {
+            let script = script(compile);
+            let exec = executor(compile)?;
+            if compile.is_present("disassemble") {
+                return exec.disassemble_file(script);
+            }
+            let output = match compile.value_of("output") {
+                Some(output) => output.to_string(),
+                None => Path::new(script)
+                    .with_extension(loxc::EXTENSION)
+                    .display()
+                    .to_string(),
+            };
+            exec.compile_file(script, &output)
+        }
+        ("completions", Some(completions)) => {
+            let shell = completions.value_of("shell").expect("a required argument");
+            let shell = shell.parse::<Shell>().expect("one of the possible values");
+            app().gen_completions_to("rlox1", shell, &mut io::stdout());
+            Ok(())
+        }
+        ("explain", Some(explain)) => {
+            let code = explain.value_of("code").expect("a required argument");
+            match codes::find(code) {
+                Some(code) => {
+                    println!("{}: {}\n\n{}", code.code, code.title, code.explanation);
+                    Ok(())
+                }
+                None => loxerr!(
+                    LX0902,
+                    "{} isn't a diagnostic code; codes look like LX0203",
+                    code
+                ),
+            }
+        }
+        ("test", Some(test)) => {
+            let paths: Vec<_> = test.values_of("paths").into_iter().flatten().collect();
+            executor(test)?.test(&paths)
+        }
+        ("bench", Some(bench)) => {
+            let runs = bench.value_of("runs").expect("a default");
+            let runs = match runs.parse() {
+                Ok(runs) => runs,
+                Err(_) => loxerr!(LX0901, "{} expects a whole number, got '{}'", "--runs", runs),
+            };
+            let names: Vec<_> = bench.values_of("benchmark").into_iter().flatten().collect();
+            executor(bench)?.bench(&names, runs)
+        }
+        // Input from a pipe or file is a program, not REPL entries.
+        _ if !io::stdin().is_terminal() => Executor::new().run_stdin(),
+        _ => Executor::new().diagnostics(renderer(matches)).run_repl(None),
+    }
+}
+
+// executor: An executor set up by a command's flags.
+fn executor(matches: &ArgMatches) -> Result<Executor, LoxError> {
+    let engine = match matches.value_of("engine") {
+        Some("vm") => Engine::Vm,
+        Some("register") => Engine::Register,
+        _ => Engine::TreeWalk,
     };
+    let mut exec = Executor::new()
+        .engine(engine)
+        .allow_io(matches.is_present("allow-io"))
+        .sandbox(matches.is_present("sandbox"))
+        .print_natives(!matches.is_present("no-print-natives"))
+        .profile(matches.is_present("profile"))
+        .dump_tokens(matches.is_present("tokens"))
+        .dump_ast(matches.is_present("ast"))
+        .trace(matches.is_present("trace"))
+        .trace_exec(matches.is_present("trace-exec"))
+        .fail_fast(matches.is_present("fail-fast"))
+        .isolate(matches.is_present("isolate"))
+        .force(matches.is_present("force"))
+        .deny_warnings(matches.is_present("deny-warnings"))
+        .color(!matches.is_present("no-color") && color_choice(matches) != ColorChoice::Never)
+        .diagnostics(renderer(matches))
+        .script_args(values(matches.values_of("args")));
+    if let Some(depth) = matches.value_of("max-call-depth") {
+        match depth.parse() {
+            Ok(depth) => {
+                if matches.is_present("sandbox") && depth > SANDBOX_MAX_CALL_DEPTH {
+                    let warning = lox_error!(
+                        LX0606,
+                        "The sandbox limits calls to a depth of {}",
+                        SANDBOX_MAX_CALL_DEPTH
+                    );
+                    if matches.is_present("deny-warnings") {
+                        return Err(warning);
+                    }
+                    if log::enabled(Verbosity::Normal) {
+                        eprintln!("{}", renderer(matches).render(Level::Warning, &warning));
+                    }
+                }
+                exec = exec.max_call_depth(depth)
+            }
+            Err(_) => loxerr!(
+                LX0901,
+                "{} expects a whole number, got '{}'",
+                "--max-call-depth",
+                depth
+            ),
+        }
+    }
+    if let Some(bytes) = matches.value_of("max-source-bytes") {
+        match bytes.parse() {
+            Ok(bytes) => exec = exec.max_source_bytes(bytes),
+            Err(_) => loxerr!(
+                LX0901,
+                "{} expects a whole number, got '{}'",
+                "--max-source-bytes",
+                bytes
+            ),
+        }
+    }
+    Ok(exec)
+}
+
+// color_choice: When to color diagnostics. --color is global, so a command's matches have it
+// wherever on the command line it was given.
+fn color_choice(matches: &ArgMatches) -> ColorChoice {
+    let matches = matches.subcommand().1.unwrap_or(matches);
+    let choice = matches.value_of("color").and_then(ColorChoice::from_name);
+    choice.unwrap_or(ColorChoice::Auto)
+}
+
+// verbosity: How much to report, as -q and -v say. They are global, like --color.
+fn verbosity(matches: &ArgMatches) -> Verbosity {
+    let matches = matches.subcommand().1.unwrap_or(matches);
+    Verbosity::from_flags(matches.is_present("quiet"), matches.occurrences_of("verbose"))
+}
+
+// lang: The language to report in, as --lang says. It is global, like --color.
+fn lang<'a>(matches: &'a ArgMatches) -> &'a str {
+    let matches = matches.subcommand().1.unwrap_or(matches);
+    matches.value_of("lang").unwrap_or("en")
+}
+
+// renderer: How to lay out diagnostics, as --color, --error-format and --error-limit say.
+// clap has made sure the limit is a number.
+fn renderer(matches: &ArgMatches) -> Renderer {
+    let sub = matches.subcommand().1.unwrap_or(matches);
+    let format = sub.value_of("error-format").and_then(Format::from_name);
+    let limit = sub.value_of("error-limit").and_then(|n| n.parse().ok());
+    Renderer::new(color_choice(matches).enabled())
+        .format(format.unwrap_or(Format::Human))
+        .error_limit(limit.filter(|&limit| limit > 0))
+}
+
+// values: The values of an argument that may be repeated, owned so they can go to the
+// executor.
+fn values(values: Option<clap::Values>) -> Vec<String> {
+    values.into_iter().flatten().map(String::from).collect()
+}
+
+// script: The script a command was given, which clap has made sure is there.
+fn script<'a>(matches: &'a ArgMatches) -> &'a str {
+    matches.value_of("script").expect("a required argument")
+}
+
+fn script_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("script").index(1)
+}
+
+fn script_args_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("args")
+        .multiple(true)
+        .last(true)
+        .value_name("ARGS")
+        .help("Arguments for the script, after --, which it sees in args()")
+}
+
+// source_args: The flags of the commands that read scripts, saying which to accept.
+fn source_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("max-source-bytes")
+            .long("max-source-bytes")
+            .value_name("N")
+            .help("Refuse scripts bigger than N bytes [default: no limit]"),
+        Arg::with_name("force")
+            .long("force")
+            .help("Read scripts that don't look like Lox: binary, or not named .lox"),
+    ]
+}
+
+fn engine_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("engine")
+        .long("engine")
+        .value_name("ENGINE")
+        .possible_values(&["treewalk", "vm", "register"])
+        .default_value("treewalk")
+        .help("The engine that runs programs; the register VM is experimental")
+}
+
+// engine_args: The flags of the commands that run programs, saying how to run them.
+fn engine_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        engine_arg(),
+        Arg::with_name("sandbox")
+            .long("sandbox")
+            .conflicts_with("allow-io")
+            .help("Run untrusted code: no file or env access, limited memory and call depth"),
+        Arg::with_name("allow-io")
+            .long("allow-io")
+            .help("Allow scripts to read and write files, and set environment variables"),
+        Arg::with_name("no-print-natives")
+            .long("no-print-natives")
+            .help("Keep print a statement only, without print() and println() functions"),
+        Arg::with_name("gc-stats")
+            .long("gc-stats")
+            .help("Print garbage collector statistics on exit"),
+        Arg::with_name("profile")
+            .long("profile")
+            .help("Print how often each node or instruction ran, and time per function, on exit"),
+        Arg::with_name("tokens")
+            .long("tokens")
+            .help("Print the tokens each program scans to, before running it"),
+        Arg::with_name("ast")
+            .long("ast")
+            .help("Print the syntax tree of each program, before running it"),
+        Arg::with_name("trace")
+            .long("trace")
+            .help("Print each statement as the tree-walking interpreter executes it"),
+        Arg::with_name("trace-exec")
+            .long("trace-exec")
+            .help("Like --trace, with line numbers, and calls with arguments and results"),
+        Arg::with_name("max-call-depth")
+            .long("max-call-depth")
+            .value_name("N")
+            .help("Limit how deeply Lox function calls may nest"),
+    ]
 }
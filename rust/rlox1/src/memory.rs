@@ -0,0 +1,89 @@
+// Heap accounting, so a sandboxed script can be stopped before it uses too much memory. The
+// library doesn't choose the program's allocator: whatever binary it is part of does, and
+// tells the library how to read the bytes in use with set_meter. Without a meter, a memory
+// limit isn't checked. rlox1 itself installs Counting, a thin wrapper around the system
+// allocator that keeps a running total; a host with another allocator, e.g. jemalloc, can
+// meter with its statistics instead.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+// Counting: The system allocator, counting what it has allocated, for a binary to install
+// with #[global_allocator] and meter with Counting::in_use.
+pub struct Counting;
+
+static IN_USE: AtomicUsize = AtomicUsize::new(0);
+
+// The binary's way of reading how many bytes are allocated, once it has given one.
+static METER: OnceLock<fn() -> usize> = OnceLock::new();
+
+impl Counting {
+    // in_use: How many bytes Counting has allocated and not freed.
+    pub fn in_use() -> usize {
+        IN_USE.load(Ordering::Relaxed)
+    }
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            IN_USE.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            IN_USE.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        IN_USE.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = System.realloc(ptr, layout, new_size);
+        if !new.is_null() {
+            IN_USE.fetch_add(new_size, Ordering::Relaxed);
+            IN_USE.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+        new
+    }
+}
+
+// set_meter: Read the bytes in use with `meter` from now on. Only the first meter given is
+// kept, as the allocator can't change either.
+pub fn set_meter(meter: fn() -> usize) {
+    let _ = METER.set(meter);
+}
+
+// in_use: How many bytes are allocated right now, if the binary has said how to tell.
+pub fn in_use() -> Option<usize> {
+    METER.get().map(|meter| meter())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::{GlobalAlloc, Layout};
+
+    use crate::memory::Counting;
+
+    // The tests don't install Counting, so it is called directly. Other tests may be using it
+    // at the same time, so only check that a big allocation shows.
+    #[test]
+    fn allocations_are_counted() {
+        let layout = Layout::from_size_align(64 << 20, 8).unwrap();
+        let before = Counting::in_use();
+        unsafe {
+            let block = Counting.alloc(layout);
+            assert!(Counting::in_use() >= before + (32 << 20));
+            Counting.dealloc(block, layout);
+        }
+    }
+}
@@ -0,0 +1,201 @@
+// NaN-boxed values for the VM's stack, the representation from chapter 30 of the book, used
+// when the `nan-boxing` feature is on. A NanBox is one u64: a number is its own bits, and
+// anything else hides in the payload of a quiet NaN, which no arithmetic produces. Nil and
+// the booleans are small tags; other values are moved into an Rc whose pointer, which fits
+// in the low 48 bits, is stored with the sign bit set.
+
+use std::fmt;
+use std::rc::Rc;
+
+use crate::value::Value;
+
+// The bits every boxed non-number has set: the exponent, the quiet bit and one more, so
+// that the canonical NaN arithmetic produces still reads as a number.
+const QNAN: u64 = 0x7ffc_0000_0000_0000;
+const SIGN_BIT: u64 = 0x8000_0000_0000_0000;
+const POINTER_BITS: u64 = 0x0000_ffff_ffff_ffff;
+
+const NIL: u64 = QNAN | 1;
+const FALSE: u64 = QNAN | 2;
+const TRUE: u64 = QNAN | 3;
+
+pub struct NanBox(u64);
+
+impl NanBox {
+    fn number(n: f64) -> Self {
+        // Every NaN becomes the canonical one, so no payload can be mistaken for a tag.
+        NanBox(if n.is_nan() {
+            f64::NAN.to_bits()
+        } else {
+            n.to_bits()
+        })
+    }
+
+    fn object(value: Value) -> Self {
+        let pointer = Rc::into_raw(Rc::new(value)) as u64;
+        assert_eq!(
+            pointer & !POINTER_BITS,
+            0,
+            "object pointers must fit in 48 bits"
+        );
+        NanBox(SIGN_BIT | QNAN | pointer)
+    }
+
+    fn is_number(&self) -> bool {
+        self.0 & QNAN != QNAN
+    }
+
+    fn as_object(&self) -> Option<*const Value> {
+        if self.0 & (SIGN_BIT | QNAN) == SIGN_BIT | QNAN {
+            Some((self.0 & POINTER_BITS) as *const Value)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        if self.is_number() {
+            Some(f64::from_bits(self.0))
+        } else {
+            None
+        }
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        self.0 != NIL && self.0 != FALSE
+    }
+
+    // with_value: Run `f` on the value this box stands for, without unboxing objects.
+    fn with_value<T>(&self, f: impl FnOnce(&Value) -> T) -> T {
+        match self.as_object() {
+            // The box owns a strong reference, so the object outlives the borrow.
+            Some(pointer) => f(unsafe { &*pointer }),
+            None => f(&Value::from(self.clone())),
+        }
+    }
+}
+
+impl From<Value> for NanBox {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Nil => NanBox(NIL),
+            Value::Bool(false) => NanBox(FALSE),
+            Value::Bool(true) => NanBox(TRUE),
+            Value::Number(n) => NanBox::number(n),
+            other => NanBox::object(other),
+        }
+    }
+}
+
+impl From<NanBox> for Value {
+    fn from(boxed: NanBox) -> Self {
+        match boxed.0 {
+            NIL => Value::Nil,
+            FALSE => Value::Bool(false),
+            TRUE => Value::Bool(true),
+            bits => match boxed.as_object() {
+                Some(pointer) => unsafe { (*pointer).clone() },
+                None => Value::Number(f64::from_bits(bits)),
+            },
+        }
+    }
+}
+
+impl Clone for NanBox {
+    fn clone(&self) -> Self {
+        if let Some(pointer) = self.as_object() {
+            unsafe { Rc::increment_strong_count(pointer) };
+        }
+        NanBox(self.0)
+    }
+}
+
+impl Drop for NanBox {
+    fn drop(&mut self) {
+        if let Some(pointer) = self.as_object() {
+            unsafe { Rc::decrement_strong_count(pointer) };
+        }
+    }
+}
+
+// Numbers compare as numbers, so NaN is unequal to itself, and objects compare as the values
+// they box. Everything else is equal exactly when the bits are.
+impl PartialEq for NanBox {
+    fn eq(&self, other: &NanBox) -> bool {
+        match (self.as_number(), other.as_number()) {
+            (Some(a), Some(b)) => a == b,
+            _ => match (self.as_object(), other.as_object()) {
+                (Some(a), Some(b)) => unsafe { *a == *b },
+                _ => self.0 == other.0,
+            },
+        }
+    }
+}
+
+impl fmt::Display for NanBox {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.with_value(|value| write!(f, "{}", value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::nanbox::NanBox;
+    use crate::value::Value;
+
+    fn round_trip(value: Value) -> String {
+        format!("{}", Value::from(NanBox::from(value)))
+    }
+
+    #[test]
+    fn values_round_trip() {
+        assert_eq!(round_trip(Value::Nil), "nil");
+        assert_eq!(round_trip(Value::Bool(true)), "true");
+        assert_eq!(round_trip(Value::Bool(false)), "false");
+        assert_eq!(round_trip(Value::Number(-1.5)), "-1.5");
+        assert_eq!(round_trip(Value::Number(f64::INFINITY)), "inf");
+        assert_eq!(round_trip(Value::Number(f64::NAN)), "NaN");
+        assert_eq!(round_trip(Value::Str("boxed".into())), "boxed");
+    }
+
+    #[test]
+    fn numbers_and_tags() {
+        assert_eq!(NanBox::from(Value::Number(2.0)).as_number(), Some(2.0));
+        assert_eq!(NanBox::from(Value::Nil).as_number(), None);
+        assert!(NanBox::from(Value::Number(f64::NAN))
+            .as_number()
+            .unwrap()
+            .is_nan());
+        assert!(!NanBox::from(Value::Nil).is_truthy());
+        assert!(!NanBox::from(Value::Bool(false)).is_truthy());
+        assert!(NanBox::from(Value::Number(0.0)).is_truthy());
+        assert!(NanBox::from(Value::Str("".into())).is_truthy());
+    }
+
+    #[test]
+    fn equality_matches_values() {
+        let string = |s: &str| NanBox::from(Value::Str(s.into()));
+        let number = |n| NanBox::from(Value::Number(n));
+        assert!(string("a") == string("a"));
+        assert!(string("a") != string("b"));
+        assert!(number(1.0) == number(1.0));
+        assert!(number(f64::NAN) != number(f64::NAN));
+        assert!(NanBox::from(Value::Nil) == NanBox::from(Value::Nil));
+        assert!(NanBox::from(Value::Nil) != NanBox::from(Value::Bool(false)));
+        assert!(number(0.0) != NanBox::from(Value::Bool(false)));
+    }
+
+    #[test]
+    fn objects_are_reference_counted() {
+        let list = Rc::new(std::cell::RefCell::new(vec![Value::Nil]));
+        let boxed = NanBox::from(Value::List(list.clone()));
+        let copy = boxed.clone();
+        assert_eq!(Rc::strong_count(&list), 2);
+        drop(boxed);
+        assert_eq!(format!("{}", copy), "[nil]");
+        drop(copy);
+        assert_eq!(Rc::strong_count(&list), 1);
+    }
+}
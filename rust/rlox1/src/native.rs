@@ -0,0 +1,234 @@
+// Binding Rust functions as natives. Defining one with define_native means checking the
+// type of each argument and converting it from a Value, then converting the result back;
+// lox_native! writes that from the function's signature:
+//
+//   lox_native! {
+//       fn hypot(x: f64, y: f64) -> f64 {
+//           x.hypot(y)
+//       }
+//   }
+//   lox.set_global("hypot", hypot());
+//
+// Each function becomes one that makes the native, a Value called by the same name in
+// messages, which the host can bind to whatever global it likes. Its parameters can be of
+// any type that is FromValue, and its result of any that is IntoValue; returning a
+// Result<_, LoxError> lets it fail. The interpreter checks the number of arguments before
+// calling it, as it does for every native.
+
+use crate::error::LoxError;
+use crate::value::Value;
+
+// FromValue: A Rust type a native can take as a parameter.
+pub trait FromValue: Sized {
+    // WHAT: The values that convert, as messages say it, e.g. "a number".
+    const WHAT: &'static str;
+
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+impl FromValue for f64 {
+    const WHAT: &'static str = "a number";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for bool {
+    const WHAT: &'static str = "a bool";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for String {
+    const WHAT: &'static str = "a string";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Str(s) => Some(s.to_string()),
+            _ => None,
+        }
+    }
+}
+
+// A Value parameter takes anything, for a native that looks at what it was given itself.
+impl FromValue for Value {
+    const WHAT: &'static str = "a value";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+
+// IntoValue: A Rust type a native can return. A Result is how a native fails, so converting
+// one can fail too.
+pub trait IntoValue {
+    fn into_value(self) -> Result<Value, LoxError>;
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Result<Value, LoxError> {
+        Ok(Value::Number(self))
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Result<Value, LoxError> {
+        Ok(Value::Bool(self))
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Result<Value, LoxError> {
+        Ok(Value::Str(self.into()))
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Result<Value, LoxError> {
+        Ok(Value::Str(self.into()))
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Result<Value, LoxError> {
+        Ok(self)
+    }
+}
+
+// A native that returns nothing returns nil, as a Lox function does.
+impl IntoValue for () {
+    fn into_value(self) -> Result<Value, LoxError> {
+        Ok(Value::Nil)
+    }
+}
+
+impl<T: IntoValue> IntoValue for Result<T, LoxError> {
+    fn into_value(self) -> Result<Value, LoxError> {
+        self?.into_value()
+    }
+}
+
+// argument: Argument `index` of the call of `func`, as the Rust type its parameter is.
+pub fn argument<T: FromValue>(args: &[Value], index: usize, func: &str) -> Result<T, LoxError> {
+    match T::from_value(&args[index]) {
+        Some(value) => Ok(value),
+        None => loxerr!(
+            LX0513,
+            "Argument {} to '{}' must be {}, got {}",
+            index + 1,
+            func,
+            T::WHAT,
+            args[index].type_name()
+        ),
+    }
+}
+
+// lox_native: Turn Rust functions into ones that make natives of them; see above. The
+// arguments are converted one parameter at a time, counting along for the messages.
+#[macro_export]
+macro_rules! lox_native {
+    ( $( $(#[$attr:meta])* $vis:vis fn $name:ident ( $( $param:ident : $typ:ty ),* $(,)? )
+        $( -> $ret:ty )? $body:block )* ) => {
+        $(
+            $(#[$attr])*
+            $vis fn $name() -> $crate::value::Value {
+                fn $name( $( $param: $typ ),* ) $( -> $ret )? $body
+                let arity = $crate::lox_native!(@count $( $param )*);
+                $crate::value::NativeFunction::from_fn(stringify!($name), arity, |args| {
+                    $crate::lox_native!(@convert $name, args, 0; $( $param: $typ ),*);
+                    $crate::native::IntoValue::into_value($name( $( $param ),* ))
+                })
+            }
+        )*
+    };
+    (@count) => { 0 };
+    (@count $first:ident $( $rest:ident )*) => { 1 + $crate::lox_native!(@count $( $rest )*) };
+    // Once every parameter has its argument, the arguments are done with, if there were any.
+    (@convert $name:ident, $args:ident, $index:expr; ) => {
+        let _ = $args;
+    };
+    (@convert $name:ident, $args:ident, $index:expr;
+        $param:ident: $typ:ty $( , $rest:ident: $rtyp:ty )*) => {
+        let $param = $crate::native::argument::<$typ>($args, $index, stringify!($name))?;
+        $crate::lox_native!(@convert $name, $args, $index + 1; $( $rest: $rtyp ),*);
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::embed::Lox;
+    use crate::error::LoxError;
+    use crate::value::Value;
+
+    lox_native! {
+        fn hypot(x: f64, y: f64) -> f64 {
+            x.hypot(y)
+        }
+
+        fn shout(text: String, times: f64) -> String {
+            format!("{}!", text.to_uppercase()).repeat(times as usize)
+        }
+
+        fn describe(value: Value) -> String {
+            format!("a {}", value.type_name())
+        }
+
+        fn half(n: f64) -> Result<f64, LoxError> {
+            if n % 2.0 != 0.0 {
+                loxerr!("{} can't be halved evenly", n)
+            }
+            Ok(n / 2.0)
+        }
+
+        fn nothing() {}
+    }
+
+    fn lox() -> Lox {
+        let mut lox = Lox::new();
+        lox.set_global("hypot", hypot());
+        lox.set_global("shout", shout());
+        lox.set_global("describe", describe());
+        lox.set_global("half", half());
+        lox.set_global("nothing", nothing());
+        lox
+    }
+
+    #[test]
+    fn natives_take_and_give_rust_types() {
+        let mut lox = lox();
+        let eval = |lox: &mut Lox, source| lox.eval(source).unwrap().to_string();
+        assert_eq!(eval(&mut lox, "hypot(3, 4);"), "5");
+        assert_eq!(eval(&mut lox, "shout(\"hey\", 2);"), "HEY!HEY!");
+        assert_eq!(eval(&mut lox, "describe([1]);"), "a list");
+        assert_eq!(eval(&mut lox, "half(4);"), "2");
+        assert_eq!(eval(&mut lox, "nothing();"), "nil");
+        assert_eq!(eval(&mut lox, "hypot;"), "<native fn hypot>");
+    }
+
+    #[test]
+    fn natives_check_their_arguments() {
+        let mut lox = lox();
+        let error = |lox: &mut Lox, source| lox.eval(source).unwrap_err().to_string();
+        assert_eq!(
+            error(&mut lox, "hypot(3);"),
+            "[line 1] Expected 2 arguments but got 1."
+        );
+        assert_eq!(
+            error(&mut lox, "shout(\"hey\", \"twice\");"),
+            "[line 1] Argument 2 to 'shout' must be a number, got string."
+        );
+        assert_eq!(
+            error(&mut lox, "half(3);"),
+            "[line 1] 3 can't be halved evenly."
+        );
+    }
+}
@@ -0,0 +1,1566 @@
+use std::rc::Rc;
+
+use crate::ast::{
+    Comments, Expr, ExprKind, FunctionDecl, Literal, MatchArm, Pattern, Stmt, StmtKind,
+};
+use crate::diagnostic::Diagnostics;
+use crate::error::{ErrorKind, LoxError, Message};
+use crate::scanner::{Comment, Token, TokenType};
+use crate::stack;
+
+const MAX_ARGUMENTS: usize = 255;
+// How deep statements and expressions may nest: past this, the passes that walk the tree, each
+// a level of Rust recursion per level of nesting, could run out of interpreter::STACK_SIZE of
+// stack. On a thread with less, parsing also stops where the stack runs low, as calls do.
+const MAX_DEPTH: usize = 256;
+
+// Recursive descent parser for the Lox grammar:
+//
+//   program     -> declaration* EOF
+//   declaration -> classDecl | funDecl | varDecl | constDecl | statement
+//   classDecl   -> "class" IDENTIFIER ( "<" IDENTIFIER )?
+//                  ( "with" IDENTIFIER ( "," IDENTIFIER )* )? "{" member* "}"
+//   member      -> function | IDENTIFIER block | "set" function
+//   funDecl     -> "fun" function
+//   function    -> IDENTIFIER "(" parameters? ")" block
+//   parameters  -> IDENTIFIER ( "," IDENTIFIER )*
+//   varDecl     -> "var" IDENTIFIER ( "=" expression )? ";"
+//   constDecl   -> "const" IDENTIFIER "=" expression ";"
+//   statement   -> printStmt | ifStmt | forInStmt | returnStmt | yieldStmt | throwStmt
+//                | tryStmt | block | exprStmt
+//   ifStmt      -> "if" "(" expression ")" statement ( "else" statement )?
+//   forInStmt   -> "for" "(" "var" IDENTIFIER "in" expression ")" statement
+//   returnStmt  -> "return" expression? ";"
+//   yieldStmt   -> "yield" expression? ";"
+//   throwStmt   -> "throw" expression ";"
+//   tryStmt     -> "try" block "catch" "(" IDENTIFIER ")" block
+//   printStmt   -> "print" expression ";"
+//   block       -> "{" declaration* "}"
+//   exprStmt    -> expression ";"
+//   expression  -> assignment
+//   assignment  -> ( IDENTIFIER | call "[" expression "]" | call "." IDENTIFIER ) "="
+//                  assignment | logicOr
+//   logicOr     -> logicAnd ( "or" logicAnd )*
+//   logicAnd    -> equality ( "and" equality )*
+//   equality    -> comparison ( ( "!=" | "==" ) comparison )*
+//   comparison  -> term ( ( ">" | ">=" | "<" | "<=" ) term )*
+//   term        -> factor ( ( "-" | "+" ) factor )*
+//   factor      -> unary ( ( "/" | "*" ) unary )*
+//   unary       -> ( "!" | "-" ) unary | call
+//   call        -> primary ( "(" arguments? ")" | "[" subscript "]" | "." IDENTIFIER )*
+//   arguments   -> expression ( "," expression )*
+//   subscript   -> expression | expression? ":" expression?
+//   primary     -> NUMBER | STRING | "true" | "false" | "nil" | "this" | IDENTIFIER
+//                | "print" | "super" "." IDENTIFIER | "(" expression ")" | list | map
+//                | match
+//   list        -> "[" ( expression ( "," expression )* ","? )? "]"
+//   map         -> "{" ( entry ( "," entry )* ","? )? "}"
+//   entry       -> expression ":" expression
+//   match       -> "match" "(" expression ")" "{" arm ( "," arm )* ","? "}"
+//   arm         -> pattern "->" expression
+//   pattern     -> NUMBER | "-" NUMBER | STRING | "true" | "false" | "nil" | IDENTIFIER
+pub struct Parser<'a> {
+    tokens: &'a [Token],
+    current: usize,
+    // How many function bodies enclose the current token, so `return` and `yield` can be
+    // rejected at the top level.
+    function_depth: usize,
+    // How many statements and expressions enclose the current token; see MAX_DEPTH.
+    depth: usize,
+    // Whether the innermost function body being parsed has yielded yet, which makes the
+    // function a generator.
+    yields: bool,
+    // The syntax errors found so far, each followed by skipping to the next statement.
+    errors: Diagnostics,
+    // The comments to attach to the tree, for `rlox1 fmt`, and the first one not attached yet.
+    comments: &'a [Comment],
+    next_comment: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(tokens: &'a [Token]) -> Self {
+        Parser {
+            tokens,
+            current: 0,
+            function_depth: 0,
+            depth: 0,
+            yields: false,
+            errors: Diagnostics::new(),
+            comments: &[],
+            next_comment: 0,
+        }
+    }
+
+    // with_comments: Have the parse keep the scanner's `comments`, attaching them to the
+    // statements in blocks and to methods, so that the formatter can print them back out.
+    pub fn with_comments(mut self, comments: &'a [Comment]) -> Self {
+        self.comments = comments;
+        self
+    }
+
+    // end_comments: The comments after the last statement, once the whole program is parsed.
+    pub fn end_comments(&mut self) -> Vec<String> {
+        let end = &self.comments[self.next_comment..];
+        self.next_comment = self.comments.len();
+        end.iter().map(|comment| comment.text.clone()).collect()
+    }
+
+    // parse: Parse the whole token stream into a list of statements. After a syntax error,
+    // parsing picks up again at the next statement, so that one error reports them all.
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, LoxError> {
+        let mut statements = Vec::new();
+        while !self.is_at_end() {
+            statements.extend(self.declaration_or_recover());
+        }
+        std::mem::take(&mut self.errors).finish(statements)
+    }
+
+    // declaration_or_recover: Parse a declaration, or note why it can't be and skip to where
+    // the next one might start.
+    fn declaration_or_recover(&mut self) -> Option<Stmt> {
+        let before = self.comments_before(self.current);
+        match self.declaration() {
+            Ok(mut stmt) => {
+                stmt.comments = self.attach(before);
+                Some(stmt)
+            }
+            Err(err) => {
+                self.errors.push(err);
+                self.synchronize();
+                None
+            }
+        }
+    }
+
+    // synchronize: Skip the rest of a statement with an error in: past the next ';', or up to
+    // a keyword that starts a statement. The token the error was found at is always skipped,
+    // so parsing makes progress.
+    fn synchronize(&mut self) {
+        while !self.is_at_end() {
+            if self.advance().typ == TokenType::Semicolon {
+                return;
+            }
+            match self.peek().typ {
+                TokenType::Class
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::Const
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Return
+                | TokenType::Throw
+                | TokenType::Try
+                | TokenType::Yield => return,
+                _ => {}
+            }
+        }
+    }
+
+    // comments_before: Take the comments not attached yet that come before the token at
+    // `index`.
+    fn comments_before(&mut self, index: usize) -> Vec<String> {
+        let token = &self.tokens[index];
+        let mut taken = Vec::new();
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if (comment.line, comment.column) >= (token.line, token.column) {
+                break;
+            }
+            taken.push(comment.text.clone());
+            self.next_comment += 1;
+        }
+        taken
+    }
+
+    // attach: The comments for a statement or method just parsed, given those `before` it:
+    // the ones inside it that nothing nested took join those, and one on the same line
+    // after its last token trails it.
+    fn attach(&mut self, mut before: Vec<String>) -> Option<Box<Comments>> {
+        before.extend(self.comments_before(self.current - 1));
+        let last = self.previous();
+        let next = self.peek();
+        let trailing = match self.comments.get(self.next_comment) {
+            Some(comment)
+                if comment.line == last.line
+                    && (comment.line, comment.column) < (next.line, next.column) =>
+            {
+                self.next_comment += 1;
+                Some(comment.text.clone())
+            }
+            _ => None,
+        };
+        if before.is_empty() && trailing.is_none() {
+            return None;
+        }
+        Some(Box::new(Comments {
+            before,
+            trailing,
+            after: Vec::new(),
+        }))
+    }
+
+    // attach_after: The comments before the closing brace of a block, after the last of its
+    // `members`, which keeps them. In an empty block they're left for the statement around it.
+    fn attach_after(&mut self, members: Option<&mut Option<Box<Comments>>>) {
+        if let Some(comments) = members {
+            let after = self.comments_before(self.current);
+            if !after.is_empty() {
+                comments.get_or_insert_with(Default::default).after = after;
+            }
+        }
+    }
+
+    // declaration: Parse a declaration or statement, noting where it starts.
+    fn declaration(&mut self) -> Result<Stmt, LoxError> {
+        let (line, span) = (self.peek().line, self.peek().span());
+        let mut stmt = self.declaration_kind()?;
+        stmt.line = line;
+        stmt.span = span;
+        Ok(stmt)
+    }
+
+    fn declaration_kind(&mut self) -> Result<Stmt, LoxError> {
+        if self.match_any(&[TokenType::Class]) {
+            self.class_declaration()
+        } else if self.match_any(&[TokenType::Fun]) {
+            Ok(Stmt::new(StmtKind::Function(self.function("function")?)))
+        } else if self.match_any(&[TokenType::Var]) {
+            self.var_declaration()
+        } else if self.match_any(&[TokenType::Const]) {
+            self.const_declaration()
+        } else {
+            self.statement()
+        }
+    }
+
+    // class_declaration: Parse a class declaration, after the `class` keyword.
+    fn class_declaration(&mut self) -> Result<Stmt, LoxError> {
+        let name = self
+            .consume(
+                TokenType::Identifier(String::new()),
+                message!(LX0208, "Expect {} name", "class"),
+            )?
+            .clone();
+        let superclass = if self.match_any(&[TokenType::Less]) {
+            let superclass = self
+                .consume(
+                    TokenType::Identifier(String::new()),
+                    message!(LX0208, "Expect {} name", "superclass"),
+                )?
+                .clone();
+            Some(Expr::new(ExprKind::Variable(superclass)))
+        } else {
+            None
+        };
+        let mut mixins = Vec::new();
+        if self.match_any(&[TokenType::With]) {
+            loop {
+                let mixin = self
+                    .consume(
+                        TokenType::Identifier(String::new()),
+                        message!(LX0208, "Expect {} name", "mixin"),
+                    )?
+                    .clone();
+                mixins.push(Expr::new(ExprKind::Variable(mixin)));
+                if !self.match_any(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(
+            TokenType::LeftBrace,
+            message!(LX0206, "Expect '{' {}", "before class body"),
+        )?;
+        let mut methods = Vec::new();
+        let mut getters = Vec::new();
+        let mut setters = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            let before = self.comments_before(self.current);
+            // `set` is only a keyword in front of another name, so `set(k, v)` is a method.
+            let is_setter = self.peek().typ == TokenType::Identifier("set".to_string())
+                && matches!(self.peek_next().typ, TokenType::Identifier(_));
+            let members = if is_setter {
+                self.advance();
+                let setter = self.function("setter")?;
+                if setter.params.len() != 1 {
+                    return Err(self.error(
+                        &setter.name,
+                        message!(LX0213, "A setter must have exactly one parameter"),
+                    ));
+                }
+                setters.push(setter);
+                &mut setters
+            } else if self.peek_next().typ == TokenType::LeftBrace {
+                getters.push(self.getter()?);
+                &mut getters
+            } else {
+                methods.push(self.function("method")?);
+                &mut methods
+            };
+            let comments = self.attach(before);
+            if let Some(member) = members.last_mut().and_then(Rc::get_mut) {
+                member.comments = comments;
+            }
+        }
+        let last = IntoIterator::into_iter([&mut methods, &mut getters, &mut setters])
+            .filter_map(|members| members.last_mut())
+            .max_by_key(|member| (member.name.line, member.name.column));
+        self.attach_after(
+            last.and_then(Rc::get_mut)
+                .map(|member| &mut member.comments),
+        );
+        self.consume(
+            TokenType::RightBrace,
+            message!(LX0205, "Expect '}' after {}", "class body"),
+        )?;
+        Ok(Stmt::new(StmtKind::Class {
+            name,
+            superclass,
+            mixins,
+            methods,
+            getters,
+            setters,
+        }))
+    }
+
+    // getter: Parse a getter, a method with a body but no parameter list: `area { ... }`.
+    fn getter(&mut self) -> Result<Rc<FunctionDecl>, LoxError> {
+        let name = self
+            .consume(
+                TokenType::Identifier(String::new()),
+                message!(LX0208, "Expect {} name", "getter"),
+            )?
+            .clone();
+        self.function_body("getter", name, Vec::new())
+    }
+
+    // function: Parse a function after the `fun` keyword, or a method. `kind` names which,
+    // for error messages.
+    fn function(&mut self, kind: &str) -> Result<Rc<FunctionDecl>, LoxError> {
+        let name = self
+            .consume(
+                TokenType::Identifier(String::new()),
+                message!(LX0208, "Expect {} name", kind),
+            )?
+            .clone();
+        self.consume(
+            TokenType::LeftParen,
+            message!(LX0204, "Expect '(' after {}", format!("{} name", kind)),
+        )?;
+        let mut params = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    return Err(self.error(
+                        self.peek(),
+                        message!(LX0210, "Can't have more than {} parameters", MAX_ARGUMENTS),
+                    ));
+                }
+                params.push(
+                    self.consume(
+                        TokenType::Identifier(String::new()),
+                        message!(LX0208, "Expect {} name", "parameter"),
+                    )?
+                    .clone(),
+                );
+                if !self.match_any(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(
+            TokenType::RightParen,
+            message!(LX0203, "Expect ')' after {}", "parameters"),
+        )?;
+        self.function_body(kind, name, params)
+    }
+
+    fn function_body(
+        &mut self,
+        kind: &str,
+        name: Token,
+        params: Vec<Token>,
+    ) -> Result<Rc<FunctionDecl>, LoxError> {
+        self.consume(
+            TokenType::LeftBrace,
+            message!(LX0206, "Expect '{' {}", format!("before {} body", kind)),
+        )?;
+        self.function_depth += 1;
+        let enclosing = std::mem::replace(&mut self.yields, false);
+        let body = self.nested(Self::block);
+        let is_generator = std::mem::replace(&mut self.yields, enclosing);
+        self.function_depth -= 1;
+        Ok(Rc::new(FunctionDecl {
+            name,
+            params,
+            body: body?,
+            is_generator,
+            comments: None,
+        }))
+    }
+
+    fn var_declaration(&mut self) -> Result<Stmt, LoxError> {
+        let name = self
+            .consume(
+                TokenType::Identifier(String::new()),
+                message!(LX0208, "Expect {} name", "variable"),
+            )?
+            .clone();
+        let initializer = if self.match_any(&[TokenType::Equal]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume(
+            TokenType::Semicolon,
+            message!(LX0202, "Expect ';' after {}", "variable declaration"),
+        )?;
+        Ok(Stmt::new(StmtKind::Var {
+            name,
+            initializer,
+            constant: false,
+        }))
+    }
+
+    // const_declaration: Like a var declaration, but the value is required. That the
+    // constant is never assigned again is checked by the resolver.
+    fn const_declaration(&mut self) -> Result<Stmt, LoxError> {
+        let name = self
+            .consume(
+                TokenType::Identifier(String::new()),
+                message!(LX0208, "Expect {} name", "constant"),
+            )?
+            .clone();
+        self.consume(
+            TokenType::Equal,
+            message!(LX0211, "Expect '=' after constant name"),
+        )?;
+        let initializer = self.expression()?;
+        self.consume(
+            TokenType::Semicolon,
+            message!(LX0202, "Expect ';' after {}", "constant declaration"),
+        )?;
+        Ok(Stmt::new(StmtKind::Var {
+            name,
+            initializer: Some(initializer),
+            constant: true,
+        }))
+    }
+
+    // statement: Parse a statement, noting where it starts.
+    fn statement(&mut self) -> Result<Stmt, LoxError> {
+        let (line, span) = (self.peek().line, self.peek().span());
+        let mut stmt = self.nested(Self::statement_kind)?;
+        stmt.line = line;
+        stmt.span = span;
+        Ok(stmt)
+    }
+
+    fn statement_kind(&mut self) -> Result<Stmt, LoxError> {
+        if self.match_any(&[TokenType::LeftBrace]) {
+            Ok(Stmt::new(StmtKind::Block(self.block()?)))
+        } else if self.match_any(&[TokenType::If]) {
+            self.if_statement()
+        } else if self.match_any(&[TokenType::While]) {
+            self.while_statement()
+        } else if self.match_any(&[TokenType::For]) {
+            self.for_statement()
+        } else if self.match_any(&[TokenType::Return]) {
+            self.return_statement()
+        } else if self.match_any(&[TokenType::Yield]) {
+            self.yield_statement()
+        } else if self.match_any(&[TokenType::Throw]) {
+            let keyword = self.previous().clone();
+            let value = self.expression()?;
+            self.consume(
+                TokenType::Semicolon,
+                message!(LX0202, "Expect ';' after {}", "thrown value"),
+            )?;
+            Ok(Stmt::new(StmtKind::Throw { keyword, value }))
+        } else if self.match_any(&[TokenType::Try]) {
+            self.try_statement()
+        } else if self.match_any(&[TokenType::Print]) {
+            let value = self.expression()?;
+            self.consume(
+                TokenType::Semicolon,
+                message!(LX0202, "Expect ';' after {}", "value"),
+            )?;
+            Ok(Stmt::new(StmtKind::Print(value)))
+        } else {
+            let expr = self.expression()?;
+            self.consume(
+                TokenType::Semicolon,
+                message!(LX0202, "Expect ';' after {}", "expression"),
+            )?;
+            Ok(Stmt::new(StmtKind::Expression(expr)))
+        }
+    }
+
+    // if_statement: Parse an if statement, after the `if` keyword. An `else` binds to the
+    // nearest `if`.
+    fn if_statement(&mut self) -> Result<Stmt, LoxError> {
+        let keyword = self.previous().clone();
+        self.consume(
+            TokenType::LeftParen,
+            message!(LX0204, "Expect '(' after {}", "'if'"),
+        )?;
+        let condition = self.expression()?;
+        self.consume(
+            TokenType::RightParen,
+            message!(LX0203, "Expect ')' after {}", "if condition"),
+        )?;
+        let then_branch = Box::new(self.statement()?);
+        let else_branch = if self.match_any(&[TokenType::Else]) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+        Ok(Stmt::new(StmtKind::If {
+            keyword,
+            condition,
+            then_branch,
+            else_branch,
+        }))
+    }
+
+    fn return_statement(&mut self) -> Result<Stmt, LoxError> {
+        let keyword = self.previous().clone();
+        if self.function_depth == 0 {
+            return Err(self.error(
+                &keyword,
+                message!(LX0401, "Can't return from top-level code"),
+            ));
+        }
+        let value = if self.check(&TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(
+            TokenType::Semicolon,
+            message!(LX0202, "Expect ';' after {}", "return value"),
+        )?;
+        Ok(Stmt::new(StmtKind::Return { keyword, value }))
+    }
+
+    // yield_statement: Parse a yield statement, after the `yield` keyword. Yielding makes the
+    // enclosing function a generator.
+    fn yield_statement(&mut self) -> Result<Stmt, LoxError> {
+        let keyword = self.previous().clone();
+        if self.function_depth == 0 {
+            return Err(self.error(
+                &keyword,
+                message!(LX0401, "Can't yield from top-level code"),
+            ));
+        }
+        self.yields = true;
+        let value = if self.check(&TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(
+            TokenType::Semicolon,
+            message!(LX0202, "Expect ';' after {}", "yielded value"),
+        )?;
+        Ok(Stmt::new(StmtKind::Yield { keyword, value }))
+    }
+
+    // try_statement: Parse a try/catch statement, after the `try` keyword. Both parts must be
+    // blocks, and the catch clause is required.
+    fn try_statement(&mut self) -> Result<Stmt, LoxError> {
+        self.consume(
+            TokenType::LeftBrace,
+            message!(LX0206, "Expect '{' {}", "after 'try'"),
+        )?;
+        let body = self.block()?;
+        self.consume(
+            TokenType::Catch,
+            message!(LX0211, "Expect 'catch' after try block"),
+        )?;
+        self.consume(
+            TokenType::LeftParen,
+            message!(LX0204, "Expect '(' after {}", "'catch'"),
+        )?;
+        let name = self
+            .consume(
+                TokenType::Identifier(String::new()),
+                message!(LX0208, "Expect {} name", "exception"),
+            )?
+            .clone();
+        self.consume(
+            TokenType::RightParen,
+            message!(LX0203, "Expect ')' after {}", "exception name"),
+        )?;
+        self.consume(
+            TokenType::LeftBrace,
+            message!(LX0206, "Expect '{' {}", "after catch clause"),
+        )?;
+        let handler = self.block()?;
+        Ok(Stmt::new(StmtKind::Try {
+            body,
+            name,
+            handler,
+        }))
+    }
+
+    fn while_statement(&mut self) -> Result<Stmt, LoxError> {
+        let keyword = self.previous().clone();
+        self.consume(
+            TokenType::LeftParen,
+            message!(LX0204, "Expect '(' after {}", "'while'"),
+        )?;
+        let condition = self.expression()?;
+        self.consume(
+            TokenType::RightParen,
+            message!(LX0203, "Expect ')' after {}", "condition"),
+        )?;
+        let body = self.statement()?;
+        Ok(Stmt::new(StmtKind::While {
+            keyword,
+            condition,
+            body: Box::new(body),
+        }))
+    }
+
+    // for_statement: Parse a `for` loop, after the keyword. `for (var x in ...)` is a for-in
+    // loop; anything else is a C-style loop with three clauses. `in` is only special here, so
+    // it is still usable as an ordinary identifier elsewhere.
+    fn for_statement(&mut self) -> Result<Stmt, LoxError> {
+        let keyword = self.previous().clone();
+        self.consume(
+            TokenType::LeftParen,
+            message!(LX0204, "Expect '(' after {}", "'for'"),
+        )?;
+        let in_keyword = TokenType::Identifier("in".to_string());
+        if matches!(self.peek().typ, TokenType::Identifier(_)) && self.peek_next().typ == in_keyword
+        {
+            return Err(self.error(
+                self.peek(),
+                message!(LX0211, "Expect 'var' after '(' in for-in loop"),
+            ));
+        }
+        let (line, span) = (self.peek().line, self.peek().span());
+        let initializer = if self.match_any(&[TokenType::Semicolon]) {
+            None
+        } else if self.match_any(&[TokenType::Var]) {
+            let name = self
+                .consume(
+                    TokenType::Identifier(String::new()),
+                    message!(LX0208, "Expect {} name", "variable"),
+                )?
+                .clone();
+            if self.peek().typ == in_keyword {
+                self.advance();
+                return self.for_in_statement(keyword, name);
+            }
+            if !self.check(&TokenType::Equal) && !self.check(&TokenType::Semicolon) {
+                return Err(self.error(
+                    self.peek(),
+                    message!(LX0211, "Expect 'in' after loop variable"),
+                ));
+            }
+            let initializer = if self.match_any(&[TokenType::Equal]) {
+                Some(self.expression()?)
+            } else {
+                None
+            };
+            self.consume(
+                TokenType::Semicolon,
+                message!(LX0202, "Expect ';' after {}", "variable declaration"),
+            )?;
+            Some(Stmt::new(StmtKind::Var {
+                name,
+                initializer,
+                constant: false,
+            }))
+        } else {
+            let expr = self.expression()?;
+            self.consume(
+                TokenType::Semicolon,
+                message!(LX0202, "Expect ';' after {}", "expression"),
+            )?;
+            Some(Stmt::new(StmtKind::Expression(expr)))
+        };
+        let initializer = initializer.map(|mut stmt| {
+            stmt.line = line;
+            stmt.span = span;
+            Box::new(stmt)
+        });
+        let condition = if self.check(&TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(
+            TokenType::Semicolon,
+            message!(LX0202, "Expect ';' after {}", "loop condition"),
+        )?;
+        let increment = if self.check(&TokenType::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(
+            TokenType::RightParen,
+            message!(LX0203, "Expect ')' after {}", "for clauses"),
+        )?;
+        let body = self.statement()?;
+        Ok(Stmt::new(StmtKind::For {
+            keyword,
+            initializer,
+            condition,
+            increment,
+            body: Box::new(body),
+        }))
+    }
+
+    // for_in_statement: Parse the rest of a for-in loop, after `in`.
+    fn for_in_statement(&mut self, keyword: Token, name: Token) -> Result<Stmt, LoxError> {
+        let iterable = self.expression()?;
+        self.consume(
+            TokenType::RightParen,
+            message!(LX0203, "Expect ')' after {}", "for-in clauses"),
+        )?;
+        let body = self.statement()?;
+        Ok(Stmt::new(StmtKind::ForIn {
+            keyword,
+            name,
+            iterable,
+            body: Box::new(body),
+        }))
+    }
+
+    // block: Parse the declarations of a block, after the opening brace.
+    fn block(&mut self) -> Result<Vec<Stmt>, LoxError> {
+        let mut statements = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            statements.extend(self.declaration_or_recover());
+        }
+        self.attach_after(statements.last_mut().map(|stmt| &mut stmt.comments));
+        self.consume(
+            TokenType::RightBrace,
+            message!(LX0205, "Expect '}' after {}", "block"),
+        )?;
+        Ok(statements)
+    }
+
+    fn expression(&mut self) -> Result<Expr, LoxError> {
+        self.nested(Self::assignment)
+    }
+
+    fn assignment(&mut self) -> Result<Expr, LoxError> {
+        let expr = self.or()?;
+        if self.match_any(&[TokenType::Equal]) {
+            let equals = self.previous().clone();
+            let value = self.expression()?;
+            match expr.kind {
+                ExprKind::Variable(name) => Ok(Expr::new(ExprKind::Assign {
+                    name,
+                    value: Box::new(value),
+                })),
+                ExprKind::Index {
+                    object,
+                    bracket,
+                    index,
+                } => Ok(Expr::new(ExprKind::SetIndex {
+                    object,
+                    bracket,
+                    index,
+                    value: Box::new(value),
+                })),
+                ExprKind::Get { object, name } => Ok(Expr::new(ExprKind::Set {
+                    object,
+                    name,
+                    value: Box::new(value),
+                })),
+                _ => Err(self.error(&equals, message!(LX0209, "Invalid assignment target"))),
+            }
+        } else {
+            Ok(expr)
+        }
+    }
+
+    // binary: Parse a left-associative chain of `operand (op operand)*`.
+    fn binary<F>(&mut self, ops: &[TokenType], mut operand: F) -> Result<Expr, LoxError>
+    where
+        F: FnMut(&mut Self) -> Result<Expr, LoxError>,
+    {
+        let mut expr = operand(self)?;
+        while self.match_any(ops) {
+            let op = self.previous().clone();
+            let right = operand(self)?;
+            expr = Expr::new(ExprKind::Binary {
+                left: Box::new(expr),
+                op,
+                right: Box::new(right),
+            });
+        }
+        Ok(expr)
+    }
+
+    // logical: Like binary, but builds short-circuiting Logical nodes.
+    fn logical<F>(&mut self, op: TokenType, mut operand: F) -> Result<Expr, LoxError>
+    where
+        F: FnMut(&mut Self) -> Result<Expr, LoxError>,
+    {
+        let mut expr = operand(self)?;
+        while self.match_any(std::slice::from_ref(&op)) {
+            let op = self.previous().clone();
+            let right = operand(self)?;
+            expr = Expr::new(ExprKind::Logical {
+                left: Box::new(expr),
+                op,
+                right: Box::new(right),
+            });
+        }
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<Expr, LoxError> {
+        self.logical(TokenType::Or, Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr, LoxError> {
+        self.logical(TokenType::And, Self::equality)
+    }
+
+    fn equality(&mut self) -> Result<Expr, LoxError> {
+        self.binary(
+            &[TokenType::BangEqual, TokenType::EqualEqual],
+            Self::comparison,
+        )
+    }
+
+    fn comparison(&mut self) -> Result<Expr, LoxError> {
+        self.binary(
+            &[
+                TokenType::Greater,
+                TokenType::GreaterEqual,
+                TokenType::Less,
+                TokenType::LessEqual,
+            ],
+            Self::term,
+        )
+    }
+
+    fn term(&mut self) -> Result<Expr, LoxError> {
+        self.binary(&[TokenType::Minus, TokenType::Plus], Self::factor)
+    }
+
+    fn factor(&mut self) -> Result<Expr, LoxError> {
+        self.binary(&[TokenType::Slash, TokenType::Star], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, LoxError> {
+        if self.match_any(&[TokenType::Bang, TokenType::Minus]) {
+            let op = self.previous().clone();
+            let right = self.nested(Self::unary)?;
+            Ok(Expr::new(ExprKind::Unary {
+                op,
+                right: Box::new(right),
+            }))
+        } else {
+            self.call()
+        }
+    }
+
+    fn call(&mut self) -> Result<Expr, LoxError> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.match_any(&[TokenType::LeftParen]) {
+                expr = self.finish_call(expr)?;
+            } else if self.match_any(&[TokenType::LeftBracket]) {
+                expr = self.subscript(expr)?;
+            } else if self.match_any(&[TokenType::Dot]) {
+                let name = self
+                    .consume(
+                        TokenType::Identifier(String::new()),
+                        message!(LX0208, "Expect property name after '.'"),
+                    )?
+                    .clone();
+                expr = Expr::new(ExprKind::Get {
+                    object: Box::new(expr),
+                    name,
+                });
+            } else {
+                break;
+            }
+        }
+        Ok(expr)
+    }
+
+    // finish_call: Parse the argument list of a call, after the opening parenthesis.
+    fn finish_call(&mut self, callee: Expr) -> Result<Expr, LoxError> {
+        let mut arguments = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    return Err(self.error(
+                        self.peek(),
+                        message!(LX0210, "Can't have more than {} arguments", MAX_ARGUMENTS),
+                    ));
+                }
+                arguments.push(self.expression()?);
+                if !self.match_any(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        let paren = self
+            .consume(
+                TokenType::RightParen,
+                message!(LX0203, "Expect ')' after {}", "arguments"),
+            )?
+            .clone();
+        Ok(Expr::new(ExprKind::Call {
+            callee: Box::new(callee),
+            paren,
+            arguments,
+        }))
+    }
+
+    // subscript: Parse `[index]` or `[start:end]` after an expression; either bound of a
+    // slice may be left out.
+    fn subscript(&mut self, object: Expr) -> Result<Expr, LoxError> {
+        let bracket = self.previous().clone();
+        let start = if self.check(&TokenType::Colon) {
+            None
+        } else {
+            Some(Box::new(self.expression()?))
+        };
+        let expr = if self.match_any(&[TokenType::Colon]) {
+            let end = if self.check(&TokenType::RightBracket) {
+                None
+            } else {
+                Some(Box::new(self.expression()?))
+            };
+            Expr::new(ExprKind::Slice {
+                object: Box::new(object),
+                bracket,
+                start,
+                end,
+            })
+        } else {
+            Expr::new(ExprKind::Index {
+                object: Box::new(object),
+                bracket,
+                index: start.expect("index expression"),
+            })
+        };
+        self.consume(
+            TokenType::RightBracket,
+            message!(LX0207, "Expect ']' after {}", "index"),
+        )?;
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, LoxError> {
+        let literal = match &self.peek().typ {
+            TokenType::False => Some(Literal::Bool(false)),
+            TokenType::True => Some(Literal::Bool(true)),
+            TokenType::Nil => Some(Literal::Nil),
+            TokenType::Number(n) => Some(Literal::Number(*n)),
+            TokenType::QuotedString(s) => Some(Literal::Str(s.clone())),
+            _ => None,
+        };
+        if let Some(literal) = literal {
+            self.advance();
+            Ok(Expr::new(ExprKind::Literal(literal)))
+        } else if self.match_any(&[TokenType::Identifier(String::new())]) {
+            Ok(Expr::new(ExprKind::Variable(self.previous().clone())))
+        } else if self.match_any(&[TokenType::This]) {
+            Ok(Expr::new(ExprKind::This(self.previous().clone())))
+        } else if self.match_any(&[TokenType::Super]) {
+            let keyword = self.previous().clone();
+            self.consume(TokenType::Dot, message!(LX0211, "Expect '.' after 'super'"))?;
+            let method = self
+                .consume(
+                    TokenType::Identifier(String::new()),
+                    message!(LX0208, "Expect {} name", "superclass method"),
+                )?
+                .clone();
+            Ok(Expr::new(ExprKind::Super { keyword, method }))
+        } else if self.match_any(&[TokenType::Print]) {
+            // Outside a print statement, `print` names the print() native, so it can be
+            // passed around like any other function.
+            let name = Token::new(
+                TokenType::Identifier("print".to_string()),
+                self.previous().line,
+            );
+            Ok(Expr::new(ExprKind::Variable(name)))
+        } else if self.match_any(&[TokenType::LeftParen]) {
+            let expr = self.expression()?;
+            self.consume(
+                TokenType::RightParen,
+                message!(LX0203, "Expect ')' after {}", "expression"),
+            )?;
+            Ok(Expr::new(ExprKind::Grouping(Box::new(expr))))
+        } else if self.match_any(&[TokenType::LeftBracket]) {
+            self.list()
+        } else if self.match_any(&[TokenType::LeftBrace]) {
+            self.map()
+        } else if self.match_any(&[TokenType::Match]) {
+            self.match_expression()
+        } else {
+            Err(self.error(self.peek(), message!(LX0201, "Expect expression")))
+        }
+    }
+
+    // list: Parse the elements of a list literal, after the opening bracket. A trailing comma
+    // is allowed.
+    fn list(&mut self) -> Result<Expr, LoxError> {
+        let mut elements = Vec::new();
+        while !self.check(&TokenType::RightBracket) {
+            elements.push(self.expression()?);
+            if !self.match_any(&[TokenType::Comma]) {
+                break;
+            }
+        }
+        self.consume(
+            TokenType::RightBracket,
+            message!(LX0207, "Expect ']' after {}", "list elements"),
+        )?;
+        Ok(Expr::new(ExprKind::List(elements)))
+    }
+
+    // map: Parse the entries of a map literal, after the opening brace. A brace only starts
+    // a map in expression position; at the start of a statement it is a block.
+    fn map(&mut self) -> Result<Expr, LoxError> {
+        let brace = self.previous().clone();
+        let mut entries = Vec::new();
+        while !self.check(&TokenType::RightBrace) {
+            let key = self.expression()?;
+            self.consume(
+                TokenType::Colon,
+                message!(LX0211, "Expect ':' after map key"),
+            )?;
+            let value = self.expression()?;
+            entries.push((key, value));
+            if !self.match_any(&[TokenType::Comma]) {
+                break;
+            }
+        }
+        self.consume(
+            TokenType::RightBrace,
+            message!(LX0205, "Expect '}' after {}", "map entries"),
+        )?;
+        Ok(Expr::new(ExprKind::Map { brace, entries }))
+    }
+
+    // match_expression: Parse a match expression, after the `match` keyword. There must be
+    // at least one arm, and a trailing comma is allowed.
+    fn match_expression(&mut self) -> Result<Expr, LoxError> {
+        let keyword = self.previous().clone();
+        self.consume(
+            TokenType::LeftParen,
+            message!(LX0204, "Expect '(' after {}", "'match'"),
+        )?;
+        let subject = self.expression()?;
+        self.consume(
+            TokenType::RightParen,
+            message!(LX0203, "Expect ')' after {}", "match value"),
+        )?;
+        self.consume(
+            TokenType::LeftBrace,
+            message!(LX0206, "Expect '{' {}", "before match arms"),
+        )?;
+        let mut arms = Vec::new();
+        loop {
+            let pattern = self.pattern()?;
+            self.consume(
+                TokenType::Arrow,
+                message!(LX0211, "Expect '->' after pattern"),
+            )?;
+            let body = self.expression()?;
+            arms.push(MatchArm { pattern, body });
+            if !self.match_any(&[TokenType::Comma]) || self.check(&TokenType::RightBrace) {
+                break;
+            }
+        }
+        self.consume(
+            TokenType::RightBrace,
+            message!(LX0205, "Expect '}' after {}", "match arms"),
+        )?;
+        Ok(Expr::new(ExprKind::Match {
+            keyword,
+            subject: Box::new(subject),
+            arms,
+        }))
+    }
+
+    // pattern: Parse the pattern of a match arm. `_` is the wildcard, any other name a
+    // binding.
+    fn pattern(&mut self) -> Result<Pattern, LoxError> {
+        let token = self.peek().clone();
+        let pattern = match token.typ {
+            TokenType::False => Pattern::Literal(Literal::Bool(false)),
+            TokenType::True => Pattern::Literal(Literal::Bool(true)),
+            TokenType::Nil => Pattern::Literal(Literal::Nil),
+            TokenType::Number(n) => Pattern::Literal(Literal::Number(n)),
+            TokenType::QuotedString(ref s) => Pattern::Literal(Literal::Str(s.clone())),
+            TokenType::Minus => {
+                self.advance();
+                match self.peek().typ {
+                    TokenType::Number(n) => Pattern::Literal(Literal::Number(-n)),
+                    _ => {
+                        return Err(self.error(
+                            self.peek(),
+                            message!(LX0212, "Expect number after '-' in pattern"),
+                        ))
+                    }
+                }
+            }
+            TokenType::Identifier(ref name) if name == "_" => Pattern::Wildcard,
+            TokenType::Identifier(_) => Pattern::Binding(token),
+            _ => return Err(self.error(&token, message!(LX0212, "Expect pattern"))),
+        };
+        self.advance();
+        Ok(pattern)
+    }
+
+    // ------------------------------------------------------------------------------------------------
+    // Token helpers
+    // ------------------------------------------------------------------------------------------------
+
+    // check: Does the current token have the same type as `typ`? Literal payloads are ignored.
+    // nested: Parse with `parse` a level deeper, or stop with an error if that is too deep.
+    fn nested<T>(&mut self, parse: fn(&mut Self) -> Result<T, LoxError>) -> Result<T, LoxError> {
+        if self.depth >= MAX_DEPTH || stack::exhausted() {
+            return Err(self.error(self.peek(), message!(LX0214, "Too much nesting")));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn check(&self, typ: &TokenType) -> bool {
+        std::mem::discriminant(&self.peek().typ) == std::mem::discriminant(typ)
+    }
+
+    fn match_any(&mut self, types: &[TokenType]) -> bool {
+        if types.iter().any(|typ| self.check(typ)) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn consume(&mut self, typ: TokenType, message: Message) -> Result<&Token, LoxError> {
+        if self.check(&typ) {
+            Ok(self.advance())
+        } else {
+            Err(self.error(self.peek(), message))
+        }
+    }
+
+    fn advance(&mut self) -> &Token {
+        if !self.is_at_end() {
+            self.current += 1;
+        }
+        self.previous()
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.peek().typ == TokenType::Eof
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current]
+    }
+
+    // peek_next: The token after the current one, or the final Eof.
+    fn peek_next(&self) -> &Token {
+        &self.tokens[(self.current + 1).min(self.tokens.len() - 1)]
+    }
+
+    fn previous(&self) -> &Token {
+        &self.tokens[self.current - 1]
+    }
+
+    fn error(&self, token: &Token, message: Message) -> LoxError {
+        let location = if token.typ == TokenType::Eof {
+            format!("[line {}] Error at end: ", token.line)
+        } else {
+            format!("[line {}] Error at '{}': ", token.line, token.typ)
+        };
+        let err = LoxError::located(&location, message);
+        err.at(token.span()).of_kind(ErrorKind::Parse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{Expr, ExprKind, NodeId, Stmt, StmtKind};
+    use crate::error::LoxError;
+    use crate::interpreter::STACK_SIZE;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn parse(src: &str) -> Result<Vec<String>, LoxError> {
+        let mut scanner = Scanner::new(src);
+        let tokens = scanner.scan_tokens()?;
+        let statements = Parser::new(tokens).parse()?;
+        Ok(statements.iter().map(|s| format!("{}", s)).collect())
+    }
+
+    macro_rules! parser_test {
+        ( FAIL: $name:ident, $src:expr, $ct:expr ) => {
+            #[test]
+            fn $name() {
+                match parse($src) {
+                    Ok(stmts) => panic!("{:?} should not parse, got {:?}", $src, stmts),
+                    Err(err) => assert!(format!("{}", err).contains($ct), "{}", err),
+                }
+            }
+        };
+        ( $name:ident, $src:expr => $( $printed:expr ),* ) => {
+            #[test]
+            fn $name() -> Result<(), LoxError> {
+                let expected: Vec<&str> = vec![ $( $printed ),* ];
+                assert_eq!(parse($src)?, expected, "Input was {:?}", $src);
+                Ok(())
+            }
+        };
+    }
+
+    parser_test!(parse_literals, "1; \"a\"; true; false; nil;" =>
+                 "(expr 1)", "(expr \"a\")", "(expr true)", "(expr false)", "(expr nil)");
+    parser_test!(parse_precedence, "1 + 2 * 3 - 4 / 5;" => "(expr (- (+ 1 (* 2 3)) (/ 4 5)))");
+    parser_test!(parse_class, "class A { init(x) { this.x = x; } get() { return this.x; } }" =>
+        "(class A (method init (x) (expr (.= this x x))) (method get () (return (. this x))))");
+    parser_test!(parse_subclass, "class B < A { f() { return super.f(); } }" =>
+        "(class B < A (method f () (return (call (super f)))))");
+    parser_test!(parse_empty_class, "class A {}" => "(class A)");
+    parser_test!(parse_match, "match (x) { 1 -> a, \"s\" -> b, -2 -> c, true -> d, y -> y, _ -> nil, };" =>
+        "(expr (match x (1 -> a) (\"s\" -> b) (-2 -> c) (true -> d) (y -> y) (_ -> nil)))");
+    parser_test!(parse_match_is_an_expression, "var v = match (1) { _ -> 2 } + 3;" => "(var v (+ (match 1 (_ -> 2)) 3))");
+    parser_test!(parse_yield, "fun g() { yield 1; yield; }" => "(fun g () (yield 1) (yield))");
+    parser_test!(parse_accessors, "class A { area { return 1; } set area(v) {} set(k, v) {} }" => "(class A (method set (k v)) (get area () (return 1)) (set area (v)))");
+    parser_test!(parse_mixins, "class C < A with X, Y { f() {} }" => "(class C < A with X Y (method f ()))");
+    parser_test!(parse_set_chain, "a.b.c = d.e = 1;" => "(expr (.= (. a b) c (.= d e 1)))");
+    parser_test!(parse_print_as_value, "print print; f(print);" => "(print print)", "(expr (call f print))");
+    parser_test!(parse_grouping, "(1 + 2) * 3;" => "(expr (* (group (+ 1 2)) 3))");
+    parser_test!(parse_unary, "!-1 == !true;" => "(expr (== (! (- 1)) (! true)))");
+    parser_test!(parse_comparison, "1 < 2 != 3 >= 4;" => "(expr (!= (< 1 2) (>= 3 4)))");
+    parser_test!(parse_print, "print 1 + 2;" => "(print (+ 1 2))");
+    parser_test!(parse_nothing, "" =>);
+    parser_test!(parse_list, "[1, 2 + 3];" => "(expr (list 1 (+ 2 3)))");
+    parser_test!(parse_empty_list, "[];" => "(expr (list))");
+    parser_test!(parse_list_trailing_comma, "[1,];" => "(expr (list 1))");
+    parser_test!(parse_var, "var a; var b = 1 + 2;" => "(var a)", "(var b (+ 1 2))");
+    parser_test!(parse_const, "const a = 1; { const b = a; }" => "(const a 1)", "(block (const b a))");
+    parser_test!(parse_assign, "a = b = c;" => "(expr (= a (= b c)))");
+    parser_test!(parse_block, "{ var a = 1; { print a; } }" => "(block (var a 1) (block (print a)))");
+    parser_test!(parse_assign_index, "a[0] = 1;" => "(expr (set-index a 0 1))");
+    parser_test!(parse_call, "f(); g(1)(2, 3);" => "(expr (call f))", "(expr (call (call g 1) 2 3))");
+    parser_test!(parse_call_and_index, "f(1)[0](2);" => "(expr (call (index (call f 1) 0) 2))");
+    parser_test!(parse_map, "var m = {\"a\": 1, 2: [],};" => "(var m (map \"a\":1 2:(list)))");
+    parser_test!(parse_empty_map, "print {};" => "(print (map))");
+    parser_test!(parse_get, "a.b.c(1).d;" => "(expr (. (call (. (. a b) c) 1) d))");
+    parser_test!(parse_for_in, "for (var x in [1]) print x;" => "(for-in x (list 1) (print x))");
+    parser_test!(parse_while, "while (a) a = a - 1;" => "(while a (expr (= a (- a 1))))");
+    parser_test!(parse_for, "for (var i = 0; i < 3; i = i + 1) print i;" =>
+                 "(for (var i 0) (< i 3) (= i (+ i 1)) (print i))");
+    parser_test!(parse_for_without_clauses, "for (;;) {} for (i = 0; ; ) {}" =>
+                 "(for _ _ _ (block))", "(for (expr (= i 0)) _ _ (block))");
+    parser_test!(parse_in_is_an_identifier, "var in = 1;" => "(var in 1)");
+    parser_test!(parse_index, "[1][0][1 + 1];" => "(expr (index (index (list 1) 0) (+ 1 1)))");
+    parser_test!(parse_index_binds_tighter, "-[1][0];" => "(expr (- (index (list 1) 0)))");
+    parser_test!(parse_slice, "\"abc\"[1:2];" => "(expr (slice \"abc\" 1 2))");
+    parser_test!(parse_open_slices, "[][:1]; [][1:]; [][:];" =>
+                 "(expr (slice (list) _ 1))", "(expr (slice (list) 1 _))", "(expr (slice (list) _ _))");
+    parser_test!(parse_set_index, "[1][0] = [2][0] = 3;" =>
+                 "(expr (set-index (list 1) 0 (set-index (list 2) 0 3)))");
+
+    parser_test!(parse_fun, "fun f(a, b) { return a + b; }" => "(fun f (a b) (return (+ a b)))");
+    parser_test!(parse_fun_no_params, "fun f() {} fun g() { return; }" => "(fun f ())", "(fun g () (return))");
+    parser_test!(parse_if, "if (a) print 1;" => "(if a (print 1))");
+    parser_test!(parse_if_else, "if (a) if (b) 1; else 2;" => "(if a (if b (expr 1) (expr 2)))");
+    parser_test!(parse_logical, "a or b and c == d;" => "(expr (or a (and b (== c d))))");
+    parser_test!(parse_logical_assign, "a = b or c;" => "(expr (= a (or b c)))");
+
+    parser_test!(parse_throw, "throw \"oops\";" => "(throw \"oops\")");
+    parser_test!(parse_try, "try { f(); g(); } catch (e) { print e; }" =>
+                 "(try ((expr (call f)) (expr (call g))) (catch e (print e)))");
+
+    parser_test!(FAIL: parse_missing_semicolon, "print 1", "Error at end: Expect ';' after value");
+    parser_test!(FAIL: parse_unclosed_group, "(1 + 2;", "Error at ';': Expect ')'");
+    parser_test!(FAIL: parse_missing_operand, "1 + ;", "Expect expression");
+    parser_test!(FAIL: parse_error_has_line, "1;\n\n*2;", "[line 3]");
+    parser_test!(FAIL: parse_unclosed_list, "[1, 2;", "Expect ']' after list elements");
+    parser_test!(FAIL: parse_list_double_comma, "[1,,];", "Error at ',': Expect expression");
+    parser_test!(FAIL: parse_var_missing_name, "var 1 = 2;", "Error at '1': Expect variable name");
+    parser_test!(FAIL: parse_var_missing_semicolon, "var a = 1", "Expect ';' after variable declaration");
+    parser_test!(FAIL: parse_const_missing_value, "const a;", "Error at ';': Expect '=' after constant name");
+    parser_test!(FAIL: parse_const_missing_name, "const = 1;", "Expect constant name");
+    parser_test!(FAIL: parse_const_missing_semicolon, "const a = 1", "Expect ';' after constant declaration");
+    parser_test!(FAIL: parse_unclosed_block, "{ print 1;", "Error at end: Expect '}' after block");
+    parser_test!(FAIL: parse_assign_to_grouping, "(a) = 1;", "Invalid assignment target");
+    parser_test!(FAIL: parse_unclosed_call, "f(1;", "Error at ';': Expect ')' after arguments");
+    parser_test!(FAIL: parse_call_trailing_comma, "f(1,);", "Error at ')': Expect expression");
+    parser_test!(FAIL: parse_map_needs_colon, "print {1 2};", "Error at '2': Expect ':' after map key");
+    parser_test!(FAIL: parse_unclosed_map, "print {1: 2;", "Expect '}' after map entries");
+    parser_test!(FAIL: parse_get_needs_name, "a.1;", "Error at '1': Expect property name after '.'");
+    parser_test!(FAIL: parse_for_in_needs_var, "for (x in y) 1;", "Expect 'var' after '(' in for-in loop");
+    parser_test!(FAIL: parse_for_in_needs_in, "for (var x of y) 1;", "Error at 'of': Expect 'in' after loop variable");
+    parser_test!(FAIL: parse_while_needs_paren, "while a {}", "Error at 'a': Expect '(' after 'while'");
+    parser_test!(FAIL: parse_for_needs_condition_semicolon, "for (;a) {}", "Error at ')': Expect ';' after loop condition");
+    parser_test!(FAIL: parse_for_needs_paren, "for (;; i j) {}", "Error at 'j': Expect ')' after for clauses");
+    parser_test!(FAIL: parse_assign_to_method_call, "a.b() = 1;", "Invalid assignment target");
+    parser_test!(FAIL: parse_class_needs_name, "class {}", "Error at '{': Expect class name");
+    parser_test!(FAIL: parse_class_needs_body, "class A;", "Error at ';': Expect '{' before class body");
+    parser_test!(FAIL: parse_superclass_needs_name, "class A < {}", "Expect superclass name");
+    parser_test!(FAIL: parse_match_needs_paren, "match x { _ -> 1 };", "Error at 'x': Expect '(' after 'match'");
+    parser_test!(FAIL: parse_match_needs_arms, "match (x) {};", "Error at '}': Expect pattern");
+    parser_test!(FAIL: parse_match_needs_arrow, "match (x) { 1 2 };", "Error at '2': Expect '->' after pattern");
+    parser_test!(FAIL: parse_match_negative_needs_number, "match (x) { -a -> 1 };", "Error at 'a': Expect number after '-' in pattern");
+    parser_test!(FAIL: parse_match_arms_need_commas, "match (x) { 1 -> 2 3 -> 4 };", "Error at '3': Expect '}' after match arms");
+    parser_test!(FAIL: parse_match_pattern_is_not_expression, "match (x) { (1) -> 2 };", "Error at '(': Expect pattern");
+    parser_test!(FAIL: parse_setter_arity, "class A { set x() {} }", "Error at 'x': A setter must have exactly one parameter");
+    parser_test!(FAIL: parse_setter_needs_parameters, "class A { set x {} }", "Error at '{': Expect '(' after setter name");
+    parser_test!(FAIL: parse_mixin_needs_name, "class A with X, {}", "Error at '{': Expect mixin name");
+    parser_test!(FAIL: parse_unclosed_class, "class A { f() {}", "Error at end: Expect '}' after class body");
+    parser_test!(FAIL: parse_method_needs_name, "class A { fun f() {} }", "Error at 'fun': Expect method name");
+    parser_test!(FAIL: parse_super_needs_dot, "super;", "Error at ';': Expect '.' after 'super'");
+    parser_test!(FAIL: parse_super_needs_method, "super.1;", "Expect superclass method name");
+    parser_test!(FAIL: parse_unclosed_index, "[1][0;", "Expect ']' after index");
+    parser_test!(FAIL: parse_assign_to_literal, "1 = 2;", "Error at '=': Invalid assignment target");
+    parser_test!(FAIL: parse_yield_at_top_level, "{ yield 1; }", "Error at 'yield': Can't yield from top-level code");
+    parser_test!(FAIL: parse_return_at_top_level, "return 1;", "Error at 'return': Can't return from top-level code");
+    parser_test!(FAIL: parse_return_in_block, "{ return; }", "Can't return from top-level code");
+    parser_test!(FAIL: parse_fun_needs_body, "fun f() print 1;", "Error at 'print': Expect '{' before function body");
+    parser_test!(FAIL: parse_fun_bad_param, "fun f(1) {}", "Error at '1': Expect parameter name");
+    parser_test!(FAIL: parse_if_needs_paren, "if a print 1;", "Error at 'a': Expect '(' after 'if'");
+    parser_test!(FAIL: parse_throw_needs_value, "throw;", "Error at ';': Expect expression");
+    parser_test!(FAIL: parse_try_needs_block, "try f();", "Error at 'f': Expect '{' after 'try'");
+    parser_test!(FAIL: parse_try_needs_catch, "try {} print 1;", "Error at 'print': Expect 'catch' after try block");
+    parser_test!(FAIL: parse_catch_needs_name, "try {} catch () {}", "Error at ')': Expect exception name");
+    parser_test!(FAIL: parse_assign_to_slice, "[1][0:1] = 2;", "Invalid assignment target");
+
+    #[test]
+    fn every_syntax_error_is_reported() {
+        let err = parse("print 1 +;\nvar = 2;\nfun f() { return 1 +; }\nprint 3;").unwrap_err();
+        let messages: Vec<_> = err.into_errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "[line 1] Error at ';': Expect expression.",
+                "[line 2] Error at '=': Expect variable name.",
+                "[line 3] Error at ';': Expect expression.",
+            ]
+        );
+    }
+
+    fn collect_ids(expr: &Expr, ids: &mut Vec<NodeId>) {
+        ids.push(expr.id);
+        match &expr.kind {
+            ExprKind::Match { subject, arms, .. } => {
+                collect_ids(subject, ids);
+                for arm in arms {
+                    collect_ids(&arm.body, ids);
+                }
+            }
+            ExprKind::Literal(_)
+            | ExprKind::Variable(_)
+            | ExprKind::This(_)
+            | ExprKind::Super { .. } => {}
+            ExprKind::Assign { value, .. } => collect_ids(value, ids),
+            ExprKind::Grouping(inner) => collect_ids(inner, ids),
+            ExprKind::List(elements) => {
+                for element in elements {
+                    collect_ids(element, ids);
+                }
+            }
+            ExprKind::Map { entries, .. } => {
+                for (key, value) in entries {
+                    collect_ids(key, ids);
+                    collect_ids(value, ids);
+                }
+            }
+            ExprKind::Unary { right, .. } => collect_ids(right, ids),
+            ExprKind::Binary { left, right, .. } | ExprKind::Logical { left, right, .. } => {
+                collect_ids(left, ids);
+                collect_ids(right, ids);
+            }
+            ExprKind::Call {
+                callee, arguments, ..
+            } => {
+                collect_ids(callee, ids);
+                for argument in arguments {
+                    collect_ids(argument, ids);
+                }
+            }
+            ExprKind::Get { object, .. } => collect_ids(object, ids),
+            ExprKind::Set { object, value, .. } => {
+                collect_ids(object, ids);
+                collect_ids(value, ids);
+            }
+            ExprKind::Index { object, index, .. } => {
+                collect_ids(object, ids);
+                collect_ids(index, ids);
+            }
+            ExprKind::Slice {
+                object, start, end, ..
+            } => {
+                collect_ids(object, ids);
+                for bound in start.iter().chain(end.iter()) {
+                    collect_ids(bound, ids);
+                }
+            }
+            ExprKind::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                collect_ids(object, ids);
+                collect_ids(index, ids);
+                collect_ids(value, ids);
+            }
+        }
+    }
+
+    fn collect_stmt_ids(statements: &[Stmt], ids: &mut Vec<NodeId>) {
+        for stmt in statements {
+            ids.push(stmt.id);
+            match &stmt.kind {
+                StmtKind::Expression(expr) | StmtKind::Print(expr) => collect_ids(expr, ids),
+                StmtKind::Var { initializer, .. } => {
+                    if let Some(initializer) = initializer {
+                        collect_ids(initializer, ids);
+                    }
+                }
+                StmtKind::Block(statements) => collect_stmt_ids(statements, ids),
+                StmtKind::Function(decl) => collect_stmt_ids(&decl.body, ids),
+                StmtKind::Class {
+                    superclass,
+                    mixins,
+                    methods,
+                    getters,
+                    setters,
+                    ..
+                } => {
+                    if let Some(superclass) = superclass {
+                        collect_ids(superclass, ids);
+                    }
+                    for mixin in mixins {
+                        collect_ids(mixin, ids);
+                    }
+                    for method in methods.iter().chain(getters).chain(setters) {
+                        collect_stmt_ids(&method.body, ids);
+                    }
+                }
+                StmtKind::If {
+                    condition,
+                    then_branch,
+                    else_branch,
+                    ..
+                } => {
+                    collect_ids(condition, ids);
+                    collect_stmt_ids(std::slice::from_ref(then_branch), ids);
+                    if let Some(else_branch) = else_branch {
+                        collect_stmt_ids(std::slice::from_ref(else_branch), ids);
+                    }
+                }
+                StmtKind::Return { value, .. } | StmtKind::Yield { value, .. } => {
+                    if let Some(value) = value {
+                        collect_ids(value, ids);
+                    }
+                }
+                StmtKind::Throw { value, .. } => collect_ids(value, ids),
+                StmtKind::Try { body, handler, .. } => {
+                    collect_stmt_ids(body, ids);
+                    collect_stmt_ids(handler, ids);
+                }
+                StmtKind::ForIn { iterable, body, .. } => {
+                    collect_ids(iterable, ids);
+                    collect_stmt_ids(std::slice::from_ref(body), ids);
+                }
+                StmtKind::While {
+                    condition, body, ..
+                } => {
+                    collect_ids(condition, ids);
+                    collect_stmt_ids(std::slice::from_ref(body), ids);
+                }
+                StmtKind::For {
+                    initializer,
+                    condition,
+                    increment,
+                    body,
+                    ..
+                } => {
+                    if let Some(initializer) = initializer {
+                        collect_stmt_ids(std::slice::from_ref(initializer), ids);
+                    }
+                    for clause in condition.iter().chain(increment) {
+                        collect_ids(clause, ids);
+                    }
+                    collect_stmt_ids(std::slice::from_ref(body), ids);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn too_many_arguments() {
+        let args = vec!["1"; 256].join(", ");
+        let err = parse(&format!("f({});", args)).unwrap_err();
+        assert!(format!("{}", err).contains("Can't have more than 255 arguments"));
+        assert!(parse(&format!("f({});", vec!["1"; 255].join(", "))).is_ok());
+    }
+
+    // Each statement and expression around the innermost counts, so the deepest a program's
+    // own nesting can go is a little under MAX_DEPTH.
+    #[test]
+    fn too_much_nesting() {
+        let nest = |depth, open: &str, inner: &str, close: &str| {
+            format!("{}{}{}", open.repeat(depth), inner, close.repeat(depth))
+        };
+        let parse = |program: &String| {
+            let program = program.clone();
+            let parsing = std::thread::Builder::new()
+                .stack_size(STACK_SIZE)
+                .spawn(move || parse(&program).map(|_| ()))
+                .unwrap();
+            parsing.join().expect("the parser overflowed")
+        };
+        for depth in [250, 1000] {
+            let programs = [
+                nest(depth, "(", "1", ")") + ";",
+                nest(depth, "-", "1", "") + ";",
+                nest(depth, "x = ", "1", "") + ";",
+                nest(depth, "{", "", "}"),
+                nest(depth, "if (true) ", "print 1;", ""),
+                nest(depth, "fun f() {", "", "}"),
+            ];
+            for program in &programs {
+                match parse(program) {
+                    Ok(()) => assert_eq!(depth, 250, "{}", program),
+                    Err(err) => {
+                        assert_eq!(depth, 1000, "{}", program);
+                        assert!(err.to_string().contains("Too much nesting"), "{}", err);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_node_gets_a_distinct_id() -> Result<(), LoxError> {
+        let mut scanner = Scanner::new("print 1 + 2; { (3); var a; }");
+        let tokens = scanner.scan_tokens()?;
+        let statements = Parser::new(tokens).parse()?;
+        let mut ids = Vec::new();
+        collect_stmt_ids(&statements, &mut ids);
+        let mut unique = ids.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(ids.len(), 9);
+        assert_eq!(unique.len(), ids.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,200 @@
+// rlox1 for a web page, e.g. an online playground: run() takes a program and gives back what
+// it printed and the errors it stopped with, laid out as rlox1 would show them, rather than
+// writing either to a terminal, which a page doesn't have. Built with the `wasm` feature;
+// built for wasm32, run() is exported to JavaScript with wasm-bindgen, as a function
+// returning an object with `output` and `errors`.
+//
+// Each run starts from a new Lox, with the standard library but no file or environment
+// access, so one visitor's program can't see what another's defined. It runs under the
+// sandbox's limits on memory and call depth, and under limits on how many statements it may
+// execute and for how long, so that a loop that never ends can't hang the page.
+
+use std::time::Duration;
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::diagnostic::{Level, Renderer};
+use crate::embed::Lox;
+use crate::executive::{SANDBOX_MAX_CALL_DEPTH, SANDBOX_MAX_MEMORY};
+use crate::memory;
+use crate::stdlib::Options;
+
+// The file name errors give for the program.
+const FILE: &str = "playground.lox";
+
+// Limits: What a program run on the page may use. Memory is only limited once memory::set_meter
+// has said how to measure it, as it has for wasm32.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub max_memory: usize,
+    pub max_call_depth: usize,
+    pub max_steps: u64,
+    pub time_limit: Duration,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_memory: SANDBOX_MAX_MEMORY,
+            max_call_depth: SANDBOX_MAX_CALL_DEPTH,
+            max_steps: 10_000_000,
+            time_limit: Duration::from_secs(5),
+        }
+    }
+}
+
+// Run: What a program did: the lines it printed, and the errors that stopped it, each
+// rendered in full, with the line of the program it points at.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen(getter_with_clone))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    pub output: String,
+    pub errors: Vec<String>,
+}
+
+// run: Run `source` on its own under the default limits, keeping what it prints.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub fn run(source: &str) -> Run {
+    run_with(source, &Limits::default())
+}
+
+// run_with: Like run, under `limits`.
+pub fn run_with(source: &str, limits: &Limits) -> Run {
+    let options = Options {
+        allow_env: false,
+        ..Options::default()
+    };
+    let mut lox = Lox::with_options(&options);
+    let interpreter = lox.interpreter();
+    interpreter.capture_output();
+    interpreter.set_max_call_depth(limits.max_call_depth);
+    if memory::in_use().is_some() {
+        interpreter.set_max_memory(limits.max_memory);
+    }
+    interpreter.set_max_steps(limits.max_steps);
+    interpreter.set_time_limit(limits.time_limit, clock::now);
+    let errors = match lox.eval(source) {
+        Ok(_) => Vec::new(),
+        Err(err) => err
+            .into_errors()
+            .iter()
+            .map(|err| Renderer::new(false).render_in(Level::Error, err, FILE, source))
+            .collect(),
+    };
+    Run {
+        output: lox.interpreter().take_output(),
+        errors,
+    }
+}
+
+// clock: The time, for the time limit. std can't tell it in a browser, so there it comes from
+// the page's Date.now().
+#[cfg(target_arch = "wasm32")]
+mod clock {
+    use std::time::Duration;
+
+    use wasm_bindgen::prelude::wasm_bindgen;
+
+    use crate::memory::{self, Counting};
+
+    // Every allocation is counted, for the memory limit, as rlox1's own binary counts them.
+    #[global_allocator]
+    static ALLOCATOR: Counting = Counting;
+
+    #[wasm_bindgen]
+    extern "C" {
+        #[wasm_bindgen(js_namespace = Date, js_name = now)]
+        fn date_now() -> f64;
+    }
+
+    // start: Meter the memory limit with Counting, as soon as the module is loaded.
+    #[wasm_bindgen(start)]
+    fn start() {
+        memory::set_meter(Counting::in_use);
+    }
+
+    // now: The time since the epoch, in the page's milliseconds.
+    pub fn now() -> Duration {
+        Duration::from_secs_f64(date_now() / 1000.0)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod clock {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    // now: The time since the epoch.
+    pub fn now() -> Duration {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::playground::{run, run_with, Limits};
+
+    #[test]
+    fn printing_is_kept_for_the_page() {
+        let run = run("print 1 + 2;\nprint(\"two\");\nfor (var i in [0, 1]) print i;");
+        assert_eq!(run.output, "3\ntwo\n0\n1\n");
+        assert!(run.errors.is_empty());
+    }
+
+    #[test]
+    fn errors_come_back_with_what_was_printed_before() {
+        let run = run("print \"before\";\nprint nope;");
+        assert_eq!(run.output, "before\n");
+        assert_eq!(run.errors.len(), 1);
+        assert!(
+            run.errors[0].starts_with("ERROR[LX0301]: [line 2] Undefined variable 'nope'."),
+            "{}",
+            run.errors[0]
+        );
+        assert!(
+            run.errors[0].contains("--> playground.lox:2:7"),
+            "{}",
+            run.errors[0]
+        );
+        let run = super::run("print 1 +;\nvar = 2;");
+        assert_eq!((run.output.as_str(), run.errors.len()), ("", 2));
+    }
+
+    #[test]
+    fn programs_that_run_too_long_are_stopped() {
+        // It would print 10,000 lines.
+        let source = "var l = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10];\n\
+                      for (var a in l) for (var b in l) for (var c in l) for (var d in l) print d;";
+        let limits = Limits {
+            max_steps: 100,
+            ..Limits::default()
+        };
+        let run = run_with(source, &limits);
+        assert!(run.output.lines().count() < 100, "{}", run.output);
+        assert!(
+            run.errors[0].starts_with("ERROR[LX0608]: The program ran for more than 100 steps."),
+            "{}",
+            run.errors[0]
+        );
+        let limits = Limits {
+            time_limit: Duration::ZERO,
+            ..Limits::default()
+        };
+        let run = run_with(source, &limits);
+        assert!(
+            run.errors[0].starts_with("ERROR[LX0609]: The program ran for longer than 0ns."),
+            "{}",
+            run.errors[0]
+        );
+        let run = super::run("fun f(n) { return f(n + 1); }\nf(0);");
+        assert!(
+            run.errors[0].contains("Stack overflow"),
+            "{}",
+            run.errors[0]
+        );
+    }
+}
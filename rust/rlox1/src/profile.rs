@@ -0,0 +1,147 @@
+// Profiling for `--profile`: how often each kind of node or instruction ran, and, on the
+// tree-walker, how long each function took. The engines fill in a Profile as they go and the
+// executive prints it on exit, hottest entries first.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Default)]
+pub struct Profile {
+    // What is being counted, for the report's heading: "nodes" or "instructions".
+    unit: &'static str,
+    counts: HashMap<String, u64>,
+    functions: HashMap<String, Timing>,
+}
+
+// Timing: One function's calls. `active` is how many of them are running, so a recursive
+// function's time is only measured from its outermost call and isn't counted twice.
+#[derive(Debug, Default)]
+struct Timing {
+    calls: u64,
+    active: usize,
+    time: Duration,
+}
+
+impl Profile {
+    pub fn new(unit: &'static str) -> Self {
+        Profile {
+            unit,
+            ..Profile::default()
+        }
+    }
+
+    // count: Note one more execution of `what`.
+    pub fn count(&mut self, what: &str) {
+        self.add(what, 1);
+    }
+
+    pub fn add(&mut self, what: &str, n: u64) {
+        match self.counts.get_mut(what) {
+            Some(count) => *count += n,
+            None => {
+                self.counts.insert(what.to_string(), n);
+            }
+        }
+    }
+
+    // enter: Note a call to `function`, returning when it started if it is the outermost
+    // call running. Pass the result to `exit` when the call returns.
+    pub fn enter(&mut self, function: &str) -> Option<Instant> {
+        if !self.functions.contains_key(function) {
+            self.functions
+                .insert(function.to_string(), Timing::default());
+        }
+        let timing = self.functions.get_mut(function).expect("just inserted");
+        timing.calls += 1;
+        timing.active += 1;
+        if timing.active == 1 {
+            Some(Instant::now())
+        } else {
+            None
+        }
+    }
+
+    pub fn exit(&mut self, function: &str, started: Option<Instant>) {
+        if let Some(timing) = self.functions.get_mut(function) {
+            timing.active -= 1;
+            if let Some(started) = started {
+                timing.time += started.elapsed();
+            }
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+}
+
+// The report: counts by frequency, then functions by time, ties broken by name so that the
+// output is stable.
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.total();
+        writeln!(f, "profile: {} {} executed", total, self.unit)?;
+        let mut counts: Vec<_> = self.counts.iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (what, count) in counts {
+            let share = 100.0 * *count as f64 / total as f64;
+            writeln!(f, "{:>12} {:>6.2}%  {}", count, share, what)?;
+        }
+        if self.functions.is_empty() {
+            return Ok(());
+        }
+        writeln!(
+            f,
+            "profile: time per function, including the functions it calls"
+        )?;
+        let mut functions: Vec<_> = self.functions.iter().collect();
+        functions.sort_by(|a, b| b.1.time.cmp(&a.1.time).then(a.0.cmp(b.0)));
+        for (name, timing) in functions {
+            writeln!(
+                f,
+                "{:>12.3}ms {:>8} calls  {}",
+                timing.time.as_secs_f64() * 1000.0,
+                timing.calls,
+                name
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::profile::Profile;
+
+    #[test]
+    fn counts_are_sorted() {
+        let mut profile = Profile::new("nodes");
+        profile.count("Literal");
+        profile.add("Binary", 3);
+        profile.count("Literal");
+        profile.count("Call");
+        assert_eq!(profile.total(), 6);
+        let report = format!("{}", profile);
+        let lines: Vec<_> = report.lines().collect();
+        assert_eq!(lines[0], "profile: 6 nodes executed");
+        assert!(lines[1].ends_with("50.00%  Binary"), "{}", lines[1]);
+        assert!(lines[2].ends_with("Literal"), "{}", lines[2]);
+        assert!(lines[3].ends_with("Call"), "{}", lines[3]);
+        assert_eq!(lines.len(), 4);
+    }
+
+    #[test]
+    fn recursive_calls_are_timed_once() {
+        let mut profile = Profile::new("nodes");
+        let outer = profile.enter("f");
+        let inner = profile.enter("f");
+        assert!(outer.is_some());
+        assert!(inner.is_none());
+        profile.exit("f", inner);
+        profile.exit("f", outer);
+        assert!(profile.enter("f").is_some());
+        let report = format!("{}", profile);
+        assert!(report.contains("3 calls  f"), "{}", report);
+    }
+}
@@ -0,0 +1,756 @@
+// Register VM: An experimental third engine, to compare a register machine with the stack VM.
+// Rather than pushing and popping a value stack, each instruction names the registers it
+// reads and writes, in a frame whose size the compiler works out. Locals are registers, so
+// `a + b` reads them in place instead of first copying both to the top of a stack.
+//
+// It compiles the same subset of Lox as the stack VM and shares its values and error
+// messages. Instructions are a Rust enum rather than bytes, since it isn't meant to be saved.
+
+use std::collections::HashMap;
+
+use crate::ast::{Expr, ExprKind, Literal, Stmt, StmtKind};
+use crate::compiler::{expression_name, statement_name, unsupported};
+use crate::error::{ErrorKind, LoxError, Span};
+use crate::interpreter::operand_error;
+use crate::interrupt;
+use crate::resolver::Resolver;
+use crate::scanner::{Token, TokenType};
+use crate::suggest;
+use crate::value::Value;
+
+// The most registers a frame can have, since an instruction names a register in one byte.
+const MAX_REGISTERS: usize = 256;
+
+// Instr: One register VM instruction. `dst` is written; `a`, `b` and `src` are read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instr {
+    Constant { dst: u8, index: u32 },
+    Nil { dst: u8 },
+    Bool { dst: u8, value: bool },
+    Move { dst: u8, src: u8 },
+    // GetGlobal, DefineGlobal, SetGlobal: Globals are named by a string constant.
+    GetGlobal { dst: u8, name: u32 },
+    DefineGlobal { src: u8, name: u32 },
+    SetGlobal { src: u8, name: u32 },
+    Equal { dst: u8, a: u8, b: u8 },
+    NotEqual { dst: u8, a: u8, b: u8 },
+    Greater { dst: u8, a: u8, b: u8 },
+    GreaterEqual { dst: u8, a: u8, b: u8 },
+    Less { dst: u8, a: u8, b: u8 },
+    LessEqual { dst: u8, a: u8, b: u8 },
+    Add { dst: u8, a: u8, b: u8 },
+    Subtract { dst: u8, a: u8, b: u8 },
+    Multiply { dst: u8, a: u8, b: u8 },
+    Divide { dst: u8, a: u8, b: u8 },
+    Not { dst: u8, src: u8 },
+    Negate { dst: u8, src: u8 },
+    Print { src: u8 },
+    // JumpIfFalse, JumpIfTrue: Continue at instruction `to` if `cond` is falsey or truthy.
+    JumpIfFalse { cond: u8, to: u32 },
+    JumpIfTrue { cond: u8, to: u32 },
+    // Jump: Continue at instruction `to`, further on.
+    Jump { to: u32 },
+    // Loop: Go back to instruction `to`, the top of a loop.
+    Loop { to: u32 },
+    // Return: End the program, with the value of `src` if there is one.
+    Return { src: Option<u8> },
+}
+
+#[derive(Debug, Default)]
+pub struct RegisterChunk {
+    pub code: Vec<Instr>,
+    pub lines: Vec<usize>,
+    // The span of the token behind each instruction, if it has one.
+    pub spans: Vec<Option<Span>>,
+    pub constants: Vec<Value>,
+    // How many registers a frame running the chunk needs.
+    pub registers: usize,
+}
+
+struct Local {
+    name: String,
+    depth: usize,
+}
+
+// RegisterCompiler: Locals take the lowest registers, in the order they are declared, and
+// the temporaries an expression needs are handed out above them and given back once the
+// statement is done.
+pub struct RegisterCompiler {
+    chunk: RegisterChunk,
+    locals: Vec<Local>,
+    scope_depth: usize,
+    // The lowest register that is neither a local nor a temporary in use.
+    next: usize,
+    line: usize,
+    span: Option<Span>,
+}
+
+impl RegisterCompiler {
+    pub fn new() -> Self {
+        RegisterCompiler {
+            chunk: RegisterChunk::default(),
+            locals: Vec::new(),
+            scope_depth: 0,
+            next: 0,
+            line: 1,
+            span: None,
+        }
+    }
+
+    pub fn compile(statements: &[Stmt]) -> Result<RegisterChunk, LoxError> {
+        let mut compiler = RegisterCompiler::new();
+        compiler.statements(statements)?;
+        Ok(compiler.finish(None))
+    }
+
+    // finish: End the chunk by returning `result`, and hand it over.
+    pub fn finish(mut self, result: Option<u8>) -> RegisterChunk {
+        self.emit(Instr::Return { src: result });
+        self.chunk
+    }
+
+    pub fn statements(&mut self, statements: &[Stmt]) -> Result<(), LoxError> {
+        for stmt in statements {
+            self.statement(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, stmt: &Stmt) -> Result<(), LoxError> {
+        match &stmt.kind {
+            StmtKind::Expression(expr) => {
+                self.operand(expr)?;
+            }
+            StmtKind::Print(expr) => {
+                let src = self.operand(expr)?;
+                self.emit(Instr::Print { src });
+            }
+            StmtKind::Var {
+                name, initializer, ..
+            } => {
+                self.line = name.line;
+                self.span = name.span();
+                let dst = self.temporary()?;
+                match initializer {
+                    Some(initializer) => self.expression(initializer, dst)?,
+                    None => self.emit(Instr::Nil { dst }),
+                }
+                if self.scope_depth > 0 {
+                    // The temporary is the next register up, so it becomes the local's.
+                    self.locals.push(Local {
+                        name: format!("{}", name.typ),
+                        depth: self.scope_depth,
+                    });
+                } else {
+                    let name = self.identifier_constant(name);
+                    self.emit(Instr::DefineGlobal { src: dst, name });
+                }
+            }
+            StmtKind::Block(statements) => {
+                self.scope_depth += 1;
+                let result = self.statements(statements);
+                self.end_scope();
+                result?;
+            }
+            StmtKind::If {
+                keyword,
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let cond = self.operand(condition)?;
+                self.line = keyword.line;
+                self.span = keyword.span();
+                let then_jump = self.emit_jump(Instr::JumpIfFalse { cond, to: 0 });
+                self.statement(then_branch)?;
+                match else_branch {
+                    Some(else_branch) => {
+                        let else_jump = self.emit_jump(Instr::Jump { to: 0 });
+                        self.patch_jump(then_jump);
+                        self.statement(else_branch)?;
+                        self.patch_jump(else_jump);
+                    }
+                    None => self.patch_jump(then_jump),
+                }
+            }
+            StmtKind::While {
+                keyword,
+                condition,
+                body,
+            } => self.compile_loop(keyword, Some(condition), None, body)?,
+            // The initializer's variable is a local in a scope around the whole loop.
+            StmtKind::For {
+                keyword,
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                self.scope_depth += 1;
+                let mut result = Ok(());
+                if let Some(initializer) = initializer {
+                    result = self.statement(initializer);
+                }
+                if result.is_ok() {
+                    result =
+                        self.compile_loop(keyword, condition.as_ref(), increment.as_ref(), body);
+                }
+                self.end_scope();
+                result?;
+            }
+            other => return Err(unsupported(statement_name(other))),
+        }
+        // Whatever the statement needed beyond its locals is free again.
+        self.next = self.locals.len();
+        Ok(())
+    }
+
+    // compile_loop: Compile the passes of a while or C-style for loop. The registers the
+    // condition and increment need are free again once each has been used.
+    fn compile_loop(
+        &mut self,
+        keyword: &Token,
+        condition: Option<&Expr>,
+        increment: Option<&Expr>,
+        body: &Stmt,
+    ) -> Result<(), LoxError> {
+        let start = self.chunk.code.len() as u32;
+        let exit = match condition {
+            Some(condition) => {
+                let cond = self.operand(condition)?;
+                self.line = keyword.line;
+                self.span = keyword.span();
+                Some(self.emit_jump(Instr::JumpIfFalse { cond, to: 0 }))
+            }
+            None => None,
+        };
+        self.next = self.locals.len();
+        self.statement(body)?;
+        if let Some(increment) = increment {
+            self.operand(increment)?;
+            self.next = self.locals.len();
+        }
+        self.line = keyword.line;
+        self.span = keyword.span();
+        self.emit(Instr::Loop { to: start });
+        if let Some(exit) = exit {
+            self.patch_jump(exit);
+        }
+        Ok(())
+    }
+
+    // end_scope: Close the innermost block, whose locals' registers are free again.
+    fn end_scope(&mut self) {
+        self.scope_depth -= 1;
+        while let Some(local) = self.locals.last() {
+            if local.depth <= self.scope_depth {
+                break;
+            }
+            self.locals.pop();
+        }
+    }
+
+    // operand: The register holding the value of `expr`. A local is read where it lives;
+    // anything else is computed into a new temporary.
+    pub fn operand(&mut self, expr: &Expr) -> Result<u8, LoxError> {
+        if let ExprKind::Variable(name) = &expr.kind {
+            if let Some(slot) = self.resolve_local(name) {
+                return Ok(slot);
+            }
+        }
+        let dst = self.temporary()?;
+        self.expression(expr, dst)?;
+        Ok(dst)
+    }
+
+    // expression: Compile `expr` so that its value ends up in register `dst`.
+    fn expression(&mut self, expr: &Expr, dst: u8) -> Result<(), LoxError> {
+        match &expr.kind {
+            ExprKind::Literal(literal) => match literal {
+                Literal::Nil => self.emit(Instr::Nil { dst }),
+                Literal::Bool(value) => self.emit(Instr::Bool { dst, value: *value }),
+                Literal::Number(n) => {
+                    let index = self.constant(Value::Number(*n));
+                    self.emit(Instr::Constant { dst, index });
+                }
+                Literal::Str(s) => {
+                    let index = self.constant(Value::Str(s.as_str().into()));
+                    self.emit(Instr::Constant { dst, index });
+                }
+            },
+            ExprKind::Grouping(inner) => self.expression(inner, dst)?,
+            ExprKind::Variable(name) => {
+                self.line = name.line;
+                self.span = name.span();
+                match self.resolve_local(name) {
+                    Some(src) => self.emit_move(dst, src),
+                    None => {
+                        let name = self.identifier_constant(name);
+                        self.emit(Instr::GetGlobal { dst, name });
+                    }
+                }
+            }
+            ExprKind::Assign { name, value } => match self.resolve_local(name) {
+                // `and` and `or` write their left operand to the destination before they
+                // evaluate the right one, which may read the variable being assigned.
+                Some(slot) if is_logical(value) => {
+                    let src = self.operand(value)?;
+                    self.line = name.line;
+                    self.span = name.span();
+                    self.emit_move(slot, src);
+                    self.emit_move(dst, slot);
+                }
+                Some(slot) => {
+                    self.expression(value, slot)?;
+                    self.emit_move(dst, slot);
+                }
+                None => {
+                    self.expression(value, dst)?;
+                    self.line = name.line;
+                    self.span = name.span();
+                    let name = self.identifier_constant(name);
+                    self.emit(Instr::SetGlobal { src: dst, name });
+                }
+            },
+            ExprKind::Logical { left, op, right } => {
+                self.expression(left, dst)?;
+                self.line = op.line;
+                self.span = op.span();
+                let jump = self.emit_jump(if op.typ == TokenType::And {
+                    Instr::JumpIfFalse { cond: dst, to: 0 }
+                } else {
+                    Instr::JumpIfTrue { cond: dst, to: 0 }
+                });
+                self.expression(right, dst)?;
+                self.patch_jump(jump);
+            }
+            ExprKind::Unary { op, right } => {
+                let src = self.operand(right)?;
+                self.line = op.line;
+                self.span = op.span();
+                match op.typ {
+                    TokenType::Bang => self.emit(Instr::Not { dst, src }),
+                    TokenType::Minus => self.emit(Instr::Negate { dst, src }),
+                    _ => return Err(unsupported(&format!("the '{}' operator", op.typ))),
+                }
+            }
+            ExprKind::Binary { left, op, right } => {
+                // The left operand is read after the right one is evaluated, so if the right
+                // one assigns to a variable, the left one must not be read in place.
+                let a = if has_assignment(right) {
+                    let a = self.temporary()?;
+                    self.expression(left, a)?;
+                    a
+                } else {
+                    self.operand(left)?
+                };
+                let b = self.operand(right)?;
+                self.line = op.line;
+                self.span = op.span();
+                self.emit(match op.typ {
+                    TokenType::EqualEqual => Instr::Equal { dst, a, b },
+                    TokenType::BangEqual => Instr::NotEqual { dst, a, b },
+                    TokenType::Greater => Instr::Greater { dst, a, b },
+                    TokenType::GreaterEqual => Instr::GreaterEqual { dst, a, b },
+                    TokenType::Less => Instr::Less { dst, a, b },
+                    TokenType::LessEqual => Instr::LessEqual { dst, a, b },
+                    TokenType::Plus => Instr::Add { dst, a, b },
+                    TokenType::Minus => Instr::Subtract { dst, a, b },
+                    TokenType::Star => Instr::Multiply { dst, a, b },
+                    TokenType::Slash => Instr::Divide { dst, a, b },
+                    _ => return Err(unsupported(&format!("the '{}' operator", op.typ))),
+                });
+            }
+            other => return Err(unsupported(expression_name(other))),
+        }
+        Ok(())
+    }
+
+    // temporary: Claim the lowest free register.
+    fn temporary(&mut self) -> Result<u8, LoxError> {
+        if self.next >= MAX_REGISTERS {
+            let location = format!("[line {}] Error: ", self.line);
+            let message = message!(LX0604, "Too many {} in one chunk", "registers");
+            return Err(LoxError::located(&location, message).of_kind(ErrorKind::Parse));
+        }
+        self.next += 1;
+        self.chunk.registers = self.chunk.registers.max(self.next);
+        Ok((self.next - 1) as u8)
+    }
+
+    fn resolve_local(&self, name: &Token) -> Option<u8> {
+        let name = format!("{}", name.typ);
+        self.locals
+            .iter()
+            .rposition(|local| local.name == name)
+            .map(|slot| slot as u8)
+    }
+
+    fn identifier_constant(&mut self, name: &Token) -> u32 {
+        self.constant(Value::Str(format!("{}", name.typ).into()))
+    }
+
+    fn constant(&mut self, value: Value) -> u32 {
+        self.chunk.constants.push(value);
+        (self.chunk.constants.len() - 1) as u32
+    }
+
+    fn emit(&mut self, instr: Instr) {
+        self.chunk.code.push(instr);
+        self.chunk.lines.push(self.line);
+        self.chunk.spans.push(self.span);
+    }
+
+    // emit_jump: Emit a forward jump whose target patch_jump fills in later, returning where
+    // it is.
+    fn emit_jump(&mut self, jump: Instr) -> usize {
+        self.emit(jump);
+        self.chunk.code.len() - 1
+    }
+
+    // patch_jump: Point the jump at `at` to the next instruction.
+    fn patch_jump(&mut self, at: usize) {
+        let to = self.chunk.code.len() as u32;
+        match &mut self.chunk.code[at] {
+            Instr::JumpIfFalse { to: target, .. }
+            | Instr::JumpIfTrue { to: target, .. }
+            | Instr::Jump { to: target } => *target = to,
+            _ => unreachable!("patching an instruction that isn't a jump"),
+        }
+    }
+
+    fn emit_move(&mut self, dst: u8, src: u8) {
+        if dst != src {
+            self.emit(Instr::Move { dst, src });
+        }
+    }
+}
+
+fn is_logical(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Logical { .. } => true,
+        ExprKind::Grouping(inner) => is_logical(inner),
+        _ => false,
+    }
+}
+
+// has_assignment: Whether evaluating `expr` may assign to a variable.
+fn has_assignment(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Assign { .. } => true,
+        ExprKind::Grouping(inner) | ExprKind::Unary { right: inner, .. } => has_assignment(inner),
+        ExprKind::Logical { left, right, .. } | ExprKind::Binary { left, right, .. } => {
+            has_assignment(left) || has_assignment(right)
+        }
+        _ => false,
+    }
+}
+
+// RegisterVm: Like Vm, globals persist from one program to the next.
+pub struct RegisterVm {
+    globals: HashMap<String, Value>,
+}
+
+impl RegisterVm {
+    pub fn new() -> Self {
+        RegisterVm {
+            globals: HashMap::new(),
+        }
+    }
+
+    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<(), LoxError> {
+        Resolver::new().resolve(statements)?;
+        let chunk = RegisterCompiler::compile(statements)?;
+        self.run(&chunk).map(|_| ())
+    }
+
+    // run: Execute a chunk in a fresh frame, giving back the value it returns.
+    pub fn run(&mut self, chunk: &RegisterChunk) -> Result<Value, LoxError> {
+        let mut ip = 0;
+        self.execute(chunk, &mut ip).map_err(|err| {
+            err.at(chunk.spans[ip - 1])
+                .unwind_from(None, None)
+                .at_runtime()
+        })
+    }
+
+    // execute: Run the chunk, leaving `ip` just past the last instruction run, so an error
+    // can be pointed at that instruction's token.
+    fn execute(&mut self, chunk: &RegisterChunk, ip: &mut usize) -> Result<Value, LoxError> {
+        let mut r = vec![Value::Nil; chunk.registers];
+        loop {
+            let instr = chunk.code[*ip];
+            *ip += 1;
+            let line = || chunk.lines[*ip - 1];
+            match instr {
+                Instr::Constant { dst, index } => {
+                    r[dst as usize] = chunk.constants[index as usize].clone()
+                }
+                Instr::Nil { dst } => r[dst as usize] = Value::Nil,
+                Instr::Bool { dst, value } => r[dst as usize] = Value::Bool(value),
+                Instr::Move { dst, src } => r[dst as usize] = r[src as usize].clone(),
+                Instr::GetGlobal { dst, name } => {
+                    let name = global_name(chunk, name);
+                    match self.globals.get(name) {
+                        Some(value) => r[dst as usize] = value.clone(),
+                        None => return Err(self.undefined(name, line())),
+                    }
+                }
+                Instr::DefineGlobal { src, name } => {
+                    let name = global_name(chunk, name);
+                    self.globals
+                        .insert(name.to_string(), r[src as usize].clone());
+                }
+                Instr::SetGlobal { src, name } => {
+                    let name = global_name(chunk, name);
+                    match self.globals.get_mut(name) {
+                        Some(slot) => *slot = r[src as usize].clone(),
+                        None => return Err(self.undefined(name, line())),
+                    }
+                }
+                Instr::Equal { dst, a, b } => {
+                    r[dst as usize] = Value::Bool(r[a as usize] == r[b as usize])
+                }
+                Instr::NotEqual { dst, a, b } => {
+                    r[dst as usize] = Value::Bool(r[a as usize] != r[b as usize])
+                }
+                Instr::Greater { dst, a, b } => {
+                    numeric(&mut r, dst, a, b, line, ">", |a, b| Value::Bool(a > b))?
+                }
+                Instr::GreaterEqual { dst, a, b } => {
+                    numeric(&mut r, dst, a, b, line, ">=", |a, b| Value::Bool(a >= b))?
+                }
+                Instr::Less { dst, a, b } => {
+                    numeric(&mut r, dst, a, b, line, "<", |a, b| Value::Bool(a < b))?
+                }
+                Instr::LessEqual { dst, a, b } => {
+                    numeric(&mut r, dst, a, b, line, "<=", |a, b| Value::Bool(a <= b))?
+                }
+                Instr::Add { dst, a, b } => {
+                    let sum = match (&r[a as usize], &r[b as usize]) {
+                        (Value::Number(a), Value::Number(b)) => Value::Number(a + b),
+                        (Value::Str(a), Value::Str(b)) => Value::Str(format!("{}{}", a, b).into()),
+                        (a, b) => {
+                            return Err(operand_error(
+                                line(),
+                                "+",
+                                "two numbers or two strings",
+                                a,
+                                b,
+                            ))
+                        }
+                    };
+                    r[dst as usize] = sum;
+                }
+                Instr::Subtract { dst, a, b } => {
+                    numeric(&mut r, dst, a, b, line, "-", |a, b| Value::Number(a - b))?
+                }
+                Instr::Multiply { dst, a, b } => {
+                    numeric(&mut r, dst, a, b, line, "*", |a, b| Value::Number(a * b))?
+                }
+                Instr::Divide { dst, a, b } => {
+                    numeric(&mut r, dst, a, b, line, "/", |a, b| Value::Number(a / b))?
+                }
+                Instr::Not { dst, src } => {
+                    r[dst as usize] = Value::Bool(!r[src as usize].is_truthy())
+                }
+                Instr::Negate { dst, src } => match r[src as usize] {
+                    Value::Number(n) => r[dst as usize] = Value::Number(-n),
+                    ref other => loxerr!(
+                        LX0501,
+                        "[line {}] Operand of '{}' must be a number, got {}",
+                        line(),
+                        "-",
+                        other.type_name()
+                    ),
+                },
+                Instr::Print { src } => println!("{}", r[src as usize]),
+                Instr::JumpIfFalse { cond, to } => {
+                    if !r[cond as usize].is_truthy() {
+                        *ip = to as usize;
+                    }
+                }
+                Instr::JumpIfTrue { cond, to } => {
+                    if r[cond as usize].is_truthy() {
+                        *ip = to as usize;
+                    }
+                }
+                Instr::Jump { to } => *ip = to as usize,
+                // Every loop comes back through here, so this is where Ctrl-C is noticed.
+                Instr::Loop { to } => {
+                    if interrupt::requested() {
+                        loxerr!(LX0603, "Interrupted")
+                    }
+                    *ip = to as usize;
+                }
+                Instr::Return { src } => {
+                    return Ok(src.map_or(Value::Nil, |src| r[src as usize].clone()))
+                }
+            }
+        }
+    }
+
+    // undefined: The error for using `name`, which isn't a global, suggesting the global most
+    // like it.
+    #[cold]
+    fn undefined(&self, name: &str, line: usize) -> LoxError {
+        let err = lox_error!(LX0301, "[line {}] Undefined variable '{}'", line, name);
+        suggest::did_you_mean(err, name, self.globals.keys().map(String::as_str))
+    }
+}
+
+// numeric: Apply an operator that takes two numbers, or fail with the operands' types.
+#[inline]
+fn numeric(
+    r: &mut [Value],
+    dst: u8,
+    a: u8,
+    b: u8,
+    line: impl FnOnce() -> usize,
+    symbol: &str,
+    f: impl FnOnce(f64, f64) -> Value,
+) -> Result<(), LoxError> {
+    match (&r[a as usize], &r[b as usize]) {
+        (Value::Number(a), Value::Number(b)) => {
+            r[dst as usize] = f(*a, *b);
+            Ok(())
+        }
+        (a, b) => Err(operand_error(line(), symbol, "numbers", a, b)),
+    }
+}
+
+fn global_name(chunk: &RegisterChunk, index: u32) -> &str {
+    match &chunk.constants[index as usize] {
+        Value::Str(name) => name,
+        other => panic!("global names are strings, got {}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use crate::ast::StmtKind;
+    use crate::bench;
+    use crate::compiler::Compiler;
+    use crate::error::LoxError;
+    use crate::parser::Parser;
+    use crate::register::{RegisterCompiler, RegisterVm};
+    use crate::resolver::Resolver;
+    use crate::scanner::Scanner;
+    use crate::vm::Vm;
+
+    // eval: Run a program on the register VM and return the printed value of its final
+    // expression statement.
+    fn eval(src: &str) -> Result<String, LoxError> {
+        let mut scanner = Scanner::new(src);
+        let mut statements = Parser::new(scanner.scan_tokens()?).parse()?;
+        Resolver::new().resolve(&statements)?;
+        let last = match statements.pop().map(|stmt| stmt.kind) {
+            Some(StmtKind::Expression(expr)) => expr,
+            _ => panic!("last statement must be an expression"),
+        };
+        let mut compiler = RegisterCompiler::new();
+        compiler.statements(&statements)?;
+        let result = compiler.operand(&last)?;
+        let chunk = compiler.finish(Some(result));
+        Ok(format!("{}", RegisterVm::new().run(&chunk)?))
+    }
+
+    macro_rules! register_test {
+        ( FAIL: $name:ident, $src:expr, $ct:expr ) => {
+            #[test]
+            fn $name() {
+                match eval($src) {
+                    Ok(value) => panic!("{:?} should fail, got {}", $src, value),
+                    Err(err) => assert!(format!("{}", err).contains($ct), "{}", err),
+                }
+            }
+        };
+        ( $name:ident, $src:expr => $printed:expr ) => {
+            #[test]
+            fn $name() -> Result<(), LoxError> {
+                assert_eq!(eval($src)?, $printed, "Input was {:?}", $src);
+                Ok(())
+            }
+        };
+    }
+
+    #[test]
+    fn errors_point_at_the_failing_token() {
+        let err = eval("var a = 1;\nvar b = a * \"2\";\nb;").unwrap_err();
+        let span = err.span().expect("a span");
+        assert_eq!((span.line, span.column, span.len), (2, 11, 1));
+    }
+
+    register_test!(register_arithmetic, "1 + 2 * 3 - 4 / 2;" => "5");
+    register_test!(register_negate_and_not, "-(1 + 2) == -3 and !nil and !!0;" => "true");
+    register_test!(register_comparisons, "1 < 2 == (2 >= 2) and 1 != nil;" => "true");
+    register_test!(register_concatenation, "\"con\" + \"cat\";" => "concat");
+    register_test!(register_and_short_circuits, "var a = 1; false and (a = 2); a;" => "1");
+    register_test!(register_or_returns_operand, "nil or \"yes\";" => "yes");
+    register_test!(register_globals, "var a = 1; a = a + 1; a * 10;" => "20");
+    register_test!(register_locals, "var r; { var a = 1; { var b = a + 1; a = b * 10; } r = a; } r;" => "20");
+    register_test!(register_shadowing, "var a = \"global\"; var r; { var a = \"local\"; r = a; } r + \" \" + a;" => "local global");
+    register_test!(register_chained_assignment, "var r; { var a; var b; a = b = 3; r = a + b; } r;" => "6");
+    register_test!(register_assignment_reads_old_value, "var r; { var a = 1; a = a or 2; var b = nil; b = b or a; r = a + b; } r;" => "2");
+    register_test!(register_operands_read_before_assignment, "var r; { var a = 1; r = a + (a = 5); } r;" => "6");
+    register_test!(register_assignment_in_left_operand, "var r; { var a = 1; r = (a = 5) + a; } r;" => "10");
+    register_test!(register_if_else, "var a = 1; if (a > 1) a = 10; else a = 20; if (a) a = a + 1; a;" => "21");
+    register_test!(register_while, "var n = 0; { var i = 0; while (i < 5) { n = n + i; i = i + 1; } } n;" => "10");
+    register_test!(register_for, "var n = 1; for (var i = 1; i <= 5; i = i + 1) n = n * i; n;" => "120");
+    register_test!(register_for_scope, "var i = \"outer\"; for (var i = 0; i < 2; i = i + 1) {} i;" => "outer");
+    register_test!(FAIL: register_undefined_global, "1;\nnope;", "[line 2] Undefined variable 'nope'");
+    register_test!(FAIL: register_undefined_global_suggests, "var counter = 1;\ncounter = countr;", "did you mean 'counter'?");
+    register_test!(FAIL: register_add_mismatch, "1 +\n nil;", "[line 1] Operands of '+' must be two numbers or two strings, got number and nil");
+    register_test!(FAIL: register_negate_string, "-\"a\";", "[line 1] Operand of '-' must be a number, got string");
+    register_test!(FAIL: register_unsupported, "fun f() {} 1;", "The bytecode engine doesn't support functions yet");
+
+    // bench_against_stack_vm: Time loops on both VMs: the book's string_equality benchmark,
+    // and a loop doing arithmetic with its variables as globals and as locals. Neither VM
+    // compiles functions yet, so the book's fib benchmark can't be compared. Run it in release:
+    //     cargo test --release register::tests::bench -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_against_stack_vm() -> Result<(), LoxError> {
+        let arithmetic = "var i = 0; var sum = 0; \
+                          while (i < 10000000) { sum = sum + i * 2 - 1; i = i + 1; }";
+        let programs = [
+            (
+                "string_equality",
+                bench::find("string_equality")
+                    .expect("a benchmark")
+                    .source
+                    .to_string(),
+            ),
+            ("arithmetic, globals", arithmetic.to_string()),
+            ("arithmetic, locals", format!("{{ {} }}", arithmetic)),
+        ];
+        for (name, src) in &programs {
+            let mut scanner = Scanner::new(src);
+            let statements = Parser::new(scanner.scan_tokens()?).parse()?;
+
+            let chunk = Compiler::compile(&statements)?;
+            let start = Instant::now();
+            Vm::new().run(&chunk)?;
+            let stack = start.elapsed();
+
+            let registers = RegisterCompiler::compile(&statements)?;
+            let start = Instant::now();
+            RegisterVm::new().run(&registers)?;
+            let register = start.elapsed();
+
+            println!("{}: stack VM {:?}, register VM {:?}", name, stack, register);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn locals_are_read_in_place() -> Result<(), LoxError> {
+        let mut scanner = Scanner::new("{ var a = 1; var b = 2; print a + b; }");
+        let chunk = RegisterCompiler::compile(&Parser::new(scanner.scan_tokens()?).parse()?)?;
+        assert_eq!(chunk.code.len(), 5);
+        assert_eq!(chunk.registers, 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,913 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{Expr, ExprKind, FunctionDecl, Literal, NodeId, Pattern, Stmt, StmtKind};
+use crate::diagnostic::Diagnostics;
+use crate::error::{ErrorKind, LoxError, Message, Span};
+use crate::scanner::{Token, TokenType};
+
+// Slot: Where a local variable lives at run time: `depth` scopes out from the innermost
+// one, at position `index` within that scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
+}
+
+// Slots: Where each local use and declaration in a program is, by its node.
+pub type Slots = HashMap<NodeId, Slot>;
+
+// Names: For each statement, the locals in scope when it runs, innermost first, so they can
+// be looked up by name while the program is paused there.
+pub type Names = HashMap<NodeId, Vec<(String, Slot)>>;
+
+// Scope: The locals declared so far in one block, function body or loop iteration. Every
+// declaration takes the next index, even one that shadows an earlier name in the same
+// scope, so indexes match the order in which the interpreter creates the values.
+#[derive(Default)]
+struct Scope {
+    names: HashMap<String, usize>,
+    // The indexes of the locals declared with `const`.
+    constants: HashSet<usize>,
+    // The variables declared with `var` or `const`, and the parameters, that nothing has used
+    // yet, by index, to warn about if the scope ends that way.
+    unused: HashMap<usize, Token>,
+    // How many of the first locals are the parameters of a function.
+    params: usize,
+    count: usize,
+}
+
+// FunctionKind: What sort of function body is being resolved, if any.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionKind {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+// ClassKind: What sort of class body is being resolved, if any, so `this` and `super` can be
+// checked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClassKind {
+    None,
+    Class,
+    Subclass,
+}
+
+// Resolver: A static pass run between parsing and interpreting. It works out which
+// declaration each variable refers to and records the slot of every local, keyed by the id
+// of the expression or declaration. Anything left out of the table is a global. It also
+// rejects what the grammar allows but the language doesn't: assignments to constants,
+// `this` and `super` outside the classes that give them meaning, and returning a value from
+// an initializer or yielding from one. And it warns about what is allowed but likely a
+// mistake: local variables that are never used, or that shadow another of the same name,
+// `if` conditions that are always true or always false, statements after a `return` or
+// `throw`, which never run, and loops over an empty list, map or string. A name starting
+// with `_` is never warned about.
+pub struct Resolver {
+    scopes: Vec<Scope>,
+    slots: HashMap<NodeId, Slot>,
+    global_constants: HashSet<String>,
+    function: FunctionKind,
+    class: ClassKind,
+    // The errors and warnings found so far, in the order they were found.
+    diagnostics: Diagnostics,
+    // The locals in scope at each statement, if they are being recorded.
+    names: Option<Names>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Resolver {
+            scopes: Vec::new(),
+            slots: HashMap::new(),
+            global_constants: HashSet::new(),
+            function: FunctionKind::None,
+            class: ClassKind::None,
+            diagnostics: Diagnostics::new(),
+            names: None,
+        }
+    }
+
+    // resolve: Resolve a program, returning the slot of every local use and declaration, or
+    // the errors found, combined into one.
+    pub fn resolve(mut self, statements: &[Stmt]) -> Result<Slots, LoxError> {
+        self.program(statements)?;
+        Ok(self.slots)
+    }
+
+    // check: Resolve a program only to check it, returning the warnings about it, or the
+    // errors found, combined into one.
+    pub fn check(mut self, statements: &[Stmt]) -> Result<Vec<LoxError>, LoxError> {
+        self.program(statements)
+    }
+
+    // resolve_with_names: Like resolve, but also record the names of the locals in scope at
+    // every statement, for a debugger.
+    pub fn resolve_with_names(
+        mut self,
+        statements: &[Stmt],
+    ) -> Result<(Slots, Names), LoxError> {
+        self.names = Some(Names::new());
+        self.program(statements)?;
+        Ok((self.slots, self.names.unwrap_or_default()))
+    }
+
+    fn program(&mut self, statements: &[Stmt]) -> Result<Vec<LoxError>, LoxError> {
+        // Global constants are collected up front, so that a function declared before a
+        // constant still can't assign to it.
+        for stmt in statements {
+            if let StmtKind::Var {
+                name,
+                constant: true,
+                ..
+            } = &stmt.kind
+            {
+                self.global_constants.insert(format!("{}", name.typ));
+            }
+        }
+        self.statements(statements);
+        // Some are found out of order: an assignment's value is resolved before its target.
+        let mut diagnostics = std::mem::take(&mut self.diagnostics);
+        diagnostics.sort();
+        let warnings = diagnostics.take_warnings();
+        diagnostics.finish(warnings)
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) {
+        let mut exited = None;
+        for stmt in statements {
+            // Only the first statement that can't be reached is warned about.
+            if let Some(keyword) = exited.take() {
+                self.warn(
+                    stmt.line,
+                    stmt.span,
+                    message!(LX0804, "Unreachable code after '{}'", keyword),
+                );
+            }
+            self.statement(stmt);
+            if let StmtKind::Return { keyword, .. } | StmtKind::Throw { keyword, .. } = &stmt.kind {
+                exited = exited.or(Some(&keyword.typ));
+            }
+        }
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        if let Some(names) = &mut self.names {
+            let mut visible = Vec::new();
+            for (depth, scope) in self.scopes.iter().rev().enumerate() {
+                let mut locals: Vec<_> = scope.names.iter().collect();
+                locals.sort_by_key(|&(_, &index)| index);
+                for (name, &index) in locals {
+                    visible.push((name.clone(), Slot { depth, index }));
+                }
+            }
+            names.insert(stmt.id, visible);
+        }
+        match &stmt.kind {
+            StmtKind::Expression(expr) | StmtKind::Print(expr) => self.expression(expr),
+            StmtKind::Var {
+                name,
+                initializer,
+                constant,
+            } => {
+                // The initializer is resolved first, so `var a = a;` in a block reads the
+                // outer `a`.
+                if let Some(initializer) = initializer {
+                    self.expression(initializer);
+                }
+                let key = format!("{}", name.typ);
+                let lint = !key.starts_with('_');
+                if lint && self.scopes.iter().any(|scope| scope.names.contains_key(&key)) {
+                    self.warn(
+                        stmt.line,
+                        name.span(),
+                        message!(LX0802, "Variable '{}' shadows another of the same name", key),
+                    );
+                }
+                self.declare(stmt.id, name);
+                if let Some(scope) = self.scopes.last_mut() {
+                    let index = scope.count - 1;
+                    if *constant {
+                        scope.constants.insert(index);
+                    }
+                    if lint {
+                        scope.unused.insert(index, name.clone());
+                    }
+                }
+            }
+            StmtKind::Function(decl) => {
+                // Declared before the body is resolved, so a local function can call itself.
+                self.declare(stmt.id, &decl.name);
+                self.function(decl, FunctionKind::Function);
+            }
+            StmtKind::Class {
+                name,
+                superclass,
+                mixins,
+                methods,
+                getters,
+                setters,
+            } => {
+                self.declare(stmt.id, name);
+                for mixin in mixins {
+                    if let ExprKind::Variable(mixin_name) = &mixin.kind {
+                        if mixin_name.typ == name.typ {
+                            self.error(mixin_name, message!(LX0405, "A class can't mix in itself"));
+                        }
+                    }
+                    self.expression(mixin);
+                }
+                let enclosing = std::mem::replace(&mut self.class, ClassKind::Class);
+                // Methods close over a scope holding the superclass, if there is one, and are
+                // bound to an instance by wrapping that in a scope holding `this`.
+                if let Some(superclass) = superclass {
+                    if let ExprKind::Variable(superclass_name) = &superclass.kind {
+                        if superclass_name.typ == name.typ {
+                            let message = message!(LX0405, "A class can't inherit from itself");
+                            self.error(superclass_name, message);
+                        }
+                    }
+                    self.expression(superclass);
+                    self.class = ClassKind::Subclass;
+                    self.scopes.push(Scope::default());
+                    self.add_local(&Token::new(TokenType::Super, name.line));
+                }
+                self.scopes.push(Scope::default());
+                self.add_local(&Token::new(TokenType::This, name.line));
+                for method in methods {
+                    let kind = if format!("{}", method.name.typ) == "init" {
+                        FunctionKind::Initializer
+                    } else {
+                        FunctionKind::Method
+                    };
+                    self.function(method, kind);
+                }
+                for (kind, accessors) in [("getter", getters), ("setter", setters)].iter() {
+                    for accessor in accessors.iter() {
+                        if methods
+                            .iter()
+                            .any(|method| method.name.typ == accessor.name.typ)
+                        {
+                            self.error(
+                                &accessor.name,
+                                message!(LX0413, "A method and a {} can't share a name", kind),
+                            );
+                        }
+                        self.function(accessor, FunctionKind::Method);
+                    }
+                }
+                self.scopes.pop();
+                if superclass.is_some() {
+                    self.scopes.pop();
+                }
+                self.class = enclosing;
+            }
+            StmtKind::Block(statements) => self.scoped(&[], false, statements),
+            StmtKind::If {
+                keyword,
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                if let Some(truthy) = constant_truth(condition) {
+                    self.warn(
+                        stmt.line,
+                        keyword.span(),
+                        message!(LX0803, "Condition is always {}", truthy),
+                    );
+                }
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            StmtKind::Return { keyword, value } => {
+                if let Some(value) = value {
+                    if self.function == FunctionKind::Initializer {
+                        let message = message!(LX0402, "Can't return a value from an initializer");
+                        self.error(keyword, message);
+                    }
+                    self.expression(value);
+                }
+            }
+            StmtKind::Yield { keyword, value } => {
+                // An initializer always returns `this`, so it can't be a generator.
+                if self.function == FunctionKind::Initializer {
+                    self.error(keyword, message!(LX0402, "Can't yield from an initializer"));
+                }
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            StmtKind::Throw { value, .. } => self.expression(value),
+            StmtKind::Try {
+                body,
+                name,
+                handler,
+            } => {
+                self.scoped(&[], false, body);
+                self.scoped(&[name], false, handler);
+            }
+            StmtKind::ForIn {
+                keyword,
+                name,
+                iterable,
+                body,
+            } => {
+                let empty = match &iterable.kind {
+                    ExprKind::List(elements) => elements.is_empty(),
+                    ExprKind::Map { entries, .. } => entries.is_empty(),
+                    ExprKind::Literal(Literal::Str(s)) => s.is_empty(),
+                    _ => false,
+                };
+                if empty {
+                    self.warn(
+                        stmt.line,
+                        keyword.span(),
+                        message!(LX0805, "Loop never runs, as what it iterates over is empty"),
+                    );
+                }
+                self.expression(iterable);
+                self.scoped(&[name], false, std::slice::from_ref(body));
+            }
+            StmtKind::While {
+                keyword,
+                condition,
+                body,
+            } => {
+                self.loop_condition(keyword, Some(condition));
+                self.expression(condition);
+                self.statement(body);
+            }
+            // The initializer's variable is in a scope of its own, around the whole loop.
+            StmtKind::For {
+                keyword,
+                initializer,
+                condition,
+                increment,
+                body,
+            } => {
+                self.loop_condition(keyword, condition.as_ref());
+                self.begin_scope(&[], false);
+                if let Some(initializer) = initializer {
+                    self.statement(initializer);
+                }
+                for clause in condition.iter().chain(increment) {
+                    self.expression(clause);
+                }
+                self.statement(body);
+                self.end_scope();
+            }
+        }
+    }
+
+    // loop_condition: Warn about a loop whose condition is always false, so that its body never
+    // runs. One that is always true is how an endless loop is written, so it is left alone.
+    fn loop_condition(&mut self, keyword: &Token, condition: Option<&Expr>) {
+        if condition.and_then(constant_truth) == Some(false) {
+            self.warn(
+                keyword.line,
+                keyword.span(),
+                message!(LX0805, "Loop never runs, as its condition is always false"),
+            );
+        }
+    }
+
+    // function: Resolve a function or method body, in a scope that starts out holding the
+    // parameters.
+    fn function(&mut self, decl: &FunctionDecl, kind: FunctionKind) {
+        let enclosing = std::mem::replace(&mut self.function, kind);
+        let params: Vec<&Token> = decl.params.iter().collect();
+        self.scoped(&params, true, &decl.body);
+        self.function = enclosing;
+    }
+
+    // scoped: Resolve statements in a new scope that starts out holding `locals`, which are
+    // warned about if they go unused when they are `params`.
+    fn scoped(&mut self, locals: &[&Token], params: bool, statements: &[Stmt]) {
+        self.begin_scope(locals, params);
+        self.statements(statements);
+        self.end_scope();
+    }
+
+    fn begin_scope(&mut self, locals: &[&Token], params: bool) {
+        self.scopes.push(Scope {
+            params: if params { locals.len() } else { 0 },
+            ..Scope::default()
+        });
+        for local in locals {
+            let index = self.add_local(local);
+            if params && !format!("{}", local.typ).starts_with('_') {
+                let scope = self.scopes.last_mut().expect("the scope just pushed");
+                scope.unused.insert(index, (*local).clone());
+            }
+        }
+    }
+
+    // end_scope: Leave the innermost scope, warning about the locals in it that were never
+    // used.
+    fn end_scope(&mut self) {
+        let scope = self.scopes.pop().expect("the scope just pushed");
+        for (&index, name) in &scope.unused {
+            let (line, span) = (name.line, name.span());
+            if index < scope.params {
+                self.warn(line, span, message!(LX0801, "Unused parameter '{}'", name.typ));
+            } else {
+                self.warn(line, span, message!(LX0801, "Unused variable '{}'", name.typ));
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Literal(_) => {}
+            ExprKind::Grouping(inner) => self.expression(inner),
+            ExprKind::List(elements) => {
+                for element in elements {
+                    self.expression(element);
+                }
+            }
+            ExprKind::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            ExprKind::Variable(name) => {
+                self.reference(expr.id, name);
+            }
+            ExprKind::Assign { name, value } => {
+                self.expression(value);
+                if self.reference(expr.id, name) {
+                    self.error(name, message!(LX0302, "Can't assign to a constant"));
+                }
+            }
+            ExprKind::Unary { right, .. } => self.expression(right),
+            ExprKind::Binary { left, right, .. } | ExprKind::Logical { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            ExprKind::Call {
+                callee, arguments, ..
+            } => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            ExprKind::Get { object, .. } => self.expression(object),
+            ExprKind::Set { object, value, .. } => {
+                self.expression(value);
+                self.expression(object);
+            }
+            ExprKind::This(keyword) => {
+                if self.class == ClassKind::None {
+                    self.error(keyword, message!(LX0403, "Can't use 'this' outside of a class"));
+                }
+                self.reference(expr.id, keyword);
+            }
+            ExprKind::Super { keyword, .. } => match self.class {
+                ClassKind::None => {
+                    self.error(keyword, message!(LX0404, "Can't use 'super' outside of a class"))
+                }
+                ClassKind::Class => {
+                    self.error(
+                        keyword,
+                        message!(LX0404, "Can't use 'super' in a class with no superclass"),
+                    )
+                }
+                ClassKind::Subclass => {
+                    self.reference(expr.id, keyword);
+                }
+            },
+            ExprKind::Index { object, index, .. } => {
+                self.expression(object);
+                self.expression(index);
+            }
+            ExprKind::Slice {
+                object, start, end, ..
+            } => {
+                self.expression(object);
+                for bound in start.iter().chain(end.iter()) {
+                    self.expression(bound);
+                }
+            }
+            ExprKind::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.expression(object);
+                self.expression(index);
+                self.expression(value);
+            }
+            ExprKind::Match { subject, arms, .. } => {
+                self.expression(subject);
+                for arm in arms {
+                    // A binding is the only local of a scope around its arm.
+                    if let Pattern::Binding(name) = &arm.pattern {
+                        self.scopes.push(Scope::default());
+                        self.add_local(name);
+                        self.expression(&arm.body);
+                        self.scopes.pop();
+                    } else {
+                        self.expression(&arm.body);
+                    }
+                }
+            }
+        }
+    }
+
+    // declare: Record the slot of a declaration, unless it is at the top level and so
+    // defines a global.
+    fn declare(&mut self, id: NodeId, name: &Token) {
+        if !self.scopes.is_empty() {
+            let index = self.add_local(name);
+            self.slots.insert(id, Slot { depth: 0, index });
+        }
+    }
+
+    fn add_local(&mut self, name: &Token) -> usize {
+        let scope = self.scopes.last_mut().expect("a local scope");
+        let index = scope.count;
+        scope.names.insert(format!("{}", name.typ), index);
+        scope.count += 1;
+        index
+    }
+
+    // reference: Record the slot a use of `name` refers to, searching outwards from the
+    // innermost scope. Function bodies search on into the scopes around their declaration,
+    // which is where their closure points at run time. Returns whether the variable found
+    // is a constant.
+    fn reference(&mut self, id: NodeId, name: &Token) -> bool {
+        let key = format!("{}", name.typ);
+        for (depth, scope) in self.scopes.iter_mut().rev().enumerate() {
+            if let Some(&index) = scope.names.get(&key) {
+                scope.unused.remove(&index);
+                self.slots.insert(id, Slot { depth, index });
+                return scope.constants.contains(&index);
+            }
+        }
+        self.global_constants.contains(&key)
+    }
+
+    // error: Record an error at `token`, in the parser's format.
+    fn error(&mut self, token: &Token, message: Message) {
+        let location = format!("[line {}] Error at '{}': ", token.line, token.typ);
+        let err = LoxError::located(&location, message);
+        self.diagnostics.push(err.at(token.span()).of_kind(ErrorKind::Parse));
+    }
+
+    // warn: Record a warning about `line`, pointing at `span` if there is one.
+    fn warn(&mut self, line: usize, span: Option<Span>, message: Message) {
+        let warning = LoxError::located(&format!("[line {}] ", line), message);
+        self.diagnostics.warn(warning.at(span).of_kind(ErrorKind::Parse));
+    }
+}
+
+// constant_truth: Whether `condition` is always true or always false, if it is a literal.
+fn constant_truth(condition: &Expr) -> Option<bool> {
+    match &condition.kind {
+        ExprKind::Grouping(inner) => constant_truth(inner),
+        ExprKind::Literal(Literal::Nil) | ExprKind::Literal(Literal::Bool(false)) => Some(false),
+        ExprKind::Literal(_) => Some(true),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{Expr, ExprKind, Stmt, StmtKind};
+    use crate::error::{LoxError, Span};
+    use crate::parser::Parser;
+    use crate::resolver::{Resolver, Slot};
+    use crate::scanner::Scanner;
+
+    // slots_of_uses: Resolve `src` and list where each use of a variable was resolved to,
+    // in source order. Globals show up as None.
+    fn slots_of_uses(src: &str) -> Result<Vec<(String, Option<Slot>)>, LoxError> {
+        let mut scanner = Scanner::new(src);
+        let statements = Parser::new(scanner.scan_tokens()?).parse()?;
+        let mut uses = Vec::new();
+        collect_uses(&statements, &mut uses);
+        let slots = Resolver::new().resolve(&statements)?;
+        Ok(uses
+            .into_iter()
+            .map(|expr| match &expr.kind {
+                ExprKind::Variable(name) => (format!("{}", name.typ), slots.get(&expr.id).copied()),
+                _ => unreachable!(),
+            })
+            .collect())
+    }
+
+    // collect_uses: The variable expressions in statements that are simple enough for these
+    // tests: expression statements, prints, blocks, functions and returns.
+    fn collect_uses<'a>(statements: &'a [Stmt], uses: &mut Vec<&'a Expr>) {
+        for stmt in statements {
+            match &stmt.kind {
+                StmtKind::Expression(expr) | StmtKind::Print(expr) => {
+                    if let ExprKind::Variable(_) = expr.kind {
+                        uses.push(expr);
+                    }
+                }
+                StmtKind::Return {
+                    value: Some(expr), ..
+                } => uses.push(expr),
+                StmtKind::Block(body) => collect_uses(body, uses),
+                StmtKind::Function(decl) => collect_uses(&decl.body, uses),
+                _ => {}
+            }
+        }
+    }
+
+    fn local(depth: usize, index: usize) -> Option<Slot> {
+        Some(Slot { depth, index })
+    }
+
+    #[test]
+    fn top_level_names_are_global() -> Result<(), LoxError> {
+        assert_eq!(
+            slots_of_uses("var a; a; b;")?,
+            vec![("a".to_string(), None), ("b".to_string(), None)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn block_locals_get_slots() -> Result<(), LoxError> {
+        let got = slots_of_uses("var g; { var a; var b; b; { var c; a; c; g; } }")?;
+        let want = vec![
+            ("b".to_string(), local(0, 1)),
+            ("a".to_string(), local(1, 0)),
+            ("c".to_string(), local(0, 0)),
+            ("g".to_string(), None),
+        ];
+        assert_eq!(got, want);
+        Ok(())
+    }
+
+    #[test]
+    fn use_before_declaration_is_outer() -> Result<(), LoxError> {
+        let got = slots_of_uses("{ var a; { a; var a; a; } }")?;
+        assert_eq!(
+            got,
+            vec![
+                ("a".to_string(), local(1, 0)),
+                ("a".to_string(), local(0, 0))
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn redeclaration_takes_a_new_slot() -> Result<(), LoxError> {
+        assert_eq!(
+            slots_of_uses("{ var a; var a; a; }")?,
+            vec![("a".to_string(), local(0, 1))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn functions_see_enclosing_locals() -> Result<(), LoxError> {
+        let got =
+            slots_of_uses("{ var outer; fun f(a, b) { var c; b; c; outer; f; g; return a; } }")?;
+        let want = vec![
+            ("b".to_string(), local(0, 1)),
+            ("c".to_string(), local(0, 2)),
+            ("outer".to_string(), local(1, 0)),
+            ("f".to_string(), local(1, 1)),
+            ("g".to_string(), None),
+            ("a".to_string(), local(0, 0)),
+        ];
+        assert_eq!(got, want);
+        Ok(())
+    }
+
+    // resolve_err: Resolve `src`, which must fail, and return the error message.
+    fn resolve_err(src: &str) -> String {
+        let mut scanner = Scanner::new(src);
+        let tokens = scanner.scan_tokens().expect("scans");
+        let statements = Parser::new(tokens).parse().expect("parses");
+        match Resolver::new().resolve(&statements) {
+            Ok(_) => panic!("{:?} should not resolve", src),
+            Err(err) => format!("{}", err),
+        }
+    }
+
+    #[test]
+    fn constants_resolve_like_variables() -> Result<(), LoxError> {
+        let got = slots_of_uses("const g = 1; { const a = g; a; }")?;
+        assert_eq!(got, vec![("a".to_string(), local(0, 0))]);
+        Ok(())
+    }
+
+    #[test]
+    fn assigning_a_constant_is_an_error() {
+        let err = resolve_err("const a = 1;\na = 2;");
+        assert_eq!(err, "[line 2] Error at 'a': Can't assign to a constant.");
+        assert!(resolve_err("{ const a = 1; { a = 2; } }").contains("Error at 'a'"));
+        assert!(resolve_err("fun f() { k = 2; } const k = 1;").contains("Error at 'k'"));
+        assert!(resolve_err("{ const c = 1; fun f() { c = 2; } }").contains("Error at 'c'"));
+    }
+
+    #[test]
+    fn this_and_super_need_a_class() {
+        assert!(
+            resolve_err("this;").contains("Error at 'this': Can't use 'this' outside of a class")
+        );
+        assert!(resolve_err("fun f() { return this; }").contains("Can't use 'this' outside"));
+        assert!(resolve_err("super.f();").contains("Can't use 'super' outside of a class"));
+        let err = resolve_err("class A { f() { super.f(); } }");
+        assert!(
+            err.contains("Can't use 'super' in a class with no superclass"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn class_errors() {
+        let err = resolve_err("class A < A {}");
+        assert_eq!(
+            err,
+            "[line 1] Error at 'A': A class can't inherit from itself."
+        );
+        let err = resolve_err("class A with B, A {}");
+        assert!(
+            err.contains("Error at 'A': A class can't mix in itself"),
+            "{}",
+            err
+        );
+        let err = resolve_err("class A { x() {} set x(v) {} }");
+        assert!(
+            err.contains("Error at 'x': A method and a setter can't share a name"),
+            "{}",
+            err
+        );
+        let err = resolve_err("class A { x {} x() {} }");
+        assert!(
+            err.contains("Error at 'x': A method and a getter can't share a name"),
+            "{}",
+            err
+        );
+        let err = resolve_err("class A { init() { return 1; } }");
+        assert!(err.contains("Error at 'return': Can't return a value from an initializer"));
+        let err = resolve_err("class A { init() { yield 1; } }");
+        assert!(err.contains("Error at 'yield': Can't yield from an initializer"));
+    }
+
+    #[test]
+    fn every_error_is_reported() {
+        let mut scanner = Scanner::new("this;\nconst a = 1;\na = 2;");
+        let statements = Parser::new(scanner.scan_tokens().unwrap()).parse().unwrap();
+        let err = Resolver::new().resolve(&statements).unwrap_err();
+        let messages: Vec<_> = err.into_errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "[line 1] Error at 'this': Can't use 'this' outside of a class.",
+                "[line 3] Error at 'a': Can't assign to a constant.",
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_are_reported_in_source_order() {
+        let mut scanner = Scanner::new("const a = 1;\na = this;");
+        let statements = Parser::new(scanner.scan_tokens().unwrap()).parse().unwrap();
+        let err = Resolver::new().resolve(&statements).unwrap_err();
+        let messages: Vec<_> = err.into_errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "[line 2] Error at 'a': Can't assign to a constant.",
+                "[line 2] Error at 'this': Can't use 'this' outside of a class.",
+            ]
+        );
+    }
+
+    // warnings: The messages of the warnings about `src`, which must resolve.
+    fn warnings(src: &str) -> Vec<String> {
+        let mut scanner = Scanner::new(src);
+        let statements = Parser::new(scanner.scan_tokens().unwrap()).parse().unwrap();
+        let warnings = Resolver::new().check(&statements).expect("resolves");
+        warnings.iter().map(|w| w.message().to_string()).collect()
+    }
+
+    #[test]
+    fn unused_locals_are_warned_about() {
+        assert_eq!(
+            warnings("var g = 1;\n{ var a = 1; var b = 2; var _c = 3; b = a; }"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            warnings("fun f(x) {\n  const unused = 1;\n}"),
+            vec![
+                "[line 1] Unused parameter 'x'",
+                "[line 2] Unused variable 'unused'"
+            ]
+        );
+        assert!(warnings("fun f(x, _y) { return x; }\nclass A { m(_) {} }").is_empty());
+    }
+
+    #[test]
+    fn shadowing_is_warned_about() {
+        assert_eq!(
+            warnings("fun f(a) { print a;\n  var a = 1;\n  { var b = a; var a = b; print a; }\n}"),
+            vec![
+                "[line 2] Variable 'a' shadows another of the same name",
+                "[line 3] Variable 'a' shadows another of the same name",
+            ]
+        );
+        // Globals and loop variables don't count.
+        assert!(warnings("var a = 1;\n{ var a = 2; print a; }").is_empty());
+        assert!(warnings("for (var x in [1]) { var _x = x; }").is_empty());
+    }
+
+    #[test]
+    fn constant_conditions_are_warned_about() {
+        assert_eq!(
+            warnings("if ((nil)) print 1;\nif (\"\") print 2; else print 3;"),
+            vec![
+                "[line 1] Condition is always false",
+                "[line 2] Condition is always true",
+            ]
+        );
+        assert!(warnings("var a = true;\nif (a) print 1;").is_empty());
+        // They point at the `if`, as a literal has no place in the source of its own.
+        let mut scanner = Scanner::new("{\n  if (nil) print 1;\n}");
+        let statements = Parser::new(scanner.scan_tokens().unwrap()).parse().unwrap();
+        let warnings = Resolver::new().check(&statements).expect("resolves");
+        let span = Span {
+            line: 2,
+            column: 3,
+            len: 2,
+        };
+        assert_eq!(warnings[0].span(), Some(span));
+    }
+
+    #[test]
+    fn unreachable_code_is_warned_about() {
+        assert_eq!(
+            warnings("fun f() {\n  return 1;\n  print 2;\n  print 3;\n}\nthrow 4;\nprint 5;"),
+            vec![
+                "[line 3] Unreachable code after 'return'",
+                "[line 7] Unreachable code after 'throw'",
+            ]
+        );
+        assert!(warnings("fun f(a) {\n  if (a) return 1;\n  return 2;\n}").is_empty());
+        // The warning points at the first token of the statement that can't be reached.
+        let mut scanner = Scanner::new("fun f() {\n  return;\n  x = 1;\n}");
+        let statements = Parser::new(scanner.scan_tokens().unwrap()).parse().unwrap();
+        let warnings = Resolver::new().check(&statements).expect("resolves");
+        let span = Span {
+            line: 3,
+            column: 3,
+            len: 1,
+        };
+        assert_eq!(warnings[0].span(), Some(span));
+    }
+
+    #[test]
+    fn loops_that_never_run_are_warned_about() {
+        assert_eq!(
+            warnings("for (var x in []) print x;\nfor (var c in \"\") print c;"),
+            vec![
+                "[line 1] Loop never runs, as what it iterates over is empty",
+                "[line 2] Loop never runs, as what it iterates over is empty",
+            ]
+        );
+        assert!(warnings("for (var x in [1]) print x;\nfor (var k in {1: 2}) print k;").is_empty());
+        assert_eq!(
+            warnings("while (false) print 1;\nfor (; (false);) print 2;"),
+            vec![
+                "[line 1] Loop never runs, as its condition is always false",
+                "[line 2] Loop never runs, as its condition is always false",
+            ]
+        );
+        assert!(warnings("while (true) print 1;\nfor (;;) print 2;").is_empty());
+    }
+
+    #[test]
+    fn initializers_may_return_early() -> Result<(), LoxError> {
+        slots_of_uses("class A { init() { fun f() { return 1; } return; } }")?;
+        Ok(())
+    }
+
+    #[test]
+    fn shadowing_a_constant_is_allowed() -> Result<(), LoxError> {
+        for src in &[
+            "const a = 1; { var a = 2; a = 3; }",
+            "{ const a = 1; var a = 2; a = 3; }",
+            "const a = 1; fun f(a) { a = 2; }",
+            "{ const a = 1; } var a; a = 2;",
+        ] {
+            slots_of_uses(src)?;
+        }
+        Ok(())
+    }
+}
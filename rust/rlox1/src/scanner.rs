@@ -1,5 +1,7 @@
-use crate::error::LoxError;
+use crate::diagnostic::Diagnostics;
+use crate::error::{ErrorKind, LoxError, Span};
 use std::fmt;
+use std::ops::Range;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenType {
@@ -8,6 +10,9 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
+    Colon,
     Comma,
     Dot,
     Minus,
@@ -25,6 +30,7 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    Arrow,
 
     // literals
     Identifier(String),
@@ -49,9 +55,107 @@ pub enum TokenType {
     Var,
     While,
 
+    // extension keywords
+    Catch,
+    Const,
+    Match,
+    Throw,
+    Try,
+    With,
+    Yield,
+
     Eof,
 }
 
+impl fmt::Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lexeme = match self {
+            TokenType::LeftParen => "(",
+            TokenType::RightParen => ")",
+            TokenType::LeftBrace => "{",
+            TokenType::RightBrace => "}",
+            TokenType::LeftBracket => "[",
+            TokenType::RightBracket => "]",
+            TokenType::Colon => ":",
+            TokenType::Comma => ",",
+            TokenType::Dot => ".",
+            TokenType::Minus => "-",
+            TokenType::Plus => "+",
+            TokenType::Semicolon => ";",
+            TokenType::Slash => "/",
+            TokenType::Star => "*",
+            TokenType::Bang => "!",
+            TokenType::BangEqual => "!=",
+            TokenType::Equal => "=",
+            TokenType::EqualEqual => "==",
+            TokenType::Greater => ">",
+            TokenType::GreaterEqual => ">=",
+            TokenType::Less => "<",
+            TokenType::LessEqual => "<=",
+            TokenType::Arrow => "->",
+            TokenType::Identifier(name) => return write!(f, "{}", name),
+            TokenType::QuotedString(s) => return write!(f, "\"{}\"", s),
+            TokenType::Number(n) => return write!(f, "{}", n),
+            TokenType::And => "and",
+            TokenType::Class => "class",
+            TokenType::Else => "else",
+            TokenType::False => "false",
+            TokenType::Fun => "fun",
+            TokenType::For => "for",
+            TokenType::If => "if",
+            TokenType::Nil => "nil",
+            TokenType::Or => "or",
+            TokenType::Print => "print",
+            TokenType::Return => "return",
+            TokenType::Super => "super",
+            TokenType::This => "this",
+            TokenType::True => "true",
+            TokenType::Var => "var",
+            TokenType::While => "while",
+            TokenType::Catch => "catch",
+            TokenType::Const => "const",
+            TokenType::Match => "match",
+            TokenType::Throw => "throw",
+            TokenType::Try => "try",
+            TokenType::With => "with",
+            TokenType::Yield => "yield",
+            TokenType::Eof => "end",
+        };
+        write!(f, "{}", lexeme)
+    }
+}
+
+impl TokenType {
+    pub fn is_keyword(&self) -> bool {
+        matches!(
+            self,
+            TokenType::And
+                | TokenType::Class
+                | TokenType::Else
+                | TokenType::False
+                | TokenType::Fun
+                | TokenType::For
+                | TokenType::If
+                | TokenType::Nil
+                | TokenType::Or
+                | TokenType::Print
+                | TokenType::Return
+                | TokenType::Super
+                | TokenType::This
+                | TokenType::True
+                | TokenType::Var
+                | TokenType::While
+                | TokenType::Catch
+                | TokenType::Const
+                | TokenType::Match
+                | TokenType::Throw
+                | TokenType::Try
+                | TokenType::With
+                | TokenType::Yield
+        )
+    }
+}
+
 impl From<String> for TokenType {
     fn from(other: String) -> TokenType {
         TokenType::Identifier(other)
@@ -87,52 +191,37 @@ where
     Ok(buf.to_string())
 }
 
-fn scan_number(data: &[char], start_index: usize) -> Result<TokenType, LoxError> {
-    if let Ok(num) = take_while(data, start_index, |ch| ch == '.' || ch.is_digit(10)) {
-        match num.parse::<f64>() {
-            Ok(num) => Ok(TokenType::Number(num)),
-            Err(msg) => loxerr!(msg),
-        }
-    } else {
-        loxerr!("Expected number but didn't find one.")
-    }
+fn scan_number(data: &[char], start_index: usize) -> Result<f64, LoxError> {
+    let num = take_while(data, start_index, |ch| ch == '.' || ch.is_ascii_digit())?;
+    num.parse::<f64>()
+        .map_err(|err| lox_error!(LX0103, "Invalid number '{}'", num).caused_by(err))
 }
 
-fn scan_identifier(data: &[char], start_index: usize) -> Result<TokenType, LoxError> {
+fn scan_identifier(data: &[char], start_index: usize) -> Result<String, LoxError> {
     if data[start_index] != '_' && !data[start_index].is_alphabetic() {
-        loxerr!("Expected identifier, found number.")
-    } else if let Ok(ident) = take_while(data, start_index, |ch| ch == '_' || ch.is_alphanumeric()) {
-	Ok(TokenType::Identifier(ident.to_string()))
-    } else {
-        loxerr!("Expected identifer but did not find one.")
+        loxerr!(LX0102, "An identifier can't start with '{}'", data[start_index])
     }
+    take_while(data, start_index, |ch| ch == '_' || ch.is_alphanumeric())
 }
 
-fn scan_quoted_string(data: &[char], start_index: usize) -> Result<(TokenType, usize), LoxError> {
+fn scan_quoted_string(data: &[char], start_index: usize) -> Result<(String, usize), LoxError> {
     let mut line_count = 0;
     let mut prev_ch = '1';
-    let tok = take_while(data, start_index, |ch| {
-        let result;
-        if ch == '\n' { line_count += 1 };
-        if ch != '"' {
-            result = true;
-        } else if prev_ch == '\\' {
-            result = true;
-        } else {
-            result = false;
-        }
+    let qstr = take_while(data, start_index, |ch| {
+        if ch == '\n' {
+            line_count += 1
+        };
+        let result = ch != '"' || prev_ch == '\\';
         prev_ch = ch;
         result
-    });
-    if let Ok(qstr) = tok {
-        if start_index + qstr.len() >= data.len() || '\"' != data[start_index + qstr.len()] {
-            // We didn't see a closing double-quote.
-            loxerr!("Missing end-quote: idx={}, len={}.", start_index + qstr.len(), data.len())
-         }
-        Ok((TokenType::QuotedString(qstr),line_count))
-    } else {
-        loxerr!("Expected quoted string, did not find one: \"{:?}\".", tok)
+    })?;
+    let end_index = start_index + qstr.chars().count();
+    if end_index >= data.len() || '\"' != data[end_index] {
+        // We didn't see a closing double-quote.
+        let at = format!(": idx={}, len={}.", end_index, data.len());
+        loxerr!(LX0101, "Missing end-quote{}", at)
     }
+    Ok((qstr, line_count))
 }
 
 macro_rules! scanner_test {
@@ -154,7 +243,7 @@ macro_rules! scanner_test {
             let exp_str = $should_be;
             let should_be = TokenType::from(exp_str);
             let func = $func;
-            let got = func(&src.chars().collect::<Vec<char>>(), 0).unwrap();
+            let got = TokenType::from(func(&src.chars().collect::<Vec<char>>(), 0).unwrap());
             assert_eq!(got, should_be, "Input was {:?}", src);
         }
     };
@@ -185,12 +274,12 @@ scanner_test!(
     ".foo_bar"
 );
 
-scanner_test!(scan_qstring_full, scan_quoted_string, "hello\"" => (TokenType::QuotedString(String::from("hello")),0));
+scanner_test!(scan_qstring_full, scan_quoted_string, "hello\"" => (String::from("hello"), 0));
 scanner_test!(FAIL: scan_qstring_partial, scan_quoted_string, "hello");
-scanner_test!(scan_empty_string, scan_quoted_string, "\"" => (TokenType::QuotedString(String::from("")),0));
+scanner_test!(scan_empty_string, scan_quoted_string, "\"" => (String::new(), 0));
 scanner_test!(scan_multiline_string,
               scan_quoted_string,
-              "a\nb\nc\"" => (TokenType::QuotedString(String::from("a\nb\nc")), 2)
+              "a\nb\nc\"" => (String::from("a\nb\nc"), 2)
 );
 
 scanner_test!(FROM: scan_number_integer, scan_number, "1234" => 1234.0);
@@ -198,15 +287,42 @@ scanner_test!(FROM: scan_number_float, scan_number, "1234.5" => 1234.5);
 scanner_test!(FAIL: scan_number_two_dots, scan_number, "1234.5.6");
 scanner_test!(FROM: scan_number_float_alpha, scan_number, "1234.5ab" => 1234.5);
 
-#[derive(Clone,Debug)]
+// KEYWORDS: Every reserved word, extensions included.
+pub const KEYWORDS: [&str; 23] = [
+    "and", "class", "else", "false", "fun", "for", "if", "nil", "or", "print", "return", "super",
+    "this", "true", "var", "while", "catch", "const", "match", "throw", "try", "with", "yield",
+];
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Token {
     pub typ: TokenType,
     pub line: usize,
+    // Where on the line the token starts, counting from 1, and how many characters it takes
+    // up. Both are 0 for a token that wasn't scanned from source.
+    pub column: usize,
+    pub len: usize,
 }
 
 impl Token {
     pub fn new(typ: TokenType, line: usize) -> Self {
-        Token { typ, line }
+        Token {
+            typ,
+            line,
+            column: 0,
+            len: 0,
+        }
+    }
+
+    // span: Where the token is in the source, if it was scanned from there.
+    pub fn span(&self) -> Option<Span> {
+        if self.column == 0 {
+            return None;
+        }
+        Some(Span {
+            line: self.line,
+            column: self.column,
+            len: self.len,
+        })
     }
 }
 
@@ -216,13 +332,38 @@ impl fmt::Display for Token {
     }
 }
 
+// Comment: A `//` comment, kept for `rlox1 fmt` to put back, and where it starts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comment {
+    pub text: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+// Lexeme: What a stretch of source scanned as, for highlighting it. Scanning stops at an
+// error, which covers the rest of the source.
+#[derive(Debug, PartialEq)]
+pub enum Lexeme {
+    Token(TokenType),
+    Comment,
+    Error,
+}
+
 #[derive(Clone)]
 pub struct Scanner {
     text: Vec<char>,
     current_index: usize,
     line: usize,
-    has_error: bool,
+    // Whether the error just found was a stray character, which scanning can carry on past.
+    stray_character: bool,
+    // The comments skipped between tokens, in order.
+    comments: Vec<Comment>,
     tokens: Vec<Token>,
+    // Where the token being scanned starts, and what's needed to work out its column: the
+    // start of the line it is on, as of the `seen` characters looked through for it so far.
+    token_start: usize,
+    line_start: usize,
+    seen: usize,
 }
 
 impl Scanner {
@@ -231,21 +372,51 @@ impl Scanner {
             text: input.chars().collect::<Vec<char>>(),
             current_index: 0,
             line: 1,
-            has_error: false,
+            stray_character: false,
+            comments: Vec::new(),
             tokens: Vec::new(),
+            token_start: 0,
+            line_start: 0,
+            seen: 0,
         }
     }
 
-    pub fn errors_found(&self) -> bool {
-        self.has_error
+    // comments: The comments scanned so far, with their `//` and without any trailing spaces.
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
+    // tokens: Scan the whole source, giving up the scanner, and its copy of the source, for
+    // the tokens.
+    pub fn tokens(mut self) -> Result<Vec<Token>, LoxError> {
+        self.scan_tokens()?;
+        Ok(self.tokens)
     }
 
+    // scan_tokens: Scan the whole source. Stray characters are all reported together, but
+    // after any other error, like a string without its end-quote, the rest of the source can't
+    // be trusted to scan, and scanning stops.
     pub fn scan_tokens(&mut self) -> Result<&Vec<Token>, LoxError> {
+        let mut errors = Diagnostics::new();
         loop {
             match self.scan_token() {
-                Err(msg) => loxerr!(msg),
-                Ok(tok) => {
+                Err(err) => {
+                    // Errors are about the character the token starts with.
+                    let span = Span {
+                        line: self.line,
+                        column: self.column(self.token_start),
+                        len: 1,
+                    };
+                    errors.push(err.at(Some(span)).of_kind(ErrorKind::Scan));
+                    if !std::mem::take(&mut self.stray_character) {
+                        break;
+                    }
+                }
+                Ok(mut tok) => {
+                    tok.column = self.column(self.token_start);
+                    tok.len = self.current_index.min(self.text.len()) - self.token_start;
                     if tok.typ == TokenType::Eof {
+                        self.tokens.push(tok);
                         break;
                     } else {
                         self.tokens.push(tok);
@@ -253,19 +424,48 @@ impl Scanner {
                 }
             }
         }
-        self.tokens.push(Token {
-            typ: TokenType::Eof,
-            line: self.line,
-        });
-        if self.has_error {
-            loxerr!("{}", "Invalid input.")
-        } else {
-            Ok(&self.tokens)
+        errors.finish(&self.tokens)
+    }
+
+    // lexemes: Scan the source into lexemes, and the character ranges they come from.
+    pub fn lexemes(&mut self) -> Vec<(Range<usize>, Lexeme)> {
+        let mut lexemes = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let start = self.current_index;
+            if self.is_at_end() {
+                break;
+            } else if self.text[start..].starts_with(&['/', '/']) {
+                self.advance_line();
+                lexemes.push((start..self.current_index, Lexeme::Comment));
+                continue;
+            }
+            match self.scan_token() {
+                Ok(token) => lexemes.push((start..self.current_index, Lexeme::Token(token.typ))),
+                Err(_) => {
+                    lexemes.push((start..self.text.len(), Lexeme::Error));
+                    break;
+                }
+            }
         }
+        lexemes
+    }
+
+    // column: The column of the character at `index`, counting from 1. Tokens are scanned in
+    // order, so finding the start of the line only needs what was scanned since last time.
+    fn column(&mut self, index: usize) -> usize {
+        for i in self.seen..index {
+            if self.text[i] == '\n' {
+                self.line_start = i + 1;
+            }
+        }
+        self.seen = self.seen.max(index);
+        index - self.line_start + 1
     }
 
     fn scan_token(&mut self) -> Result<Token, LoxError> {
         self.skip_whitespace();
+        self.token_start = self.current_index;
         let line = self.line;
         let c = self.advance();
         match c {
@@ -275,9 +475,18 @@ impl Scanner {
                 ')' => Ok(Token::new(TokenType::RightParen, line)),
                 '{' => Ok(Token::new(TokenType::LeftBrace, line)),
                 '}' => Ok(Token::new(TokenType::RightBrace, line)),
+                '[' => Ok(Token::new(TokenType::LeftBracket, line)),
+                ']' => Ok(Token::new(TokenType::RightBracket, line)),
+                ':' => Ok(Token::new(TokenType::Colon, line)),
                 ',' => Ok(Token::new(TokenType::Comma, line)),
                 '.' => Ok(Token::new(TokenType::Dot, line)),
-                '-' => Ok(Token::new(TokenType::Minus, line)),
+                '-' => {
+                    if self.match_advance('>') {
+                        Ok(Token::new(TokenType::Arrow, line))
+                    } else {
+                        Ok(Token::new(TokenType::Minus, line))
+                    }
+                }
                 '+' => Ok(Token::new(TokenType::Plus, line)),
                 ';' => Ok(Token::new(TokenType::Semicolon, line)),
                 '*' => Ok(Token::new(TokenType::Star, line)),
@@ -312,56 +521,71 @@ impl Scanner {
                 '/' => {
                     if self.match_advance('/') {
                         self.advance_line();
+                        let text: String = self.text[self.token_start..self.current_index]
+                            .iter()
+                            .collect();
+                        let column = self.column(self.token_start);
+                        self.comments.push(Comment {
+                            text: text.trim_end().to_string(),
+                            line,
+                            column,
+                        });
                         self.scan_token()
                     } else {
                         Ok(Token::new(TokenType::Slash, line))
                     }
                 }
-                '"' => match scan_quoted_string(&self.text, self.current_index) {
-                    Err(msg) => loxerr!(msg),
-                    Ok(toktype) => {
-                        if let (TokenType::QuotedString(the_string),line_count) = toktype {
-                            self.current_index += the_string.len() + 1;
-                            self.line += line_count;
-                            Ok(Token::new(TokenType::QuotedString(the_string), line))
-                        } else {
-                            loxerr!("Something bad happened: {:?}.", toktype)
-                        }
-                    },
+                '"' => {
+                    let (the_string, line_count) = scan_quoted_string(&self.text, self.current_index)?;
+                    self.current_index += the_string.chars().count() + 1;
+                    self.line += line_count;
+                    Ok(Token::new(TokenType::QuotedString(the_string), line))
                 },
                 _ => {
                     if c.is_alphabetic() || c == '_' {
-                        match scan_identifier(&self.text, self.current_index-1) {
-                            Err(msg) => loxerr!(msg),
-                            Ok(toktype) => {
-				if let TokenType::Identifier(the_string) = toktype {
-                                    self.current_index += the_string.len() - 1;
-				    let toktype = match the_string.as_str() {
-					"and" => TokenType::And,
-					"return" => TokenType::Return,
-					_ => TokenType::Identifier(the_string),
-				    };
-				    Ok(Token::new(toktype, line))
-				} else {
-				    loxerr!("Something bad happened getting an identifier: {:?}", toktype)
-				}
-			    },
-                        }
-                    } else if c.is_numeric() {
-                        match scan_number(&self.text, self.current_index) {
-                            Err(msg) => loxerr!(msg),
-                            Ok(toktype) => {
-                                if let TokenType::Number(num) = toktype {
-                                    self.current_index += format!("{}",num).len();
-                                    Ok(Token::new(toktype, line))
-                                } else {
-                                    loxerr!("Something bad happened")
-                                }
-                            },
-                        }
+                        let the_string = scan_identifier(&self.text, self.current_index-1)?;
+                        self.current_index += the_string.chars().count() - 1;
+                        let toktype = match the_string.as_str() {
+                            "and" => TokenType::And,
+                            "class" => TokenType::Class,
+                            "else" => TokenType::Else,
+                            "false" => TokenType::False,
+                            "fun" => TokenType::Fun,
+                            "for" => TokenType::For,
+                            "if" => TokenType::If,
+                            "nil" => TokenType::Nil,
+                            "or" => TokenType::Or,
+                            "print" => TokenType::Print,
+                            "return" => TokenType::Return,
+                            "super" => TokenType::Super,
+                            "this" => TokenType::This,
+                            "true" => TokenType::True,
+                            "var" => TokenType::Var,
+                            "while" => TokenType::While,
+                            "catch" => TokenType::Catch,
+                            "const" => TokenType::Const,
+                            "match" => TokenType::Match,
+                            "throw" => TokenType::Throw,
+                            "try" => TokenType::Try,
+                            "with" => TokenType::With,
+                            "yield" => TokenType::Yield,
+                            _ => TokenType::Identifier(the_string),
+                        };
+                        Ok(Token::new(toktype, line))
+                    } else if c.is_ascii_digit() {
+                        let start = self.current_index - 1;
+                        let number = scan_number(&self.text, start)?;
+                        // Skip the digits actually consumed; re-formatting the parsed value
+                        // would miscount "1.0" or "007".
+                        let len = take_while(&self.text, start, |ch| {
+                            ch == '.' || ch.is_ascii_digit()
+                        })?
+                        .len();
+                        self.current_index = start + len;
+                        Ok(Token::new(TokenType::Number(number), line))
                     } else {
-                        self.has_error = true;
-                        loxerr!("Invalid character on line {}: {}", self.line, c);
+                        self.stray_character = true;
+                        loxerr!(LX0102, "Invalid character on line {}: {}", self.line, c);
                     }
                 }
             },
@@ -387,8 +611,7 @@ impl Scanner {
     }
 
     fn advance_line(&mut self) {
-	// BUG: Will fail if no more linefeeds
-        while self.peek() != Some('\n') {
+        while !self.is_at_end() && self.peek() != Some('\n') {
             self.current_index += 1;
         }
     }
@@ -420,10 +643,7 @@ macro_rules! scanner_test_tokens {
         #[test]
         fn $name() -> Result<(), LoxError> {
             let src: &str = $src;
-            let mut typs = Vec::new();
-            $(
-                typs.push($toktyp);
-            )+
+            let typs = vec![ $( $toktyp ),+ ];
             let mut scanner = Scanner::new(&String::from(src));
             let tokens = scanner.scan_tokens()?;
             // assert_eq!(tokens.len(), typs.len(), "Token lengths match?" );
@@ -452,9 +672,30 @@ scanner_test_tokens!(
     TokenType::Eof
 );
 
+scanner_test_tokens!(
+    scan_brackets,
+    "[1,]",
+    TokenType::LeftBracket,
+    TokenType::Number(1.0),
+    TokenType::Comma,
+    TokenType::RightBracket,
+    TokenType::Eof
+);
+
+scanner_test_tokens!(
+    scan_slice,
+    "a[1:]",
+    TokenType::Identifier("a".to_string()),
+    TokenType::LeftBracket,
+    TokenType::Number(1.0),
+    TokenType::Colon,
+    TokenType::RightBracket,
+    TokenType::Eof
+);
+
 scanner_test_tokens!(
     scan_two_char_tokens,
-    "! != == = < <= > >= /",
+    "! != == = < <= > >= / -> - >",
     TokenType::Bang,
     TokenType::BangEqual,
     TokenType::EqualEqual,
@@ -464,6 +705,9 @@ scanner_test_tokens!(
     TokenType::Greater,
     TokenType::GreaterEqual,
     TokenType::Slash,
+    TokenType::Arrow,
+    TokenType::Minus,
+    TokenType::Greater,
     TokenType::Eof
 );
 
@@ -501,3 +745,151 @@ scanner_test_tokens!(
     TokenType::Return,
     TokenType::Eof
 );
+
+scanner_test_tokens!(
+    test_scan_all_keywords,
+    "and class else false fun for if nil or print return super this true var while",
+    TokenType::And,
+    TokenType::Class,
+    TokenType::Else,
+    TokenType::False,
+    TokenType::Fun,
+    TokenType::For,
+    TokenType::If,
+    TokenType::Nil,
+    TokenType::Or,
+    TokenType::Print,
+    TokenType::Return,
+    TokenType::Super,
+    TokenType::This,
+    TokenType::True,
+    TokenType::Var,
+    TokenType::While,
+    TokenType::Eof
+);
+
+scanner_test_tokens!(
+    test_scan_extension_keywords,
+    "try catch throw trying const with match yield",
+    TokenType::Try,
+    TokenType::Catch,
+    TokenType::Throw,
+    TokenType::Identifier("trying".to_string()),
+    TokenType::Const,
+    TokenType::With,
+    TokenType::Match,
+    TokenType::Yield,
+    TokenType::Eof
+);
+
+scanner_test_tokens!(
+    test_scan_numbers,
+    "123 1.0 007+",
+    TokenType::Number(123.0),
+    TokenType::Number(1.0),
+    TokenType::Number(7.0),
+    TokenType::Plus,
+    TokenType::Eof
+);
+
+scanner_test_tokens!(
+    test_scan_comment_at_end,
+    "( // no trailing newline",
+    TokenType::LeftParen,
+    TokenType::Eof
+);
+
+#[cfg(test)]
+#[test]
+fn comments_are_kept() -> Result<(), LoxError> {
+    let mut scanner = Scanner::new("// first  \nvar a; // second\n  a / 2;");
+    assert_eq!(scanner.scan_tokens()?.len(), 8);
+    let comment = |text: &str, line, column| Comment {
+        text: text.to_string(),
+        line,
+        column,
+    };
+    assert_eq!(
+        scanner.comments(),
+        &[comment("// first", 1, 1), comment("// second", 2, 8)]
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn lexemes_cover_the_source() {
+    let lexemes = Scanner::new("var s = \"a b\"; // note\nprint 1.5 @ x").lexemes();
+    assert_eq!(
+        lexemes,
+        vec![
+            (0..3, Lexeme::Token(TokenType::Var)),
+            (4..5, Lexeme::Token(TokenType::Identifier("s".to_string()))),
+            (6..7, Lexeme::Token(TokenType::Equal)),
+            (8..13, Lexeme::Token(TokenType::QuotedString("a b".to_string()))),
+            (13..14, Lexeme::Token(TokenType::Semicolon)),
+            (15..22, Lexeme::Comment),
+            (23..28, Lexeme::Token(TokenType::Print)),
+            (29..32, Lexeme::Token(TokenType::Number(1.5))),
+            (33..36, Lexeme::Error),
+        ]
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn tokens_know_where_they_are() {
+    let tokens = Scanner::new("var s =\n  \"a\" + 12;").scan_tokens().unwrap().clone();
+    let spans: Vec<_> = tokens
+        .iter()
+        .map(|token| token.span().map(|span| (span.line, span.column, span.len)))
+        .collect();
+    assert_eq!(
+        spans,
+        [
+            Some((1, 1, 3)),
+            Some((1, 5, 1)),
+            Some((1, 7, 1)),
+            Some((2, 3, 3)),
+            Some((2, 7, 1)),
+            Some((2, 9, 2)),
+            Some((2, 11, 1)),
+            Some((2, 12, 0)),
+        ]
+    );
+    // Tokens made up by the parser are from nowhere in particular.
+    assert_eq!(Token::new(TokenType::Nil, 1).span(), None);
+    let err = Scanner::new("var s;\n  @").scan_tokens().unwrap_err();
+    let span = err.span().unwrap();
+    assert_eq!((span.line, span.column, span.len), (2, 3, 1));
+}
+
+#[cfg(test)]
+#[test]
+fn stray_characters_are_all_reported() {
+    let err = Scanner::new("print @;\nprint #;\n\"open").scan_tokens().unwrap_err();
+    let messages: Vec<_> = err.into_errors().iter().map(|e| e.message().to_string()).collect();
+    assert_eq!(messages.len(), 3, "{:?}", messages);
+    assert_eq!(messages[0], "Invalid character on line 1: @");
+    assert_eq!(messages[1], "Invalid character on line 2: #");
+    assert!(messages[2].starts_with("Missing end-quote"), "{}", messages[2]);
+}
+
+#[cfg(test)]
+#[test]
+fn bad_numbers_say_which() {
+    let err = Scanner::new("print 1.2.3;").scan_tokens().unwrap_err();
+    assert_eq!(err.to_string(), "Invalid number '1.2.3'.");
+    let cause = std::error::Error::source(&err);
+    assert!(cause.is_some_and(|cause| cause.is::<std::num::ParseFloatError>()));
+}
+
+#[cfg(test)]
+#[test]
+fn keywords_are_keywords() {
+    for keyword in KEYWORDS.iter() {
+        let tokens = Scanner::new(keyword).scan_tokens().unwrap().clone();
+        assert!(tokens[0].typ.is_keyword(), "{}", keyword);
+    }
+    assert!(!TokenType::Identifier("set".to_string()).is_keyword());
+}
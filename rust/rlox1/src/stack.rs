@@ -0,0 +1,89 @@
+// How much of the thread's stack is left. Lox calls recurse on the Rust stack, and the call
+// limit, DEFAULT_MAX_CALL_DEPTH, is only safe on a thread with interpreter::STACK_SIZE of
+// stack, as rlox1 runs on. A host may call Lox from a thread with far less, so each call also
+// checks that a margin of stack is left, and ends a deep recursion with a "Stack overflow"
+// error rather than the Rust stack overflow that would abort the host. Linux says where a
+// thread's stack ends; elsewhere, only the call limit applies.
+
+use std::cell::Cell;
+
+// MARGIN: The stack a Lox call must have left to go ahead: enough for the call itself, with
+// room to spare for the natives and errors it may make on the way.
+pub const MARGIN: usize = 256 * 1024;
+
+thread_local! {
+    // The lowest address of this thread's stack, once looked up.
+    static END: Cell<Option<Option<usize>>> = const { Cell::new(None) };
+}
+
+// exhausted: Whether less than MARGIN of the thread's stack is left.
+pub fn exhausted() -> bool {
+    remaining().is_some_and(|left| left < MARGIN)
+}
+
+// remaining: How many bytes of the thread's stack are left, if that is known.
+pub fn remaining() -> Option<usize> {
+    let here = Cell::new(0u8);
+    let end = END.with(|cached| match cached.get() {
+        Some(end) => end,
+        None => {
+            let end = end();
+            cached.set(Some(end));
+            end
+        }
+    })?;
+    // The stack grows down, towards its end.
+    Some((here.as_ptr() as usize).saturating_sub(end))
+}
+
+#[cfg(target_os = "linux")]
+fn end() -> Option<usize> {
+    use std::mem::MaybeUninit;
+    use std::ptr;
+
+    // SAFETY: The attributes are initialized by pthread_getattr_np before they are read, and
+    // destroyed once the stack's bounds are out of them.
+    unsafe {
+        let mut attr = MaybeUninit::<libc::pthread_attr_t>::uninit();
+        if libc::pthread_getattr_np(libc::pthread_self(), attr.as_mut_ptr()) != 0 {
+            return None;
+        }
+        let mut attr = attr.assume_init();
+        let mut low = ptr::null_mut();
+        let mut size = 0;
+        let found = libc::pthread_attr_getstack(&attr, &mut low, &mut size) == 0;
+        libc::pthread_attr_destroy(&mut attr);
+        if found {
+            Some(low as usize)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn end() -> Option<usize> {
+    None
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::thread;
+
+    use crate::stack::remaining;
+
+    #[test]
+    fn the_stack_left_is_within_the_thread_stack() {
+        let left = thread::Builder::new()
+            .stack_size(4 << 20)
+            .spawn(|| remaining().unwrap())
+            .unwrap()
+            .join()
+            .unwrap();
+        assert!(
+            left > (3 << 20) && left <= (4 << 20) + (64 << 10),
+            "{}",
+            left
+        );
+    }
+}
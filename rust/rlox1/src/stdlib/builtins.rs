@@ -0,0 +1,188 @@
+// Core natives that are part of the language rather than any one area of the library, such
+// as assertions and exit(). Errors they raise get the line of the call, like any native's.
+
+use crate::error::LoxError;
+use crate::interpreter::Interpreter;
+use crate::stdlib::expect_number;
+use crate::value::Value;
+
+pub fn register(interpreter: &mut Interpreter) {
+    // assert: Fail with `message` unless `cond` is truthy.
+    interpreter.define_native_with("assert", 2, |interpreter, args| {
+        if !args[0].is_truthy() {
+            loxerr!(LX0514, "Assertion failed: {}", interpreter.show(&args[1])?)
+        }
+        Ok(Value::Nil)
+    });
+
+    // error: Always fail, with `message` as the error. Like any runtime error, it can be
+    // caught by try/catch.
+    interpreter.define_native_with("error", 1, |interpreter, args| {
+        loxerr!(LX0519, "{}", interpreter.show(&args[0])?)
+    });
+
+    // type: The name of a value's type, the same one runtime errors use, except that an
+    // instance's type is its class.
+    interpreter.define_native("type", 1, |args| match &args[0] {
+        Value::Instance(instance) => Ok(Value::Str(instance.borrow().class.name.as_str().into())),
+        other => Ok(Value::Str(other.type_name().into())),
+    });
+
+    // instanceOf: Whether `value` is an instance of `class` or of a class inheriting from it.
+    interpreter.define_native("instanceOf", 2, |args| {
+        let class = match &args[1] {
+            Value::Class(class) => class,
+            other => loxerr!(
+                LX0513,
+                "Argument {} to '{}' must be {}, got {}",
+                2,
+                "instanceOf",
+                "a class",
+                other.type_name()
+            ),
+        };
+        Ok(Value::Bool(match &args[0] {
+            Value::Instance(instance) => instance.borrow().class.is_or_inherits(class),
+            _ => false,
+        }))
+    });
+
+    // className: The name of a class, or of an instance's class; nil for any other value.
+    interpreter.define_native("className", 1, |args| match &args[0] {
+        Value::Class(class) => Ok(Value::Str(class.name.as_str().into())),
+        Value::Instance(instance) => Ok(Value::Str(instance.borrow().class.name.as_str().into())),
+        _ => Ok(Value::Nil),
+    });
+
+    // exit: Stop the script, making `code` the process exit code. try/catch can't intercept
+    // it.
+    interpreter.define_native("exit", 1, |args| {
+        let code = expect_number(args, 0, "exit")?;
+        if !(0.0..=255.0).contains(&code) || code.fract() != 0.0 {
+            loxerr!(
+                LX0516,
+                "Exit code must be a whole number from 0 to 255, got {}",
+                code
+            )
+        }
+        Err(LoxError::exit(code as i32))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::LoxError;
+    use crate::stdlib::tests::{eval, eval_err};
+
+    #[test]
+    fn assert_passes() -> Result<(), LoxError> {
+        assert_eq!(eval("assert(1 + 1 == 2, \"math works\");")?, "nil");
+        assert_eq!(eval("assert(0, \"0 is truthy\");")?, "nil");
+        Ok(())
+    }
+
+    #[test]
+    fn assert_fails_with_location() {
+        let err = eval_err("1;\nassert(nil, \"expected a value\");");
+        assert_eq!(err, "[line 2] Assertion failed: expected a value.");
+        let err = eval_err("assert(1 > 2, [1]);");
+        assert!(err.contains("Assertion failed: [1]"), "{}", err);
+    }
+
+    #[test]
+    fn error_fails_with_message() {
+        assert_eq!(eval_err("\n\nerror(\"bad input\");"), "[line 3] bad input.");
+    }
+
+    #[test]
+    fn errors_are_catchable() -> Result<(), LoxError> {
+        let src = "var got; try { assert(false, \"no\"); } catch (e) { got = e; } got;";
+        assert_eq!(eval(src)?, "[line 1] Assertion failed: no");
+        Ok(())
+    }
+
+    #[test]
+    fn type_names_every_kind_of_value() -> Result<(), LoxError> {
+        let src = "fun f() {} [type(1), type(\"s\"), type(true), type(nil), type(f), type(clock), type([]), type({})];";
+        assert_eq!(
+            eval(src)?,
+            "[\"number\", \"string\", \"bool\", \"nil\", \"function\", \"function\", \"list\", \"map\"]"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn type_of_classes_and_instances() -> Result<(), LoxError> {
+        let src =
+            "class Point {} class Point3 < Point {} [type(Point), type(Point()), type(Point3())];";
+        assert_eq!(eval(src)?, "[\"class\", \"Point\", \"Point3\"]");
+        Ok(())
+    }
+
+    #[test]
+    fn type_guards_code() -> Result<(), LoxError> {
+        let src =
+            "fun double(x) { if (type(x) != \"number\") error(\"not a number\"); return x * 2; }
+                   var got; try { double(\"a\"); } catch (e) { got = e; } [double(2), got];";
+        assert_eq!(eval(src)?, "[4, \"[line 1] not a number\"]");
+        Ok(())
+    }
+
+    #[test]
+    fn instance_of_follows_inheritance() -> Result<(), LoxError> {
+        let src = "class A {} class B < A {} class C < B {} class D {} var c = C();
+                   [instanceOf(c, C), instanceOf(c, A), instanceOf(A(), C), instanceOf(c, D)];";
+        assert_eq!(eval(src)?, "[true, true, false, false]");
+        assert_eq!(
+            eval("class A {} [instanceOf(1, A), instanceOf(A, A)];")?,
+            "[false, false]"
+        );
+        // Mixins lend their methods, not their type.
+        let src = "class X {} class C with X {} instanceOf(C(), X);";
+        assert_eq!(eval(src)?, "false");
+        Ok(())
+    }
+
+    #[test]
+    fn instance_of_needs_a_class() {
+        let err = eval_err("class A {} instanceOf(A(), A());");
+        assert!(
+            err.contains("Argument 2 to 'instanceOf' must be a class, got instance"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn class_names() -> Result<(), LoxError> {
+        let src = "class A {} class B < A {} [className(A), className(B()), className(1)];";
+        assert_eq!(eval(src)?, "[\"A\", \"B\", nil]");
+        Ok(())
+    }
+
+    #[test]
+    fn exit_stops_the_script() {
+        let src = "var n = 0; try { exit(3); } catch (e) { n = 1; } n = 2; n;";
+        match eval(src) {
+            Ok(value) => panic!("exit() should stop the script, got {}", value),
+            Err(err) => assert_eq!(err.exit_code(), Some(3)),
+        }
+    }
+
+    #[test]
+    fn exit_code_is_checked() {
+        let err = eval_err("exit(256);");
+        assert!(
+            err.contains("Exit code must be a whole number from 0 to 255, got 256"),
+            "{}",
+            err
+        );
+        assert!(eval_err("exit(1.5);").contains("got 1.5"));
+        assert!(eval_err("exit(\"1\");").contains("must be a number, got string"));
+    }
+
+    #[test]
+    fn arity_is_checked() {
+        assert!(eval_err("assert(true);").contains("Expected 2 arguments but got 1"));
+    }
+}
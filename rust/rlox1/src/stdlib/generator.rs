@@ -0,0 +1,28 @@
+// Generator natives: the methods available on generators, which come back as natives bound
+// to their generator like list methods do. for-in loops over a generator without them.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::value::{LoxGenerator, NativeFn, NativeFunction, Value};
+
+pub const METHODS: [&str; 1] = ["next"];
+
+// method: The method `name` bound to `generator`, or None if generators have no such method.
+pub fn method(generator: &Rc<RefCell<LoxGenerator>>, name: &str) -> Option<Value> {
+    let generator = generator.clone();
+    let (arity, func): (usize, Box<NativeFn>) = match name {
+        // next: Run the generator to its next `yield` and return the value yielded, or nil
+        // once the generator has finished.
+        "next" => (
+            0,
+            Box::new(move |interpreter, _| interpreter.generator_next(&generator)),
+        ),
+        _ => return None,
+    };
+    Some(Value::Native(Rc::new(NativeFunction {
+        name: name.to_string(),
+        arity,
+        func,
+    })))
+}
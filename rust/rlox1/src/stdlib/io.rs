@@ -0,0 +1,188 @@
+// I/O natives, in the `IO` namespace apart from print() and println(), which stay globals
+// alongside the print statement. Console access is always available, so a script run from a
+// file can read what is piped to it, e.g. `cat data | rlox1 run filter.lox`; a script read from
+// stdin has used it up, and finds no input. File access needs
+// `Options::allow_io` (the `--allow-io` flag). Without it the file natives still exist but
+// refuse to run, so a script gets a clear message instead of an undefined property.
+
+use std::fs;
+use std::io::{self, BufRead, Read};
+
+use crate::error::LoxError;
+use crate::interpreter::Interpreter;
+use crate::stdlib::{expect_str, Options};
+use crate::value::{Namespace, Value};
+
+pub fn register(interpreter: &mut Interpreter, options: &Options) {
+    let mut io = Namespace::new("IO");
+
+    // readLine: The next line of stdin without its line ending, or nil at end of input.
+    io.define_native("readLine", 0, |_| {
+        let mut line = String::new();
+        if io::stdin().lock().read_line(&mut line)? == 0 {
+            return Ok(Value::Nil);
+        }
+        let len = line.trim_end_matches(&['\r', '\n'][..]).len();
+        line.truncate(len);
+        Ok(Value::Str(line.into()))
+    });
+
+    // readAll: The rest of stdin, or "" at end of input.
+    io.define_native("readAll", 0, |_| {
+        let mut input = String::new();
+        io::stdin().lock().read_to_string(&mut input)?;
+        Ok(Value::Str(input.into()))
+    });
+
+    // print, println: Printing as functions, so it can be passed to other functions, e.g.
+    // `each(items, println)`. println ends the line, as the print statement does; print
+    // doesn't, so a line can be built up a piece at a time. Called at the start of a
+    // statement, `print(x);` parses as the statement, which ends the line too.
+    if options.print_natives {
+        let output = interpreter.output();
+        interpreter.define_native_with("print", 1, move |interpreter, args| {
+            output.text(&interpreter.show(&args[0])?);
+            Ok(Value::Nil)
+        });
+        let output = interpreter.output();
+        interpreter.define_native_with("println", 1, move |interpreter, args| {
+            output.line(&interpreter.show(&args[0])?);
+            Ok(Value::Nil)
+        });
+    }
+
+    // eprint: Like the print statement, but to stderr.
+    io.define_native_with("eprint", 1, |interpreter, args| {
+        eprintln!("{}", interpreter.show(&args[0])?);
+        Ok(Value::Nil)
+    });
+
+    let allow_io = options.allow_io;
+    io.define_native("readFile", 1, move |args| {
+        check_allowed(allow_io, "IO.readFile")?;
+        let path = expect_str(args, 0, "IO.readFile")?;
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(Value::Str(text.into())),
+            Err(err) => {
+                let message = lox_error!(LX0705, "Could not read file '{}': {}", path, err);
+                Err(message.caused_by(err))
+            }
+        }
+    });
+
+    io.define_native("writeFile", 2, move |args| {
+        check_allowed(allow_io, "IO.writeFile")?;
+        let path = expect_str(args, 0, "IO.writeFile")?;
+        let text = expect_str(args, 1, "IO.writeFile")?;
+        match fs::write(path, text) {
+            Ok(()) => Ok(Value::Nil),
+            Err(err) => {
+                let message = lox_error!(LX0705, "Could not write file '{}': {}", path, err);
+                Err(message.caused_by(err))
+            }
+        }
+    });
+
+    interpreter.define_namespace(io);
+}
+
+// check_allowed: Refuse to run `func` unless the host allows I/O.
+pub fn check_allowed(allow_io: bool, func: &str) -> Result<(), LoxError> {
+    if !allow_io {
+        loxerr!(
+            LX0706,
+            "'{}' is disabled; run with --allow-io to enable it",
+            func
+        )
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use crate::error::LoxError;
+    use crate::interpreter::tests::eval_with;
+    use crate::stdlib::tests::{eval, eval_err, eval_with_io};
+    use crate::stdlib::Options;
+
+    fn temp_path(name: &str) -> String {
+        let mut path = env::temp_dir();
+        path.push(format!("rlox1-io-{}-{}", std::process::id(), name));
+        format!("{}", path.display())
+    }
+
+    #[test]
+    fn print_is_a_function_value() -> Result<(), LoxError> {
+        let src = "fun each(l, f) { for (var x in l) f(x); } each([], print); [print, println];";
+        assert_eq!(eval(src)?, "[<native fn print>, <native fn println>]");
+        assert_eq!(eval("var p = println; p(\"printed by a test\");")?, "nil");
+        Ok(())
+    }
+
+    #[test]
+    fn print_natives_can_be_left_out() {
+        let options = Options {
+            print_natives: false,
+            ..Options::default()
+        };
+        let err = eval_with("var p = print; p;", |interp| {
+            crate::stdlib::register(interp, &options)
+        })
+        .unwrap_err();
+        assert!(
+            format!("{}", err).contains("Undefined variable 'print'"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn file_access_is_disabled_by_default() {
+        let err = eval_err("IO.readFile(\"/etc/hostname\");");
+        assert!(
+            err.contains("'IO.readFile' is disabled; run with --allow-io"),
+            "{}",
+            err
+        );
+        let err = eval_err("IO.writeFile(\"x\", \"y\");");
+        assert!(err.contains("'IO.writeFile' is disabled"), "{}", err);
+    }
+
+    #[test]
+    fn write_then_read() -> Result<(), LoxError> {
+        let path = temp_path("roundtrip");
+        let src = format!(
+            "IO.writeFile({:?}, \"line 1\nline 2\"); IO.readFile({:?});",
+            path, path
+        );
+        let got = eval_with_io(&src);
+        let _ = fs::remove_file(&path);
+        assert_eq!(got?, "line 1\nline 2");
+        Ok(())
+    }
+
+    #[test]
+    fn read_missing_file() {
+        let path = temp_path("missing");
+        match eval_with_io(&format!("IO.readFile({:?});", path)) {
+            Ok(value) => panic!("expected an error, got {}", value),
+            Err(err) => {
+                assert!(format!("{}", err).contains("Could not read file"));
+                // The io::Error is kept, for code embedding rlox1 to look at.
+                let cause = std::error::Error::source(&err)
+                    .and_then(|cause| cause.downcast_ref::<std::io::Error>())
+                    .map(std::io::Error::kind);
+                assert_eq!(cause, Some(std::io::ErrorKind::NotFound));
+            }
+        }
+    }
+
+    #[test]
+    fn eprint_returns_nil() -> Result<(), LoxError> {
+        assert_eq!(eval("IO.eprint(\"to stderr\");")?, "nil");
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+// JSON natives: jsonParse() reads JSON text into nil, bools, numbers, strings, lists and maps,
+// and jsonStringify() writes such a value as JSON text; see json.rs for how the two match up.
+// Like str() and num(), they are conversions, so they are globals.
+
+use crate::interpreter::Interpreter;
+use crate::stdlib::expect_str;
+use crate::value::Value;
+
+pub fn register(interpreter: &mut Interpreter) {
+    interpreter.define_native("jsonParse", 1, |args| {
+        Value::from_json(expect_str(args, 0, "jsonParse")?)
+    });
+
+    interpreter.define_native("jsonStringify", 1, |args| {
+        Ok(Value::Str(args[0].to_json()?.into()))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::stdlib::tests::{eval, eval_err, eval_with_io};
+
+    #[test]
+    fn scripts_read_and_write_json() {
+        // Lox strings have no escapes to write a double quote with, so JSON comes from files.
+        let mut path = env::temp_dir();
+        path.push(format!("rlox1-json-{}", std::process::id()));
+        fs::write(&path, r#"{"name": "lox", "sizes": [1, 2]}"#).unwrap();
+        let program = format!(
+            "var config = jsonParse(IO.readFile({:?}));
+             config.get(\"sizes\").push(3);
+             config.set(\"ok\", true);
+             jsonStringify(config);",
+            path.display()
+        );
+        let got = eval_with_io(&program);
+        let _ = fs::remove_file(&path);
+        assert_eq!(got.unwrap(), r#"{"name":"lox","sizes":[1,2,3],"ok":true}"#);
+        assert_eq!(eval("jsonParse(\"[1, 2.5]\")[1];").unwrap(), "2.5");
+        assert_eq!(
+            eval("jsonStringify([nil, \"a\"]);").unwrap(),
+            r#"[null,"a"]"#
+        );
+    }
+
+    #[test]
+    fn json_errors_are_runtime_errors() {
+        assert_eq!(
+            eval_err("jsonParse(\"[1,]\");"),
+            "[line 1] Invalid JSON at line 1, column 4: expected a value."
+        );
+        assert_eq!(
+            eval_err("jsonStringify(clock);"),
+            "[line 1] Can't convert a function to JSON."
+        );
+        assert_eq!(
+            eval_err("jsonParse(1);"),
+            "[line 1] Argument 1 to 'jsonParse' must be a string, got number."
+        );
+    }
+}
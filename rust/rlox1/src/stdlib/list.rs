@@ -0,0 +1,160 @@
+// List natives: the `List` namespace, with `List.new()`, `List.of(a, b, ...)` and
+// `List.from(items)`, and the methods available on list values, e.g. `xs.push(1)`. Methods
+// are looked up by the interpreter when a property is read from a list, and come back as
+// natives bound to that list.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::error::LoxError;
+use crate::interpreter::Interpreter;
+use crate::value::{Namespace, NativeFn, NativeFunction, Value, VARIADIC};
+
+type List = Rc<RefCell<Vec<Value>>>;
+
+pub fn register(interpreter: &mut Interpreter) {
+    let mut list = Namespace::new("List");
+    list.define_native("new", 0, |_| Ok(Value::list(Vec::new())));
+    // of: A new list of the arguments, however many there are.
+    list.define_native("of", VARIADIC, |args| Ok(Value::list(args.to_vec())));
+    // from: A new list of the items for-in would visit: a list's elements, a string's
+    // characters or a map's keys.
+    list.define_native("from", 1, |args| match &args[0] {
+        Value::List(items) => Ok(Value::list(items.borrow().clone())),
+        Value::Str(s) => Ok(Value::list(
+            s.chars()
+                .map(|c| Value::Str(c.to_string().into()))
+                .collect(),
+        )),
+        Value::Map(map) => Ok(Value::list(map.borrow().keys())),
+        other => loxerr!(
+            LX0513,
+            "Argument {} to '{}' must be {}, got {}",
+            1,
+            "List.from",
+            "a list, map or string",
+            other.type_name()
+        ),
+    });
+    interpreter.define_namespace(list);
+}
+
+// METHODS: The names `method` knows, for the REPL to complete.
+pub const METHODS: [&str; 6] = ["push", "pop", "len", "get", "set", "contains"];
+
+// method: The method `name` bound to `list`, or None if lists have no such method.
+pub fn method(list: &List, name: &str) -> Option<Value> {
+    let list = list.clone();
+    let (arity, func): (usize, Box<NativeFn>) = match name {
+        "push" => (
+            1,
+            Box::new(move |_, args| {
+                list.borrow_mut().push(args[0].clone());
+                Ok(Value::Nil)
+            }),
+        ),
+        "pop" => (
+            0,
+            Box::new(move |_, _| match list.borrow_mut().pop() {
+                Some(value) => Ok(value),
+                None => loxerr!(LX0515, "Can't pop from an empty list"),
+            }),
+        ),
+        "len" => (
+            0,
+            Box::new(move |_, _| Ok(Value::Number(list.borrow().len() as f64))),
+        ),
+        "get" => (
+            1,
+            Box::new(move |_, args| {
+                let items = list.borrow();
+                let i = position(&args[0], items.len())?;
+                Ok(items[i].clone())
+            }),
+        ),
+        "set" => (
+            2,
+            Box::new(move |_, args| {
+                let mut items = list.borrow_mut();
+                let i = position(&args[0], items.len())?;
+                items[i] = args[1].clone();
+                Ok(args[1].clone())
+            }),
+        ),
+        "contains" => (
+            1,
+            Box::new(move |_, args| Ok(Value::Bool(list.borrow().contains(&args[0])))),
+        ),
+        _ => return None,
+    };
+    Some(Value::Native(Rc::new(NativeFunction {
+        name: name.to_string(),
+        arity,
+        func,
+    })))
+}
+
+fn position(index: &Value, len: usize) -> Result<usize, LoxError> {
+    match index {
+        Value::Number(n) if n.fract() == 0.0 && *n >= 0.0 && (*n as usize) < len => Ok(*n as usize),
+        Value::Number(n) if n.fract() == 0.0 => {
+            loxerr!(
+                LX0503,
+                "Index {} out of range for {} of length {}",
+                n,
+                "list",
+                len
+            )
+        }
+        other => loxerr!(
+            LX0504,
+            "{} index must be an integer, got {}",
+            "List",
+            other.type_name()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::LoxError;
+    use crate::stdlib::tests::{eval, eval_err};
+
+    macro_rules! list_test {
+        ( $name:ident, $src:expr => $printed:expr ) => {
+            #[test]
+            fn $name() -> Result<(), LoxError> {
+                assert_eq!(eval($src)?, $printed, "Input was {:?}", $src);
+                Ok(())
+            }
+        };
+    }
+
+    list_test!(constructor, "List.new();" => "[]");
+    list_test!(of_arguments, "[List.of(), List.of(1), List.of(1, \"a\", [2])];" => "[[], [1], [1, \"a\", [2]]]");
+    list_test!(from_list_copies, "var a = [1, 2]; var b = List.from(a); b.push(3); [a, b];" => "[[1, 2], [1, 2, 3]]");
+    list_test!(from_string, "List.from(\"héllo\");" => "[\"h\", \"é\", \"l\", \"l\", \"o\"]");
+    list_test!(from_map, "List.from({\"a\": 1, 2: 3});" => "[\"a\", 2]");
+    list_test!(push_and_len, "var l = List.new(); l.push(1); l.push(\"b\"); l.len();" => "2");
+    list_test!(push_mutates, "var l = [1]; l.push(2); l;" => "[1, 2]");
+    list_test!(pop_returns_last, "var l = [1, 2]; l.pop() + l.len();" => "3");
+    list_test!(get_element, "[4, 5, 6].get(2);" => "6");
+    list_test!(set_element, "var l = [1, 2]; l.set(0, 9); l;" => "[9, 2]");
+    list_test!(contains_element, "[1, \"a\", nil].contains(\"a\");" => "true");
+    list_test!(contains_missing, "[1, 2].contains(3);" => "false");
+    list_test!(bound_method_keeps_list, "var l = []; var push = l.push; push(1); push(2); l;" => "[1, 2]");
+    list_test!(iterate, "var total = 0; for (var x in [1, 2, 3]) total = total + x; total;" => "6");
+    list_test!(iterate_sees_changes, "var l = [1, 2, 3]; var seen = []; for (var x in l) { seen.push(x); l.pop(); } seen;" => "[1, 2]");
+
+    #[test]
+    fn method_errors() {
+        assert!(eval_err("[].pop();").contains("Can't pop from an empty list"));
+        assert!(eval_err("[1].get(1);").contains("Index 1 out of range for list of length 1"));
+        assert!(eval_err("[1].set(\"0\", 1);").contains("List index must be an integer"));
+        assert!(eval_err("[1].push();").contains("Expected 1 arguments but got 0"));
+        assert!(eval_err("[1].shuffle();").contains("Undefined property 'shuffle' on list"));
+        assert!(eval_err("List.from(1);")
+            .contains("Argument 1 to 'List.from' must be a list, map or string, got number"));
+        assert!(eval_err("List.from();").contains("Expected 1 arguments but got 0"));
+    }
+}
@@ -0,0 +1,115 @@
+// Map natives: the `Map` namespace, with `Map.new()`, and the methods available on map
+// values, e.g. `m.set("k", 1)`. Like list methods, they come back as natives bound to their
+// map.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::interpreter::Interpreter;
+use crate::value::{LoxMap, Namespace, NativeFn, NativeFunction, Value};
+
+type Map = Rc<RefCell<LoxMap>>;
+
+pub fn register(interpreter: &mut Interpreter) {
+    let mut map = Namespace::new("Map");
+    map.define_native("new", 0, |_| Ok(Value::map(LoxMap::new())));
+    interpreter.define_namespace(map);
+}
+
+pub const METHODS: [&str; 5] = ["get", "set", "remove", "keys", "len"];
+
+// method: The method `name` bound to `map`, or None if maps have no such method.
+pub fn method(map: &Map, name: &str) -> Option<Value> {
+    let map = map.clone();
+    let (arity, func): (usize, Box<NativeFn>) = match name {
+        // get: The value stored under a key, or nil if there is none.
+        "get" => (
+            1,
+            Box::new(move |interpreter, args| {
+                Ok(interpreter.map_get(&map, &args[0])?.unwrap_or(Value::Nil))
+            }),
+        ),
+        "set" => (
+            2,
+            Box::new(move |interpreter, args| {
+                interpreter.map_set(&map, args[0].clone(), args[1].clone())?;
+                Ok(args[1].clone())
+            }),
+        ),
+        // remove: Delete a key, returning its value, or nil if it was not there.
+        "remove" => (
+            1,
+            Box::new(move |interpreter, args| {
+                Ok(interpreter
+                    .map_remove(&map, &args[0])?
+                    .unwrap_or(Value::Nil))
+            }),
+        ),
+        "keys" => (
+            0,
+            Box::new(move |_, _| Ok(Value::list(map.borrow().keys()))),
+        ),
+        "len" => (
+            0,
+            Box::new(move |_, _| Ok(Value::Number(map.borrow().len() as f64))),
+        ),
+        _ => return None,
+    };
+    Some(Value::Native(Rc::new(NativeFunction {
+        name: name.to_string(),
+        arity,
+        func,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::LoxError;
+    use crate::stdlib::tests::{eval, eval_err};
+
+    macro_rules! map_test {
+        ( $name:ident, $src:expr => $printed:expr ) => {
+            #[test]
+            fn $name() -> Result<(), LoxError> {
+                assert_eq!(eval($src)?, $printed, "Input was {:?}", $src);
+                Ok(())
+            }
+        };
+    }
+
+    map_test!(constructor, "Map.new();" => "{}");
+    map_test!(set_and_get, "var m = Map.new(); m.set(\"a\", 1); m.get(\"a\");" => "1");
+    map_test!(get_missing, "Map.new().get(\"a\");" => "nil");
+    map_test!(set_returns_value, "Map.new().set(1, 2);" => "2");
+    map_test!(remove_existing, "var m = {1: 2}; [m.remove(1), m.len()];" => "[2, 0]");
+    map_test!(remove_missing, "Map.new().remove(1);" => "nil");
+    map_test!(keys_in_insertion_order, "var m = {\"b\": 1}; m.set(\"a\", 2); m.set(\"b\", 3); m.keys();" => "[\"b\", \"a\"]");
+    map_test!(len_counts_entries, "({nil: 1, true: 2, 3: 3, \"4\": 4}).len();" => "4");
+    map_test!(number_keys_compare_by_value, "var m = {}; m.set(1, \"x\"); m.get(2 - 1);" => "x");
+    map_test!(literal_and_constructor_agree, "var m = Map.new(); m[\"k\"] = 1; m;" => "{\"k\": 1}");
+    map_test!(instance_keys_by_identity, "class K {} var a = K(); var m = {a: 1}; [m[a], m.get(K()), m.len()];" => "[1, nil, 1]");
+    map_test!(hash_and_equals, "class P { init(x) { this.x = x; } hash() { return this.x; } equals(o) { return this.x == o.x; } } var m = {P(1): \"a\"}; m[P(1)] = \"b\"; [m.get(P(1)), m.len()];" => "[\"b\", 1]");
+    map_test!(equals_separates_hash_collisions, "class P { init(x) { this.x = x; } hash() { return 0; } equals(o) { return this.x == o.x; } } var m = Map.new(); m.set(P(1), 1); m.set(P(2), 2); m.remove(P(1)); [m.len(), m.get(P(2)), m.get(P(1))];" => "[1, 2, nil]");
+    map_test!(hash_without_equals_is_identity, "class P { hash() { return 0; } } var a = P(); var m = {a: 1}; [m.get(a), m.get(P())];" => "[1, nil]");
+
+    #[test]
+    fn key_errors() {
+        assert!(eval_err("Map.new().set([], 1);").contains("A list can't be used as a map key"));
+        assert!(eval_err("Map.new().get({});").contains("A map can't be used as a map key"));
+        assert!(
+            eval_err("Map.new().frobnicate();").contains("Undefined property 'frobnicate' on map")
+        );
+        let err = eval_err("class P {\n  hash() { return []; } }\nMap.new().get(P());");
+        assert!(
+            err.contains("[line 2] A list can't be used as a map key"),
+            "{}",
+            err
+        );
+        let err = eval_err("class P { hash() { return 0; }\n  equals(o) { return o.nope; } }\nvar m = {P(): 1, P(): 2}; m;");
+        assert!(
+            err.contains("[line 2] Undefined property 'nope' on P instance"),
+            "{}",
+            err
+        );
+    }
+}
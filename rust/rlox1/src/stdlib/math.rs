@@ -0,0 +1,142 @@
+// Math natives and constants, in the `Math` namespace.
+
+use std::cell::Cell;
+use std::f64::consts;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::interpreter::Interpreter;
+use crate::stdlib::expect_number;
+use crate::value::{Namespace, Value};
+
+pub fn register(interpreter: &mut Interpreter) {
+    let mut math = Namespace::new("Math");
+    math.define("PI", Value::Number(consts::PI));
+    math.define("E", Value::Number(consts::E));
+
+    unary(&mut math, "sqrt", f64::sqrt);
+    unary(&mut math, "abs", f64::abs);
+    unary(&mut math, "floor", f64::floor);
+    unary(&mut math, "ceil", f64::ceil);
+    // Rust's min and max ignore a NaN argument; in Lox, as in arithmetic, NaN wins.
+    binary(&mut math, "min", |a, b| nan_or(a, b, f64::min));
+    binary(&mut math, "max", |a, b| nan_or(a, b, f64::max));
+    binary(&mut math, "pow", f64::powf);
+
+    // isNaN: Whether `v` is the number NaN, the one value `v != v` also detects. Anything
+    // that isn't a number is not NaN.
+    math.define_native("isNaN", 1, |args| {
+        Ok(Value::Bool(
+            matches!(args[0], Value::Number(n) if n.is_nan()),
+        ))
+    });
+
+    // random: A number in [0, 1) from a xorshift generator seeded by the clock. Good enough
+    // for games and shuffling, not for anything that needs real randomness.
+    let state = Rc::new(Cell::new(seed()));
+    math.define_native("random", 0, move |_| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        Ok(Value::Number((x >> 11) as f64 / (1u64 << 53) as f64))
+    });
+
+    interpreter.define_namespace(math);
+}
+
+fn unary(math: &mut Namespace, name: &str, f: fn(f64) -> f64) {
+    let qualified = format!("Math.{}", name);
+    math.define_native(name, 1, move |args| {
+        Ok(Value::Number(f(expect_number(args, 0, &qualified)?)))
+    });
+}
+
+fn binary(math: &mut Namespace, name: &str, f: fn(f64, f64) -> f64) {
+    let qualified = format!("Math.{}", name);
+    math.define_native(name, 2, move |args| {
+        let a = expect_number(args, 0, &qualified)?;
+        let b = expect_number(args, 1, &qualified)?;
+        Ok(Value::Number(f(a, b)))
+    });
+}
+
+// nan_or: NaN if either argument is NaN, otherwise `f(a, b)`.
+fn nan_or(a: f64, b: f64, f: fn(f64, f64) -> f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::NAN
+    } else {
+        f(a, b)
+    }
+}
+
+fn seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    // xorshift gets stuck on zero, so make sure at least one bit is set.
+    nanos | 1
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::LoxError;
+    use crate::stdlib::tests::{eval, eval_err};
+
+    macro_rules! math_test {
+        ( $name:ident, $src:expr => $printed:expr ) => {
+            #[test]
+            fn $name() -> Result<(), LoxError> {
+                assert_eq!(eval($src)?, $printed, "Input was {:?}", $src);
+                Ok(())
+            }
+        };
+    }
+
+    math_test!(sqrt_of_square, "Math.sqrt(16);" => "4");
+    math_test!(abs_of_negative, "Math.abs(-2.5);" => "2.5");
+    math_test!(floor_of_fraction, "Math.floor(-1.5);" => "-2");
+    math_test!(ceil_of_fraction, "Math.ceil(1.2);" => "2");
+    math_test!(min_of_two, "Math.min(3, -1);" => "-1");
+    math_test!(max_of_two, "Math.max(3, -1);" => "3");
+    math_test!(pow_of_two, "Math.pow(2, 10);" => "1024");
+    math_test!(min_max_keep_nan, "var n = 0 / 0; [Math.min(n, 1), Math.min(1, n), Math.max(n, 1), Math.max(1, n)];" => "[NaN, NaN, NaN, NaN]");
+    math_test!(is_nan_of_numbers, "[Math.isNaN(0 / 0), Math.isNaN(Math.sqrt(-1)), Math.isNaN(1 / 0), Math.isNaN(-0)];" => "[true, true, false, false]");
+    math_test!(is_nan_of_other_values, "[Math.isNaN(\"NaN\"), Math.isNaN(nil), Math.isNaN([0 / 0])];" => "[false, false, false]");
+    math_test!(pi_constant, "Math.floor(Math.PI * 1000);" => "3141");
+    math_test!(e_constant, "Math.floor(Math.E * 1000);" => "2718");
+
+    #[test]
+    fn random_is_in_unit_interval() -> Result<(), LoxError> {
+        for _ in 0..10 {
+            assert_eq!(eval("var r = Math.random(); r >= 0 == (r < 1);")?, "true");
+        }
+        assert_eq!(eval("Math.random() == Math.random();")?, "false");
+        Ok(())
+    }
+
+    #[test]
+    fn library_lives_in_namespaces() -> Result<(), LoxError> {
+        assert_eq!(
+            eval("[Math, Math.sqrt];")?,
+            "[<namespace Math>, <native fn Math.sqrt>]"
+        );
+        assert_eq!(eval("type(Math);")?, "namespace");
+        assert!(eval_err("sqrt(4);").contains("Undefined variable 'sqrt'"));
+        assert!(eval_err("Math.tau;").contains("Undefined property 'tau' on namespace Math"));
+        assert!(eval_err("Math.PI = 3;").contains("Only instances have fields"));
+        Ok(())
+    }
+
+    #[test]
+    fn argument_errors() {
+        assert!(
+            eval_err("Math.sqrt(\"4\");").contains("Argument 1 to 'Math.sqrt' must be a number")
+        );
+        assert!(eval_err("Math.pow(2, nil);")
+            .contains("Argument 2 to 'Math.pow' must be a number, got nil"));
+        assert!(eval_err("Math.min(1);").contains("Expected 2 arguments but got 1"));
+    }
+}
@@ -0,0 +1,139 @@
+// The standard library: natives registered into an interpreter's globals. Each submodule
+// covers one area and exposes a `register` function. Apart from core functions such as
+// type() and str() and the JSON conversions, natives are grouped into namespaces (`Math`,
+// `String`, `List`, `Map`, `IO`) rather than each taking a global name.
+
+use crate::error::LoxError;
+use crate::interpreter::Interpreter;
+use crate::value::Value;
+
+mod builtins;
+pub mod generator;
+pub mod io;
+mod json;
+pub mod list;
+pub mod map;
+mod math;
+mod process;
+mod string;
+pub mod weakref;
+
+// Options: Host-controlled switches for natives that reach outside the interpreter.
+#[derive(Debug, Clone)]
+pub struct Options {
+    // allow_io: Let scripts read and write files. Off by default so untrusted scripts can be
+    // run without touching the file system.
+    pub allow_io: bool,
+    // print_natives: Define print() and println(), for hosts that want printing to stay a
+    // statement only.
+    pub print_natives: bool,
+    // allow_env: Let scripts read environment variables, which can hold secrets. Only the
+    // sandbox turns it off.
+    pub allow_env: bool,
+    // args: The script's command-line arguments, for args() and argc.
+    pub args: Vec<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            allow_io: false,
+            print_natives: true,
+            allow_env: true,
+            args: Vec::new(),
+        }
+    }
+}
+
+// register: Define every standard library native and namespace in the interpreter's globals.
+pub fn register(interpreter: &mut Interpreter, options: &Options) {
+    builtins::register(interpreter);
+    io::register(interpreter, options);
+    json::register(interpreter);
+    list::register(interpreter);
+    map::register(interpreter);
+    math::register(interpreter);
+    process::register(interpreter, options);
+    string::register(interpreter);
+    weakref::register(interpreter);
+}
+
+// ------------------------------------------------------------------------------------------------
+// Argument helpers
+// ------------------------------------------------------------------------------------------------
+
+// Arguments are numbered from 1 in messages, matching how users count them.
+
+fn expect_str<'a>(args: &'a [Value], index: usize, func: &str) -> Result<&'a str, LoxError> {
+    match &args[index] {
+        Value::Str(s) => Ok(s),
+        other => loxerr!(
+            LX0513,
+            "Argument {} to '{}' must be {}, got {}",
+            index + 1,
+            func,
+            "a string",
+            other.type_name()
+        ),
+    }
+}
+
+fn expect_number(args: &[Value], index: usize, func: &str) -> Result<f64, LoxError> {
+    match &args[index] {
+        Value::Number(n) => Ok(*n),
+        other => loxerr!(
+            LX0513,
+            "Argument {} to '{}' must be {}, got {}",
+            index + 1,
+            func,
+            "a number",
+            other.type_name()
+        ),
+    }
+}
+
+// expect_count: A non-negative whole number, such as an index or a length.
+fn expect_count(args: &[Value], index: usize, func: &str) -> Result<usize, LoxError> {
+    let n = expect_number(args, index, func)?;
+    if n < 0.0 || n.fract() != 0.0 {
+        loxerr!(
+            LX0513,
+            "Argument {} to '{}' must be {}, got {}",
+            index + 1,
+            func,
+            "a non-negative integer",
+            n
+        )
+    }
+    Ok(n as usize)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::error::LoxError;
+    use crate::interpreter::tests::eval_with;
+    use crate::stdlib::Options;
+
+    // eval: Run a program with the standard library loaded and return the printed value
+    // of its final expression statement.
+    pub fn eval(src: &str) -> Result<String, LoxError> {
+        eval_with(src, |interp| super::register(interp, &Options::default()))
+    }
+
+    // eval_with_io: Like eval, with file access allowed.
+    pub fn eval_with_io(src: &str) -> Result<String, LoxError> {
+        let options = Options {
+            allow_io: true,
+            ..Options::default()
+        };
+        eval_with(src, |interp| super::register(interp, &options))
+    }
+
+    // eval_err: Like eval, but the program must fail; returns the error message.
+    pub fn eval_err(src: &str) -> String {
+        match eval(src) {
+            Ok(value) => panic!("{:?} should fail, got {}", src, value),
+            Err(err) => format!("{}", err),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+// Natives about the process running the script: the arguments given after `--` on the
+// command line, as `args()` and `argc`, and its environment variables, so a script can act
+// like any other command-line tool. Reading the environment is allowed outside the sandbox;
+// changing it reaches outside the interpreter, so like file access it needs `--allow-io`.
+
+use std::env;
+
+use crate::error::LoxError;
+use crate::interpreter::Interpreter;
+use crate::stdlib::io::check_allowed;
+use crate::stdlib::{expect_str, Options};
+use crate::value::Value;
+
+pub fn register(interpreter: &mut Interpreter, options: &Options) {
+    // args: The script's arguments, as a new list each time so changing it changes nothing.
+    let args = options.args.clone();
+    interpreter.define_native("args", 0, move |_| {
+        let args = args.iter().map(|arg| Value::Str(arg.as_str().into()));
+        Ok(Value::list(args.collect()))
+    });
+
+    // argc: How many arguments there are.
+    interpreter.define_global("argc", Value::Number(options.args.len() as f64));
+
+    // getenv: The value of an environment variable, or nil if it isn't set.
+    let allow_env = options.allow_env;
+    interpreter.define_native("getenv", 1, move |args| {
+        if !allow_env {
+            loxerr!(LX0706, "'{}' is disabled in the sandbox", "getenv")
+        }
+        let name = expect_str(args, 0, "getenv")?;
+        Ok(match env::var(name) {
+            Ok(value) => Value::Str(value.into()),
+            Err(_) => Value::Nil,
+        })
+    });
+
+    // setenv: Set an environment variable, for this process and any it starts.
+    let allow_io = options.allow_io;
+    interpreter.define_native("setenv", 2, move |args| {
+        check_allowed(allow_io, "setenv")?;
+        let name = expect_str(args, 0, "setenv")?;
+        let value = expect_str(args, 1, "setenv")?;
+        check_env(name, value)?;
+        env::set_var(name, value);
+        Ok(Value::Nil)
+    });
+}
+
+// check_env: Refuse what the OS can't store, which would otherwise make set_var panic.
+fn check_env(name: &str, value: &str) -> Result<(), LoxError> {
+    if name.is_empty() || name.contains(&['=', '\0'][..]) {
+        loxerr!(LX0708, "Invalid environment variable name '{}'", name)
+    } else if value.contains('\0') {
+        loxerr!(
+            LX0708,
+            "The value of environment variable '{}' can't contain NUL",
+            name
+        )
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::LoxError;
+    use crate::interpreter::tests::eval_with;
+    use crate::stdlib::tests::{eval, eval_err, eval_with_io};
+    use crate::stdlib::Options;
+
+    fn eval_with_args(src: &str, args: &[&str]) -> Result<String, LoxError> {
+        let options = Options {
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            ..Options::default()
+        };
+        eval_with(src, |interp| crate::stdlib::register(interp, &options))
+    }
+
+    #[test]
+    fn arguments_are_a_list() -> Result<(), LoxError> {
+        assert_eq!(
+            eval_with_args("args();", &["a", "b c"])?,
+            "[\"a\", \"b c\"]"
+        );
+        assert_eq!(eval_with_args("argc;", &["a", "b c"])?, "2");
+        assert_eq!(eval("[args(), argc];")?, "[[], 0]");
+        let src = "var a = args(); a.push(\"d\"); args();";
+        assert_eq!(eval_with_args(src, &["a"])?, "[\"a\"]");
+        Ok(())
+    }
+
+    #[test]
+    fn environment_variables() -> Result<(), LoxError> {
+        let src = "setenv(\"RLOX1_TEST_SETENV\", \"set\"); getenv(\"RLOX1_TEST_SETENV\");";
+        assert_eq!(eval_with_io(src)?, "set");
+        assert_eq!(eval("getenv(\"RLOX1_TEST_NOT_SET\");")?, "nil");
+        let err = eval_err("setenv(\"RLOX1_TEST_SETENV\", \"x\");");
+        assert!(
+            err.contains("'setenv' is disabled; run with --allow-io"),
+            "{}",
+            err
+        );
+        let err = eval_with_io("setenv(\"A=B\", \"x\");").unwrap_err();
+        assert!(
+            format!("{}", err).contains("Invalid environment variable name 'A=B'"),
+            "{}",
+            err
+        );
+        Ok(())
+    }
+}
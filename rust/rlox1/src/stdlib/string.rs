@@ -0,0 +1,172 @@
+// String natives. The string functions live in the `String` namespace, while the str() and
+// num() conversions stay globals, like type(). Positions and lengths count characters, not
+// bytes, so they agree with string indexing.
+
+use crate::interpreter::Interpreter;
+use crate::stdlib::{expect_count, expect_str};
+use crate::value::{Namespace, Value};
+
+pub fn register(interpreter: &mut Interpreter) {
+    let mut string = Namespace::new("String");
+
+    string.define_native("len", 1, |args| {
+        let s = expect_str(args, 0, "String.len")?;
+        Ok(Value::Number(s.chars().count() as f64))
+    });
+
+    string.define_native("substr", 3, |args| {
+        let s = expect_str(args, 0, "String.substr")?;
+        let start = expect_count(args, 1, "String.substr")?;
+        let len = expect_count(args, 2, "String.substr")?;
+        let count = s.chars().count();
+        if start + len > count {
+            loxerr!(
+                LX0516,
+                "String.substr({}, {}) is out of range for string of length {}",
+                start,
+                len,
+                count
+            )
+        }
+        Ok(Value::Str(
+            s.chars().skip(start).take(len).collect::<String>().into(),
+        ))
+    });
+
+    string.define_native("upper", 1, |args| {
+        Ok(Value::Str(
+            expect_str(args, 0, "String.upper")?.to_uppercase().into(),
+        ))
+    });
+
+    string.define_native("lower", 1, |args| {
+        Ok(Value::Str(
+            expect_str(args, 0, "String.lower")?.to_lowercase().into(),
+        ))
+    });
+
+    // indexOf: The character position of the first match, or -1.
+    string.define_native("indexOf", 2, |args| {
+        let s = expect_str(args, 0, "String.indexOf")?;
+        let needle = expect_str(args, 1, "String.indexOf")?;
+        Ok(Value::Number(match s.find(needle) {
+            Some(byte_index) => s[..byte_index].chars().count() as f64,
+            None => -1.0,
+        }))
+    });
+
+    // split: An empty separator splits the string into its characters.
+    string.define_native("split", 2, |args| {
+        let s = expect_str(args, 0, "String.split")?;
+        let sep = expect_str(args, 1, "String.split")?;
+        let parts = if sep.is_empty() {
+            s.chars()
+                .map(|c| Value::Str(c.to_string().into()))
+                .collect()
+        } else {
+            s.split(sep).map(|p| Value::Str(p.into())).collect()
+        };
+        Ok(Value::list(parts))
+    });
+
+    interpreter.define_namespace(string);
+
+    // str: Any value as a string, written the way `print` would write it.
+    interpreter.define_native_with("str", 1, |interpreter, args| {
+        Ok(Value::Str(interpreter.show(&args[0])?.into()))
+    });
+
+    // num: Parse a string as a number, or nil if it isn't one, so input from IO.readLine()
+    // can be checked without a try. Surrounding whitespace is ignored, and numbers pass through.
+    interpreter.define_native("num", 1, |args| match &args[0] {
+        Value::Number(n) => Ok(Value::Number(*n)),
+        Value::Str(s) => Ok(parse_number(s.trim()).map_or(Value::Nil, Value::Number)),
+        other => loxerr!(
+            LX0513,
+            "Argument {} to '{}' must be {}, got {}",
+            1,
+            "num",
+            "a string or number",
+            other.type_name()
+        ),
+    });
+}
+
+// parse_number: A finite decimal number, with an optional sign and exponent. Rust also
+// parses "inf" and "NaN", which Lox has no literals for, so those are rejected.
+fn parse_number(s: &str) -> Option<f64> {
+    match s.parse::<f64>() {
+        Ok(n) if n.is_finite() => Some(n),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::LoxError;
+    use crate::stdlib::tests::{eval, eval_err};
+
+    macro_rules! string_test {
+        ( $name:ident, $src:expr => $printed:expr ) => {
+            #[test]
+            fn $name() -> Result<(), LoxError> {
+                assert_eq!(eval($src)?, $printed, "Input was {:?}", $src);
+                Ok(())
+            }
+        };
+    }
+
+    string_test!(len_of_string, "String.len(\"hello\");" => "5");
+    string_test!(len_counts_chars, "String.len(\"héllo\");" => "5");
+    string_test!(len_of_empty, "String.len(\"\");" => "0");
+    string_test!(substr_middle, "String.substr(\"hello\", 1, 3);" => "ell");
+    string_test!(substr_empty, "String.substr(\"hello\", 5, 0);" => "");
+    string_test!(upper_case, "String.upper(\"MiXed 1\");" => "MIXED 1");
+    string_test!(lower_case, "String.lower(\"MiXed 1\");" => "mixed 1");
+    string_test!(index_of_found, "String.indexOf(\"héllo\", \"llo\");" => "2");
+    string_test!(index_of_missing, "String.indexOf(\"hello\", \"z\");" => "-1");
+    string_test!(split_on_separator, "String.split(\"a,b,,c\", \",\");" => "[\"a\", \"b\", \"\", \"c\"]");
+    string_test!(split_into_chars, "String.split(\"abc\", \"\");" => "[\"a\", \"b\", \"c\"]");
+    string_test!(split_result_is_list, "String.split(\"a b\", \" \")[1];" => "b");
+    string_test!(str_of_number, "str(1.5) + \"!\";" => "1.5!");
+    string_test!(str_of_values, "str(nil) + str(true) + str([1, \"a\"]);" => "niltrue[1, \"a\"]");
+    string_test!(str_of_string, "str(\"a\");" => "a");
+    string_test!(num_of_string, "num(\"3.5\") + 1;" => "4.5");
+    string_test!(num_trims_whitespace, "num(\" -2e3  \");" => "-2000");
+    string_test!(num_of_number, "num(7);" => "7");
+    string_test!(num_round_trips_str, "num(str(0.1 + 0.2)) == 0.1 + 0.2;" => "true");
+    string_test!(num_of_garbage_is_nil, "[num(\"12abc\"), num(\"\"), num(\"inf\"), num(\"NaN\")];" => "[nil, nil, nil, nil]");
+
+    #[test]
+    fn argument_errors() {
+        assert!(eval_err("String.len(1);")
+            .contains("Argument 1 to 'String.len' must be a string, got number"));
+        assert!(eval_err("String.upper(nil);")
+            .contains("Argument 1 to 'String.upper' must be a string, got nil"));
+        assert!(eval_err("String.split(\"a\", 1);")
+            .contains("Argument 2 to 'String.split' must be a string"));
+        assert!(
+            eval_err("String.substr(\"abc\", 0.5, 1);").contains("must be a non-negative integer")
+        );
+        assert!(
+            eval_err("String.substr(\"abc\", -1, 1);").contains("must be a non-negative integer")
+        );
+        assert!(eval_err("num(nil);")
+            .contains("Argument 1 to 'num' must be a string or number, got nil"));
+    }
+
+    #[test]
+    fn substr_out_of_range() {
+        let err = eval_err("\nString.substr(\"abc\", 2, 2);");
+        assert!(
+            err.contains("[line 2] String.substr(2, 2) is out of range for string of length 3"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn arity_is_checked() {
+        assert!(eval_err("String.substr(\"abc\", 1);").contains("Expected 3 arguments but got 2"));
+    }
+}
@@ -0,0 +1,74 @@
+// Weak reference natives: `weakref(obj)`, whose handle's `get()` method returns the object
+// until it is freed and nil after, and `gc()`, which runs the cycle collector on demand. The
+// pair lets scripts keep caches that don't keep their entries alive, and lets tests check
+// what the collector frees.
+
+use std::rc::Rc;
+
+use crate::gc;
+use crate::interpreter::Interpreter;
+use crate::value::{NativeFunction, Value, WeakRef};
+
+pub fn register(interpreter: &mut Interpreter) {
+    interpreter.define_native("weakref", 1, |args| match WeakRef::new(&args[0]) {
+        Some(weak) => Ok(Value::WeakRef(Rc::new(weak))),
+        None => loxerr!(
+            LX0513,
+            "Argument {} to '{}' must be {}, got {}",
+            1,
+            "weakref",
+            "a list, map, function, class or instance",
+            args[0].type_name()
+        ),
+    });
+
+    // gc: Collect cycles now rather than when the collector next decides to, returning how
+    // many objects were freed.
+    interpreter.define_native("gc", 0, |_| Ok(Value::Number(gc::collect() as f64)));
+}
+
+pub const METHODS: [&str; 1] = ["get"];
+
+// method: The method `name` bound to `weak`, or None if weak references have no such method.
+pub fn method(weak: &Rc<WeakRef>, name: &str) -> Option<Value> {
+    let weak = weak.clone();
+    match name {
+        "get" => Some(NativeFunction::from_fn(name, 0, move |_| Ok(weak.get()))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::LoxError;
+    use crate::stdlib::tests::{eval, eval_err};
+
+    macro_rules! weakref_test {
+        ( $name:ident, $src:expr => $printed:expr ) => {
+            #[test]
+            fn $name() -> Result<(), LoxError> {
+                assert_eq!(eval($src)?, $printed, "Input was {:?}", $src);
+                Ok(())
+            }
+        };
+    }
+
+    weakref_test!(get_while_alive, "var xs = [1]; var w = weakref(xs); w.get();" => "[1]");
+    weakref_test!(get_is_the_same_object, "class A {} var a = A(); weakref(a).get() == a;" => "true");
+    weakref_test!(nil_once_freed, "var w = weakref([1]); w.get();" => "nil");
+    weakref_test!(nil_once_dropped, "var xs = [1]; var w = weakref(xs); xs = nil; w.get();" => "nil");
+    weakref_test!(nil_once_cycle_collected, "class Node {} var n = Node(); n.self = n; var w = weakref(n); n = nil; var before = w.get() != nil; gc(); [before, w.get()];" => "[true, nil]");
+    weakref_test!(gc_counts_freed, "var xs = []; xs.push(xs); xs = nil; gc() > 0;" => "true");
+    weakref_test!(printed, "[type(weakref({})), weakref({})];" => "[\"weakref\", <weakref>]");
+
+    #[test]
+    fn weakref_errors() {
+        let err = eval_err("weakref(1);");
+        assert!(
+            err.contains("Argument 1 to 'weakref' must be a list, map, function, class or instance, got number"),
+            "{}",
+            err
+        );
+        assert!(eval_err("weakref([]).set(1);").contains("Undefined property 'set' on weakref"));
+    }
+}
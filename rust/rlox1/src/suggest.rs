@@ -0,0 +1,106 @@
+// Suggestions for names that aren't defined: the defined name most like the one used, in
+// case it was a typo, e.g. "did you mean 'counter'?" after "Undefined variable 'countr'".
+
+use crate::error::LoxError;
+
+// did_you_mean: `err`, about `name` not being defined, with a suggestion of whichever of
+// `candidates` is most like it, if one is close enough to be what was meant.
+pub fn did_you_mean<'a, I>(err: LoxError, name: &str, candidates: I) -> LoxError
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    match closest(name, candidates) {
+        Some(candidate) => err.help(&format!("did you mean '{}'?", candidate)),
+        None => err,
+    }
+}
+
+// closest: The candidate the fewest edits away from `name`, if that is few enough: at most a
+// third of its length, so short names aren't taken for typos of anything. Names differing
+// only in case are the closest of all. Ties go to the first in alphabetical order, so that
+// the suggestion doesn't depend on the order of a HashMap.
+pub fn closest<'a, I>(name: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let limit = name.chars().count() / 3;
+    candidates
+        .into_iter()
+        .filter(|&candidate| candidate != name)
+        .filter_map(|candidate| {
+            let edits = if candidate.to_lowercase() == name.to_lowercase() {
+                0
+            } else {
+                distance(name, candidate)
+            };
+            if edits <= limit {
+                Some((edits, candidate))
+            } else {
+                None
+            }
+        })
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+// distance: The Levenshtein distance between `a` and `b`: how many characters must be
+// inserted, deleted or replaced to turn one into the other.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // The distances from the part of `a` seen so far to each prefix of `b`.
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let replaced = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::suggest::{closest, did_you_mean, distance};
+
+    #[test]
+    fn distance_counts_edits() {
+        assert_eq!(distance("counter", "counter"), 0);
+        assert_eq!(distance("countr", "counter"), 1);
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("", "abc"), 3);
+        assert_eq!(distance("héllo", "hello"), 1);
+    }
+
+    #[test]
+    fn only_close_names_are_suggested() {
+        let names = ["counter", "total", "print", "Point", "x"];
+        assert_eq!(closest("countr", names), Some("counter"));
+        assert_eq!(closest("totl", names), Some("total"));
+        assert_eq!(closest("point", names), Some("Point"));
+        // Too short to be sure of, too different, or the name itself.
+        assert_eq!(closest("y", names), None);
+        assert_eq!(closest("sum", names), None);
+        assert_eq!(closest("x", names), None);
+        // Of two as close as each other, the first alphabetically, whatever the order.
+        assert_eq!(closest("bat", ["cat", "bag", "hat"]), Some("bag"));
+    }
+
+    #[test]
+    fn suggestions_follow_the_message() {
+        let err = lox_error!(LX0301, "[line {}] Undefined variable '{}'", 2, "countr");
+        let suggested = did_you_mean(err.clone(), "countr", vec!["counter"]);
+        assert_eq!(
+            suggested.to_string(),
+            "[line 2] Undefined variable 'countr'; did you mean 'counter'?"
+        );
+        assert_eq!(suggested.code(), Some("LX0301"));
+        let unchanged = did_you_mean(err, "countr", vec!["total"]);
+        assert_eq!(
+            unchanged.to_string(),
+            "[line 2] Undefined variable 'countr'."
+        );
+    }
+}
@@ -0,0 +1,728 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+use crate::ast::FunctionDecl;
+use crate::environment::{Scope, ScopeRef};
+use crate::error::LoxError;
+use crate::gc;
+use crate::interpreter::Interpreter;
+use crate::resolver::Slots;
+
+pub type NativeFn = dyn Fn(&mut Interpreter, &[Value]) -> Result<Value, LoxError>;
+
+// VARIADIC: The arity of a native that takes any number of arguments, e.g. `List.of`.
+pub const VARIADIC: usize = usize::MAX;
+
+// NativeFunction: A function implemented in Rust and callable from Lox. The interpreter
+// checks the arity before calling `func`, so implementations may index `args` freely, unless
+// the arity is VARIADIC. Most
+// natives ignore the interpreter; it is there for those that need to run Lox code, e.g. a
+// key's `hash()` method.
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: usize,
+    pub func: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    // from_fn: A native that only needs its arguments, the kind hosts and most of the
+    // standard library define.
+    pub fn from_fn<F>(name: &str, arity: usize, func: F) -> Value
+    where
+        F: Fn(&[Value]) -> Result<Value, LoxError> + 'static,
+    {
+        Value::Native(Rc::new(NativeFunction {
+            name: name.to_string(),
+            arity,
+            func: Box::new(move |_, args| func(args)),
+        }))
+    }
+
+    // accepts: Whether the native can be called with `count` arguments.
+    pub fn accepts(&self, count: usize) -> bool {
+        self.arity == VARIADIC || self.arity == count
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.arity {
+            VARIADIC => write!(f, "NativeFunction({}/...)", self.name),
+            arity => write!(f, "NativeFunction({}/{})", self.name, arity),
+        }
+    }
+}
+
+// LoxFunction: A function declared in Lox, together with the scope it was declared in.
+// Calls run the body in a fresh scope for the parameters, inside that closure, so the body
+// sees the locals around its declaration for as long as the function lives.
+pub struct LoxFunction {
+    pub declaration: Rc<FunctionDecl>,
+    pub closure: Option<ScopeRef>,
+    // slots: Where the locals are of the program the function was declared in, which its
+    // body runs with, as the interpreter only keeps those of the program running.
+    pub slots: Rc<Slots>,
+    // is_initializer: Set for a class's `init` method, which always returns `this`.
+    pub is_initializer: bool,
+}
+
+impl LoxFunction {
+    pub fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    // bind: The method bound to `instance`: the same function, inside one more scope that
+    // holds `this`.
+    pub fn bind(&self, instance: Value) -> Value {
+        Value::function(LoxFunction {
+            declaration: self.declaration.clone(),
+            closure: Some(Scope::new_ref(vec![instance], self.closure.clone())),
+            slots: self.slots.clone(),
+            is_initializer: self.is_initializer,
+        })
+    }
+}
+
+// A closure can hold the function itself (e.g. a local recursive function), so Debug only
+// shows the name rather than following the scope chain.
+impl fmt::Debug for LoxFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LoxFunction({})", self.declaration.name.typ)
+    }
+}
+
+// LoxClass: A class declared in Lox. Methods, getters and setters are looked up on the class
+// itself, then up the chain of superclasses.
+pub struct LoxClass {
+    pub name: String,
+    pub superclass: Option<Rc<LoxClass>>,
+    pub methods: HashMap<String, Rc<LoxFunction>>,
+    // getters, setters: Run on reading or assigning the property of the same name, e.g.
+    // `area { ... }` and `set area(value) { ... }`.
+    pub getters: HashMap<String, Rc<LoxFunction>>,
+    pub setters: HashMap<String, Rc<LoxFunction>>,
+}
+
+impl LoxClass {
+    pub fn find_method(&self, name: &str) -> Option<Rc<LoxFunction>> {
+        self.find(|class| &class.methods, name)
+    }
+
+    pub fn find_getter(&self, name: &str) -> Option<Rc<LoxFunction>> {
+        self.find(|class| &class.getters, name)
+    }
+
+    pub fn find_setter(&self, name: &str) -> Option<Rc<LoxFunction>> {
+        self.find(|class| &class.setters, name)
+    }
+
+    fn find(
+        &self,
+        table: fn(&LoxClass) -> &HashMap<String, Rc<LoxFunction>>,
+        name: &str,
+    ) -> Option<Rc<LoxFunction>> {
+        match table(self).get(name) {
+            Some(function) => Some(function.clone()),
+            None => self.superclass.as_ref()?.find(table, name),
+        }
+    }
+
+    // all_methods: Every method an instance of this class can call, inherited ones included,
+    // by name.
+    pub fn all_methods(&self) -> HashMap<String, Rc<LoxFunction>> {
+        let mut methods = match &self.superclass {
+            Some(superclass) => superclass.all_methods(),
+            None => HashMap::new(),
+        };
+        methods.extend(self.methods.iter().map(|(k, v)| (k.clone(), v.clone())));
+        methods
+    }
+
+    // all_getters: Every getter an instance of this class has, inherited ones included.
+    pub fn all_getters(&self) -> Vec<&str> {
+        let mut getters = match &self.superclass {
+            Some(superclass) => superclass.all_getters(),
+            None => Vec::new(),
+        };
+        getters.extend(self.getters.keys().map(String::as_str));
+        getters
+    }
+
+    // is_or_inherits: Whether this is `class` or one of its subclasses, however indirect.
+    pub fn is_or_inherits(&self, class: &LoxClass) -> bool {
+        if std::ptr::eq(self, class) {
+            return true;
+        }
+        match &self.superclass {
+            Some(superclass) => superclass.is_or_inherits(class),
+            None => false,
+        }
+    }
+}
+
+impl fmt::Debug for LoxClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LoxClass({})", self.name)
+    }
+}
+
+// LoxInstance: An object made by calling a class. Fields are created by assigning to them.
+pub struct LoxInstance {
+    pub class: Rc<LoxClass>,
+    pub fields: HashMap<String, Value>,
+}
+
+impl LoxInstance {
+    pub fn new(class: Rc<LoxClass>) -> Self {
+        LoxInstance {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+}
+
+// Fields can refer back to the instance, so Debug stops at the class name.
+impl fmt::Debug for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LoxInstance({})", self.class.name)
+    }
+}
+
+// LoxGenerator: What calling a generator function makes: an iterator that runs the function's
+// body up to each `yield` in turn.
+pub struct LoxGenerator {
+    pub declaration: Rc<FunctionDecl>,
+    pub slots: Rc<Slots>,
+    pub state: GeneratorState,
+}
+
+pub enum GeneratorState {
+    // Suspended: Paused at a `yield`, or before the first statement. `scope` is the scope
+    // chain to carry on in and `path` leads back to the statement it paused in, innermost
+    // statement first.
+    Suspended {
+        scope: ScopeRef,
+        path: Vec<ResumePoint>,
+    },
+    Running,
+    Done,
+}
+
+// ResumePoint: How to re-enter one of the statements around a paused `yield`, without
+// repeating the parts of it that already ran.
+pub enum ResumePoint {
+    // Statement: The position of the statement to re-enter in its block or body.
+    Statement(usize),
+    // If: Which branch was taken.
+    If { then: bool },
+    // Try: Whether the body had already failed over to the handler.
+    Try { in_handler: bool },
+    // ForIn: What the loop is over, and how far through it the paused pass is.
+    ForIn { iterable: Value, position: usize },
+    // Loop: A pass of a while or C-style for loop, which carries on with the increment.
+    Loop,
+    // Yield: The `yield` itself, which is done once resumed.
+    Yield,
+}
+
+// The scope chain can hold the generator itself, so Debug stops at the function's name.
+impl fmt::Debug for LoxGenerator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LoxGenerator({})", self.declaration.name.typ)
+    }
+}
+
+// Namespace: A named group of library values, e.g. `Math`, whose members are read like
+// properties: `Math.sqrt(2)`. Scripts can't add or change members.
+pub struct Namespace {
+    pub name: String,
+    members: HashMap<String, Value>,
+}
+
+impl Namespace {
+    pub fn new(name: &str) -> Self {
+        Namespace {
+            name: name.to_string(),
+            members: HashMap::new(),
+        }
+    }
+
+    pub fn define(&mut self, name: &str, value: Value) {
+        self.members.insert(name.to_string(), value);
+    }
+
+    // define_native: Add a native member. It is named after the namespace as well, e.g.
+    // `Math.sqrt`, both when printed and in argument errors.
+    pub fn define_native<F>(&mut self, name: &str, arity: usize, func: F)
+    where
+        F: Fn(&[Value]) -> Result<Value, LoxError> + 'static,
+    {
+        let qualified = format!("{}.{}", self.name, name);
+        self.define(name, NativeFunction::from_fn(&qualified, arity, func));
+    }
+
+    // define_native_with: Like define_native, for a native that needs the interpreter.
+    pub fn define_native_with<F>(&mut self, name: &str, arity: usize, func: F)
+    where
+        F: Fn(&mut Interpreter, &[Value]) -> Result<Value, LoxError> + 'static,
+    {
+        let native = NativeFunction {
+            name: format!("{}.{}", self.name, name),
+            arity,
+            func: Box::new(func),
+        };
+        self.define(name, Value::Native(Rc::new(native)));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.members.get(name).cloned()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.members.keys().map(String::as_str)
+    }
+}
+
+impl fmt::Debug for Namespace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Namespace({})", self.name)
+    }
+}
+
+// WeakRef: A reference to an object that doesn't keep it alive, made by `weakref(obj)`. Only
+// values the collector tracks can be referred to weakly.
+pub enum WeakRef {
+    List(Weak<RefCell<Vec<Value>>>),
+    Map(Weak<RefCell<LoxMap>>),
+    Function(Weak<LoxFunction>),
+    Class(Weak<LoxClass>),
+    Instance(Weak<RefCell<LoxInstance>>),
+}
+
+impl WeakRef {
+    pub fn new(value: &Value) -> Option<Self> {
+        Some(match value {
+            Value::List(rc) => WeakRef::List(Rc::downgrade(rc)),
+            Value::Map(rc) => WeakRef::Map(Rc::downgrade(rc)),
+            Value::Function(rc) => WeakRef::Function(Rc::downgrade(rc)),
+            Value::Class(rc) => WeakRef::Class(Rc::downgrade(rc)),
+            Value::Instance(rc) => WeakRef::Instance(Rc::downgrade(rc)),
+            _ => return None,
+        })
+    }
+
+    // get: The object, or nil once it has been freed.
+    pub fn get(&self) -> Value {
+        let value = match self {
+            WeakRef::List(weak) => weak.upgrade().map(Value::List),
+            WeakRef::Map(weak) => weak.upgrade().map(Value::Map),
+            WeakRef::Function(weak) => weak.upgrade().map(Value::Function),
+            WeakRef::Class(weak) => weak.upgrade().map(Value::Class),
+            WeakRef::Instance(weak) => weak.upgrade().map(Value::Instance),
+        };
+        value.unwrap_or(Value::Nil)
+    }
+}
+
+impl fmt::Debug for WeakRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "WeakRef")
+    }
+}
+
+// MapKey: The hashable form of a value usable as a map key. Numbers hash by bit pattern,
+// with -0 folded into 0 so that keys which compare equal also hash equal, and NaN is refused.
+// Instances hash by identity, unless their class has a `hash()` method: the interpreter then
+// makes a Hashed key from its result, and tells keys with the same hash apart with `equals()`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    Nil,
+    Bool(bool),
+    Number(u64),
+    Str(Rc<str>),
+    Instance(usize),
+    Hashed(Box<MapKey>),
+}
+
+impl MapKey {
+    pub fn from_value(value: &Value) -> Result<MapKey, LoxError> {
+        match value {
+            Value::Nil => Ok(MapKey::Nil),
+            Value::Bool(b) => Ok(MapKey::Bool(*b)),
+            Value::Number(n) if *n == 0.0 => Ok(MapKey::Number(0.0f64.to_bits())),
+            // NaN isn't equal to itself, so a value stored under it could never be looked up.
+            Value::Number(n) if n.is_nan() => {
+                loxerr!(LX0508, "{} can't be used as a map key", "NaN")
+            }
+            Value::Number(n) => Ok(MapKey::Number(n.to_bits())),
+            Value::Str(s) => Ok(MapKey::Str(s.clone())),
+            // The map holds on to its keys, so the address stays unique while it's in use.
+            Value::Instance(instance) => Ok(MapKey::Instance(Rc::as_ptr(instance) as usize)),
+            other => loxerr!(
+                LX0508,
+                "{} can't be used as a map key",
+                format!("A {}", other.type_name())
+            ),
+        }
+    }
+}
+
+// LoxMap: A hash map that remembers insertion order, so iteration and printing are
+// deterministic. Finding a key can mean running Lox code, so lookups are split in two: the
+// interpreter asks for the entries stored under a key's hash, decides which (if any) is the
+// key it wants, then reads or updates that entry by position.
+#[derive(Debug, Clone, Default)]
+pub struct LoxMap {
+    entries: Vec<(Value, Value)>,
+    // The positions of the entries with each hashed key. Only keys made from `hash()` can
+    // share a bucket.
+    index: HashMap<MapKey, Vec<usize>>,
+}
+
+impl LoxMap {
+    pub fn new() -> Self {
+        LoxMap::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // candidates: The positions and keys of the entries stored under `hashed`.
+    pub fn candidates(&self, hashed: &MapKey) -> Vec<(usize, Value)> {
+        match self.index.get(hashed) {
+            Some(positions) => positions
+                .iter()
+                .map(|&i| (i, self.entries[i].0.clone()))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn value_at(&self, position: usize) -> Value {
+        self.entries[position].1.clone()
+    }
+
+    // set_at: Update an entry. The key keeps its original position.
+    pub fn set_at(&mut self, position: usize, value: Value) {
+        self.entries[position].1 = value;
+    }
+
+    // insert: Add an entry for a key that isn't in the map yet.
+    pub fn insert(&mut self, hashed: MapKey, key: Value, value: Value) {
+        self.index
+            .entry(hashed)
+            .or_default()
+            .push(self.entries.len());
+        self.entries.push((key, value));
+    }
+
+    pub fn remove_at(&mut self, position: usize) -> Value {
+        let (_, value) = self.entries.remove(position);
+        for positions in self.index.values_mut() {
+            positions.retain(|&i| i != position);
+            for i in positions.iter_mut() {
+                if *i > position {
+                    *i -= 1;
+                }
+            }
+        }
+        self.index.retain(|_, positions| !positions.is_empty());
+        value
+    }
+
+    // key_at: The key at `position` in insertion order.
+    pub fn key_at(&self, position: usize) -> Option<Value> {
+        self.entries.get(position).map(|(key, _)| key.clone())
+    }
+
+    pub fn keys(&self) -> Vec<Value> {
+        self.entries.iter().map(|(key, _)| key.clone()).collect()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.entries.iter().map(|(_, value)| value)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.index.clear();
+    }
+}
+
+// Value: The runtime representation of Lox values. Lists, maps and instances are reference
+// types: copies of a Value share the same underlying object.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    Str(Rc<str>),
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<LoxMap>>),
+    Native(Rc<NativeFunction>),
+    Function(Rc<LoxFunction>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
+    Generator(Rc<RefCell<LoxGenerator>>),
+    Namespace(Rc<Namespace>),
+    WeakRef(Rc<WeakRef>),
+}
+
+impl Value {
+    // list, map, function, class, instance and generator: Create container values,
+    // registering them with the cycle collector. Containers should always be made through
+    // these.
+    pub fn list(items: Vec<Value>) -> Self {
+        let list = Rc::new(RefCell::new(items));
+        gc::track_list(&list);
+        Value::List(list)
+    }
+
+    pub fn map(map: LoxMap) -> Self {
+        let map = Rc::new(RefCell::new(map));
+        gc::track_map(&map);
+        Value::Map(map)
+    }
+
+    pub fn function(function: LoxFunction) -> Self {
+        let function = Rc::new(function);
+        gc::track_function(&function);
+        Value::Function(function)
+    }
+
+    pub fn class(class: LoxClass) -> Self {
+        let class = Rc::new(class);
+        gc::track_class(&class);
+        Value::Class(class)
+    }
+
+    pub fn instance(instance: LoxInstance) -> Self {
+        let instance = Rc::new(RefCell::new(instance));
+        gc::track_instance(&instance);
+        Value::Instance(instance)
+    }
+
+    pub fn generator(generator: LoxGenerator) -> Self {
+        let generator = Rc::new(RefCell::new(generator));
+        gc::track_generator(&generator);
+        Value::Generator(generator)
+    }
+
+    // from_json and to_json: Read a value from JSON text, or write one as JSON text, for a
+    // host passing structured data in and out; json.rs says which values have a JSON form.
+    pub fn from_json(text: &str) -> Result<Value, LoxError> {
+        crate::json::parse(text)
+    }
+
+    pub fn to_json(&self) -> Result<String, LoxError> {
+        crate::json::stringify(self)
+    }
+
+    // is_truthy: Lox follows Ruby here: only nil and false are falsey.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    // type_name: The name of the value's type, as used in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "bool",
+            Value::Number(_) => "number",
+            Value::Str(_) => "string",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Native(_) | Value::Function(_) => "function",
+            Value::Class(_) => "class",
+            Value::Instance(_) => "instance",
+            Value::Generator(_) => "generator",
+            Value::Namespace(_) => "namespace",
+            Value::WeakRef(_) => "weakref",
+        }
+    }
+
+    // repr: The value as it would appear inside a collection; see fmt_nested.
+    pub fn repr(&self) -> String {
+        match self {
+            Value::Str(s) => format!("{:?}", s),
+            _ => format!("{}", self),
+        }
+    }
+
+    // fmt_nested: Strings are quoted when they appear inside a collection, so that
+    // `[1, "1"]` does not print as `[1, 1]`.
+    fn fmt_nested(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Str(s) => write!(f, "{:?}", s),
+            _ => write!(f, "{}", self),
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => Rc::ptr_eq(a, b) || a == b,
+            (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b),
+            (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b),
+            (Value::Native(a), Value::Native(b)) => Rc::ptr_eq(a, b),
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Class(a), Value::Class(b)) => Rc::ptr_eq(a, b),
+            (Value::Instance(a), Value::Instance(b)) => Rc::ptr_eq(a, b),
+            (Value::Generator(a), Value::Generator(b)) => Rc::ptr_eq(a, b),
+            (Value::Namespace(a), Value::Namespace(b)) => Rc::ptr_eq(a, b),
+            (Value::WeakRef(a), Value::WeakRef(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.fmt_nested(f)?;
+                }
+                write!(f, "]")
+            }
+            Value::Map(map) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.borrow().entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    key.fmt_nested(f)?;
+                    write!(f, ": ")?;
+                    value.fmt_nested(f)?;
+                }
+                write!(f, "}}")
+            }
+            Value::Native(native) => write!(f, "<native fn {}>", native.name),
+            Value::Function(function) => write!(f, "<fn {}>", function.declaration.name.typ),
+            Value::Class(class) => write!(f, "<class {}>", class.name),
+            Value::Instance(instance) => write!(f, "<{} instance>", instance.borrow().class.name),
+            Value::Generator(generator) => {
+                write!(f, "<generator {}>", generator.borrow().declaration.name.typ)
+            }
+            Value::Namespace(namespace) => write!(f, "<namespace {}>", namespace.name),
+            Value::WeakRef(_) => write!(f, "<weakref>"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::value::{LoxMap, MapKey, Value};
+
+    #[test]
+    fn display_values() {
+        assert_eq!(format!("{}", Value::Nil), "nil");
+        assert_eq!(format!("{}", Value::Bool(true)), "true");
+        assert_eq!(format!("{}", Value::Number(3.0)), "3");
+        assert_eq!(format!("{}", Value::Number(2.5)), "2.5");
+        assert_eq!(format!("{}", Value::Str("hi".into())), "hi");
+    }
+
+    #[test]
+    fn display_lists() {
+        let inner = Value::list(vec![Value::Nil]);
+        let list = Value::list(vec![Value::Number(1.0), Value::Str("two".into()), inner]);
+        assert_eq!(format!("{}", list), "[1, \"two\", [nil]]");
+        assert_eq!(format!("{}", Value::list(vec![])), "[]");
+    }
+
+    // set, get: Map updates and lookups for keys that hash without running Lox code.
+    fn set(map: &mut LoxMap, key: Value, value: Value) {
+        let hashed = MapKey::from_value(&key).unwrap();
+        match map.candidates(&hashed).first() {
+            Some(&(i, _)) => map.set_at(i, value),
+            None => map.insert(hashed, key, value),
+        }
+    }
+
+    fn get(map: &LoxMap, key: Value) -> Option<Value> {
+        let hashed = MapKey::from_value(&key).unwrap();
+        let (i, _) = *map.candidates(&hashed).first()?;
+        Some(map.value_at(i))
+    }
+
+    #[test]
+    fn display_maps() {
+        let mut map = LoxMap::new();
+        set(&mut map, Value::Str("a".into()), Value::Number(1.0));
+        set(&mut map, Value::Number(2.0), Value::list(vec![]));
+        assert_eq!(format!("{}", Value::map(map)), "{\"a\": 1, 2: []}");
+        assert_eq!(format!("{}", Value::map(LoxMap::new())), "{}");
+    }
+
+    #[test]
+    fn map_keeps_insertion_order() {
+        let mut map = LoxMap::new();
+        for key in &["c", "a", "b"] {
+            set(&mut map, Value::Str((*key).into()), Value::Nil);
+        }
+        set(&mut map, Value::Str("c".into()), Value::Bool(true));
+        map.remove_at(1);
+        let keys: Vec<String> = map.keys().iter().map(|k| format!("{}", k)).collect();
+        assert_eq!(keys, vec!["c", "b"]);
+        assert_eq!(get(&map, Value::Str("b".into())), Some(Value::Nil));
+        assert_eq!(get(&map, Value::Str("c".into())), Some(Value::Bool(true)));
+        assert_eq!(get(&map, Value::Str("a".into())), None);
+    }
+
+    #[test]
+    fn map_key_normalizes_zero() {
+        let mut map = LoxMap::new();
+        set(&mut map, Value::Number(0.0), Value::Bool(true));
+        assert_eq!(get(&map, Value::Number(-0.0)), Some(Value::Bool(true)));
+    }
+
+    #[test]
+    fn unhashable_keys() {
+        let err = MapKey::from_value(&Value::list(vec![])).unwrap_err();
+        assert!(format!("{}", err).contains("A list can't be used as a map key"));
+    }
+
+    #[test]
+    fn nan_key_is_refused() {
+        let err = MapKey::from_value(&Value::Number(f64::NAN)).unwrap_err();
+        assert!(format!("{}", err).contains("NaN can't be used as a map key"));
+    }
+
+    #[test]
+    fn truthiness() {
+        assert!(!Value::Nil.is_truthy());
+        assert!(!Value::Bool(false).is_truthy());
+        assert!(Value::Bool(true).is_truthy());
+        assert!(Value::Number(0.0).is_truthy());
+        assert!(Value::Str("".into()).is_truthy());
+        assert!(Value::list(vec![]).is_truthy());
+    }
+
+    #[test]
+    fn lists_compare_by_identity() {
+        let a = Value::list(vec![Value::Number(1.0)]);
+        let b = Value::list(vec![Value::Number(1.0)]);
+        assert_eq!(a, a.clone());
+        assert_ne!(a, b);
+    }
+}
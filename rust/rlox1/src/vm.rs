@@ -0,0 +1,770 @@
+// VM: Runs compiled chunks on a value stack, the clox design from Part III of the book. It
+// is an alternative engine to the tree-walking interpreter, sharing its front end, its values
+// and its error messages, so the two can be checked against each other.
+//
+// Memory: clox needs its own mark-sweep collector because its objects are raw pointers. Here
+// the stack and globals hold the interpreter's reference-counted values, which are freed as
+// soon as nothing refers to them, and the cycles reference counting misses are left to the
+// collector in gc.rs, which finds its roots without being told them. The VM can't create
+// containers yet; once it can, it should call gc::maybe_collect, as the interpreter does.
+// What the VM does own is its intern table, which it sweeps as clox does, when it has grown.
+//
+// Strings are interned, as in clox: every string the VM loads or makes is looked up in one
+// table, so equal strings share an allocation, `==` compares them by pointer, and globals are
+// found by the address of their name rather than by hashing it.
+
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasherDefault, Hash, Hasher};
+use std::rc::Rc;
+
+use crate::ast::Stmt;
+use crate::chunk::{Chunk, OpCode};
+use crate::compiler::Compiler;
+use crate::error::LoxError;
+use crate::interpreter::operand_error;
+use crate::interrupt;
+use crate::profile::Profile;
+use crate::resolver::Resolver;
+use crate::suggest;
+use crate::value::Value;
+use slot::{as_number, equal, to_slot, to_value, Slot};
+
+// slot: What the stack and globals hold: values themselves, or NaN-boxed values when the
+// `nan-boxing` feature is on. The VM only touches slots through this module.
+#[cfg(not(feature = "nan-boxing"))]
+mod slot {
+    use std::rc::Rc;
+
+    use crate::value::Value;
+
+    pub type Slot = Value;
+
+    pub fn to_slot(value: Value) -> Slot {
+        value
+    }
+
+    pub fn to_value(slot: Slot) -> Value {
+        slot
+    }
+
+    pub fn as_number(slot: &Slot) -> Option<f64> {
+        match slot {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    // equal: Every string in the VM is interned, so equal strings are the same string.
+    pub fn equal(a: &Slot, b: &Slot) -> bool {
+        match (a, b) {
+            (Value::Str(a), Value::Str(b)) => Rc::ptr_eq(a, b),
+            _ => a == b,
+        }
+    }
+}
+
+#[cfg(feature = "nan-boxing")]
+mod slot {
+    use crate::nanbox::NanBox;
+    use crate::value::Value;
+
+    pub type Slot = NanBox;
+
+    pub fn to_slot(value: Value) -> Slot {
+        NanBox::from(value)
+    }
+
+    pub fn to_value(slot: Slot) -> Value {
+        Value::from(slot)
+    }
+
+    pub fn as_number(slot: &Slot) -> Option<f64> {
+        slot.as_number()
+    }
+
+    pub fn equal(a: &Slot, b: &Slot) -> bool {
+        a == b
+    }
+}
+
+// Vm: The VM's state between programs. Like the interpreter's, globals defined by one call
+// to `interpret` are visible to the next.
+pub struct Vm {
+    stack: Vec<Slot>,
+    globals: HashMap<Name, Slot, Fnv>,
+    strings: Strings,
+    // How often each opcode has run, indexed by its byte, when profiling.
+    counts: Option<Vec<u64>>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Vm {
+            stack: Vec::new(),
+            globals: HashMap::default(),
+            strings: Strings::default(),
+            counts: None,
+        }
+    }
+
+    // profile: Count every instruction executed from now on.
+    pub fn profile(&mut self) {
+        self.counts = Some(vec![0; OpCode::COUNT]);
+    }
+
+    // take_profile: The instructions executed since `profile` was called, by opcode.
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        let counts = self.counts.take()?;
+        let mut profile = Profile::new("instructions");
+        for (byte, count) in counts.into_iter().enumerate() {
+            if count > 0 {
+                let op = OpCode::from_byte(byte as u8).expect("a valid opcode");
+                profile.add(&format!("{:?}", op), count);
+            }
+        }
+        Some(profile)
+    }
+
+    // interpret: Compile a program and run it. The resolver still runs first, so programs
+    // the tree-walker would reject statically are rejected here as well.
+    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<(), LoxError> {
+        Resolver::new().resolve(statements)?;
+        let chunk = Compiler::compile(statements)?;
+        self.run(&chunk).map(|_| ())
+    }
+
+    // run: Execute a chunk to its return, giving back whatever it left on top of the stack.
+    pub fn run(&mut self, chunk: &Chunk) -> Result<Value, LoxError> {
+        // There are no calls yet, so an error's trace is just the script.
+        let mut start = 0;
+        let result = self.execute(chunk, &mut start).map_err(|err| {
+            err.at(chunk.span_at(start))
+                .unwind_from(None, None)
+                .at_runtime()
+        });
+        self.stack.clear();
+        self.strings.sweep();
+        result
+    }
+
+    // execute: The dispatch loop. It is tuned for throughput: operands are read from a local
+    // copy of the code, source lines are only looked up when an error needs one, and each
+    // operator has its own arm, so an instruction is decoded exactly once. `current` is left
+    // holding where the last instruction run starts, for pointing an error at its token.
+    fn execute(&mut self, chunk: &Chunk, current: &mut usize) -> Result<Value, LoxError> {
+        let code = &chunk.code[..];
+        let mut constants = Vec::with_capacity(chunk.constants.len());
+        // The interned form of each string constant, for the instructions naming globals.
+        let mut names = Vec::with_capacity(chunk.constants.len());
+        for constant in &chunk.constants {
+            match constant {
+                Value::Str(s) => {
+                    let s = self.strings.intern(s);
+                    constants.push(to_slot(Value::Str(s.clone())));
+                    names.push(Some(Name(s)));
+                }
+                other => {
+                    constants.push(to_slot(other.clone()));
+                    names.push(None);
+                }
+            }
+        }
+        let name = |index: u8| names[index as usize].as_ref().expect("a string constant");
+        let mut ip = 0;
+        loop {
+            // Where the instruction starts, for finding its line if it fails.
+            let start = ip;
+            *current = start;
+            let op = OpCode::from_byte(code[ip]).expect("a valid opcode");
+            if let Some(counts) = &mut self.counts {
+                counts[op as usize] += 1;
+            }
+            ip += 1;
+            match op {
+                OpCode::Constant => {
+                    self.stack.push(constants[code[ip] as usize].clone());
+                    ip += 1;
+                }
+                OpCode::ConstantLong => {
+                    self.stack.push(constants[read_u24(code, ip)].clone());
+                    ip += 3;
+                }
+                OpCode::Nil => self.push(Value::Nil),
+                OpCode::True => self.push(Value::Bool(true)),
+                OpCode::False => self.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal => {
+                    self.stack.push(self.stack[code[ip] as usize].clone());
+                    ip += 1;
+                }
+                OpCode::SetLocal => {
+                    self.stack[code[ip] as usize] = self.peek().clone();
+                    ip += 1;
+                }
+                OpCode::GetGlobal => {
+                    let name = name(code[ip]);
+                    ip += 1;
+                    match self.globals.get(name) {
+                        Some(value) => self.stack.push(value.clone()),
+                        None => return Err(self.undefined(&name.0, chunk.line_at(start))),
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let name = name(code[ip]).clone();
+                    ip += 1;
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                }
+                OpCode::SetGlobal => {
+                    let name = name(code[ip]);
+                    ip += 1;
+                    let value = self.peek().clone();
+                    match self.globals.get_mut(name) {
+                        Some(slot) => *slot = value,
+                        None => return Err(self.undefined(&name.0, chunk.line_at(start))),
+                    }
+                }
+                OpCode::Equal => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    self.push(Value::Bool(equal(&a, &b)));
+                }
+                OpCode::Greater => self.comparison(chunk, start, ">", |a, b| a > b)?,
+                OpCode::GreaterEqual => self.comparison(chunk, start, ">=", |a, b| a >= b)?,
+                OpCode::Less => self.comparison(chunk, start, "<", |a, b| a < b)?,
+                OpCode::LessEqual => self.comparison(chunk, start, "<=", |a, b| a <= b)?,
+                OpCode::Add => {
+                    if !self.binary(|a, b| Value::Number(a + b)) {
+                        self.concatenate(chunk.line_at(start))?;
+                    }
+                }
+                OpCode::Subtract => self.arithmetic(chunk, start, "-", |a, b| a - b)?,
+                OpCode::Multiply => self.arithmetic(chunk, start, "*", |a, b| a * b)?,
+                OpCode::Divide => self.arithmetic(chunk, start, "/", |a, b| a / b)?,
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.push(Value::Bool(!value.is_truthy()));
+                }
+                OpCode::Negate => {
+                    let value = self.pop();
+                    match as_number(&value) {
+                        Some(n) => self.push(Value::Number(-n)),
+                        None => loxerr!(
+                            LX0501,
+                            "[line {}] Operand of '{}' must be a number, got {}",
+                            chunk.line_at(start),
+                            "-",
+                            to_value(value).type_name()
+                        ),
+                    }
+                }
+                OpCode::Print => println!("{}", self.pop()),
+                OpCode::Jump => {
+                    ip += read_u16(code, ip) + 2;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = read_u16(code, ip);
+                    ip += 2;
+                    if !self.peek().is_truthy() {
+                        ip += offset;
+                    }
+                }
+                // Every loop comes back through here, so this is where Ctrl-C is noticed.
+                OpCode::Loop => {
+                    if interrupt::requested() {
+                        loxerr!(LX0603, "Interrupted")
+                    }
+                    ip = ip + 2 - read_u16(code, ip);
+                }
+                OpCode::Return => return Ok(self.stack.pop().map_or(Value::Nil, to_value)),
+                OpCode::AddLocals => {
+                    let a = &self.stack[code[ip] as usize];
+                    let b = &self.stack[code[ip + 1] as usize];
+                    ip += 2;
+                    match (as_number(a), as_number(b)) {
+                        (Some(a), Some(b)) => self.push(Value::Number(a + b)),
+                        _ => {
+                            let (a, b) = (a.clone(), b.clone());
+                            self.stack.push(a);
+                            self.stack.push(b);
+                            self.concatenate(chunk.line_at(start))?;
+                        }
+                    }
+                }
+                OpCode::AddConstant => {
+                    let constant = &constants[code[ip] as usize];
+                    ip += 1;
+                    match (as_number(self.peek()), as_number(constant)) {
+                        (Some(a), Some(b)) => {
+                            *self.stack.last_mut().expect("a value on the stack") =
+                                to_slot(Value::Number(a + b))
+                        }
+                        _ => {
+                            self.stack.push(constant.clone());
+                            self.concatenate(chunk.line_at(start))?;
+                        }
+                    }
+                }
+                OpCode::SetLocalPop => {
+                    let value = self.pop();
+                    self.stack[code[ip] as usize] = value;
+                    ip += 1;
+                }
+            }
+        }
+    }
+
+    // binary: Replace the top two slots with `f` of them, if both are numbers. The result
+    // goes where the left operand was, so the stack only shrinks by one.
+    #[inline]
+    fn binary(&mut self, f: impl FnOnce(f64, f64) -> Value) -> bool {
+        let len = self.stack.len();
+        match (
+            as_number(&self.stack[len - 2]),
+            as_number(&self.stack[len - 1]),
+        ) {
+            (Some(a), Some(b)) => {
+                self.stack.pop();
+                self.stack[len - 2] = to_slot(f(a, b));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    #[inline]
+    fn arithmetic(
+        &mut self,
+        chunk: &Chunk,
+        start: usize,
+        symbol: &str,
+        f: impl FnOnce(f64, f64) -> f64,
+    ) -> Result<(), LoxError> {
+        if self.binary(|a, b| Value::Number(f(a, b))) {
+            Ok(())
+        } else {
+            Err(self.operands_error(chunk.line_at(start), symbol, "numbers"))
+        }
+    }
+
+    #[inline]
+    fn comparison(
+        &mut self,
+        chunk: &Chunk,
+        start: usize,
+        symbol: &str,
+        f: impl FnOnce(f64, f64) -> bool,
+    ) -> Result<(), LoxError> {
+        if self.binary(|a, b| Value::Bool(f(a, b))) {
+            Ok(())
+        } else {
+            Err(self.operands_error(chunk.line_at(start), symbol, "numbers"))
+        }
+    }
+
+    // concatenate: The slow path of `+`, for operands that aren't both numbers.
+    fn concatenate(&mut self, line: usize) -> Result<(), LoxError> {
+        let b = to_value(self.pop());
+        let a = to_value(self.pop());
+        match (a, b) {
+            (Value::Str(a), Value::Str(b)) => {
+                let mut joined = String::with_capacity(a.len() + b.len());
+                joined.push_str(&a);
+                joined.push_str(&b);
+                let joined = self.strings.intern(&joined);
+                self.push(Value::Str(joined));
+                Ok(())
+            }
+            (a, b) => Err(operand_error(
+                line,
+                "+",
+                "two numbers or two strings",
+                &a,
+                &b,
+            )),
+        }
+    }
+
+    // operands_error: Pop the operands of a binary operator that can't take them, and report
+    // their types.
+    #[cold]
+    fn operands_error(&mut self, line: usize, symbol: &str, expected: &str) -> LoxError {
+        let b = to_value(self.pop());
+        let a = to_value(self.pop());
+        operand_error(line, symbol, expected, &a, &b)
+    }
+
+    // undefined: The error for using `name`, which isn't a global, suggesting the global most
+    // like it.
+    #[cold]
+    fn undefined(&self, name: &str, line: usize) -> LoxError {
+        let err = lox_error!(LX0301, "[line {}] Undefined variable '{}'", line, name);
+        suggest::did_you_mean(err, name, self.globals.keys().map(|name| &*name.0))
+    }
+
+    #[inline]
+    fn push(&mut self, value: Value) {
+        self.stack.push(to_slot(value));
+    }
+
+    #[inline]
+    fn pop(&mut self) -> Slot {
+        self.stack.pop().expect("a value on the stack")
+    }
+
+    #[inline]
+    fn peek(&self) -> &Slot {
+        self.stack.last().expect("a value on the stack")
+    }
+}
+
+// Strings: The intern table. Strings only the table refers to are swept out after each run,
+// as clox removes unmarked strings from its table before freeing them, and during a run
+// whenever the table has doubled since it was last swept, as clox collects when its heap
+// has grown by GC_HEAP_GROW_FACTOR.
+#[derive(Default)]
+struct Strings {
+    table: HashSet<Rc<str>, Fnv>,
+    // How many strings the table may hold before it is swept.
+    next_sweep: usize,
+}
+
+// MIN_SWEEP: The fewest strings the table is swept at, so a small table isn't swept over and
+// over.
+const MIN_SWEEP: usize = 1024;
+
+impl Strings {
+    fn intern(&mut self, s: &str) -> Rc<str> {
+        if let Some(interned) = self.table.get(s) {
+            return interned.clone();
+        }
+        if self.table.len() >= self.next_sweep {
+            self.sweep();
+        }
+        let interned: Rc<str> = Rc::from(s);
+        self.table.insert(interned.clone());
+        interned
+    }
+
+    fn sweep(&mut self) {
+        self.table.retain(|s| Rc::strong_count(s) > 1);
+        self.next_sweep = MIN_SWEEP.max(2 * self.table.len());
+    }
+}
+
+// Fnv1a: The hash function clox uses for its tables. It is much quicker than the standard
+// library's default on the short keys the VM hashes, and needn't resist collision attacks.
+struct Fnv1a(u64);
+
+type Fnv = BuildHasherDefault<Fnv1a>;
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+// Name: An interned string used as a global's name. Interned strings are equal only if they
+// are the same string, so names hash and compare by address.
+#[derive(Clone)]
+struct Name(Rc<str>);
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Name) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Name {}
+
+impl Hash for Name {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (Rc::as_ptr(&self.0) as *const u8 as usize).hash(state);
+    }
+}
+
+#[inline]
+fn read_u16(code: &[u8], at: usize) -> usize {
+    (code[at] as usize) << 8 | code[at + 1] as usize
+}
+
+#[inline]
+fn read_u24(code: &[u8], at: usize) -> usize {
+    (code[at] as usize) << 16 | read_u16(code, at + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::ast::StmtKind;
+    use crate::compiler::Compiler;
+    use crate::error::LoxError;
+    use crate::parser::Parser;
+    use crate::resolver::Resolver;
+    use crate::scanner::Scanner;
+    use crate::value::Value;
+    use crate::vm::{to_value, Strings, Vm, MIN_SWEEP};
+
+    // eval: Run a program on the VM and return the printed value of its final expression
+    // statement, which is left on the stack rather than popped.
+    fn eval(src: &str) -> Result<String, LoxError> {
+        let mut scanner = Scanner::new(src);
+        let mut statements = Parser::new(scanner.scan_tokens()?).parse()?;
+        Resolver::new().resolve(&statements)?;
+        let last = match statements.pop().map(|stmt| stmt.kind) {
+            Some(StmtKind::Expression(expr)) => expr,
+            _ => panic!("last statement must be an expression"),
+        };
+        let mut compiler = Compiler::new();
+        compiler.statements(&statements)?;
+        compiler.expression(&last)?;
+        Ok(format!("{}", Vm::new().run(&compiler.finish())?))
+    }
+
+    macro_rules! vm_test {
+        ( FAIL: $name:ident, $src:expr, $ct:expr ) => {
+            #[test]
+            fn $name() {
+                match eval($src) {
+                    Ok(value) => panic!("{:?} should fail, got {}", $src, value),
+                    Err(err) => assert!(format!("{}", err).contains($ct), "{}", err),
+                }
+            }
+        };
+        ( $name:ident, $src:expr => $printed:expr ) => {
+            #[test]
+            fn $name() -> Result<(), LoxError> {
+                assert_eq!(eval($src)?, $printed, "Input was {:?}", $src);
+                Ok(())
+            }
+        };
+    }
+
+    vm_test!(vm_arithmetic, "1 + 2 * 3 - 4 / 2;" => "5");
+    vm_test!(vm_grouping, "(1 + 2) * 3;" => "9");
+    vm_test!(vm_negate, "-(1 + 2);" => "-3");
+    vm_test!(vm_not, "!nil and !!0;" => "true");
+    vm_test!(vm_comparisons, "1 < 2 == (2 >= 2);" => "true");
+    vm_test!(vm_nan_comparisons, "var nan = 0 / 0; nan <= nan or nan >= nan;" => "false");
+    vm_test!(vm_equality, "\"a\" + \"b\" == \"ab\" and 1 != nil;" => "true");
+    vm_test!(vm_concatenation, "\"con\" + \"cat\";" => "concat");
+    vm_test!(vm_and_short_circuits, "var a = 1; false and (a = 2); a;" => "1");
+    vm_test!(vm_or_returns_operand, "nil or \"yes\";" => "yes");
+    vm_test!(vm_globals, "var a = 1; a = a + 1; a * 10;" => "20");
+    vm_test!(vm_uninitialized_global, "var b; b;" => "nil");
+    vm_test!(vm_global_assignment_is_an_expression, "var a; var b = a = 3; b;" => "3");
+    vm_test!(vm_locals, "var r; { var a = 1; { var b = a + 1; a = b * 10; } r = a; } r;" => "20");
+    vm_test!(vm_shadowing, "var a = \"global\"; var r; { var a = \"local\"; r = a; } r + \" \" + a;" => "local global");
+    vm_test!(vm_add_locals, "var r; { var a = 1; var b = 2; r = a + b; } r;" => "3");
+    vm_test!(vm_add_local_strings, "var r; { var a = \"a\"; var b = \"b\"; r = a + b; } r;" => "ab");
+    vm_test!(vm_add_constants, "var r; { var a = \"a\"; r = a + \"b\" + \"c\"; } r;" => "abc");
+    vm_test!(vm_add_number_constants, "var m; { var n = 1; n = n + 2; m = n; } m;" => "3");
+    vm_test!(vm_add_across_a_jump, "var r; { var a = 1; var b = 2; r = a + (nil or b); } r;" => "3");
+    vm_test!(vm_assign_locals, "var r; { var a = 1; var b; b = a = 3; a = a + b; r = a; } r;" => "6");
+    vm_test!(FAIL: vm_add_locals_mismatch, "{ var a = 1; var b = nil;\n a + b; } 1;", "[line 2] Operands of '+' must be two numbers or two strings, got number and nil");
+    vm_test!(FAIL: vm_add_constant_mismatch, "{ var a = nil; a + 1; } 1;", "Operands of '+' must be two numbers or two strings, got nil and number");
+    vm_test!(FAIL: vm_undefined_global, "1;\nnope;", "[line 2] Undefined variable 'nope'");
+    vm_test!(FAIL: vm_assign_undefined_global, "nope = 1;", "[line 1] Undefined variable 'nope'");
+    vm_test!(FAIL: vm_undefined_global_suggests, "var counter = 1;\ncountr;", "did you mean 'counter'?");
+    vm_test!(FAIL: vm_negate_string, "-\"a\";", "[line 1] Operand of '-' must be a number, got string");
+    vm_test!(FAIL: vm_add_mismatch, "1 +\n nil;", "[line 1] Operands of '+' must be two numbers or two strings, got number and nil");
+    vm_test!(FAIL: vm_compare_mismatch, "1 < \"a\";", "[line 1] Operands of '<' must be numbers, got number and string");
+    vm_test!(FAIL: vm_unsupported, "fun f() {} 1;", "The bytecode engine doesn't support functions yet");
+
+    #[test]
+    fn long_constants() -> Result<(), LoxError> {
+        let src: String = (0..300).map(|n| format!("{} + ", n)).collect();
+        assert_eq!(eval(&format!("{}0;", src))?, "44850");
+        Ok(())
+    }
+
+    // bench: Compile a program made of `step` repeated with N counting up, inside `frame`,
+    // and time running it many times, returning the time per run and the chunk's size.
+    fn bench(frame: &str, step: &str) -> Result<(std::time::Duration, usize), LoxError> {
+        let body: String = (0..200)
+            .map(|n| step.replace('N', &n.to_string()))
+            .collect();
+        let mut scanner = Scanner::new(&frame.replace("BODY", &body));
+        let statements = Parser::new(scanner.scan_tokens()?).parse()?;
+        let chunk = Compiler::compile(&statements)?;
+        let mut vm = Vm::new();
+        let runs = 2000;
+        let start = std::time::Instant::now();
+        for _ in 0..runs {
+            vm.run(&chunk)?;
+        }
+        Ok((start.elapsed() / runs, chunk.code.len()))
+    }
+
+    const BENCH_STEP: &str =
+        "a = a * 1.0001 + N - b / 3; c = !(a < b) == (c or b >= N); b = b + 0.5;\n";
+
+    // The benchmarks are ignored by default; run them in release, e.g.
+    //     cargo test --release vm::tests::bench -- --ignored --nocapture
+    // and again with `--features nan-boxing` to compare value representations, or with
+    // `--features superinstructions` to compare instruction sets.
+
+    // bench_value_representation: Arithmetic on globals and locals, to compare the enum
+    // values with NaN-boxed ones.
+    #[test]
+    #[ignore]
+    fn bench_value_representation() -> Result<(), LoxError> {
+        let frame = "var a = 1; var b = 2; { var c = false; BODY }";
+        let (time, size) = bench(frame, BENCH_STEP)?;
+        let representation = if cfg!(feature = "nan-boxing") {
+            "nan-boxed"
+        } else {
+            "enum"
+        };
+        println!(
+            "{} values: {:?} per run of {} bytes",
+            representation, time, size
+        );
+        Ok(())
+    }
+
+    // bench_dispatch: The same arithmetic on locals only, so the time is spent in the
+    // dispatch loop rather than in looking up globals.
+    #[test]
+    #[ignore]
+    fn bench_dispatch() -> Result<(), LoxError> {
+        let frame = "{ var a = 1; var b = 2; var c = false; BODY }";
+        let (time, size) = bench(frame, BENCH_STEP)?;
+        let throughput = size as f64 / time.as_secs_f64() / 1e6;
+        println!(
+            "dispatch: {:?} per run, {:.0} MB of bytecode/s",
+            time, throughput
+        );
+        Ok(())
+    }
+
+    // bench_loops: A counting loop on locals, the shape of the book's loop benchmarks, which
+    // is where fusing `i = i + 1` and `sum + i` pays off.
+    #[test]
+    #[ignore]
+    fn bench_loops() -> Result<(), LoxError> {
+        let src = "{ var sum = 0; var i = 0; var step = 2;\n\
+                   while (i < 1000000) { sum = sum + i + step; i = i + 1; } }";
+        let mut scanner = Scanner::new(src);
+        let chunk = Compiler::compile(&Parser::new(scanner.scan_tokens()?).parse()?)?;
+        let mut vm = Vm::new();
+        let start = std::time::Instant::now();
+        vm.run(&chunk)?;
+        println!(
+            "loops: {:?} for a million passes, {} bytes of bytecode",
+            start.elapsed(),
+            chunk.code.len()
+        );
+        Ok(())
+    }
+
+    // bench_strings: Concatenation and comparison of strings, which interning speeds up.
+    #[test]
+    #[ignore]
+    fn bench_strings() -> Result<(), LoxError> {
+        let frame = "{ var long = \"a string long enough that comparing it byte by byte costs                      something\"; var key = \"\"; var c = false; BODY }";
+        let step = "key = \"key\" + \"N\"; c = key == \"keyN\" and long == \"a string long                     enough that comparing it byte by byte costs something\" and key != long;\n";
+        let (time, _) = bench(frame, step)?;
+        println!("strings: {:?} per run", time);
+        Ok(())
+    }
+
+    fn interpret(vm: &mut Vm, src: &str) -> Result<(), LoxError> {
+        let mut scanner = Scanner::new(src);
+        vm.interpret(&Parser::new(scanner.scan_tokens()?).parse()?)
+    }
+
+    #[test]
+    fn errors_are_traced_to_the_script() {
+        let err = interpret(&mut Vm::new(), "1;\n-nil;").unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "[line 2] Operand of '-' must be a number, got nil.\n[line 2] in script"
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_failing_token() {
+        let column = |src| {
+            let err = interpret(&mut Vm::new(), src).unwrap_err();
+            err.span().map(|span| (span.line, span.column))
+        };
+        assert_eq!(column("1;\n1 + -nil;"), Some((2, 5)));
+        assert_eq!(column("print 1 < nope;"), Some((1, 11)));
+        // Fused instructions point at the operator that completed them.
+        assert_eq!(column("{ var a = 1; var b = nil;\n a + b; }"), Some((2, 4)));
+    }
+
+    #[test]
+    fn strings_are_interned() -> Result<(), LoxError> {
+        let mut vm = Vm::new();
+        interpret(
+            &mut vm,
+            "var a = \"ab\"; var b = \"a\" + \"b\"; var c = \"x\";",
+        )?;
+        let string = |name: &str| {
+            let (_, value) = vm.globals.iter().find(|(key, _)| &*key.0 == name).unwrap();
+            match to_value(value.clone()) {
+                Value::Str(s) => s,
+                other => panic!("{} is not a string", other),
+            }
+        };
+        assert!(Rc::ptr_eq(&string("a"), &string("b")));
+        // The names a, b and c, and the strings "ab" and "x"; "a" and "b" were dropped.
+        assert_eq!(vm.strings.table.len(), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn strings_are_swept_as_the_table_grows() {
+        let mut strings = Strings::default();
+        let kept = strings.intern("kept");
+        for i in 0..10 * MIN_SWEEP {
+            strings.intern(&i.to_string());
+        }
+        assert!(strings.table.len() <= MIN_SWEEP, "{}", strings.table.len());
+        assert!(Rc::ptr_eq(&kept, &strings.intern("kept")));
+    }
+
+    #[test]
+    fn profile_counts_opcodes() -> Result<(), LoxError> {
+        let mut vm = Vm::new();
+        vm.profile();
+        interpret(&mut vm, "var a = 1; a = a + a;")?;
+        let report = format!("{}", vm.take_profile().unwrap());
+        assert!(
+            report.starts_with("profile: 8 instructions executed"),
+            "{}",
+            report
+        );
+        assert!(report.contains("2  25.00%  GetGlobal"), "{}", report);
+        assert!(vm.take_profile().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn globals_persist_between_programs() -> Result<(), LoxError> {
+        let mut vm = Vm::new();
+        interpret(&mut vm, "var a = 1;")?;
+        interpret(&mut vm, "a = a + 1;")?;
+        let err = interpret(&mut vm, "print a + nil;").unwrap_err();
+        assert!(format!("{}", err).contains("got number and nil"), "{}", err);
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+// The commands that look at a script without running it, which `check` and `lint` can do for
+// whole directories of them; `rlox1 test`, which runs scripts and checks what they print against
+// their expect comments; and `rlox1 completions`.
+
+mod common;
+
+use std::process::Output;
+
+use common::rlox1_in_tests;
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+const PROGRAM: &str = "var a=1;\nprint a+2;\n";
+
+#[test]
+fn scripts_are_shown_without_running() {
+    let output = rlox1_in_tests(&["tokenize", "-"], PROGRAM);
+    assert!(stdout(&output).starts_with("Var 1\nIdentifier(\"a\") 1\nEqual 1\n"));
+    assert!(stdout(&output).ends_with("Semicolon 2\nEof 3\n"));
+    let output = rlox1_in_tests(&["parse", "-"], PROGRAM);
+    assert_eq!(stdout(&output), "(var a 1)\n(print (+ a 2))\n");
+    let output = rlox1_in_tests(&["fmt", "-"], PROGRAM);
+    assert_eq!(stdout(&output), "var a = 1;\nprint a + 2;\n");
+    let output = rlox1_in_tests(&["fmt", "--check", "-"], "var a = 1;\nprint a + 2;\n");
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    let output = rlox1_in_tests(&["fmt", "--check", "-"], PROGRAM);
+    assert_eq!(output.status.code(), Some(65));
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "ERROR[LX0904]: <stdin> isn't formatted; run rlox1 fmt --write to format it.\n"
+    );
+    // Comments are kept, and this script's are where the formatter would put them already.
+    let output = rlox1_in_tests(&["fmt", "--check", "multiline.lox"], "");
+    assert!(output.status.success());
+}
+
+#[test]
+fn check_reports_errors_without_running() {
+    let output = rlox1_in_tests(&["check", "-"], PROGRAM);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    let output = rlox1_in_tests(&["check", "-"], "print 1;\nreturn 2;");
+    assert_eq!(output.status.code(), Some(65));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Can't return from top-level code"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn check_looks_through_directories() {
+    let output = rlox1_in_tests(&["check", "--recursive", "expect", "multiline.lox"], "");
+    assert_eq!(
+        stdout(&output),
+        "expect/output.lox: ok\nexpect/runtime-error.lox: ok\nexpect/syntax-error.lox: 1 error\n\
+         multiline.lox: ok\n4 scripts checked, 1 with errors\n"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("--> expect/syntax-error.lox:1:10"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.ends_with("ERROR[LX0905]: 1 of 4 scripts have errors.\n"));
+    assert_eq!(output.status.code(), Some(65));
+    let output = rlox1_in_tests(&["check", "-r", "engines"], "");
+    assert!(stdout(&output).ends_with("7 scripts checked, 0 with errors\n"));
+    assert!(output.status.success());
+    // A directory is only checked when asked to.
+    let output = rlox1_in_tests(&["check", "engines"], "");
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "ERROR[LX0711]: engines is a directory; use --recursive to check the scripts in it.\n"
+    );
+}
+
+#[test]
+fn lint_fails_on_warnings() {
+    let output = rlox1_in_tests(&["lint", "-"], PROGRAM);
+    assert_eq!(
+        stdout(&output),
+        "-: ok\n1 scripts checked, 0 with errors or warnings\n"
+    );
+    assert!(output.status.success());
+    let output = rlox1_in_tests(&["lint", "-"], "fun f(a, b) {\n  return a;\n}\n");
+    assert_eq!(
+        stdout(&output),
+        "-: 1 warning\n1 scripts checked, 1 with errors or warnings\n"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("WARNING[LX0801]: [line 1] Unused parameter 'b'."),
+        "{}",
+        stderr
+    );
+    assert_eq!(output.status.code(), Some(65));
+    // Warnings alone don't fail a check.
+    let output = rlox1_in_tests(&["check", "-"], "fun f(a, b) {\n  return a;\n}\n");
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_checks_expect_comments() {
+    let output = rlox1_in_tests(&["test", "expect"], "");
+    assert_eq!(stdout(&output), "3 passed, 0 failed\n");
+    assert!(output.status.success());
+    let output = rlox1_in_tests(&["test", "expect-fail.lox"], "");
+    assert_eq!(
+        stdout(&output),
+        "FAIL expect-fail.lox\n  expected output [\"1\", \"3\"], got [\"1\", \"2\"]\n0 passed, 1 failed\n"
+    );
+    assert!(!output.status.success());
+}
+
+#[test]
+fn completions_cover_every_command() {
+    for shell in &["bash", "zsh", "fish"] {
+        let output = rlox1_in_tests(&["completions", shell], "");
+        let script = stdout(&output);
+        for word in &["run", "repl", "completions", "fail-fast", "engine"] {
+            assert!(script.contains(word), "{} completion lacks {}", shell, word);
+        }
+    }
+    let output = rlox1_in_tests(&["completions", "tcsh"], "");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn explain_describes_a_code() {
+    let output = rlox1_in_tests(&["explain", "LX0203"], "");
+    assert!(
+        stdout(&output).starts_with("LX0203: Expect ')' after expression\n\n"),
+        "{}",
+        stdout(&output)
+    );
+    assert!(output.status.success());
+    let output = rlox1_in_tests(&["explain", "LX9999"], "");
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "ERROR[LX0902]: LX9999 isn't a diagnostic code; codes look like LX0203.\n"
+    );
+    assert!(!output.status.success());
+}
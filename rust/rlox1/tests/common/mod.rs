@@ -0,0 +1,39 @@
+// Running the rlox1 binary, for the integration tests. Each test file takes what it needs
+// with `mod common;`, so some of this goes unused in any one of them.
+#![allow(dead_code)]
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+// rlox1: Run rlox1 with `args`, write `stdin` to it, and wait for it to finish.
+pub fn rlox1(args: &[&str], stdin: impl AsRef<[u8]>) -> Output {
+    run(Command::new(env!("CARGO_BIN_EXE_rlox1")).args(args), stdin)
+}
+
+// rlox1_in_tests: Like rlox1, but from resources/test, so that the scripts there can be named
+// by their paths under it.
+pub fn rlox1_in_tests(args: &[&str], stdin: impl AsRef<[u8]>) -> Output {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test");
+    run(
+        Command::new(env!("CARGO_BIN_EXE_rlox1"))
+            .current_dir(dir)
+            .args(args),
+        stdin,
+    )
+}
+
+fn run(command: &mut Command, stdin: impl AsRef<[u8]>) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("the interpreter runs");
+    let mut input = child.stdin.take().expect("a pipe to stdin");
+    input
+        .write_all(stdin.as_ref())
+        .expect("the input is written");
+    drop(input);
+    child.wait_with_output().expect("the interpreter finishes")
+}
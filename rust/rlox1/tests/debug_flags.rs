@@ -0,0 +1,159 @@
+// --tokens, --ast, --trace and --trace-exec print what rlox1 makes of a program to stderr,
+// leaving the program's own output alone, and so do the logs that -v and -vv turn on, and
+// the backtraces RLOX_BACKTRACE adds to errors.
+
+mod common;
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+use common::rlox1;
+
+// run: Run `program` from stdin with `args`.
+fn run(args: &[&str], program: &str) -> Output {
+    rlox1(&[&["run"], args, &["-"]].concat(), program)
+}
+
+const PROGRAM: &str = "fun f(n) {\n  return n + 1;\n}\nprint f(1);\n";
+
+#[test]
+fn dumps_go_to_stderr() {
+    let output = run(&["--tokens", "--ast", "--trace"], PROGRAM);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<_> = stderr.lines().collect();
+    assert_eq!(
+        lines[..3],
+        [
+            "token: Fun 1",
+            "token: Identifier(\"f\") 1",
+            "token: LeftParen 1"
+        ]
+    );
+    assert!(lines.contains(&"token: Eof 5"), "{}", stderr);
+    assert!(lines.contains(&"ast: (print (call f 1))"), "{}", stderr);
+    let trace: Vec<_> = lines
+        .into_iter()
+        .filter(|line| line.starts_with("trace:"))
+        .collect();
+    assert_eq!(
+        trace,
+        [
+            "trace: (fun f (n) (return (+ n 1)))",
+            "trace: (print (call f 1))",
+            "trace:   (return (+ n 1))"
+        ]
+    );
+}
+
+#[test]
+fn trace_exec_shows_lines_and_calls() {
+    let output = run(&["--trace-exec"], PROGRAM);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "exec: [line 1] (fun f (n) (return (+ n 1)))\n\
+         exec: [line 4] (print (call f 1))\n\
+         exec: -> f(1)\n\
+         exec:   [line 2] (return (+ n 1))\n\
+         exec: <- f returned 2\n"
+    );
+    let output = run(
+        &["--trace-exec"],
+        "fun f(s) { throw s + \"!\"; }\nf(\"no\");",
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("exec: -> f(\"no\")\n"), "{}", stderr);
+    assert!(stderr.contains("exec: <- f threw \"no!\"\n"), "{}", stderr);
+}
+
+#[test]
+fn only_the_tree_walker_traces() {
+    let output = run(&["--trace", "--engine=vm"], PROGRAM);
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Only the tree-walker can trace"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn verbose_flags_log_each_phase() {
+    let output = run(&[], PROGRAM);
+    assert!(output.stderr.is_empty());
+    let output = run(&["-v"], PROGRAM);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let phases: Vec<_> = stderr
+        .lines()
+        .map(|line| line.split(':').next().unwrap())
+        .collect();
+    assert_eq!(
+        phases,
+        [
+            "[debug] scan",
+            "[debug] parse",
+            "[debug] resolve",
+            "[debug] execute"
+        ]
+    );
+    assert!(!stderr.contains("[trace]"));
+    let output = run(&["-vv", "--fail-fast"], PROGRAM);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("[debug] scan: 19 tokens in "),
+        "{}",
+        stderr
+    );
+    // Failing fast still resolves the program first, for its warnings.
+    assert!(stderr.contains("[debug] resolve"), "{}", stderr);
+}
+
+#[test]
+fn quiet_leaves_out_warnings() {
+    let args = ["--sandbox", "--max-call-depth=500"];
+    let output = run(&args, PROGRAM);
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("WARNING[LX0606]: "));
+    let output = run(&[&args[..], &["-q"]].concat(), PROGRAM);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n");
+    assert!(output.stderr.is_empty());
+    let output = run(&["-q"], "print nope;");
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("ERROR[LX0301]: "));
+}
+
+#[test]
+fn rlox_backtrace_shows_where_errors_are_made() {
+    let run = |backtrace: &str| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rlox1"))
+            .args(["run", "-"])
+            .env("RLOX_BACKTRACE", backtrace)
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("the interpreter runs");
+        let mut input = child.stdin.take().expect("a pipe to stdin");
+        input
+            .write_all(b"print nope;")
+            .expect("the program is written");
+        drop(input);
+        let output = child.wait_with_output().expect("the interpreter finishes");
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+    let stderr = run("1");
+    assert!(
+        stderr.starts_with("ERROR[LX0301]: [line 1] Undefined variable 'nope'."),
+        "{}",
+        stderr
+    );
+    let (_, backtrace) = stderr
+        .split_once("[line 1] in script\nrlox1 backtrace:\n")
+        .unwrap();
+    assert!(
+        backtrace.contains("rlox1::error::LoxError::new"),
+        "{}",
+        backtrace
+    );
+    assert!(!run("0").contains("backtrace"));
+}
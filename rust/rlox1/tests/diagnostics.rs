@@ -0,0 +1,250 @@
+// Errors quote the line of the script they are on, with the part at fault underlined, the way
+// rustc does. Errors and warnings are colored by level on a terminal, or as --color says, and
+// --error-format=json writes them for tools instead. --error-limit caps how many are shown.
+
+mod common;
+
+use common::rlox1;
+
+#[test]
+fn errors_underline_the_source() {
+    let output = rlox1(&["run", "-"], "var a = 1;\n{\n  a = 2;\n}\nprint nope;\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "ERROR[LX0301]: [line 5] Undefined variable 'nope'.\n \
+         --> <stdin>:5:7\n  \
+         |\n\
+         5 | print nope;\n  \
+         |       ^^^^\n\
+         [line 5] in script\n"
+    );
+    for engine in &["treewalk", "vm", "register"] {
+        let output = rlox1(
+            &["run", "--engine", engine, "-"],
+            "var a = 1;\nprint a +;\n",
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&output.stderr),
+            "ERROR[LX0201]: [line 2] Error at ';': Expect expression.\n \
+             --> <stdin>:2:10\n  \
+             |\n\
+             2 | print a +;\n  \
+             |          ^\n",
+            "{}",
+            engine
+        );
+    }
+}
+
+#[test]
+fn color_is_chosen_by_flag() {
+    let output = rlox1(&["run", "--color=always", "-"], "print nope;");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("\x1b[1;31mERROR[LX0301]:\x1b[0m "),
+        "{:?}",
+        stderr
+    );
+    // The flag goes before or after the command, and by default, not on a terminal, there's
+    // no color.
+    let output = rlox1(&["--color", "always", "check", "-"], "return;");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("\x1b[1;31mERROR[LX0401]:\x1b[0m "),
+        "{:?}",
+        stderr
+    );
+    for args in &[&["run", "--color=never", "-"][..], &["run", "-"]] {
+        let output = rlox1(args, "print nope;");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.starts_with("ERROR[LX0301]: ") && !stderr.contains('\x1b'),
+            "{:?}",
+            stderr
+        );
+    }
+}
+
+#[test]
+fn warnings_do_not_stop_the_program() {
+    let output = rlox1(
+        &[
+            "run",
+            "--sandbox",
+            "--max-call-depth=500",
+            "--color=always",
+            "-",
+        ],
+        "print 1;",
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "\x1b[1;33mWARNING[LX0606]:\x1b[0m \x1b[1mThe sandbox limits calls to a depth of 200.\x1b[0m\n"
+    );
+    assert!(output.status.success());
+}
+
+#[test]
+fn runtime_errors_do_not_stop_the_rest() {
+    let program = "print nope;\nprint 1;\nprint -nil;\nprint 2;\n";
+    let output = rlox1(&["run", "-"], program);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n2\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("ERROR[LX0301]: [line 1] Undefined variable"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("\nERROR[LX0501]: [line 3] Operand of '-'"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.ends_with("\nNOTE: 2 errors; exiting with status 70.\n"),
+        "{}",
+        stderr
+    );
+    assert_eq!(output.status.code(), Some(70));
+    // --fail-fast stops at the first, as the book's interpreters do.
+    let output = rlox1(&["run", "--fail-fast", "--engine=vm", "-"], program);
+    assert!(output.stdout.is_empty());
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("NOTE"));
+    assert_eq!(output.status.code(), Some(70));
+    // Static errors are all found before anything runs.
+    let output = rlox1(&["run", "-"], "print 1;\nprint 2 +;\nprint 3\n");
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("ERROR[LX02").count(), 2, "{}", stderr);
+    assert!(stderr.ends_with("NOTE: 2 errors; exiting with status 65.\n"));
+    assert_eq!(output.status.code(), Some(65));
+}
+
+#[test]
+fn variables_whose_declaration_failed_are_not_reported_again() {
+    let program = "var x = nope;\nprint x;\nvar y = x;\nprint 1;\nvar x = 2;\nprint x;\nprint y;\n";
+    for engine in &["treewalk", "vm", "register"] {
+        let output = rlox1(&["run", "--engine", engine, "-"], program);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n2\n");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(
+            stderr.matches("ERROR[").count(),
+            1,
+            "{}: {}",
+            engine,
+            stderr
+        );
+        assert!(stderr.contains("Undefined variable 'nope'"), "{}", stderr);
+        assert_eq!(output.status.code(), Some(70));
+    }
+}
+
+#[test]
+fn errors_past_the_limit_are_summed_up() {
+    let program = "print ;\n".repeat(25);
+    let errors = |stderr: &str| stderr.matches("ERROR[LX0201]").count();
+    let output = rlox1(&["run", "-"], &program);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(errors(&stderr), 20, "{}", stderr);
+    assert!(
+        stderr.ends_with(
+            "NOTE: And 5 more errors; see --error-limit.\n\
+             NOTE: 25 errors; exiting with status 65.\n"
+        ),
+        "{}",
+        stderr
+    );
+    let output = rlox1(&["run", "--error-limit=3", "-"], &program);
+    assert_eq!(errors(&String::from_utf8_lossy(&output.stderr)), 3);
+    let output = rlox1(&["run", "--error-limit=0", "-"], &program);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(errors(&stderr), 25);
+    assert!(!stderr.contains("more errors"));
+    assert_eq!(output.status.code(), Some(65));
+}
+
+#[test]
+fn json_errors_are_for_tools() {
+    let output = rlox1(
+        &["run", "--error-format=json", "--color=always", "-"],
+        "var a = 1;\nprint a +;\n",
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "{\"severity\":\"error\",\"code\":\"LX0201\",\
+         \"message\":\"[line 2] Error at ';': Expect expression.\",\
+         \"file\":\"<stdin>\",\"span\":{\"line\":2,\"column\":10,\"length\":1}}\n"
+    );
+    assert_eq!(output.status.code(), Some(65));
+}
+
+#[test]
+fn lints_are_warnings_unless_denied() {
+    let program = "{\n  var unused = 1;\n}\nprint 2;\n";
+    let output = rlox1(&["run", "-"], program);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "WARNING[LX0801]: [line 2] Unused variable 'unused'.\n \
+         --> <stdin>:2:7\n  \
+         |\n\
+         2 |   var unused = 1;\n  \
+         |       ^^^^^^\n"
+    );
+    assert!(output.status.success());
+    // Denied, they stop the program before it runs, like any other static error.
+    let output = rlox1(&["--deny-warnings", "run", "-"], program);
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("ERROR[LX0801]: [line 2] Unused variable"),
+        "{}",
+        stderr
+    );
+    assert_eq!(output.status.code(), Some(65));
+}
+
+#[test]
+fn messages_are_translated_by_lang() {
+    let output = rlox1(&["--lang", "es", "run", "-"], "print nope;\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "ERROR[LX0301]: [line 1] Variable 'nope' no definida.\n \
+         --> <stdin>:1:7\n  \
+         |\n\
+         1 | print nope;\n  \
+         |       ^^^^\n\
+         [line 1] in script\n"
+    );
+    // A catalog can come from a file, and what it lacks stays in English.
+    let path = std::env::temp_dir().join(format!("rlox1-catalog-{}.txt", std::process::id()));
+    std::fs::write(
+        &path,
+        "# Unused variable '{}'\nLX0801: Variable '{}' inutilisée\n",
+    )
+    .expect("the catalog is written");
+    let lang = path.to_str().unwrap();
+    let output = rlox1(
+        &["run", "--lang", lang, "--error-format=json", "-"],
+        "{ var x; }\nprint nope;\n",
+    );
+    std::fs::remove_file(&path).expect("the catalog is removable");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(r#""message":"[line 1] Variable 'x' inutilisée.""#),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains(r#""message":"[line 2] Undefined variable 'nope'.""#),
+        "{}",
+        stderr
+    );
+    let output = rlox1(&["--lang", "xx", "run", "-"], "print 1;\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "ERROR[LX0713]: No catalog for language 'xx'; use en, es, or the path of a catalog file.\n"
+    );
+    assert!(output.stdout.is_empty());
+}
@@ -0,0 +1,75 @@
+// Differential tests for the engines: every script in resources/test/engines is run on the
+// tree-walking interpreter and on each bytecode engine, and the runs must print the same
+// output and the same errors. A bytecode engine skips scripts using features it doesn't
+// compile yet, so the directory can hold any Lox program.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+const SCRIPTS: &str = "resources/test/engines";
+
+// The engines checked against the tree-walker.
+const BYTECODE_ENGINES: &[&str] = &["vm", "register"];
+
+fn run(engine: &str, script: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rlox1"))
+        .arg("run")
+        .arg(format!("--engine={}", engine))
+        .arg(script)
+        .output()
+        .expect("the interpreter runs")
+}
+
+#[test]
+fn engines_agree() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(SCRIPTS);
+    let mut scripts: Vec<_> = fs::read_dir(&dir)
+        .expect("the scripts directory exists")
+        .map(|entry| entry.expect("a readable entry").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "lox"))
+        .collect();
+    scripts.sort();
+    assert!(!scripts.is_empty(), "no scripts in {}", dir.display());
+
+    let mut compared = 0;
+    for script in &scripts {
+        let treewalk = run("treewalk", script);
+        for engine in BYTECODE_ENGINES {
+            let output = run(engine, script);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if stderr.contains("The bytecode engine doesn't support") {
+                eprintln!(
+                    "{}: skipping {}: {}",
+                    engine,
+                    script.display(),
+                    stderr.trim()
+                );
+                continue;
+            }
+            assert_eq!(
+                String::from_utf8_lossy(&treewalk.stdout),
+                String::from_utf8_lossy(&output.stdout),
+                "{}: {} printed different output",
+                script.display(),
+                engine
+            );
+            assert_eq!(
+                String::from_utf8_lossy(&treewalk.stderr),
+                stderr,
+                "{}: {} reported different errors",
+                script.display(),
+                engine
+            );
+            assert_eq!(
+                treewalk.status.code(),
+                output.status.code(),
+                "{}: {} exited differently",
+                script.display(),
+                engine
+            );
+            compared += 1;
+        }
+    }
+    assert!(compared > 0, "every script was skipped");
+}
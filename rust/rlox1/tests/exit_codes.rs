@@ -0,0 +1,30 @@
+// rlox1 exits the way the book's interpreters do, so the official test harness can tell a
+// program that couldn't run (65) from one that failed partway through (70).
+
+use std::path::Path;
+use std::process::Command;
+
+fn status(args: &[&str]) -> Option<i32> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test");
+    Command::new(env!("CARGO_BIN_EXE_rlox1"))
+        .current_dir(dir)
+        .arg("run")
+        .args(args)
+        .output()
+        .expect("the interpreter runs")
+        .status
+        .code()
+}
+
+#[test]
+fn exit_codes_say_how_the_program_ended() {
+    assert_eq!(status(&["multiline.lox"]), Some(0));
+    assert_eq!(status(&["syntax-error.lox"]), Some(65));
+    assert_eq!(status(&["no-such-file.lox"]), Some(65));
+    assert_eq!(status(&["error-line.lox"]), Some(70));
+    assert_eq!(status(&["--engine=vm", "error-line.lox"]), Some(70));
+    assert_eq!(
+        status(&["--max-call-depth=lots", "multiline.lox"]),
+        Some(65)
+    );
+}
@@ -0,0 +1,90 @@
+// Scripts should be Lox source: a script not named .lox is warned about, a binary file is
+// refused, unless --force says they are meant, and a script that isn't UTF-8 is an error that
+// says where.
+
+mod common;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use common::rlox1;
+
+// script: A temporary file called `name` holding `contents`.
+fn script(name: &str, contents: &[u8]) -> PathBuf {
+    let path = env::temp_dir().join(format!("rlox1-{}-{}", std::process::id(), name));
+    fs::write(&path, contents).expect("a temporary file");
+    path
+}
+
+#[test]
+fn scripts_should_be_named_lox() {
+    let path = script("script.txt", b"print 1;\n");
+    let path = path.to_str().unwrap();
+    let output = rlox1(&["run", path], b"");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        format!(
+            "WARNING[LX0704]: {} doesn't end in .lox, so may not be a Lox script; use --force if it is.\n",
+            path
+        )
+    );
+    assert!(output.status.success());
+    let output = rlox1(&["check", "--force", path], b"");
+    fs::remove_file(path).expect("the temporary file is removable");
+    assert!(output.stderr.is_empty());
+    assert!(output.status.success());
+}
+
+#[test]
+fn binary_files_are_refused() {
+    let path = script("binary.lox", b"print \"a\0b\";\n");
+    let path = path.to_str().unwrap();
+    let output = rlox1(&["run", path], b"");
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        format!(
+            "ERROR[LX0702]: File {} looks like a binary file, not a Lox script; \
+             use --force to read it anyway.\n",
+            path
+        )
+    );
+    assert_eq!(output.status.code(), Some(65));
+    let output = rlox1(&["run", "--force", path], b"");
+    fs::remove_file(path).expect("the temporary file is removable");
+    assert_eq!(output.stdout, b"a\0b\n");
+    assert!(output.status.success());
+}
+
+#[test]
+fn scripts_must_be_utf8() {
+    let program = b"print 1;\nprint \"caf\xe9\";\n";
+    let output = rlox1(&["run", "-"], program);
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "ERROR[LX0701]: File stdin is not UTF-8: there's a stray byte, 0xe9, at line 2, column 11.\n"
+    );
+    assert_eq!(output.status.code(), Some(65));
+    // Forcing doesn't help: there's no knowing what the byte was meant to be.
+    let path = script("latin1.lox", program);
+    let path = path.to_str().unwrap();
+    let output = rlox1(&["run", "--force", path], b"");
+    fs::remove_file(path).expect("the temporary file is removable");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("is not UTF-8"), "{}", stderr);
+    assert_eq!(output.status.code(), Some(65));
+}
+
+#[test]
+fn a_byte_order_mark_is_left_off() {
+    let output = rlox1(&["run", "-"], b"\xef\xbb\xbfprint 1;\n");
+    assert_eq!(output.stdout, b"1\n");
+    assert!(output.status.success());
+    // Columns on the first line don't count it.
+    let output = rlox1(&["run", "-"], b"\xef\xbb\xbfprint nope;\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--> <stdin>:1:7\n"), "{}", stderr);
+}
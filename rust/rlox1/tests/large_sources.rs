@@ -0,0 +1,44 @@
+// Scripts can be as large as they need to be, unless --max-source-bytes says otherwise.
+
+mod common;
+
+use std::env;
+use std::fs;
+
+use common::rlox1;
+
+// program: A script of a few megabytes, which prints the sum of what it declares.
+fn program() -> String {
+    let mut program = String::from("var total = 0;\n");
+    for i in 0..100_000 {
+        program.push_str(&format!(
+            "var v{} = {}; total = total + v{}; // keep a running total\n",
+            i, i, i
+        ));
+    }
+    program.push_str("print total;\n");
+    program
+}
+
+#[test]
+fn large_scripts_run() {
+    let program = program();
+    assert!(program.len() > 5_000_000);
+    let path = env::temp_dir().join(format!("rlox1-large-{}.lox", std::process::id()));
+    fs::write(&path, &program).expect("a temporary file");
+    let output = rlox1(&["run", path.to_str().unwrap()], "");
+    fs::remove_file(&path).expect("the temporary file is removable");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "4999950000\n");
+    let output = rlox1(&["run", "-"], &program);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "4999950000\n");
+}
+
+#[test]
+fn the_size_can_be_limited() {
+    let output = rlox1(&["run", "--max-source-bytes=8", "-"], "print 12345;");
+    assert_eq!(output.status.code(), Some(65));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("is too large (9 > 8)"), "{}", stderr);
+    let output = rlox1(&["run", "--max-source-bytes=12", "-"], "print 12345;");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "12345\n");
+}
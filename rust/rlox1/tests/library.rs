@@ -0,0 +1,105 @@
+// rlox1 is a library as well as a command: a Rust program can scan and parse Lox itself, run
+// it on an interpreter it keeps, give it natives of its own, and read back its globals and
+// errors. A Lox does the same for a host with less to say about how, lox_native! makes
+// natives of Rust functions, and structured data goes in and out as JSON.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rlox1::error::{ErrorKind, LoxError, EX_DATAERR};
+use rlox1::interpreter::Interpreter;
+use rlox1::parser::Parser;
+use rlox1::scanner::Scanner;
+use rlox1::value::Value;
+use rlox1::{lox_native, Lox};
+
+fn run(interpreter: &mut Interpreter, source: &str) -> Result<(), LoxError> {
+    let tokens = Scanner::new(source).tokens()?;
+    let statements = Parser::new(&tokens).parse()?;
+    interpreter.interpret(&statements)
+}
+
+#[test]
+fn globals_outlast_each_run() {
+    let mut interpreter = Interpreter::new();
+    run(&mut interpreter, "var x = 1 + 2;").unwrap();
+    run(
+        &mut interpreter,
+        "fun double(n) { return n * 2; }\nvar y = double(x);",
+    )
+    .unwrap();
+    assert_eq!(interpreter.global("y"), Some(&Value::Number(6.0)));
+    assert_eq!(interpreter.global("z"), None);
+}
+
+#[test]
+fn hosts_give_lox_natives() {
+    let mut interpreter = Interpreter::new();
+    let reported = Rc::new(RefCell::new(Vec::new()));
+    let log = Rc::clone(&reported);
+    interpreter.define_native("report", 1, move |args| {
+        log.borrow_mut().push(args[0].to_string());
+        Ok(Value::Nil)
+    });
+    interpreter.define_global("answer", Value::Number(42.0));
+    run(&mut interpreter, "report(\"hi\");\nreport(answer + 1);").unwrap();
+    assert_eq!(*reported.borrow(), vec!["hi", "43"]);
+}
+
+#[test]
+fn errors_say_what_went_wrong() {
+    let err = run(&mut Interpreter::new(), "print nope;").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Runtime);
+    assert_eq!(err.code(), Some("LX0301"));
+    assert_eq!(err.line(), Some(1));
+    let err = run(&mut Interpreter::new(), "print 1 +;").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Parse);
+    assert_eq!(err.status(), EX_DATAERR);
+}
+
+#[test]
+fn hosts_drive_scripts_through_a_lox() {
+    let mut lox = Lox::new();
+    lox.set_global("greeting", Value::Str("Hello".into()));
+    lox.eval("fun greet(name) { return greeting + \", \" + name + \"!\"; }")
+        .unwrap();
+    let greeted = lox.call("greet", &[Value::Str("host".into())]).unwrap();
+    assert_eq!(greeted.to_string(), "Hello, host!");
+    assert_eq!(
+        lox.eval("greet(\"Lox\");").unwrap().to_string(),
+        "Hello, Lox!"
+    );
+}
+
+lox_native! {
+    fn repeat(text: String, times: f64) -> String {
+        text.repeat(times as usize)
+    }
+}
+
+#[test]
+fn rust_functions_bind_as_natives() {
+    let mut lox = Lox::new();
+    lox.set_global("repeat", repeat());
+    assert_eq!(
+        lox.eval("repeat(\"ab\", 3);").unwrap().to_string(),
+        "ababab"
+    );
+    let err = lox.eval("repeat(3, \"ab\");").unwrap_err();
+    assert_eq!(err.code(), Some("LX0513"));
+}
+
+#[test]
+fn structured_data_goes_in_and_out_as_json() {
+    let mut lox = Lox::new();
+    let config = Value::from_json(r#"{"retries": 3, "hosts": ["a", "b"]}"#).unwrap();
+    lox.set_global("config", config);
+    let result = lox
+        .eval("config.set(\"retries\", config.get(\"retries\") + 1);\nconfig;")
+        .unwrap();
+    assert_eq!(
+        result.to_json().unwrap(),
+        r#"{"retries":4,"hosts":["a","b"]}"#
+    );
+    assert_eq!(Value::from_json("[1,").unwrap_err().code(), Some("LX0517"));
+}
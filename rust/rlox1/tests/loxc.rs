@@ -0,0 +1,98 @@
+// `rlox1 compile` writes a .loxc file that runs without its source: compiling each engine
+// test script that the VM supports and running the result must print what running the
+// script does.
+
+mod common;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use common::rlox1;
+
+#[test]
+fn compiled_scripts_run_like_their_source() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/engines");
+    let out = env::temp_dir().join(format!("rlox1-loxc-{}", std::process::id()));
+    fs::create_dir_all(&out).expect("a temporary directory");
+    for entry in fs::read_dir(&dir).expect("the scripts directory exists") {
+        let script = entry.expect("a readable entry").path();
+        let compiled = out.join(script.with_extension("loxc").file_name().unwrap());
+        let (script, compiled) = (script.to_str().unwrap(), compiled.to_str().unwrap());
+        let compile = rlox1(&["compile", script, "-o", compiled], "");
+        let stderr = String::from_utf8_lossy(&compile.stderr);
+        if stderr.contains("The bytecode engine doesn't support") {
+            continue;
+        }
+        assert!(stderr.is_empty(), "{}: {}", script, stderr);
+        // A compiled file runs as one chunk, so the source stops at its first error too. It
+        // isn't linted, so the source's warnings are left out.
+        let source = rlox1(&["run", "-q", "--engine=vm", "--fail-fast", script], "");
+        let loaded = rlox1(&["run", compiled], "");
+        assert_eq!(source.stdout, loaded.stdout, "{}", script);
+        // A compiled file has no source to quote, so its errors come without the snippet.
+        assert_eq!(
+            without_snippets(&source.stderr),
+            String::from_utf8_lossy(&loaded.stderr),
+            "{}",
+            script
+        );
+    }
+    fs::remove_dir_all(&out).expect("the temporary directory is removable");
+}
+
+// without_snippets: Error output with the quoted source lines taken out.
+fn without_snippets(stderr: &[u8]) -> String {
+    String::from_utf8_lossy(stderr)
+        .lines()
+        .filter(|line| {
+            // The quoted lines start with a gutter holding at most a line number.
+            let line = line.trim_start();
+            let after_gutter = line.trim_start_matches(|c: char| c.is_ascii_digit());
+            !line.starts_with("-->") && !after_gutter.trim_start().starts_with('|')
+        })
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+#[test]
+fn invalid_files_are_refused() {
+    let path = env::temp_dir().join(format!("rlox1-invalid-{}.loxc", std::process::id()));
+    fs::write(&path, "print 1;").expect("a temporary file");
+    let output = rlox1(&["run", path.to_str().unwrap()], "");
+    fs::remove_file(&path).expect("the temporary file is removable");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Not a compiled Lox file"), "{}", stderr);
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn stack_underflow_is_refused_on_load() {
+    // Well formed, but its code pops from an empty stack, which would crash the VM: Pop, then
+    // Return.
+    let code = b"LOXC\x04\x00\x02\x00\x00\x00\x05\x1a\x01\x00\x00\x00\
+                 \x00\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00";
+    let path = env::temp_dir().join(format!("rlox1-underflow-{}.loxc", std::process::id()));
+    fs::write(&path, code).expect("a temporary file");
+    let output = rlox1(&["run", "--engine", "vm", path.to_str().unwrap()], "");
+    fs::remove_file(&path).expect("the temporary file is removable");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("The Pop instruction at offset 0 needs 1 values on the stack"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("Internal interpreter error"), "{}", stderr);
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn disassembly_lists_the_bytecode() {
+    let script = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/engines/scopes.lox");
+    let output = rlox1(&["compile", "--disassemble", script.to_str().unwrap()], "");
+    let listing = String::from_utf8_lossy(&output.stdout);
+    assert!(listing.starts_with("== "), "{}", listing);
+    assert!(listing.contains("0000    1 "), "{}", listing);
+    assert!(listing.trim_end().ends_with("Return"), "{}", listing);
+    assert!(output.stderr.is_empty());
+}
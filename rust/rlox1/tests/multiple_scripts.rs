@@ -0,0 +1,45 @@
+// `rlox1 run` takes several scripts and runs them in turn, e.g. a prelude before the script
+// that uses it. They share globals unless --isolate is given, and the errors of all of them
+// are reported together.
+
+mod common;
+
+use common::rlox1_in_tests;
+
+const SCRIPT: &str = "print greet(\"world\");";
+
+#[test]
+fn scripts_share_globals() {
+    let output = rlox1_in_tests(&["run", "prelude.lox", "-"], SCRIPT);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello world\n");
+    assert!(output.status.success());
+    let output = rlox1_in_tests(&["run", "--isolate", "prelude.lox", "-"], SCRIPT);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Undefined variable 'greet'"), "{}", stderr);
+    assert_eq!(output.status.code(), Some(70));
+}
+
+#[test]
+fn errors_in_one_script_do_not_stop_the_next() {
+    let output = rlox1_in_tests(
+        &["run", "syntax-error.lox", "error-line.lox", "-"],
+        "print 3;",
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--> syntax-error.lox:2:10"), "{}", stderr);
+    assert!(stderr.contains("--> error-line.lox:3:3"), "{}", stderr);
+    // The status is the first error's.
+    assert!(
+        stderr.ends_with("NOTE: 2 errors; exiting with status 65.\n"),
+        "{}",
+        stderr
+    );
+    assert_eq!(output.status.code(), Some(65));
+    let output = rlox1_in_tests(&["run", "--fail-fast", "error-line.lox", "-"], "print 3;");
+    assert!(output.stdout.is_empty());
+    assert_eq!(output.status.code(), Some(70));
+    // exit() stops them all.
+    let output = rlox1_in_tests(&["run", "-", "syntax-error.lox"], "exit(4);");
+    assert_eq!(output.status.code(), Some(4));
+}
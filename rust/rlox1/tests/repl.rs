@@ -0,0 +1,134 @@
+// The REPL reads entries from stdin even when it isn't a terminal, which lets a session be
+// scripted: `:record` saves what is typed, and `repl --replay` runs it again.
+
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::process::{Command, Output, Stdio};
+
+fn repl(args: &[&str], typed: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rlox1"))
+        .arg("repl")
+        .args(args)
+        // Keep the history of these sessions out of the user's.
+        .env("XDG_DATA_HOME", env::temp_dir().join("rlox1-repl-test"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("the interpreter runs");
+    let mut input = child.stdin.take().expect("a pipe to stdin");
+    input
+        .write_all(typed.as_bytes())
+        .expect("the entries are written");
+    drop(input);
+    child.wait_with_output().expect("the interpreter finishes")
+}
+
+#[test]
+fn transcripts_are_recorded_and_replayed() {
+    let path = env::temp_dir().join(format!("rlox1-transcript-{}.lox", std::process::id()));
+    let path = path.to_str().unwrap();
+    let typed = format!(
+        "var a = 1;\n:record {}\nfun f(x) {{\n  return x + a;\n}}\nprint f(2);\n:stop\nprint 9;\n",
+        path
+    );
+    let output = repl(&[], &typed);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "> > > ... ... > 3\n> > 9\n> "
+    );
+    let transcript = fs::read_to_string(path).expect("the transcript was saved");
+    assert_eq!(transcript, "fun f(x) {\n  return x + a;\n}\nprint f(2);\n");
+    // The transcript is shown as if typed, and then the REPL carries on reading stdin.
+    let output = repl(&["--replay", path], "var a = 10;\nprint f(3);\n");
+    fs::remove_file(path).expect("the transcript is removable");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "> fun f(x) {\n...   return x + a;\n... }\n> print f(2);\n> > 13\n> "
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("ERROR[LX0301]: [line 2] Undefined variable 'a'."),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn unknown_commands_are_errors() {
+    let output = repl(&[], ":bogus\n:stop\nprint 1;\n");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "> > > 1\n> ");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "ERROR[LX0907]: Unknown command ':bogus'; try :record FILE or :stop.\n\
+         ERROR[LX0907]: Nothing is being recorded.\n"
+    );
+    assert!(output.status.success());
+}
+
+#[test]
+fn errors_quote_the_entry_like_a_script() {
+    let typed = "print nope;\nfun f() {\n  print nope;\n}\nf();\n";
+    let output = repl(&[], typed);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "ERROR[LX0301]: [line 1] Undefined variable 'nope'.\n \
+         --> <repl>:1:7\n  \
+         |\n\
+         1 | print nope;\n  \
+         |       ^^^^\n\
+         [line 1] in script\n\
+         ERROR[LX0301]: [line 2] Undefined variable 'nope'.\n\
+         [line 2] in f()\n\
+         [line 1] in script\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn ctrl_c_stops_the_entry_not_the_repl() {
+    // The call to f would take until long after the test times out, and the try is no help.
+    // g prints 0 before it calls f, so Ctrl-C always lands in a call g has started.
+    let typed = "fun f(n) { if (n < 1) return 0; return f(n - 1) + f(n - 1); }\n\
+                 fun g() { print 0; return f(100); }\ntry { g(); } catch (e) {}\nprint 1;\n";
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rlox1"))
+        .arg("repl")
+        .env("XDG_DATA_HOME", env::temp_dir().join("rlox1-repl-test"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("the interpreter runs");
+    let mut input = child.stdin.take().expect("a pipe to stdin");
+    input
+        .write_all(typed.as_bytes())
+        .expect("the entries are written");
+    drop(input);
+    // Wait until the REPL has run an entry, so that the handler is installed.
+    let mut stdout = child.stdout.take().expect("a pipe from stdout");
+    let mut seen = Vec::new();
+    let mut buffer = [0; 64];
+    while !String::from_utf8_lossy(&seen).contains("0\n") {
+        let n = stdout.read(&mut buffer).expect("stdout is readable");
+        assert!(n > 0, "the REPL stopped early");
+        seen.extend_from_slice(&buffer[..n]);
+    }
+    let killed = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .expect("kill runs");
+    assert!(killed.success());
+    stdout.read_to_end(&mut seen).expect("stdout is readable");
+    let output = child.wait_with_output().expect("the interpreter finishes");
+    assert_eq!(String::from_utf8_lossy(&seen), "> > > 0\n> 1\n> ");
+    // The trace shows how deep in f the script was.
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("ERROR[LX0603]: Interrupted.\n"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.ends_with("in script\n"), "{}", stderr);
+    assert!(output.status.success());
+}
@@ -0,0 +1,51 @@
+// `--sandbox` runs untrusted scripts: no file or environment access, and limits on memory and
+// call depth that end the script with a runtime error instead of taking the machine down.
+
+mod common;
+
+use std::process::Output;
+
+use common::rlox1;
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn sandboxed_scripts_cannot_reach_the_environment() {
+    let output = rlox1(
+        &["run", "--sandbox", "-"],
+        "print 1; print getenv(\"HOME\");",
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stderr(&output).contains("'getenv' is disabled in the sandbox"));
+    // clap rejects the flags before reading the program, so there is no program to send.
+    let output = rlox1(&["run", "--sandbox", "--allow-io", "-"], "");
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    let output = rlox1(&["run", "--sandbox", "--engine=vm", "-"], "print 1;");
+    assert!(stderr(&output).contains("Only the tree-walker can run in the sandbox"));
+}
+
+#[test]
+fn sandboxed_scripts_are_limited() {
+    let output = rlox1(
+        &["run", "--sandbox", "-"],
+        "fun f(n) { return f(n + 1); } f(0);",
+    );
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stderr(&output).contains("Stack overflow"));
+    // Each pass doubles the string, to 50 GB by the end if nothing stops it.
+    let program = "var s = \"xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx\";\n\
+                   for (var i in [1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,21,22,23,\n\
+                   24,25,26,27,28,29,30]) s = s + s;\nprint \"done\";";
+    let output = rlox1(&["run", "--sandbox", "-"], program);
+    assert_eq!(output.status.code(), Some(70));
+    assert!(
+        stderr(&output).contains("Out of memory"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(output.stdout.is_empty());
+}
@@ -0,0 +1,19 @@
+// Arguments after `--` go to the script, which sees them in args() and argc.
+
+mod common;
+
+use common::rlox1;
+
+const PROGRAM: &str = "print argc; for (var arg in args()) print arg;";
+
+#[test]
+fn arguments_after_the_dashes_go_to_the_script() {
+    let output = rlox1(&["run", "-", "--", "one", "two words", "--trace"], PROGRAM);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "3\none\ntwo words\n--trace\n"
+    );
+    assert!(output.stderr.is_empty());
+    let output = rlox1(&["run", "-"], PROGRAM);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "0\n");
+}
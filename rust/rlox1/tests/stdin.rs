@@ -0,0 +1,46 @@
+// Programs can come from stdin, so rlox1 works in pipelines: `run -` reads the script from
+// stdin, as do `run` without a script and rlox1 without a command when stdin isn't a terminal.
+// A script run from a file has stdin as its input instead.
+
+mod common;
+
+use std::path::Path;
+
+use common::rlox1;
+
+const PROGRAM: &str = "fun greet(name) {\n  print \"hello \" + name;\n}\ngreet(\"pipe\");\n";
+
+#[test]
+fn dash_reads_the_script_from_stdin() {
+    let output = rlox1(&["run", "-"], PROGRAM);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello pipe\n");
+    assert!(output.status.success());
+}
+
+#[test]
+fn piped_input_runs_as_a_program() {
+    let output = rlox1(&[], PROGRAM);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello pipe\n");
+    let output = rlox1(&["run"], PROGRAM);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello pipe\n");
+    let output = rlox1(&["run", "--engine=vm", "-"], "print 1 +;");
+    assert_eq!(output.status.code(), Some(65));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn scripts_from_files_read_stdin_as_input() {
+    let filter = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/filter.lox");
+    let output = rlox1(&["run", filter.to_str().unwrap()], "pipe\nfilter\nlast");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "first: pipe\nrest: FILTER\nrest: LAST\n"
+    );
+    assert!(output.status.success());
+    // A script from stdin has read it all.
+    let output = rlox1(
+        &["run", "-"],
+        "print IO.readLine(); print IO.readAll() == \"\";",
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "nil\ntrue\n");
+}